@@ -0,0 +1,58 @@
+package ai.dottxt.outlines;
+
+import java.nio.ByteBuffer;
+
+/**
+ * Native entry points of outlines-core, built with the {@code jni} feature.
+ *
+ * <p>Indexes are referred to by the handles returned by {@link #newIndex}, which must be released
+ * with {@link #freeIndex}. Invalid arguments raise an {@link IllegalArgumentException}.
+ */
+public final class OutlinesCore {
+    static {
+        System.loadLibrary("outlines_core");
+    }
+
+    private OutlinesCore() {}
+
+    /** Compiles a JSON Schema into a regular expression, {@code whitespacePattern} may be null. */
+    public static native String buildRegexFromSchema(String json, String whitespacePattern);
+
+    /**
+     * Builds the index of an automaton over a vocabulary.
+     *
+     * @param transitions the {@code fromState, transitionKey, toState} triples of the automaton,
+     *     one after the other
+     * @param symbols the symbols of the automaton's alphabet
+     * @param symbolKeys the transition key of each symbol
+     * @param tokens the tokens of the vocabulary
+     * @param tokenIds the id of each token
+     */
+    public static native long newIndex(
+            int initial,
+            int[] finals,
+            int[] transitions,
+            int alphabetAnythingValue,
+            String[] symbols,
+            int[] symbolKeys,
+            String[] tokens,
+            int[] tokenIds,
+            int eosTokenId);
+
+    public static native void freeIndex(long index);
+
+    public static native int initialState(long index);
+
+    /** Returns the state reached by generating {@code tokenId}, or -1 if it is not allowed. */
+    public static native int nextState(long index, int state, int tokenId);
+
+    public static native boolean isFinalState(long index, int state);
+
+    public static native int[] allowedTokens(long index, int state);
+
+    /**
+     * Writes the tokens allowed in {@code state} as a bitmask into a direct buffer, token {@code i}
+     * being bit {@code i % 8} of byte {@code i / 8}.
+     */
+    public static native void writeMask(long index, int state, ByteBuffer mask);
+}
@@ -0,0 +1,63 @@
+/** Lexical forms of the integers, each one forbidden unless set. */
+export interface IntegerForms {
+  plusSign?: boolean
+  negativeZero?: boolean
+  leadingZeros?: boolean
+}
+
+export interface SchemaOptions {
+  whitespacePattern?: string
+  draft?: string
+  unicodeStrings?: boolean
+  strictEscapes?: boolean
+  asciiOnly?: boolean
+  unorderedRequired?: boolean
+  caseInsensitive?: boolean
+  integerForms?: IntegerForms
+  exponents?: boolean
+  json5Numbers?: boolean
+  /** `"optional"`, `"required"` or `"forbidden"`. */
+  timeFraction?: string
+  /** `"optional_utc"`, `"utc"`, `"numeric"`, `"utc_or_numeric"` or `"forbidden"`. */
+  timeOffset?: string
+  /** `"codepoints"`, `"utf16"` or `"bytes"`. */
+  lengthSemantics?: string
+  indent?: number
+  compact?: boolean
+  maxArrayItems?: number
+  maxNestingDepth?: number
+  timeoutMs?: number
+  markdownFence?: boolean
+  fencePreamble?: number
+}
+
+export function buildRegexFromSchema(json: string, options?: SchemaOptions): string
+
+export class FSMInfo {
+  /** `transitions` lists the `[fromState, transitionKey, toState]` triples of the automaton. */
+  constructor(
+    initial: number,
+    finals: Array<number>,
+    transitions: Array<[number, number, number]>,
+    alphabetAnythingValue: number,
+    alphabetSymbolMapping: Record<string, number>
+  )
+  withMaxLength(maxLength: number): FSMInfo
+}
+
+export class Vocabulary {
+  static fromDict(map: Record<string, Array<number>>): Vocabulary
+}
+
+export class Index {
+  constructor(
+    fsmInfo: FSMInfo,
+    vocabulary: Vocabulary,
+    eosTokenId: number,
+    frozenTokens: Array<string>
+  )
+  getAllowedTokens(state: number): Array<number> | null
+  getNextState(state: number, tokenId: number): number | null
+  isFinalState(state: number): boolean
+  getInitialState(): number
+}
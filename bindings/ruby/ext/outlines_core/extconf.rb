@@ -0,0 +1,8 @@
+require "mkmf"
+require "rb_sys/mkmf"
+
+# The extension is the crate at the root of the repository, built with its Ruby bindings.
+create_rust_makefile("outlines_core/outlines_core") do |r|
+  r.ext_dir = File.expand_path("../../../..", __dir__)
+  r.features = ["ruby-bindings"]
+end
@@ -0,0 +1 @@
+require "outlines_core/outlines_core"
@@ -1,15 +1,125 @@
+from typing import Any, List, Optional
+
 from .outlines_core_rs import (  # noqa: F401
     BOOLEAN,
     DATE,
     DATE_TIME,
+    EMAIL,
+    HOSTNAME,
+    IDN_EMAIL,
+    IDN_HOSTNAME,
     INTEGER,
+    IRI,
+    JSON_POINTER,
     NULL,
     NUMBER,
+    PHONE,
+    RELATIVE_JSON_POINTER,
+    SEMVER,
     STRING,
     STRING_INNER,
+    STRING_INNER_ASCII,
+    STRING_INNER_ASCII_STRICT,
+    STRING_INNER_STRICT,
+    STRING_INNER_UNICODE,
     TIME,
+    URI,
     UUID,
     WHITESPACE,
+    Schema,
+    SchemaBuilder,
+    build_fsm_from_schema,
+    build_jsonl_regex_from_schema,
+    build_regex_from_json5,
     build_regex_from_schema,
+    build_regex_from_schema_file,
+    build_regex_from_yaml,
     to_regex,
 )
+
+
+def build_regex_from_model(
+    model: Any, whitespace_pattern: Optional[str] = None, **options: Any
+) -> str:
+    """Compile the JSON Schema of a Pydantic v2 model to a regular expression.
+
+    The references of the schema point into its `$defs`, whatever the `ref_template` the model
+    is configured with, and the encodings specific to Pydantic are normalized: enums of a single
+    value become constants and nested nullable `anyOf` alternatives are flattened.
+
+    Parameters
+    ----------
+    model
+        The Pydantic model class.
+    whitespace_pattern
+        Pattern matched between the tokens of the JSON.
+    options
+        The options of `build_regex_from_schema`.
+
+    Returns
+    -------
+    The regular expression matching the JSON documents of the model.
+
+    """
+    if not hasattr(model, "model_json_schema"):
+        raise TypeError(f"{model!r} is not a Pydantic v2 model class")
+    schema = model.model_json_schema(ref_template="#/$defs/{model}")
+    return to_regex(_normalize_pydantic(schema), whitespace_pattern, **options)
+
+
+def _normalize_pydantic(schema: Any) -> Any:
+    if isinstance(schema, list):
+        return [_normalize_pydantic(subschema) for subschema in schema]
+    if not isinstance(schema, dict):
+        return schema
+
+    schema = {key: _normalize_pydantic(value) for key, value in schema.items()}
+    enum = schema.get("enum")
+    if isinstance(enum, list) and len(enum) == 1 and "const" not in schema:
+        schema["const"] = schema.pop("enum")[0]
+    elif "const" in schema:
+        schema.pop("enum", None)
+
+    any_of = schema.get("anyOf")
+    if isinstance(any_of, list):
+        alternatives: List[Any] = []
+        for alternative in any_of:
+            if isinstance(alternative, dict) and list(alternative) == ["anyOf"]:
+                nested = alternative["anyOf"]
+            else:
+                nested = [alternative]
+            for flattened in nested:
+                if flattened not in alternatives:
+                    alternatives.append(flattened)
+        schema["anyOf"] = alternatives
+    return schema
+
+
+def build_regex_from_msgspec(
+    type: Any, whitespace_pattern: Optional[str] = None, **options: Any
+) -> str:
+    """Compile the JSON Schema of a msgspec `Struct`, `TypedDict` or other type to a regular
+    expression.
+
+    The tagged unions of structs are compiled from the `discriminator` of their schema, each
+    alternative having its tag field fixed to its tag.
+
+    Parameters
+    ----------
+    type
+        The type given to `msgspec.json.schema`.
+    whitespace_pattern
+        Pattern matched between the tokens of the JSON.
+    options
+        The options of `build_regex_from_schema`.
+
+    Returns
+    -------
+    The regular expression matching the JSON documents of the type.
+
+    """
+    try:
+        import msgspec
+    except ImportError as e:
+        raise ImportError("build_regex_from_msgspec requires msgspec") from e
+    return to_regex(msgspec.json.schema(type), whitespace_pattern, **options)
@@ -1,4 +1,6 @@
-from typing import Dict, List, Optional, Set, Tuple
+import asyncio
+import os
+from typing import Any, Callable, Dict, List, Optional, Set, Tuple, Union
 
 class FSMInfo:
     initial: int
@@ -15,11 +17,450 @@ class FSMInfo:
         alphabet_anything_value: int,
         alphabet_symbol_mapping: Dict[str, int],
     ) -> None: ...
+    @staticmethod
+    def from_interegular(fsm: Any) -> "FSMInfo":
+        """
+        Converts an interegular `FSM`, a dictionary of its `alphabet`, `states`, `initial`,
+        `finals` and `map` fields, or the pickle of either.
+        """
+        ...
+    @staticmethod
+    def from_words(words: List[Union[str, bytes]]) -> "FSMInfo":
+        """
+        Builds the byte-level automaton accepting exactly the given words, their minimal
+        automaton, without compiling their alternation as a regular expression.
+        """
+        ...
+    @staticmethod
+    def from_wordlist(path: Union[str, "os.PathLike[str]"]) -> "FSMInfo":
+        """
+        Builds the byte-level automaton accepting exactly the words of a file of one word per
+        line, see `from_words`.
+        """
+        ...
+    def with_max_length(self, max_length: int) -> "FSMInfo": ...
+    def compress_alphabet(self) -> "FSMInfo":
+        """
+        Merges the transition keys which lead to the same states into one key per class.
+        """
+        ...
+    def shortest_completion(self, state: int) -> Optional[str]: ...
+    def shortest_string(self) -> Optional[str]:
+        """
+        Shortest string the automaton accepts, or `None` if it accepts none. The bytes of a
+        byte-level automaton are decoded.
+        """
+        ...
+    def enumerate_strings(self, max_len: int, limit: int) -> List[str]:
+        """
+        Strings of at most `max_len` symbols the automaton accepts, at most `limit` of them,
+        in the lexicographic order of their bytes.
+        """
+        ...
+    def equivalent(self, other: "FSMInfo") -> bool: ...
+    def concat(self, other: "FSMInfo") -> "FSMInfo": ...
+    def union(self, other: "FSMInfo") -> "FSMInfo": ...
+    def optional(self) -> "FSMInfo": ...
+    def repeat(self, min: int, max: Optional[int] = None) -> "FSMInfo": ...
+    def forbid_substrings(self, substrings: List[str]) -> "FSMInfo":
+        """
+        Automaton of the strings of this one which contain none of `substrings`.
+        """
+        ...
+
+class PartialValidator:
+    state: Optional[int]
+    valid_up_to: int
+
+    def __init__(self, fsm_info: FSMInfo, byte_level: bool = False) -> None: ...
+    def feed(self, text: str) -> bool: ...
+    def is_viable(self) -> bool: ...
+    def is_final(self) -> bool: ...
+    def allowed_symbols(self) -> List[str]: ...
+    def allows_anything_else(self) -> bool: ...
+    def completion(self) -> Optional[str]: ...
+    def reset(self) -> None: ...
+
+class Template:
+    """
+    Literal text interleaved with holes filled by a regex, a JSON Schema or choices.
+    """
+
+    def __init__(
+        self,
+        whitespace_pattern: Optional[str] = None,
+        *,
+        draft: Optional[str] = None,
+        unicode_strings: bool = False,
+        strict_escapes: bool = False,
+        ascii_only: bool = False,
+        unordered_required: bool = False,
+        case_insensitive: bool = False,
+        integer_forms: Optional[Dict[str, bool]] = None,
+        exponents: bool = True,
+        json5_numbers: bool = False,
+        time_fraction: Optional[str] = None,
+        time_offset: Optional[str] = None,
+        length_semantics: Optional[str] = None,
+        indent: Optional[int] = None,
+        compact: bool = False,
+        max_array_items: Optional[int] = None,
+        max_nesting_depth: Optional[int] = None,
+        markdown_fence: bool = False,
+        fence_preamble: Optional[int] = None,
+        timeout: Optional[float] = None,
+        cancellation: Optional["CancellationToken"] = None,
+    ) -> None:
+        """
+        Creates an empty template, its schemas compiled with the given options.
+        """
+        ...
+    def text(self, text: str) -> "Template": ...
+    def regex(self, regex: str) -> "Template": ...
+    def schema(self, schema: str) -> "Template": ...
+    def choice(self, choices: List[str]) -> "Template": ...
+    def free_text(self, sentinel: str, max_length: Optional[int] = None) -> "Template":
+        """
+        Appends free text ended by the first occurrence of `sentinel`, of at most
+        `max_length` bytes before it. A template with a bounded free text can only be
+        compiled with `to_fsm`.
+        """
+        ...
+    def to_regex(self) -> str: ...
+    def to_fsm(self) -> FSMInfo: ...
+
+class Table:
+    """
+    GitHub-flavored Markdown table: a header of column names, a separator row and between
+    `min_rows` and `max_rows` data rows, each ended by a newline.
+    """
+
+    def __init__(self, min_rows: int = 0, max_rows: Optional[int] = None) -> None: ...
+    def column(self, name: str, cell: Union[str, List[str], None] = None) -> "Table":
+        """
+        Appends a column of `"text"` (the default), `"integer"`, `"number"` or `"boolean"`
+        cells, or of one of a list of choices.
+        """
+        ...
+    def pattern_column(self, name: str, regex: str) -> "Table": ...
+    def to_regex(self) -> str: ...
+    def to_fsm(self) -> FSMInfo: ...
+
+class Schema:
+    """
+    JSON Schema built with `SchemaBuilder`. Each method returns a new schema with the keyword
+    set, and raises `TypeError` when the keyword does not apply to the type of the schema.
+    """
+
+    def property(self, name: str, schema: "Schema") -> "Schema": ...
+    def required(self, names: List[str]) -> "Schema": ...
+    def additional_properties(self, schema: "Schema") -> "Schema": ...
+    def closed(self) -> "Schema": ...
+    def min_len(self, min_length: int) -> "Schema": ...
+    def max_len(self, max_length: int) -> "Schema": ...
+    def pattern(self, pattern: str) -> "Schema": ...
+    def format(self, format: str) -> "Schema": ...
+    def minimum(self, minimum: float) -> "Schema": ...
+    def maximum(self, maximum: float) -> "Schema": ...
+    def min_items(self, min_items: int) -> "Schema": ...
+    def max_items(self, max_items: int) -> "Schema": ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def to_regex(
+        self, whitespace_pattern: Optional[str] = None, **options: Any
+    ) -> str: ...
+
+class SchemaBuilder:
+    """
+    Entry points of the schemas of each type, e.g.
+    `SchemaBuilder.object().property("name", SchemaBuilder.string().min_len(1)).required(["name"])`.
+    """
+
+    @staticmethod
+    def object() -> Schema: ...
+    @staticmethod
+    def array(items: Schema) -> Schema: ...
+    @staticmethod
+    def string() -> Schema: ...
+    @staticmethod
+    def integer() -> Schema: ...
+    @staticmethod
+    def number() -> Schema: ...
+    @staticmethod
+    def boolean() -> Schema: ...
+    @staticmethod
+    def null() -> Schema: ...
+    @staticmethod
+    def enumeration(values: List[Any]) -> Schema: ...
+    @staticmethod
+    def constant(value: Any) -> Schema: ...
+    @staticmethod
+    def any_of(schemas: List[Schema]) -> Schema: ...
 
 def build_regex_from_schema(
-    json: str, whitespace_pattern: Optional[str] = None
+    json: str,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> str: ...
+def build_jsonl_regex_from_schema(
+    json: str,
+    min_lines: int = 1,
+    max_lines: Optional[int] = None,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> str:
+    """
+    Compiles the schema into JSON Lines: between `min_lines` and `max_lines` instances, each
+    on its own line ended by a line break.
+    """
+    ...
+def build_regex_from_json5(
+    json5: str,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> str:
+    """
+    Compiles a schema written in JSON5, with comments, trailing commas, unquoted keys or
+    single-quoted strings.
+    """
+    ...
+def build_regex_from_yaml(
+    yaml: str,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> str:
+    """
+    Compiles a schema written in YAML, as OpenAPI documents often are.
+    """
+    ...
+def build_regex_from_schema_file(
+    path: Union[str, "os.PathLike[str]"],
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> str:
+    """
+    Compiles the schema of a JSON file, which may be gzip-compressed, of a YAML file named
+    `*.yaml` or `*.yml`, or of a JSON5 file named `*.json5` or `*.jsonc`. Raises `OSError` when the file can not be read and `ValueError` when
+    it can not be parsed.
+    """
+    ...
+def build_fsm_from_schema(
+    json: str,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
+) -> FSMInfo:
+    """
+    Compiles the schema into a byte-level automaton. Raises `ValueError` when the schema admits
+    no outputs, listing the innermost subschemas which admit no value.
+    """
+    ...
+def to_regex(
+    json: Any,
+    whitespace_pattern: Optional[str] = None,
+    *,
+    draft: Optional[str] = None,
+    unicode_strings: bool = False,
+    strict_escapes: bool = False,
+    ascii_only: bool = False,
+    unordered_required: bool = False,
+    case_insensitive: bool = False,
+    integer_forms: Optional[Dict[str, bool]] = None,
+    exponents: bool = True,
+    json5_numbers: bool = False,
+    time_fraction: Optional[str] = None,
+    time_offset: Optional[str] = None,
+    length_semantics: Optional[str] = None,
+    indent: Optional[int] = None,
+    compact: bool = False,
+    max_array_items: Optional[int] = None,
+    max_nesting_depth: Optional[int] = None,
+    markdown_fence: bool = False,
+    fence_preamble: Optional[int] = None,
+    timeout: Optional[float] = None,
+    cancellation: Optional["CancellationToken"] = None,
+    ref_loader: Optional[Callable[[str], Any]] = None,
+    keyword_extension: Optional[Callable[[str, Any, Dict[str, Any]], Any]] = None,
+    wordlists: Optional[Dict[str, Union[str, "os.PathLike[str]", List[str]]]] = None,
 ) -> str: ...
-def to_regex(json: Dict, whitespace_pattern: Optional[str] = None) -> str: ...
+def lint_schema(json: str) -> List[Dict[str, str]]:
+    """
+    Flags the constructs of the schema known to make its compilation explode, each with its
+    `severity` ("info", "warning" or "error"), the JSON `pointer` to its subschema, a `message`
+    and a `suggestion`, without compiling it.
+    """
+    ...
+def integer_range_regex(lo: int, hi: int) -> str: ...
+def build_choice_index(
+    choices: List[str], vocabulary: "Vocabulary", eos_token_id: int
+) -> "Index": ...
+def compile(
+    json: str,
+    vocabulary: "Vocabulary",
+    eos_token_id: int,
+    whitespace_pattern: Optional[str] = None,
+) -> "Guide":
+    """
+    Compiles a JSON Schema into a guide in its initial state. The automata of the last compiled
+    schemas are kept, keyed by the schema, the whitespace pattern and the fingerprint of the
+    vocabulary, so that compiling a schema again is a lookup.
+    """
+    ...
+def compile_async(
+    json: str,
+    vocabulary: "Vocabulary",
+    eos_token_id: int,
+    whitespace_pattern: Optional[str] = None,
+) -> "asyncio.Future[Guide]":
+    """
+    Compiles a JSON Schema into a guide like `compile`, on a background thread, without blocking
+    the running event loop. Cancelling the future cancels the compilation.
+    """
+    ...
+def get_stats() -> Dict[str, Optional[Union[int, float]]]:
+    """
+    Returns the counters of the work done in the process: `indexes_built`, `index_build_ms`,
+    `average_build_ms` (`None` before the first index), `states_expanded`,
+    `compile_cache_hits`, `compile_cache_misses`, `transition_keys_cache_hits` and
+    `masks_served`. The counters only grow.
+    """
+    ...
 def _walk_fsm(
     fsm_transitions: Dict[Tuple[int, int], int],
     fsm_initial: int,
@@ -39,29 +480,55 @@ def state_scan_tokens(
 def get_token_transition_keys(
     alphabet_symbol_mapping: Dict[str, int],
     alphabet_anything_value: int,
-    token_str: str,
+    token_str: Union[str, bytes],
 ) -> List[int]: ...
 def get_vocabulary_transition_keys(
     alphabet_symbol_mapping: Dict[str, int],
     alphabet_anything_value: int,
     vocabulary: Vocabulary,
     frozen_tokens: Set[str],
-) -> Dict[str, List[int]]: ...
+) -> Dict[str, List[int]]:
+    """
+    Gets the transition keys of every token. Frozen tokens are read whole, with the key of the
+    symbol they are equal to, and get no key when they are several characters long and not a
+    symbol of the alphabet.
+    """
+    ...
 def create_fsm_index_end_to_end(
     fsm_info: FSMInfo,
     vocabulary: Vocabulary,
     frozen_tokens: frozenset[str],
-) -> Dict[int, Dict[int, int]]: ...
+    progress: Optional[Callable[[int, int], Any]] = None,
+) -> Dict[int, Dict[int, int]]:
+    """
+    Maps each state to the tokens allowed in it and the states they lead to, sorted by state
+    then by token.
+    """
+    ...
 
 BOOLEAN: str
 DATE: str
 DATE_TIME: str
+EMAIL: str
+HOSTNAME: str
+IDN_EMAIL: str
+IDN_HOSTNAME: str
 INTEGER: str
+IRI: str
+JSON_POINTER: str
 NULL: str
 NUMBER: str
+PHONE: str
+RELATIVE_JSON_POINTER: str
+SEMVER: str
 STRING: str
 STRING_INNER: str
+STRING_INNER_UNICODE: str
+STRING_INNER_STRICT: str
+STRING_INNER_ASCII: str
+STRING_INNER_ASCII_STRICT: str
 TIME: str
+URI: str
 UUID: str
 WHITESPACE: str
 
@@ -71,9 +538,10 @@ class Vocabulary:
     """
 
     @staticmethod
-    def from_dict(map: Dict[str, List[int]]) -> "Vocabulary":
+    def from_dict(map: Dict[Union[str, bytes], List[int]]) -> "Vocabulary":
         """
-        Creates a vocabulary from a dictionary of tokens to token IDs.
+        Creates a vocabulary from a dictionary of tokens to token IDs. Tokens given as bytes,
+        which need not be valid UTF-8, are written with the symbols of a byte-level automaton.
         """
         ...
     def __repr__(self) -> str:
@@ -87,9 +555,49 @@ class Vocabulary:
         """
         ...
 
+class CancellationToken:
+    """
+    Flag cancelling the compilations it is given to, from another thread.
+    """
+
+    def __init__(self) -> None: ...
+    def cancel(self) -> None: ...
+    def is_cancelled(self) -> bool: ...
+
 class Index:
+    def __init__(
+        self,
+        fsm_info: FSMInfo,
+        vocabulary: "Vocabulary",
+        eos_token_id: int,
+        frozen_tokens: Set[str],
+        *,
+        timeout: Optional[float] = None,
+        cancellation: Optional[CancellationToken] = None,
+        progress: Optional[Callable[[int, int], Any]] = None,
+        max_bytes: Optional[int] = None,
+        special_tokens: Optional[Union[str, List[int]]] = None,
+        token_healing: Optional[Union[str, bytes]] = None,
+    ) -> None:
+        """
+        Builds the index, raising `TimeoutError` once `timeout` seconds have elapsed and
+        `ValueError` once `cancellation` is cancelled. `progress` is called after each state
+        with the numbers of states processed and of states left to process, and stops the
+        construction by raising. `MemoryError` is raised once the estimated size of the
+        transitions exceeds `max_bytes`.
+
+        `special_tokens` is `"forbid_all"`, `"eos_only"`, or the list of the ids of the allowed
+        special tokens, EOS included. The listed tokens and the frozen tokens are read whole,
+        and the ones not allowed are left out, while EOS and the frozen tokens are allowed
+        without a policy.
+
+        `token_healing` is the text of the last tokens cut back from the prompt, which the output
+        writes again: the first tokens allowed are then the ones extending the prompt past the
+        cut while keeping the output valid.
+        """
+        ...
     def get_allowed_tokens(self, state: int) -> Optional[List[int]]:
-        """Returns allowed tokens in this state."""
+        """Returns allowed tokens in this state, sorted."""
         ...
     def get_next_state(self, state: int, token_id: int) -> Optional[int]:
         """Updates the state."""
@@ -98,8 +606,122 @@ class Index:
         """Determines whether the current state is a final state."""
         ...
     def get_index_dict(self) -> Dict[int, Dict[int, int]]:
-        """Returns the Index as a Python Dict object."""
+        """Returns the Index as a Python Dict object, sorted by state then by token."""
         ...
     def get_initial_state(self) -> int:
         """Returns the ID of the initial state of the input FSM automata."""
         ...
+    def to_token_dfa(self) -> "TokenDfa":
+        """Returns the index as an automaton over token IDs, stepped without hashing."""
+        ...
+    def to_mask_table(self, vocab_size: int) -> "MaskTable":
+        """Returns the bitmasks of the tokens allowed in every state, stored contiguously."""
+        ...
+    def diff(self, other: "Index") -> Dict:
+        """Returns the states and tokens whose transitions differ in the other index."""
+        ...
+    def merge(self, other: "Index") -> "Index":
+        """Returns the index allowing the tokens of both indexes in each state."""
+        ...
+
+class TokenDfa:
+    def get_allowed_tokens(self, state: int) -> List[int]:
+        """Returns allowed tokens in this state, sorted."""
+        ...
+    def get_next_state(self, state: int, token_id: int) -> Optional[int]:
+        """Updates the state."""
+        ...
+    def is_final_state(self, state: int) -> bool:
+        """Determines whether the current state is a final state."""
+        ...
+    def get_initial_state(self) -> int:
+        """Returns the ID of the initial state, which is always 0."""
+        ...
+    def num_states(self) -> int:
+        """Returns the number of states."""
+        ...
+    def apply_mask(self, logits: Any, state: int) -> None:
+        """
+        Sets the logits of the tokens not allowed in the state to `-inf`, in place, in a
+        writable buffer of 32-bit floats such as a NumPy array of `float32`.
+        """
+        ...
+
+class Guide:
+    def __init__(self, index: Index) -> None: ...
+    def get_state(self) -> int:
+        """Returns the current state."""
+        ...
+    def get_allowed_tokens(self) -> List[int]:
+        """Returns the tokens allowed in the current state, sorted."""
+        ...
+    def is_final(self) -> bool:
+        """Determines whether the current state is a final state."""
+        ...
+    def advance(self, token_id: int) -> int:
+        """Moves to the state the token leads to, and returns it."""
+        ...
+    def peek(self, token_id: int) -> Optional[int]:
+        """Returns the state the token would lead to, without moving."""
+        ...
+    def resume(self, text: str) -> str:
+        """
+        Moves to the state reached by reading already written text, which need not be split
+        into tokens, up to the last point tokens reach. Returns the rest of the text, such as
+        the beginning of a token, which is left to generate again.
+        """
+        ...
+    def with_state(self, state: int) -> "Guide":
+        """Returns a guide in another state, sharing the automaton of this one."""
+        ...
+    def verify(self, token_ids: List[int]) -> int:
+        """
+        Returns the number of tokens of a draft which are allowed one after the other from the
+        current state, without moving.
+        """
+        ...
+    def write_mask(self, mask: Any) -> None:
+        """
+        Writes the tokens allowed in the current state as a bitmask into a writable buffer of
+        64-bit integers, token `i` being bit `i % 64` of `mask[i // 64]`, without allocating.
+        """
+        ...
+    def apply_mask(self, logits: Any) -> None:
+        """
+        Sets the logits of the tokens not allowed in the current state to `-inf`, in place, in a
+        writable buffer of 32-bit floats such as a NumPy array of `float32`.
+        """
+        ...
+
+class BatchGuide:
+    def __init__(self, index: Index, batch_size: int) -> None: ...
+    def get_states(self) -> List[int]:
+        """Returns the current state of each generation."""
+        ...
+    def __len__(self) -> int: ...
+    def advance_all(self, token_ids: List[int]) -> None:
+        """Moves each generation with its token, none of them moving if a token is not allowed."""
+        ...
+    def push(self) -> int:
+        """Starts a new generation, and returns its position in the batch."""
+        ...
+    def swap_remove(self, i: int) -> None:
+        """Removes a generation, the last generation taking its position."""
+        ...
+
+class MaskTable:
+    def get_masks(self) -> bytes:
+        """
+        Returns the masks as little-endian 32-bit words, token `i` being bit `i % 32` of the
+        word `i // 32` of a row, e.g. for `torch.frombuffer(..., dtype=torch.int32)`.
+        """
+        ...
+    def get_rows(self) -> List[int]:
+        """Returns the row of the mask of each state, indexed by state."""
+        ...
+    def num_rows(self) -> int:
+        """Returns the number of rows, the last one being empty."""
+        ...
+    def words_per_row(self) -> int:
+        """Returns the number of 32-bit words of each row."""
+        ...
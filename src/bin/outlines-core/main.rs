@@ -0,0 +1,264 @@
+//! Compiles and inspects constraints from the command line.
+mod tokenizer;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use outlines_core::index::{FSMInfo, Index};
+use outlines_core::json_schema::{self, Whitespace};
+use outlines_core::prelude::*;
+
+use tokenizer::Tokenizer;
+
+/// End-of-sequence tokens of the common tokenizers, looked up if `--eos-token` is not given.
+const EOS_TOKENS: [&str; 5] = ["</s>", "<|endoftext|>", "<|im_end|>", "<|eot_id|>", "<eos>"];
+
+#[derive(Parser)]
+#[command(
+    name = "outlines-core",
+    version,
+    about = "Compiles and inspects structured generation constraints"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the regular expression of a JSON Schema.
+    Regex {
+        /// JSON Schema file, in JSON (possibly gzip-compressed), in YAML (`*.yaml`, `*.yml`) or in
+        /// JSON5 (`*.json5`, `*.jsonc`).
+        schema: PathBuf,
+        #[command(flatten)]
+        whitespace: WhitespaceArgs,
+    },
+    /// Builds the index of a constraint over the vocabulary of a tokenizer.
+    Index {
+        /// Hugging Face `tokenizer.json` file.
+        #[arg(long)]
+        tokenizer: PathBuf,
+        /// End-of-sequence token, guessed from the usual names if not given.
+        #[arg(long)]
+        eos_token: Option<String>,
+        #[command(flatten)]
+        constraint: ConstraintArgs,
+        /// File the serialized index is written to.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Prints the automaton of a constraint in the Graphviz DOT language.
+    Dot {
+        #[command(flatten)]
+        constraint: ConstraintArgs,
+    },
+    /// Prints random strings satisfying a constraint, one per line.
+    Sample {
+        #[command(flatten)]
+        constraint: ConstraintArgs,
+        /// Number of strings.
+        #[arg(short = 'n', long, default_value_t = 5)]
+        count: usize,
+        /// Maximum length of the strings, in bytes.
+        #[arg(long, default_value_t = 256)]
+        max_length: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct ConstraintSource {
+    /// JSON Schema file, in JSON (possibly gzip-compressed), in YAML (`*.yaml`, `*.yml`) or in
+    /// JSON5 (`*.json5`, `*.jsonc`).
+    #[arg(long)]
+    schema: Option<PathBuf>,
+    /// Regular expression.
+    #[arg(long)]
+    regex: Option<String>,
+}
+
+#[derive(Args)]
+struct ConstraintArgs {
+    #[command(flatten)]
+    source: ConstraintSource,
+    #[command(flatten)]
+    whitespace: WhitespaceArgs,
+}
+
+#[derive(Args)]
+#[group(multiple = false)]
+struct WhitespaceArgs {
+    /// Pattern of the whitespace allowed between the tokens of a schema's JSON.
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+    /// Pretty-prints the JSON with this indentation.
+    #[arg(long)]
+    indent: Option<usize>,
+    /// Forbids whitespace between the tokens of the JSON.
+    #[arg(long)]
+    compact: bool,
+}
+
+impl WhitespaceArgs {
+    fn options(&self) -> json_schema::Options {
+        let options = json_schema::Options::new();
+        match (&self.whitespace_pattern, self.indent, self.compact) {
+            (Some(pattern), _, _) => options.whitespace_pattern(pattern),
+            (_, Some(indent), _) => options.whitespace(Whitespace::Pretty(indent)),
+            (_, _, true) => options.whitespace(Whitespace::None),
+            _ => options,
+        }
+    }
+}
+
+fn schema_regex(path: &Path, whitespace: &WhitespaceArgs) -> Result<String> {
+    json_schema::build_regex_from_schema_file(path, &whitespace.options())
+}
+
+impl ConstraintArgs {
+    fn fsm_info(&self) -> Result<FSMInfo> {
+        let regex = match (&self.source.schema, &self.source.regex) {
+            (Some(schema), _) => schema_regex(schema, &self.whitespace)?,
+            (_, Some(regex)) => regex.clone(),
+            (None, None) => unreachable!("clap requires a schema or a regex"),
+        };
+        Ok(FSMInfo::from_regex(&regex)?)
+    }
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Regex { schema, whitespace } => {
+            println!("{}", schema_regex(&schema, &whitespace)?);
+        }
+        Command::Index {
+            tokenizer,
+            eos_token,
+            constraint,
+            output,
+        } => {
+            let json = fs::read_to_string(&tokenizer)
+                .with_context(|| format!("Failed to read {}", tokenizer.display()))?;
+            let tokenizer = Tokenizer::from_json(&json)?;
+            let eos_token_id = match eos_token {
+                Some(eos_token) => *tokenizer
+                    .special_tokens
+                    .get(&eos_token)
+                    .ok_or_else(|| anyhow!("'{}' is not a special token", eos_token))?,
+                None => *EOS_TOKENS
+                    .iter()
+                    .find_map(|eos_token| tokenizer.special_tokens.get(*eos_token))
+                    .ok_or_else(|| {
+                        anyhow!("No end-of-sequence token found, set one with --eos-token")
+                    })?,
+            };
+
+            let index = Index::new(
+                &constraint.fsm_info()?,
+                &tokenizer.vocabulary,
+                eos_token_id,
+                Default::default(),
+            )?;
+            fs::write(&output, index.to_bytes())
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            eprintln!(
+                "Wrote an index of {} states to {}",
+                index.index().len(),
+                output.display()
+            );
+        }
+        Command::Dot { constraint } => {
+            print!("{}", constraint.fsm_info()?.to_dot());
+        }
+        Command::Sample {
+            constraint,
+            count,
+            max_length,
+            seed,
+        } => {
+            let fsm_info = constraint.fsm_info()?.with_max_length(max_length);
+            if fsm_info.transitions().is_empty() && !fsm_info.finals().contains(&fsm_info.initial())
+            {
+                return Err(anyhow!(
+                    "No string of at most {} bytes satisfies the constraint",
+                    max_length
+                ));
+            }
+            let mut rng = XorShift::new(seed);
+            for _ in 0..count {
+                println!("{}", sample(&fsm_info, &mut rng));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Walks the automaton at random until it stops in a final state. Every state of an automaton
+/// bounded by `FSMInfo::with_max_length` reaches a final state, so the walk always ends.
+fn sample(fsm_info: &FSMInfo, rng: &mut XorShift) -> String {
+    let mut symbols: HashMap<TransitionKey, Vec<&str>> = HashMap::new();
+    for (symbol, &key) in fsm_info.alphabet_symbol_mapping() {
+        symbols.entry(key).or_default().push(symbol);
+    }
+    for key_symbols in symbols.values_mut() {
+        key_symbols.sort_unstable();
+    }
+    let mut transitions: Vec<(State, TransitionKey, State)> = fsm_info
+        .transitions()
+        .iter()
+        .filter(|((_, key), _)| symbols.contains_key(key))
+        .map(|(&(from_state, key), &to_state)| (from_state, key, to_state))
+        .collect();
+    transitions.sort_unstable();
+
+    let mut bytes = Vec::new();
+    let mut state = fsm_info.initial();
+    loop {
+        let next: Vec<&(State, TransitionKey, State)> = transitions
+            .iter()
+            .filter(|(from_state, _, _)| *from_state == state)
+            .collect();
+        // Stopping counts as one more way to go on from a final state.
+        let stop = fsm_info.finals().contains(&state) as usize;
+        if next.len() + stop == 0 {
+            break;
+        }
+        let choice = rng.below(next.len() + stop);
+        if choice == next.len() {
+            break;
+        }
+
+        let (_, key, to_state) = next[choice];
+        let key_symbols = &symbols[key];
+        let symbol = key_symbols[rng.below(key_symbols.len())];
+        match symbol.strip_prefix('\0') {
+            Some(hex) => bytes.push(u8::from_str_radix(hex, 16).expect("a byte symbol")),
+            None => bytes.extend_from_slice(symbol.as_bytes()),
+        }
+        state = *to_state;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Small deterministic generator, so that samples can be reproduced from their seed.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero.
+        XorShift(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
@@ -0,0 +1,142 @@
+//! Vocabulary of a Hugging Face `tokenizer.json` file.
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use outlines_core::prelude::*;
+use serde_json::Value;
+
+pub struct Tokenizer {
+    /// Tokens written as the automata's symbols, special tokens left out.
+    pub vocabulary: Vocabulary,
+    pub special_tokens: HashMap<String, TokenId>,
+}
+
+impl Tokenizer {
+    pub fn from_json(json: &str) -> Result<Tokenizer> {
+        let tokenizer: Value = serde_json::from_str(json)?;
+        let model = tokenizer
+            .get("model")
+            .ok_or_else(|| anyhow!("The tokenizer has no 'model'"))?;
+
+        let mut tokens: Vec<(String, TokenId)> = match model.get("vocab") {
+            Some(Value::Object(vocab)) => vocab
+                .iter()
+                .map(|(token, id)| Ok((token.clone(), as_token_id(id)?)))
+                .collect::<Result<_>>()?,
+            // Unigram models list `[token, score]` pairs in the order of their ids.
+            Some(Value::Array(vocab)) => vocab
+                .iter()
+                .enumerate()
+                .map(|(id, entry)| {
+                    let token = entry
+                        .get(0)
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| anyhow!("Invalid Unigram vocabulary entry {}", entry))?;
+                    Ok((token.to_string(), id as TokenId))
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(anyhow!("The tokenizer's model has no 'vocab'")),
+        };
+
+        let mut special_tokens = HashMap::new();
+        for added_token in tokenizer
+            .get("added_tokens")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let content = added_token
+                .get("content")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("Invalid added token {}", added_token))?;
+            let id = as_token_id(added_token.get("id").unwrap_or(&Value::Null))?;
+            if added_token.get("special").and_then(Value::as_bool) == Some(true) {
+                special_tokens.insert(content.to_string(), id);
+            } else {
+                tokens.push((content.to_string(), id));
+            }
+        }
+
+        let byte_level = has_type(&tokenizer["decoder"], "ByteLevel")
+            || has_type(&tokenizer["pre_tokenizer"], "ByteLevel");
+        let unicode_to_bytes = gpt2_unicode_to_bytes();
+
+        let mut vocabulary = Vocabulary::new();
+        for (token, id) in tokens {
+            if special_tokens.contains_key(&token) {
+                continue;
+            }
+            let bytes = if byte_level {
+                token
+                    .chars()
+                    .map(|c| {
+                        unicode_to_bytes
+                            .get(&c)
+                            .copied()
+                            .ok_or_else(|| anyhow!("Cannot convert token `{}` to bytes", token))
+                    })
+                    .collect::<Result<Vec<u8>>>()?
+            } else if let Some(byte) = byte_fallback(&token) {
+                vec![byte]
+            } else {
+                // SentencePiece models write spaces as `▁`.
+                token.replace('\u{2581}', " ").into_bytes()
+            };
+            if !bytes.is_empty() {
+                vocabulary.insert_bytes_in_place(bytes, id);
+            }
+        }
+
+        Ok(Tokenizer {
+            vocabulary,
+            special_tokens,
+        })
+    }
+}
+
+fn as_token_id(value: &Value) -> Result<TokenId> {
+    value
+        .as_u64()
+        .and_then(|id| TokenId::try_from(id).ok())
+        .ok_or_else(|| anyhow!("Invalid token id {}", value))
+}
+
+/// Whether a normalizer, pre-tokenizer or decoder is, or is a sequence containing, `type_name`.
+fn has_type(component: &Value, type_name: &str) -> bool {
+    if component.get("type").and_then(Value::as_str) == Some(type_name) {
+        return true;
+    }
+    ["decoders", "pretokenizers"].iter().any(|sequence| {
+        component
+            .get(sequence)
+            .and_then(Value::as_array)
+            .is_some_and(|components| components.iter().any(|c| has_type(c, type_name)))
+    })
+}
+
+/// Byte of the `<0xXX>` tokens that byte fallback models use for bytes outside the vocabulary.
+fn byte_fallback(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(hex, 16).ok()
+}
+
+/// Inverse of the mapping GPT-2 style tokenizers use to write every byte as a printable
+/// character.
+fn gpt2_unicode_to_bytes() -> HashMap<char, u8> {
+    let printable = |byte: u8| matches!(byte, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    let mut mapping = HashMap::new();
+    let mut shifted = 0;
+    for byte in 0..=u8::MAX {
+        let c = if printable(byte) {
+            char::from(byte)
+        } else {
+            shifted += 1;
+            char::from_u32(0xFF + shifted).expect("below the surrogates")
+        };
+        mapping.insert(c, byte);
+    }
+    mapping
+}
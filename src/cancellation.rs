@@ -0,0 +1,80 @@
+/// Cancellation of long compilations.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Flag shared with a running compilation, which gives up soon after it is cancelled.
+///
+/// Clones share the flag, so that one of them can be kept to cancel the compilation run with
+/// another, from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Conditions under which a compilation gives up, checked as it goes.
+#[derive(Debug, Clone)]
+pub(crate) struct Deadline {
+    token: Option<CancellationToken>,
+    timeout: Option<(Instant, Duration)>,
+}
+
+impl Deadline {
+    /// Starts counting the timeout from now.
+    pub(crate) fn new(token: Option<&CancellationToken>, timeout: Option<Duration>) -> Self {
+        Deadline {
+            token: token.cloned(),
+            timeout: timeout.map(|timeout| (Instant::now(), timeout)),
+        }
+    }
+
+    pub(crate) fn check(&self) -> Result<(), crate::Error> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(crate::Error::Cancelled);
+        }
+        match self.timeout {
+            Some((start, timeout)) if start.elapsed() > timeout => {
+                Err(crate::Error::Timeout { timeout })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadline() {
+        let token = CancellationToken::new();
+        let deadline = Deadline::new(Some(&token), Some(Duration::from_secs(3600)));
+        assert!(deadline.check().is_ok());
+        token.clone().cancel();
+        assert!(matches!(deadline.check(), Err(crate::Error::Cancelled)));
+
+        let deadline = Deadline::new(None, Some(Duration::ZERO));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(matches!(
+            deadline.check(),
+            Err(crate::Error::Timeout { timeout }) if timeout == Duration::ZERO
+        ));
+        assert!(Deadline::new(None, None).check().is_ok());
+    }
+}
@@ -0,0 +1,117 @@
+/// Aho-Corasick automata, finding the occurrences of a set of patterns in a single scan.
+use std::collections::VecDeque;
+
+/// Automaton reading a sequence of symbols and telling, after each symbol, which patterns end
+/// there. Its states are the prefixes of the patterns, the state 0 being the empty prefix.
+///
+/// The edges are kept in sorted vectors rather than maps, so that the automaton of a whole
+/// vocabulary stays about the size of its trie.
+pub(crate) struct AhoCorasick<T> {
+    /// Edges of the trie of the patterns, sorted by symbol.
+    children: Vec<Vec<(T, usize)>>,
+    /// Longest proper suffix of each prefix which is a prefix too.
+    fail: Vec<usize>,
+    /// Patterns equal to each prefix.
+    patterns: Vec<Vec<usize>>,
+    /// Longest proper suffix of each prefix which is a pattern.
+    output: Vec<Option<usize>>,
+}
+
+impl<T: Clone + Ord> AhoCorasick<T> {
+    pub(crate) fn new(patterns: &[Vec<T>]) -> Self {
+        let mut automaton = Self {
+            children: vec![Vec::new()],
+            fail: vec![0],
+            patterns: vec![Vec::new()],
+            output: vec![None],
+        };
+        for (pattern_id, pattern) in patterns.iter().enumerate() {
+            let mut state = 0;
+            for symbol in pattern {
+                let edges = &automaton.children[state];
+                state = match edges.binary_search_by(|(s, _)| s.cmp(symbol)) {
+                    Ok(i) => edges[i].1,
+                    Err(i) => {
+                        let child = automaton.children.len();
+                        automaton.children[state].insert(i, (symbol.clone(), child));
+                        automaton.children.push(Vec::new());
+                        automaton.fail.push(0);
+                        automaton.patterns.push(Vec::new());
+                        automaton.output.push(None);
+                        child
+                    }
+                };
+            }
+            automaton.patterns[state].push(pattern_id);
+        }
+
+        // The links of a state only depend on those of the shallower states.
+        let mut queue: VecDeque<usize> = automaton.children[0].iter().map(|&(_, c)| c).collect();
+        while let Some(state) = queue.pop_front() {
+            for i in 0..automaton.children[state].len() {
+                let (symbol, child) = automaton.children[state][i].clone();
+                let fail = automaton.next(automaton.fail[state], &symbol);
+                automaton.fail[child] = fail;
+                automaton.output[child] = match automaton.patterns[fail].is_empty() {
+                    true => automaton.output[fail],
+                    false => Some(fail),
+                };
+                queue.push_back(child);
+            }
+        }
+        automaton
+    }
+
+    /// State reached by reading `symbol` from `state`.
+    pub(crate) fn next(&self, mut state: usize, symbol: &T) -> usize {
+        loop {
+            let edges = &self.children[state];
+            if let Ok(i) = edges.binary_search_by(|(s, _)| s.cmp(symbol)) {
+                return edges[i].1;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.fail[state];
+        }
+    }
+
+    /// Whether a pattern ends at `state`.
+    pub(crate) fn is_match(&self, state: usize) -> bool {
+        !self.patterns[state].is_empty() || self.output[state].is_some()
+    }
+
+    /// Indices of the patterns ending at `state`, longest first.
+    pub(crate) fn matches(&self, state: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut prefix = Some(state);
+        std::iter::from_fn(move || {
+            let state = prefix?;
+            prefix = self.output[state];
+            Some(&self.patterns[state])
+        })
+        .flatten()
+        .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aho_corasick() {
+        let patterns: Vec<Vec<char>> = ["he", "she", "his", "hers"]
+            .iter()
+            .map(|pattern| pattern.chars().collect())
+            .collect();
+        let automaton = AhoCorasick::new(&patterns);
+        let mut state = 0;
+        let mut found = Vec::new();
+        for (i, c) in "ushers".chars().enumerate() {
+            state = automaton.next(state, &c);
+            found.extend(automaton.matches(state).map(|p| (i, p)));
+        }
+        assert_eq!(found, [(3, 1), (3, 0), (5, 3)]);
+        assert!(!automaton.is_match(automaton.next(0, &'h')));
+    }
+}
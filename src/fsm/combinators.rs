@@ -0,0 +1,642 @@
+/// Builders assembling automata out of other automata.
+use super::aho_corasick::AhoCorasick;
+use super::{byte_symbol, byte_symbols, MARKER_DIGITS, MARKER_END, MARKER_START};
+use crate::index::FSMInfo;
+use crate::prelude::*;
+use crate::regex::token_symbols;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Automaton with ε-transitions, over an alphabet shared by all the automata it is built from.
+struct Nfa {
+    transitions: Vec<HashMap<TransitionKey, Vec<usize>>>,
+    epsilons: Vec<Vec<usize>>,
+}
+
+/// Part of an [`Nfa`], entered through `start` and left through `end`.
+#[derive(Clone, Copy)]
+struct Fragment {
+    start: usize,
+    end: usize,
+}
+
+/// Alphabet of several automata: symbols are given the same key when every automaton gives
+/// them the same key, and so are the symbols none of them has.
+struct SharedAlphabet {
+    symbol_mapping: HashMap<String, TransitionKey>,
+    anything_value: TransitionKey,
+    /// Key of each automaton for each shared key.
+    keys: Vec<Vec<TransitionKey>>,
+}
+
+impl SharedAlphabet {
+    fn new(fsms: &[&FSMInfo]) -> Self {
+        let mut symbols: Vec<&String> = fsms
+            .iter()
+            .flat_map(|fsm| fsm.alphabet_symbol_mapping.keys())
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let own_keys = |symbol: Option<&String>| -> Vec<TransitionKey> {
+            fsms.iter()
+                .map(|fsm| {
+                    symbol
+                        .and_then(|symbol| fsm.alphabet_symbol_mapping.get(symbol))
+                        .copied()
+                        .unwrap_or(fsm.alphabet_anything_value)
+                })
+                .collect()
+        };
+
+        let mut shared_keys: HashMap<Vec<TransitionKey>, TransitionKey> = HashMap::new();
+        let mut keys: Vec<Vec<TransitionKey>> = vec![Vec::new(); fsms.len()];
+        let mut shared_key = |own_keys: Vec<TransitionKey>| {
+            let next_key = shared_keys.len() as TransitionKey;
+            *shared_keys.entry(own_keys.clone()).or_insert_with(|| {
+                for (fsm_keys, own_key) in keys.iter_mut().zip(own_keys) {
+                    fsm_keys.push(own_key);
+                }
+                next_key
+            })
+        };
+
+        let anything_value = shared_key(own_keys(None));
+        let symbol_mapping = symbols
+            .into_iter()
+            .map(|symbol| (symbol.clone(), shared_key(own_keys(Some(symbol)))))
+            .collect();
+        SharedAlphabet {
+            symbol_mapping,
+            anything_value,
+            keys,
+        }
+    }
+}
+
+impl Nfa {
+    fn new() -> Self {
+        Nfa {
+            transitions: Vec::new(),
+            epsilons: Vec::new(),
+        }
+    }
+
+    fn add_state(&mut self) -> usize {
+        self.transitions.push(HashMap::new());
+        self.epsilons.push(Vec::new());
+        self.transitions.len() - 1
+    }
+
+    /// Copies the automaton into the NFA, `keys` giving its key for each shared key.
+    fn add_fsm(&mut self, fsm: &FSMInfo, keys: &[TransitionKey]) -> Fragment {
+        self.add_fsm_states(fsm, keys).0
+    }
+
+    /// Copies the automaton into the NFA like [`Nfa::add_fsm`], along with the state of the
+    /// NFA of each of its states.
+    fn add_fsm_states(
+        &mut self,
+        fsm: &FSMInfo,
+        keys: &[TransitionKey],
+    ) -> (Fragment, HashMap<State, usize>) {
+        let mut states: HashMap<State, usize> = HashMap::new();
+        let mut state = |nfa: &mut Nfa, fsm_state: State| {
+            *states.entry(fsm_state).or_insert_with(|| nfa.add_state())
+        };
+
+        // Fresh start and end states keep the ε-transitions added to the fragment off the
+        // transitions of the automaton.
+        let start = self.add_state();
+        let initial = state(self, fsm.initial);
+        self.epsilons[start].push(initial);
+        let end = self.add_state();
+        for &(from_state, key) in fsm.transitions.keys() {
+            state(self, from_state);
+            state(self, fsm.transitions[&(from_state, key)]);
+        }
+        for &final_state in &fsm.finals {
+            let final_state = state(self, final_state);
+            self.epsilons[final_state].push(end);
+        }
+        for (shared_key, own_key) in keys.iter().enumerate() {
+            for (&fsm_state, &nfa_state) in &states {
+                if let Some(to_state) = fsm.transitions.get(&(fsm_state, *own_key)) {
+                    let to_state = states[to_state];
+                    self.transitions[nfa_state]
+                        .entry(shared_key as TransitionKey)
+                        .or_default()
+                        .push(to_state);
+                }
+            }
+        }
+        (Fragment { start, end }, states)
+    }
+
+    fn concat(&mut self, first: Fragment, second: Fragment) -> Fragment {
+        self.epsilons[first.end].push(second.start);
+        Fragment {
+            start: first.start,
+            end: second.end,
+        }
+    }
+
+    fn union(&mut self, first: Fragment, second: Fragment) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
+        self.epsilons[start].extend([first.start, second.start]);
+        self.epsilons[first.end].push(end);
+        self.epsilons[second.end].push(end);
+        Fragment { start, end }
+    }
+
+    fn optional(&mut self, fragment: Fragment) -> Fragment {
+        self.epsilons[fragment.start].push(fragment.end);
+        fragment
+    }
+
+    fn star(&mut self, fragment: Fragment) -> Fragment {
+        let start = self.add_state();
+        self.epsilons[start].push(fragment.start);
+        self.epsilons[fragment.end].push(start);
+        Fragment { start, end: start }
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(&self.epsilons[state]);
+            }
+        }
+        closure
+    }
+
+    /// Builds the deterministic automaton of the fragment, by the subset construction.
+    fn determinize(&self, fragment: Fragment, alphabet: SharedAlphabet) -> FSMInfo {
+        let initial = self.closure([fragment.start]);
+        let mut states: HashMap<BTreeSet<usize>, State> = HashMap::from([(initial.clone(), 0)]);
+        let mut queue = VecDeque::from([initial]);
+        let mut finals = HashSet::new();
+        let mut transitions = HashMap::new();
+        while let Some(nfa_states) = queue.pop_front() {
+            let state = states[&nfa_states];
+            if nfa_states.contains(&fragment.end) {
+                finals.insert(state);
+            }
+            // Only the keys read by one of the states lead somewhere.
+            let mut moves: BTreeMap<TransitionKey, Vec<usize>> = BTreeMap::new();
+            for &nfa_state in &nfa_states {
+                for (&key, to_states) in &self.transitions[nfa_state] {
+                    moves.entry(key).or_default().extend(to_states);
+                }
+            }
+            for (key, to_states) in moves {
+                let next_nfa_states = self.closure(to_states);
+                let next_state = match states.get(&next_nfa_states) {
+                    Some(&next_state) => next_state,
+                    None => {
+                        let next_state = states.len() as State;
+                        states.insert(next_nfa_states.clone(), next_state);
+                        queue.push_back(next_nfa_states);
+                        next_state
+                    }
+                };
+                transitions.insert((state, key), next_state);
+            }
+        }
+
+        FSMInfo::new(
+            0,
+            finals,
+            transitions,
+            alphabet.anything_value,
+            alphabet.symbol_mapping,
+        )
+        .trim()
+    }
+}
+
+impl FSMInfo {
+    /// Automaton of the strings made of a string of `self` followed by a string of `other`.
+    ///
+    /// The automata are combined over the union of their alphabets, so combining a byte-level
+    /// automaton with one over characters rarely makes sense.
+    pub fn concat(&self, other: &FSMInfo) -> FSMInfo {
+        let alphabet = SharedAlphabet::new(&[self, other]);
+        let mut nfa = Nfa::new();
+        let first = nfa.add_fsm(self, &alphabet.keys[0]);
+        let second = nfa.add_fsm(other, &alphabet.keys[1]);
+        let fragment = nfa.concat(first, second);
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Automaton of the strings of `self` preceded by `prefix`, which is written with the
+    /// symbols of the alphabet of `self`, like a token.
+    pub fn with_prefix(&self, prefix: &str) -> FSMInfo {
+        // The literal has an alphabet of its own symbols, as the key of anything else of `self`
+        // would also read the symbols of neither.
+        let symbols = token_symbols(prefix);
+        let mut alphabet_symbol_mapping: HashMap<String, TransitionKey> = HashMap::new();
+        for symbol in &symbols {
+            let next_key = alphabet_symbol_mapping.len() as TransitionKey;
+            alphabet_symbol_mapping
+                .entry(symbol.clone())
+                .or_insert(next_key);
+        }
+        let keys: Vec<TransitionKey> = symbols
+            .iter()
+            .map(|symbol| alphabet_symbol_mapping[symbol])
+            .collect();
+        let literal = FSMInfo::new(
+            0,
+            HashSet::from([keys.len() as State]),
+            keys.iter()
+                .enumerate()
+                .map(|(i, &key)| ((i as State, key), i as State + 1))
+                .collect(),
+            alphabet_symbol_mapping.len() as TransitionKey,
+            alphabet_symbol_mapping,
+        );
+        literal.concat(self)
+    }
+
+    /// Automaton of the strings of either `self` or `other`.
+    pub fn union(&self, other: &FSMInfo) -> FSMInfo {
+        let alphabet = SharedAlphabet::new(&[self, other]);
+        let mut nfa = Nfa::new();
+        let first = nfa.add_fsm(self, &alphabet.keys[0]);
+        let second = nfa.add_fsm(other, &alphabet.keys[1]);
+        let fragment = nfa.union(first, second);
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Automaton of the strings of `self` and of the empty string.
+    pub fn optional(&self) -> FSMInfo {
+        self.repeat(0, Some(1))
+    }
+
+    /// Automaton of the strings made of `min` to `max` strings of `self`, with no upper bound
+    /// when `max` is `None`.
+    pub fn repeat(&self, min: usize, max: Option<usize>) -> FSMInfo {
+        let alphabet = SharedAlphabet::new(&[self]);
+        let mut nfa = Nfa::new();
+
+        // Starts with the empty string, which is the automaton of zero repetitions.
+        let empty = nfa.add_state();
+        let mut fragment = Fragment {
+            start: empty,
+            end: empty,
+        };
+        for _ in 0..min {
+            let copy = nfa.add_fsm(self, &alphabet.keys[0]);
+            fragment = nfa.concat(fragment, copy);
+        }
+        match max {
+            None => {
+                let copy = nfa.add_fsm(self, &alphabet.keys[0]);
+                let star = nfa.star(copy);
+                fragment = nfa.concat(fragment, star);
+            }
+            Some(max) => {
+                for _ in min..max {
+                    let copy = nfa.add_fsm(self, &alphabet.keys[0]);
+                    let copy = nfa.optional(copy);
+                    fragment = nfa.concat(fragment, copy);
+                }
+            }
+        }
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Automaton of the strings made of one string of each of `parts`, in any order, separated
+    /// by strings of `separator`, such as the required properties of an object.
+    ///
+    /// The automaton keeps track of the set of parts written so far rather than of their
+    /// order, so that it grows with the `2^n` sets of `n` parts, not with their `n!` orders.
+    pub fn any_order(parts: &[FSMInfo], separator: &FSMInfo) -> FSMInfo {
+        let fsms: Vec<&FSMInfo> = std::iter::once(separator).chain(parts).collect();
+        let alphabet = SharedAlphabet::new(&fsms);
+        let mut nfa = Nfa::new();
+
+        // Sets of parts are bit sets, each with the state reached once they are written.
+        let sets = 1usize << parts.len();
+        let written: Vec<usize> = (0..sets).map(|_| nfa.add_state()).collect();
+        for set in 0..sets - 1 {
+            let next = match set {
+                0 => written[0],
+                _ => {
+                    let copy = nfa.add_fsm(separator, &alphabet.keys[0]);
+                    nfa.epsilons[written[set]].push(copy.start);
+                    copy.end
+                }
+            };
+            for (i, part) in parts.iter().enumerate() {
+                if set & (1 << i) != 0 {
+                    continue;
+                }
+                let copy = nfa.add_fsm(part, &alphabet.keys[i + 1]);
+                nfa.epsilons[next].push(copy.start);
+                nfa.epsilons[copy.end].push(written[set | (1 << i)]);
+            }
+        }
+        let fragment = Fragment {
+            start: written[0],
+            end: written[sets - 1],
+        };
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Automaton of the strings made of one string of each of some of `parts`, at least one, in
+    /// the order of `parts` and separated by strings of `separator`, such as the optional
+    /// properties of an object.
+    ///
+    /// The automaton keeps track of the next part and of whether a part was written already,
+    /// so that it grows linearly with the number of parts.
+    pub fn subsequences(parts: &[FSMInfo], separator: &FSMInfo) -> FSMInfo {
+        let fsms: Vec<&FSMInfo> = std::iter::once(separator).chain(parts).collect();
+        let alphabet = SharedAlphabet::new(&fsms);
+        let mut nfa = Nfa::new();
+
+        // States before each part, with no part written yet and with some parts written.
+        let empty: Vec<usize> = (0..=parts.len()).map(|_| nfa.add_state()).collect();
+        let written: Vec<usize> = (0..=parts.len()).map(|_| nfa.add_state()).collect();
+        for (i, part) in parts.iter().enumerate() {
+            nfa.epsilons[empty[i]].push(empty[i + 1]);
+            nfa.epsilons[written[i]].push(written[i + 1]);
+
+            let first = nfa.add_fsm(part, &alphabet.keys[i + 1]);
+            nfa.epsilons[empty[i]].push(first.start);
+            nfa.epsilons[first.end].push(written[i + 1]);
+
+            let separator = nfa.add_fsm(separator, &alphabet.keys[0]);
+            let next = nfa.add_fsm(part, &alphabet.keys[i + 1]);
+            let next = nfa.concat(separator, next);
+            nfa.epsilons[written[i]].push(next.start);
+            nfa.epsilons[next.end].push(written[i + 1]);
+        }
+        let fragment = Fragment {
+            start: empty[0],
+            end: written[parts.len()],
+        };
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Replaces each marker of `fragments` read by `self` with the strings of its automaton, as
+    /// written by [`FSMInfo::from_regex_with_fragments`].
+    ///
+    /// The markers are read by paths of `self`, and each path is replaced by a copy of its
+    /// automaton, so that they are spliced wherever the pattern reads them.
+    pub(crate) fn splice(&self, fragments: &[FSMInfo]) -> FSMInfo {
+        let key = |byte: u8| {
+            self.alphabet_symbol_mapping
+                .get(&byte_symbol(byte))
+                .copied()
+        };
+        let marker_keys: HashSet<TransitionKey> = [MARKER_START, MARKER_END]
+            .into_iter()
+            .chain((0..10).map(|digit| MARKER_DIGITS + digit))
+            .filter_map(key)
+            .collect();
+        let fsms: Vec<&FSMInfo> = std::iter::once(self).chain(fragments).collect();
+        let alphabet = SharedAlphabet::new(&fsms);
+        let mut nfa = Nfa::new();
+        let first_state = nfa.transitions.len();
+        let (fragment, states) = nfa.add_fsm_states(self, &alphabet.keys[0]);
+
+        // The bytes of the markers are only read by the markers, whose paths are left out.
+        for transitions in &mut nfa.transitions[first_state..] {
+            transitions.retain(|&shared_key, _| {
+                !marker_keys.contains(&alphabet.keys[0][shared_key as usize])
+            });
+        }
+        let next = |state: State, byte: u8| {
+            key(byte).and_then(|key| self.transitions.get(&(state, key)).copied())
+        };
+        let mut paths = Vec::new();
+        for &from_state in states.keys() {
+            let Some(state) = next(from_state, MARKER_START) else {
+                continue;
+            };
+            // The markers read from a state are told apart by their digits.
+            let mut stack = vec![(state, None)];
+            while let Some((state, index)) = stack.pop() {
+                if let (Some(index), Some(to_state)) = (index, next(state, MARKER_END)) {
+                    paths.push((from_state, index, to_state));
+                }
+                for digit in 0..10 {
+                    if let Some(next_state) = next(state, MARKER_DIGITS + digit) {
+                        let index = index.unwrap_or(0) * 10 + digit as usize;
+                        stack.push((next_state, Some(index)));
+                    }
+                }
+            }
+        }
+        for (from_state, index, to_state) in paths {
+            let (Some(fsm), Some(&from), Some(&to)) = (
+                fragments.get(index),
+                states.get(&from_state),
+                states.get(&to_state),
+            ) else {
+                continue;
+            };
+            let copy = nfa.add_fsm(fsm, &alphabet.keys[index + 1]);
+            nfa.epsilons[from].push(copy.start);
+            nfa.epsilons[copy.end].push(to);
+        }
+        nfa.determinize(fragment, alphabet)
+    }
+
+    /// Automaton of the strings of `self` which contain none of `substrings`, such as markers
+    /// of personal data or prompt injections.
+    ///
+    /// The strings are read along an Aho-Corasick automaton of the substrings, which spots
+    /// their occurrences, and the states where one ends are left out. The substrings are
+    /// written in text: their non-ASCII characters are read as bytes by a byte-level automaton.
+    /// Multi-character symbols, like frozen tokens, are read character by character.
+    pub fn forbid_substrings(&self, substrings: &[&str]) -> FSMInfo {
+        let byte_level = self
+            .alphabet_symbol_mapping
+            .keys()
+            .any(|s| s.starts_with('\0'));
+        let units = |text: &str| -> Vec<String> {
+            let mut units = Vec::new();
+            for c in text.chars() {
+                match c.is_ascii() || !byte_level {
+                    true => units.push(c.to_string()),
+                    false => units.extend(token_symbols(&byte_symbols(
+                        c.encode_utf8(&mut [0; 4]).as_bytes(),
+                    ))),
+                }
+            }
+            units
+        };
+        let patterns: Vec<Vec<String>> = substrings.iter().map(|s| units(s)).collect();
+        let automaton = AhoCorasick::new(&patterns);
+
+        // The units of the substrings the alphabet lacks are read by the key of anything else
+        // of `self`, but not by the Aho-Corasick automaton, so they get symbols of their own.
+        let mut symbols: Vec<String> = self.alphabet_symbol_mapping.keys().cloned().collect();
+        symbols.extend(patterns.iter().flatten().cloned());
+        symbols.sort_unstable();
+        symbols.dedup();
+        let own_key = |symbol: &String| {
+            self.alphabet_symbol_mapping
+                .get(symbol)
+                .copied()
+                .unwrap_or(self.alphabet_anything_value)
+        };
+        let symbol_units: Vec<Vec<String>> = symbols.iter().map(|s| token_symbols(s)).collect();
+        let anything_value = symbols.len() as TransitionKey;
+
+        let initial = (self.initial, 0);
+        let mut states = HashMap::from([(initial, 0)]);
+        let mut queue = VecDeque::from([initial]);
+        let mut finals = HashSet::new();
+        let mut transitions = HashMap::new();
+        if automaton.is_match(0) {
+            queue.clear();
+        }
+        while let Some((fsm_state, ac_state)) = queue.pop_front() {
+            let state = states[&(fsm_state, ac_state)];
+            if self.finals.contains(&fsm_state) {
+                finals.insert(state);
+            }
+            let keys = symbols
+                .iter()
+                .map(own_key)
+                .chain([self.alphabet_anything_value]);
+            for (key, own_key) in keys.enumerate() {
+                let Some(&next_fsm_state) = self.transitions.get(&(fsm_state, own_key)) else {
+                    continue;
+                };
+                // Anything else is none of the units of the substrings.
+                let next_ac_state = match symbol_units.get(key) {
+                    Some(units) => units.iter().try_fold(ac_state, |ac_state, unit| {
+                        let next = automaton.next(ac_state, unit);
+                        (!automaton.is_match(next)).then_some(next)
+                    }),
+                    None => Some(0),
+                };
+                let Some(next_ac_state) = next_ac_state else {
+                    continue;
+                };
+                let next = (next_fsm_state, next_ac_state);
+                let next_state = match states.get(&next) {
+                    Some(&next_state) => next_state,
+                    None => {
+                        let next_state = states.len() as State;
+                        states.insert(next, next_state);
+                        queue.push_back(next);
+                        next_state
+                    }
+                };
+                transitions.insert((state, key as TransitionKey), next_state);
+            }
+        }
+
+        let alphabet_symbol_mapping = symbols
+            .into_iter()
+            .enumerate()
+            .map(|(key, symbol)| (symbol, key as TransitionKey))
+            .collect();
+        FSMInfo::new(
+            0,
+            finals,
+            transitions,
+            anything_value,
+            alphabet_symbol_mapping,
+        )
+        .trim()
+        .compress_alphabet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsm::fragment_marker;
+
+    fn regex(pattern: &str) -> FSMInfo {
+        FSMInfo::from_regex(pattern).unwrap()
+    }
+
+    #[test]
+    fn combinators() {
+        assert!(regex("ab+")
+            .concat(&regex("c|d"))
+            .equivalent(&regex("ab+(c|d)")));
+        assert!(regex("a*").concat(&regex("a")).equivalent(&regex("a+")));
+        assert!(regex("[0-9]+")
+            .union(&regex("x"))
+            .equivalent(&regex("[0-9]+|x")));
+        assert!(regex("ab").optional().equivalent(&regex("(ab)?")));
+        assert!(regex("ab")
+            .repeat(2, Some(4))
+            .equivalent(&regex("(ab){2,4}")));
+        assert!(regex("a|bc").repeat(1, None).equivalent(&regex("(a|bc)+")));
+        assert!(regex("a").repeat(0, Some(0)).equivalent(&regex("")));
+        // Going back to the initial state does not end an optional automaton.
+        assert!(regex("(ab)*c").optional().equivalent(&regex("((ab)*c)?")));
+        assert!(regex("[0-9]+")
+            .with_prefix("\0C3\0A9a")
+            .equivalent(&regex("éa[0-9]+")));
+        assert!(regex("b|c").with_prefix("").equivalent(&regex("b|c")));
+    }
+
+    #[test]
+    fn any_order() {
+        let parts = [regex("a"), regex("bc"), regex("d+")];
+        let fsm_info = FSMInfo::any_order(&parts, &regex(",|;"));
+        assert!(fsm_info.equivalent(&regex(
+            "(a[,;](bc[,;]d+|d+[,;]bc)|bc[,;](a[,;]d+|d+[,;]a)|d+[,;](a[,;]bc|bc[,;]a))"
+        )));
+        assert!(FSMInfo::any_order(&parts[..1], &regex(",")).equivalent(&regex("a")));
+
+        // The states are the sets of parts written so far, and there are fewer transitions than
+        // orders of the parts.
+        let parts: Vec<FSMInfo> = (0..8).map(|i| regex(&format!("k{i}:[0-9]+"))).collect();
+        let fsm_info = FSMInfo::any_order(&parts, &regex(","));
+        assert!(fsm_info.transitions.len() < (1..=8).product());
+        assert_eq!(fsm_info.shortest_string().map(|s| s.len()), Some(39));
+    }
+
+    #[test]
+    fn subsequences() {
+        let parts = [regex("a"), regex("bc"), regex("d+")];
+        let fsm_info = FSMInfo::subsequences(&parts, &regex(","));
+        assert!(fsm_info.equivalent(&regex("a(,bc)?(,d+)?|bc(,d+)?|d+")));
+        assert!(FSMInfo::subsequences(&[], &regex(",")).is_empty());
+    }
+
+    #[test]
+    fn splice() {
+        let pattern = format!("x({}|y){}", fragment_marker(0), fragment_marker(1));
+        let fsm_info =
+            FSMInfo::from_regex_with_fragments(&pattern, &[regex("ab"), regex("é*")]).unwrap();
+        assert!(fsm_info.equivalent(&regex("x(ab|y)é*")));
+
+        // Markers follow each other, with indices of several digits.
+        let fragments: Vec<FSMInfo> = (0..12).map(|i| regex(&format!("<{i}>"))).collect();
+        let pattern = format!("({}{})+", fragment_marker(11), fragment_marker(3));
+        let fsm_info = FSMInfo::from_regex_with_fragments(&pattern, &fragments).unwrap();
+        assert!(fsm_info.equivalent(&regex("(<11><3>)+")));
+    }
+
+    #[test]
+    fn forbid_substrings() {
+        let fsm_info = regex("[a-z]*").forbid_substrings(&["ab"]);
+        assert!(fsm_info.equivalent(&regex("([b-z]|a+[c-z])*a*")));
+        let fsm_info = regex("[a-z]*").forbid_substrings(&["ab", "bca"]);
+        assert_eq!(
+            fsm_info.enumerate_strings(3, 20000).len(),
+            1 + 26 + 675 + 17523
+        );
+        let fsm_info = regex("(é|e)+").forbid_substrings(&["éé"]);
+        assert!(fsm_info.equivalent(&regex("é?(e+é?)+|é")));
+        assert!(regex("a*").forbid_substrings(&[""]).is_empty());
+        assert!(regex("x|ab")
+            .forbid_substrings(&[])
+            .equivalent(&regex("x|ab")));
+    }
+}
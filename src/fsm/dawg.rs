@@ -0,0 +1,192 @@
+/// Minimal automata of finite sets of words, built from the words without going through a
+/// regular expression.
+use super::byte_symbol;
+use crate::index::FSMInfo;
+use crate::prelude::*;
+use std::hash::Hash;
+
+/// State of a [`Dawg`]: whether it is final, and its edges sorted by symbol.
+type Node<T> = (bool, Vec<(T, usize)>);
+
+/// Directed acyclic word graph: the minimal deterministic automaton of a set of words, whose
+/// words share their prefixes and their suffixes. Its initial state is the state 0.
+pub(crate) struct Dawg<T> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T: Copy + Ord + Hash> Dawg<T> {
+    /// Builds the automaton of `words` one word after the other, in order, as Daciuk et al.
+    /// do: the states left behind by a word, which the next ones can not extend, are merged
+    /// with the equivalent states met so far. Only those of the last word are ever apart from
+    /// the minimal automaton, which keeps large sets of words small while they are added.
+    pub(crate) fn new(words: &[Vec<T>]) -> Self {
+        let mut words: Vec<&[T]> = words.iter().map(Vec::as_slice).collect();
+        words.sort_unstable();
+        words.dedup();
+
+        let mut dawg = Dawg {
+            nodes: vec![(false, Vec::new())],
+        };
+        let mut register: HashMap<Node<T>, usize> = HashMap::new();
+        // Edges of the path of the last word which are not minimized yet.
+        let mut unchecked: Vec<(usize, usize)> = Vec::new();
+        let mut previous: &[T] = &[];
+        for word in words {
+            let common = word
+                .iter()
+                .zip(previous)
+                .take_while(|(a, b)| a == b)
+                .count();
+            dawg.minimize(&mut unchecked, &mut register, common);
+            let mut node = unchecked.last().map_or(0, |&(_, child)| child);
+            for &symbol in &word[common..] {
+                dawg.nodes.push((false, Vec::new()));
+                let child = dawg.nodes.len() - 1;
+                dawg.nodes[node].1.push((symbol, child));
+                unchecked.push((node, child));
+                node = child;
+            }
+            dawg.nodes[node].0 = true;
+            previous = word;
+        }
+        dawg.minimize(&mut unchecked, &mut register, 0);
+        dawg
+    }
+
+    /// Merges the states of the path of the last word beyond its first `depth` edges with their
+    /// registered equivalents, or registers them.
+    fn minimize(
+        &mut self,
+        unchecked: &mut Vec<(usize, usize)>,
+        register: &mut HashMap<Node<T>, usize>,
+        depth: usize,
+    ) {
+        while unchecked.len() > depth {
+            let (parent, child) = unchecked.pop().expect("edges remain");
+            match register.get(&self.nodes[child]) {
+                Some(&equivalent) => {
+                    self.nodes[parent]
+                        .1
+                        .last_mut()
+                        .expect("the edge was pushed")
+                        .1 = equivalent;
+                    // The deepest state of the path is the last one created.
+                    if child == self.nodes.len() - 1 {
+                        self.nodes.pop();
+                    }
+                }
+                None => {
+                    register.insert(self.nodes[child].clone(), child);
+                }
+            }
+        }
+    }
+}
+
+impl Dawg<char> {
+    /// Regular expression matching the words, with the edges leading to the same state written
+    /// as a character class followed by the expression of that state. Alternatives are always
+    /// grouped, so that it can be embedded as is.
+    pub(crate) fn to_regex(&self) -> String {
+        let mut written = HashMap::new();
+        self.state_regex(0, &mut written)
+    }
+
+    fn state_regex(&self, state: usize, written: &mut HashMap<usize, String>) -> String {
+        if let Some(regex) = written.get(&state) {
+            return regex.clone();
+        }
+        let (is_final, edges) = &self.nodes[state];
+        let mut targets: Vec<(usize, Vec<char>)> = Vec::new();
+        for &(c, target) in edges {
+            match targets.iter_mut().find(|(t, _)| *t == target) {
+                Some((_, chars)) => chars.push(c),
+                None => targets.push((target, vec![c])),
+            }
+        }
+        let alternatives: Vec<String> = targets
+            .into_iter()
+            .map(|(target, chars)| {
+                let label = match chars.as_slice() {
+                    [c] => regex::escape(&c.to_string()),
+                    chars => format!(
+                        "[{}]",
+                        chars
+                            .iter()
+                            .map(|c| regex::escape(&c.to_string()))
+                            .collect::<String>()
+                    ),
+                };
+                format!("{}{}", label, self.state_regex(target, written))
+            })
+            .collect();
+        let regex = match (alternatives.as_slice(), is_final) {
+            ([], _) => String::new(),
+            ([alternative], false) => alternative.clone(),
+            (alternatives, false) => format!("({})", alternatives.join("|")),
+            (alternatives, true) => format!("({})?", alternatives.join("|")),
+        };
+        written.insert(state, regex.clone());
+        regex
+    }
+}
+
+impl FSMInfo {
+    /// Builds the byte-level automaton accepting exactly `words`, as the minimal automaton of
+    /// the words rather than from the alternation of the words as a regular expression, which
+    /// large sets of words, like the values of an enormous enum, would make huge.
+    ///
+    /// It is laid out like the automata of [`FSMInfo::from_regex`], each byte being its own
+    /// transition key.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// # use outlines_core::index::FSMInfo;
+    /// #
+    /// let fsm_info = FSMInfo::from_words(["tap", "taps", "top", "tops"]);
+    /// assert_eq!(fsm_info.enumerate_strings(4, 10), ["tap", "taps", "top", "tops"]);
+    /// // The words share their prefixes and their suffixes.
+    /// assert_eq!(fsm_info.transitions().len(), 5);
+    /// ```
+    pub fn from_words<W: AsRef<[u8]>>(words: impl IntoIterator<Item = W>) -> FSMInfo {
+        let words: Vec<Vec<u8>> = words.into_iter().map(|w| w.as_ref().to_vec()).collect();
+        let dawg = Dawg::new(&words);
+
+        let mut transitions = HashMap::new();
+        let mut finals = HashSet::new();
+        for (state, (is_final, edges)) in dawg.nodes.iter().enumerate() {
+            if *is_final {
+                finals.insert(state as State);
+            }
+            for &(byte, target) in edges {
+                transitions.insert((state as State, byte as TransitionKey), target as State);
+            }
+        }
+        let alphabet_symbol_mapping = (0..=u8::MAX)
+            .map(|byte| (byte_symbol(byte), byte as TransitionKey))
+            .collect();
+        FSMInfo::new(0, finals, transitions, 256, alphabet_symbol_mapping).trim()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dawg() {
+        let words: Vec<Vec<char>> = ["tap", "taps", "top", "tops", "ax"]
+            .iter()
+            .map(|word| word.chars().collect())
+            .collect();
+        let dawg = Dawg::new(&words);
+        // The initial state, `a`, `t`, `ta`/`to`, `tap`/`top` and the state ending the words.
+        assert_eq!(dawg.nodes.len(), 6);
+        assert_eq!(dawg.to_regex(), "(ax|t[ao]p(s)?)");
+
+        let fsm_info = FSMInfo::from_words(["b", "ab", "", "ab"]);
+        assert_eq!(fsm_info.enumerate_strings(2, 10), ["", "ab", "b"]);
+        assert!(FSMInfo::from_words(Vec::<&str>::new()).is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+/// Automata compiled by Python's interegular.
+use crate::index::{FSMInfo, Result};
+use crate::prelude::*;
+use crate::Error;
+use serde::Deserialize;
+
+/// Symbol standing for the characters outside of the alphabet, as interegular writes it.
+pub const ANYTHING_ELSE: &str = "anything_else";
+
+/// Fields of an interegular `FSM`, as held by its `__dict__` and written by its pickles.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::prelude::*;
+/// # use outlines_core::fsm::InteregularFsm;
+/// # use outlines_core::index::FSMInfo;
+/// #
+/// // interegular.parse_pattern("ab*").to_fsm(), dumped to JSON.
+/// let fsm: InteregularFsm = serde_json::from_str(
+///     r#"{
+///         "alphabet": {"a": 0, "b": 1, "anything_else": 2},
+///         "states": [0, 1],
+///         "initial": 0,
+///         "finals": [1],
+///         "map": {"0": {"0": 1}, "1": {"1": 1}}
+///     }"#,
+/// )
+/// .unwrap();
+/// let fsm_info = FSMInfo::from_interegular(fsm).unwrap();
+/// assert_eq!(fsm_info.shortest_string().as_deref(), Some("a"));
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InteregularFsm {
+    /// Transition key of each symbol, [`ANYTHING_ELSE`] being the key of the characters outside
+    /// of the alphabet.
+    pub alphabet: HashMap<String, TransitionKey>,
+    pub states: HashSet<State>,
+    pub initial: State,
+    pub finals: HashSet<State>,
+    /// Transitions of each state, by transition key.
+    pub map: HashMap<State, HashMap<TransitionKey, State>>,
+}
+
+impl FSMInfo {
+    /// Converts an automaton compiled by interegular, checking that its transitions only use
+    /// its states and the keys of its alphabet.
+    ///
+    /// Without an [`ANYTHING_ELSE`] symbol, the characters outside of the alphabet are given a
+    /// key of their own, which no transition uses.
+    pub fn from_interegular(fsm: InteregularFsm) -> Result<FSMInfo> {
+        let invalid = |message: String| Error::InvalidInteregularFsm(message);
+        let InteregularFsm {
+            mut alphabet,
+            states,
+            initial,
+            finals,
+            map,
+        } = fsm;
+
+        let alphabet_anything_value = match alphabet.remove(ANYTHING_ELSE) {
+            Some(key) => key,
+            None => alphabet.values().max().map_or(0, |&key| key + 1),
+        };
+        let keys: HashSet<TransitionKey> = alphabet
+            .values()
+            .copied()
+            .chain([alphabet_anything_value])
+            .collect();
+
+        if !states.contains(&initial) {
+            return Err(invalid(format!(
+                "the initial state {} is not a state",
+                initial
+            )));
+        }
+        if let Some(state) = finals.iter().find(|state| !states.contains(state)) {
+            return Err(invalid(format!("the final state {} is not a state", state)));
+        }
+        let mut transitions = HashMap::new();
+        for (&from_state, state_map) in &map {
+            for (&key, &to_state) in state_map {
+                for state in [from_state, to_state] {
+                    if !states.contains(&state) {
+                        return Err(invalid(format!(
+                            "the transition from {} to {} uses {}, which is not a state",
+                            from_state, to_state, state
+                        )));
+                    }
+                }
+                if !keys.contains(&key) {
+                    return Err(invalid(format!(
+                        "the transition from {} to {} uses the key {}, which no symbol has",
+                        from_state, to_state, key
+                    )));
+                }
+                transitions.insert((from_state, key), to_state);
+            }
+        }
+
+        Ok(FSMInfo::new(
+            initial,
+            finals,
+            transitions,
+            alphabet_anything_value,
+            alphabet,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn a_b_star() -> InteregularFsm {
+        InteregularFsm {
+            alphabet: HashMap::from([
+                ("a".to_string(), 0),
+                ("b".to_string(), 1),
+                (ANYTHING_ELSE.to_string(), 2),
+            ]),
+            states: HashSet::from([0, 1, 2]),
+            initial: 0,
+            finals: HashSet::from([1]),
+            map: HashMap::from([
+                (0, HashMap::from([(0, 1)])),
+                (1, HashMap::from([(1, 1), (2, 2)])),
+            ]),
+        }
+    }
+
+    #[test]
+    fn from_interegular() {
+        let fsm = FSMInfo::from_interegular(a_b_star()).unwrap();
+        assert_eq!(fsm.alphabet_anything_value, 2);
+        assert!(!fsm.alphabet_symbol_mapping.contains_key(ANYTHING_ELSE));
+        assert_eq!(fsm.transitions[&(1, 2)], 2);
+        assert_eq!(fsm.enumerate_strings(3, 10), ["a", "ab", "abb"]);
+
+        let mut without_anything_else = a_b_star();
+        without_anything_else.alphabet.remove(ANYTHING_ELSE);
+        without_anything_else.map.remove(&1);
+        let fsm = FSMInfo::from_interegular(without_anything_else).unwrap();
+        assert_eq!(fsm.alphabet_anything_value, 2);
+
+        let mut unknown_state = a_b_star();
+        unknown_state.initial = 3;
+        assert!(FSMInfo::from_interegular(unknown_state).is_err());
+
+        let mut unknown_key = a_b_star();
+        unknown_key.map.insert(0, HashMap::from([(5, 1)]));
+        assert_eq!(
+            FSMInfo::from_interegular(unknown_key)
+                .unwrap_err()
+                .to_string(),
+            "Invalid interegular automaton: the transition from 0 to 1 uses the key 5, which no \
+             symbol has"
+        );
+    }
+}
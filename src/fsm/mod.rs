@@ -0,0 +1,901 @@
+/// Operations on the automata described by `FSMInfo`.
+use crate::index::{FSMInfo, Result};
+use crate::prelude::*;
+use crate::Error;
+use regex_automata::dfa::{dense, Automaton, StartError, StartKind};
+use regex_automata::util::{start, syntax};
+use regex_automata::{Anchored, MatchKind};
+use std::collections::{hash_map::Entry, BTreeMap, VecDeque};
+
+pub(crate) mod aho_corasick;
+mod combinators;
+pub(crate) mod dawg;
+mod interegular;
+
+pub use interegular::{InteregularFsm, ANYTHING_ELSE};
+
+/// Symbol of a byte in a byte-level automaton, as written by `make_byte_level_fsm`.
+pub fn byte_symbol(byte: u8) -> String {
+    if byte < 0x80 {
+        char::from(byte).to_string()
+    } else {
+        format!("\0{:02X}", byte)
+    }
+}
+
+/// Symbols of a token given as bytes, which need not be valid UTF-8, in a byte-level automaton.
+pub fn byte_symbols(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte_symbol(byte)).collect()
+}
+
+/// Bytes which never occur in UTF-8, writing the markers of the automata spliced into a
+/// pattern by [`FSMInfo::from_regex_with_fragments`]: the index of the automaton in decimal,
+/// with the digits written from `MARKER_DIGITS`, between `MARKER_START` and `MARKER_END`.
+const MARKER_START: u8 = 0xC0;
+const MARKER_END: u8 = 0xC1;
+const MARKER_DIGITS: u8 = 0xF5;
+
+/// Pattern of the marker of the automaton `index` of the fragments of
+/// [`FSMInfo::from_regex_with_fragments`].
+pub(crate) fn fragment_marker(index: usize) -> String {
+    let digits: String = index
+        .to_string()
+        .bytes()
+        .map(|digit| format!(r"\x{:02X}", MARKER_DIGITS + (digit - b'0')))
+        .collect();
+    format!(r"(?-u:\x{:02X}{digits}\x{:02X})", MARKER_START, MARKER_END)
+}
+
+/// Code point of a single character symbol, or byte of a `\0XX` symbol.
+fn symbol_code(symbol: &str) -> Option<u32> {
+    let mut chars = symbol.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c as u32),
+        (Some('\0'), Some(_)) if symbol.len() == 3 => u32::from_str_radix(&symbol[1..], 16).ok(),
+        _ => None,
+    }
+}
+
+/// Label of a DOT edge, with consecutive characters written as ranges like `a-z`.
+fn dot_label(mut symbols: Vec<&str>) -> String {
+    symbols.sort_unstable_by_key(|symbol| {
+        (symbol_code(symbol).is_none(), symbol_code(symbol), *symbol)
+    });
+    symbols.dedup();
+
+    let escape = |code: u32| match char::from_u32(code) {
+        Some(c @ ('"' | '\\')) => format!("\\{}", c),
+        Some(c) if code < 0x80 && !c.is_ascii_control() => c.to_string(),
+        _ if code <= 0xFF => format!("\\\\x{:02X}", code),
+        Some(c) => c.to_string(),
+        None => format!("\\\\u{{{:X}}}", code),
+    };
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < symbols.len() {
+        let Some(start) = symbol_code(symbols[i]) else {
+            parts.push(symbols[i].replace('\\', "\\\\").replace('"', "\\\""));
+            i += 1;
+            continue;
+        };
+        let mut end = start;
+        while i + 1 < symbols.len() && symbol_code(symbols[i + 1]) == Some(end + 1) {
+            end += 1;
+            i += 1;
+        }
+        parts.push(match end - start {
+            0 => escape(start),
+            1 => format!("{}{}", escape(start), escape(end)),
+            _ => format!("{}-{}", escape(start), escape(end)),
+        });
+        i += 1;
+    }
+    parts.join(" ")
+}
+
+impl FSMInfo {
+    /// Compiles a regular expression into a byte-level automaton accepting exactly the strings
+    /// it matches, laid out like the automata of `make_byte_level_fsm`.
+    ///
+    /// Every byte has a symbol, bytes that lead to the same states sharing a transition key, so
+    /// the key of anything else is never used.
+    pub fn from_regex(pattern: &str) -> Result<FSMInfo> {
+        let invalid_regex = |reason: String| Error::InvalidRegex {
+            pattern: pattern.to_string(),
+            reason,
+        };
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .match_kind(MatchKind::All)
+                    .start_kind(StartKind::Anchored),
+            )
+            .build(pattern)
+            .map_err(|e| invalid_regex(e.to_string()))?;
+        Self::walk_dense_dfa(&dfa).map_err(|e| invalid_regex(e.to_string()))
+    }
+
+    /// Compiles a regular expression like [`FSMInfo::from_regex`], with the automata of
+    /// `fragments` in place of their markers, written by [`fragment_marker`]. Automata with no
+    /// short pattern, like that of [`FSMInfo::any_order`], are built apart and spliced into the
+    /// automaton of the pattern.
+    pub(crate) fn from_regex_with_fragments(
+        pattern: &str,
+        fragments: &[FSMInfo],
+    ) -> Result<FSMInfo> {
+        if fragments.is_empty() {
+            return Self::from_regex(pattern);
+        }
+        let invalid_regex = |reason: String| Error::InvalidRegex {
+            pattern: pattern.to_string(),
+            reason,
+        };
+        // The markers are made of bytes which are not UTF-8.
+        let dfa = dense::Builder::new()
+            .syntax(syntax::Config::new().utf8(false))
+            .configure(
+                dense::Config::new()
+                    .match_kind(MatchKind::All)
+                    .start_kind(StartKind::Anchored),
+            )
+            .build(pattern)
+            .map_err(|e| invalid_regex(e.to_string()))?;
+        let fsm_info = Self::walk_dense_dfa(&dfa).map_err(|e| invalid_regex(e.to_string()))?;
+        Ok(fsm_info.splice(fragments))
+    }
+
+    /// Converts a dense DFA built by `regex-automata`, accepting the strings it matches from its
+    /// anchored start state, laid out like the automata of [`FSMInfo::from_regex`].
+    ///
+    /// The DFA needs an anchored start state. Its match kind should be `MatchKind::All`, the
+    /// other kinds dropping the matches which extend a match they prefer.
+    pub fn from_dense_dfa<T: AsRef<[u32]>>(dfa: &dense::DFA<T>) -> Result<FSMInfo> {
+        Self::walk_dense_dfa(dfa).map_err(|e| Error::InvalidDfa(e.to_string()))
+    }
+
+    /// Converts a dense DFA serialized by `regex-automata` in the native endianness, like
+    /// `DFA::to_bytes_native_endian` writes it, see [`FSMInfo::from_dense_dfa`].
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// # use outlines_core::index::FSMInfo;
+    /// # use regex_automata::dfa::dense;
+    /// #
+    /// let dfa = dense::DFA::new("ab+").unwrap();
+    /// let (bytes, padding) = dfa.to_bytes_native_endian();
+    /// let fsm_info = FSMInfo::from_dense_dfa_bytes(&bytes[padding..]).unwrap();
+    /// assert_eq!(fsm_info.enumerate_strings(3, 10), ["ab", "abb"]);
+    /// ```
+    pub fn from_dense_dfa_bytes(bytes: &[u8]) -> Result<FSMInfo> {
+        // The DFA is read in place, from a buffer aligned to the size of its words.
+        let mut buffer = vec![0; bytes.len() + 3];
+        let offset = buffer.as_ptr().align_offset(4);
+        let aligned = &mut buffer[offset..offset + bytes.len()];
+        aligned.copy_from_slice(bytes);
+        let (dfa, _) =
+            dense::DFA::from_bytes(aligned).map_err(|e| Error::InvalidDfa(e.to_string()))?;
+        Self::from_dense_dfa(&dfa)
+    }
+
+    /// Walks the states of a DFA reached from its anchored start state.
+    fn walk_dense_dfa<T: AsRef<[u32]>>(
+        dfa: &dense::DFA<T>,
+    ) -> std::result::Result<FSMInfo, StartError> {
+        let start_state = dfa.start_state(&start::Config::new().anchored(Anchored::Yes))?;
+
+        let classes = dfa.byte_classes();
+        let alphabet_symbol_mapping: HashMap<String, TransitionKey> = (0..=u8::MAX)
+            .map(|byte| (byte_symbol(byte), classes.get(byte) as TransitionKey))
+            .collect();
+        let representatives: Vec<u8> = classes
+            .representatives(..)
+            .filter_map(|unit| unit.as_u8())
+            .collect();
+
+        let mut states = HashMap::from([(start_state, 0)]);
+        let mut queue = VecDeque::from([start_state]);
+        let mut finals = HashSet::new();
+        let mut transitions = HashMap::new();
+        while let Some(dfa_state) = queue.pop_front() {
+            let state = states[&dfa_state];
+            // Matches are reported one byte late, so the end of the input tells whether the bytes
+            // read so far are a match.
+            if dfa.is_match_state(dfa.next_eoi_state(dfa_state)) {
+                finals.insert(state);
+            }
+            for &byte in &representatives {
+                let next_dfa_state = dfa.next_state(dfa_state, byte);
+                if dfa.is_dead_state(next_dfa_state) || dfa.is_quit_state(next_dfa_state) {
+                    continue;
+                }
+                let next_state = match states.get(&next_dfa_state) {
+                    Some(&next_state) => next_state,
+                    None => {
+                        let next_state = states.len() as State;
+                        states.insert(next_dfa_state, next_state);
+                        queue.push_back(next_dfa_state);
+                        next_state
+                    }
+                };
+                transitions.insert((state, classes.get(byte) as TransitionKey), next_state);
+            }
+        }
+
+        Ok(FSMInfo::new(
+            0,
+            finals,
+            transitions,
+            classes.alphabet_len() as TransitionKey,
+            alphabet_symbol_mapping,
+        )
+        .trim())
+    }
+
+    /// Removes the states from which no final state can be reached, numbering the states left in
+    /// the order they are reached from the initial state.
+    pub(crate) fn trim(&self) -> FSMInfo {
+        let adjacency = self.adjacency();
+        let distances = self.distances_to_final();
+
+        let mut finals = HashSet::new();
+        let mut transitions = HashMap::new();
+        let mut states = HashMap::from([(self.initial, 0)]);
+        let mut queue = VecDeque::new();
+        if distances.contains_key(&self.initial) {
+            queue.push_back(self.initial);
+        }
+        while let Some(state) = queue.pop_front() {
+            let trimmed_state = states[&state];
+            if self.finals.contains(&state) {
+                finals.insert(trimmed_state);
+            }
+            for &(key, next_state) in adjacency.get(&state).into_iter().flatten() {
+                if !distances.contains_key(&next_state) {
+                    continue;
+                }
+                let next_trimmed_state = match states.get(&next_state) {
+                    Some(&next_trimmed_state) => next_trimmed_state,
+                    None => {
+                        let next_trimmed_state = states.len() as State;
+                        states.insert(next_state, next_trimmed_state);
+                        queue.push_back(next_state);
+                        next_trimmed_state
+                    }
+                };
+                transitions.insert((trimmed_state, key), next_trimmed_state);
+            }
+        }
+
+        FSMInfo::new(
+            0,
+            finals,
+            transitions,
+            self.alphabet_anything_value,
+            self.alphabet_symbol_mapping.clone(),
+        )
+    }
+
+    /// Merges the transition keys leading from every state to the same state, or to none, into
+    /// a single key per class, numbered in the order of their smallest key.
+    ///
+    /// The single-character symbols of the class of anything else are left out of the alphabet,
+    /// since they are read with its key anyway, while the symbols of several characters, such as
+    /// frozen tokens, are kept. The automaton accepts the same strings.
+    pub fn compress_alphabet(&self) -> FSMInfo {
+        let mut columns: BTreeMap<TransitionKey, Vec<(State, State)>> = self
+            .alphabet_symbol_mapping
+            .values()
+            .chain([&self.alphabet_anything_value])
+            .map(|&key| (key, Vec::new()))
+            .collect();
+        for (&(state, key), &next_state) in &self.transitions {
+            columns.entry(key).or_default().push((state, next_state));
+        }
+
+        let mut classes: HashMap<Vec<(State, State)>, TransitionKey> = HashMap::new();
+        let mut key_classes: HashMap<TransitionKey, TransitionKey> = HashMap::new();
+        for (key, mut column) in columns {
+            column.sort_unstable();
+            let num_classes = classes.len() as TransitionKey;
+            let class = *classes.entry(column).or_insert(num_classes);
+            key_classes.insert(key, class);
+        }
+
+        let anything_class = key_classes[&self.alphabet_anything_value];
+        let alphabet_symbol_mapping = self
+            .alphabet_symbol_mapping
+            .iter()
+            .map(|(symbol, key)| (symbol, key_classes[key]))
+            .filter(|&(symbol, class)| {
+                class != anything_class || crate::regex::token_symbols(symbol).len() > 1
+            })
+            .map(|(symbol, class)| (symbol.clone(), class))
+            .collect();
+        let transitions = self
+            .transitions
+            .iter()
+            .map(|(&(state, key), &next_state)| ((state, key_classes[&key]), next_state))
+            .collect();
+
+        FSMInfo::new(
+            self.initial,
+            self.finals.clone(),
+            transitions,
+            anything_class,
+            alphabet_symbol_mapping,
+        )
+    }
+
+    /// Outgoing transitions of every state, ordered by transition key.
+    pub(crate) fn adjacency(&self) -> HashMap<State, Vec<(TransitionKey, State)>> {
+        let mut adjacency: HashMap<State, Vec<(TransitionKey, State)>> = HashMap::new();
+        for (&(from_state, key), &to_state) in &self.transitions {
+            adjacency
+                .entry(from_state)
+                .or_default()
+                .push((key, to_state));
+        }
+        for transitions in adjacency.values_mut() {
+            transitions.sort_unstable();
+        }
+        adjacency
+    }
+
+    /// Length of the shortest path from each state to a final state. States that can not reach a
+    /// final state are left out.
+    pub(crate) fn distances_to_final(&self) -> HashMap<State, usize> {
+        let mut predecessors: HashMap<State, Vec<State>> = HashMap::new();
+        for (&(from_state, _), &to_state) in &self.transitions {
+            predecessors.entry(to_state).or_default().push(from_state);
+        }
+
+        let mut distances: HashMap<State, usize> =
+            self.finals.iter().map(|&state| (state, 0)).collect();
+        let mut queue: VecDeque<State> = self.finals.iter().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let distance = distances[&state] + 1;
+            for &predecessor in predecessors.get(&state).into_iter().flatten() {
+                if let Entry::Vacant(entry) = distances.entry(predecessor) {
+                    entry.insert(distance);
+                    queue.push_back(predecessor);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Whether the automaton accepts no string at all.
+    pub fn is_empty(&self) -> bool {
+        !self.distances_to_final().contains_key(&self.initial)
+    }
+
+    /// Shortest string leading from `state` to a final state, written with the automaton's
+    /// symbols, or `None` if no final state can be reached.
+    ///
+    /// Among the shortest strings, the one taking the smallest transition keys is returned, and
+    /// each key is written with its smallest symbol. Transitions on characters outside of the
+    /// alphabet are not followed, as there is no symbol to write them with.
+    pub fn shortest_completion(&self, state: State) -> Option<String> {
+        Some(self.shortest_path(state)?.concat())
+    }
+
+    /// Shortest string the automaton accepts, or `None` if it accepts none, as picked by
+    /// [`FSMInfo::shortest_completion`]. The bytes of a byte-level automaton are decoded, so
+    /// that the string reads as the text it stands for.
+    pub fn shortest_string(&self) -> Option<String> {
+        let mut bytes = Vec::new();
+        for symbol in self.shortest_path(self.initial)? {
+            match symbol.strip_prefix('\0') {
+                Some(hex) if hex.len() == 2 => {
+                    bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                _ => bytes.extend_from_slice(symbol.as_bytes()),
+            }
+        }
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Strings of at most `max_len` symbols the automaton accepts, at most `limit` of them, in
+    /// the lexicographic order of their bytes. The bytes of a byte-level automaton are decoded,
+    /// and `max_len` then counts bytes. Transitions on characters outside of the alphabet are
+    /// not followed, as there is no symbol to write them with.
+    pub fn enumerate_strings(&self, max_len: usize, limit: usize) -> Vec<String> {
+        let mut symbols: HashMap<TransitionKey, Vec<Vec<u8>>> = HashMap::new();
+        for (symbol, &key) in &self.alphabet_symbol_mapping {
+            let bytes = match symbol.strip_prefix('\0') {
+                Some(hex) if hex.len() == 2 => match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => vec![byte],
+                    Err(_) => symbol.as_bytes().to_vec(),
+                },
+                _ => symbol.as_bytes().to_vec(),
+            };
+            symbols.entry(key).or_default().push(bytes);
+        }
+
+        let mut edges: HashMap<State, Vec<(&[u8], State)>> = HashMap::new();
+        for (&(from_state, key), &to_state) in &self.transitions {
+            for symbol in symbols.get(&key).into_iter().flatten() {
+                edges
+                    .entry(from_state)
+                    .or_default()
+                    .push((symbol, to_state));
+            }
+        }
+        for state_edges in edges.values_mut() {
+            state_edges.sort_unstable();
+        }
+
+        let mut strings = Vec::new();
+        self.enumerate(
+            self.initial,
+            &mut Vec::new(),
+            max_len,
+            &edges,
+            &self.distances_to_final(),
+            limit,
+            &mut strings,
+        );
+        strings
+    }
+
+    /// Walks the strings extending `prefix`, once at `state`, by at most `remaining` symbols.
+    #[allow(clippy::too_many_arguments)]
+    fn enumerate(
+        &self,
+        state: State,
+        prefix: &mut Vec<u8>,
+        remaining: usize,
+        edges: &HashMap<State, Vec<(&[u8], State)>>,
+        distances: &HashMap<State, usize>,
+        limit: usize,
+        strings: &mut Vec<String>,
+    ) {
+        if strings.len() >= limit {
+            return;
+        }
+        if self.finals.contains(&state) {
+            strings.push(String::from_utf8_lossy(prefix).into_owned());
+        }
+        if remaining == 0 {
+            return;
+        }
+        for &(symbol, next_state) in edges.get(&state).into_iter().flatten() {
+            // Only the walks which can still end in a final state are followed.
+            if distances
+                .get(&next_state)
+                .is_none_or(|&distance| distance >= remaining)
+            {
+                continue;
+            }
+            let len = prefix.len();
+            prefix.extend_from_slice(symbol);
+            self.enumerate(
+                next_state,
+                prefix,
+                remaining - 1,
+                edges,
+                distances,
+                limit,
+                strings,
+            );
+            prefix.truncate(len);
+        }
+    }
+
+    /// Symbols of the shortest string leading from `state` to a final state.
+    fn shortest_path(&self, state: State) -> Option<Vec<&str>> {
+        let mut symbols: HashMap<TransitionKey, &str> = HashMap::new();
+        for (symbol, &key) in &self.alphabet_symbol_mapping {
+            symbols
+                .entry(key)
+                .and_modify(|smallest| *smallest = (*smallest).min(symbol.as_str()))
+                .or_insert(symbol.as_str());
+        }
+
+        let adjacency = self.adjacency();
+        let mut predecessors: HashMap<State, (State, TransitionKey)> = HashMap::new();
+        let mut queue = VecDeque::from([state]);
+        let mut seen = HashSet::from([state]);
+        let mut end_state = None;
+        while let Some(current) = queue.pop_front() {
+            if self.finals.contains(&current) {
+                end_state = Some(current);
+                break;
+            }
+            for &(key, next_state) in adjacency.get(&current).into_iter().flatten() {
+                if symbols.contains_key(&key) && seen.insert(next_state) {
+                    predecessors.insert(next_state, (current, key));
+                    queue.push_back(next_state);
+                }
+            }
+        }
+
+        let mut current = end_state?;
+        let mut path = Vec::new();
+        while let Some(&(previous, key)) = predecessors.get(&current) {
+            path.push(symbols[&key]);
+            current = previous;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Whether both automata accept the same strings.
+    ///
+    /// The automata are walked together over the symbols of both alphabets, along with a symbol
+    /// in neither, and must agree on which of the pairs of states they reach are final. Symbols
+    /// are compared as written, so a byte-level automaton is only equivalent to another
+    /// byte-level automaton.
+    pub fn equivalent(&self, other: &FSMInfo) -> bool {
+        let mut key_pairs: HashSet<(TransitionKey, TransitionKey)> =
+            HashSet::from([(self.alphabet_anything_value, other.alphabet_anything_value)]);
+        for symbol in self
+            .alphabet_symbol_mapping
+            .keys()
+            .chain(other.alphabet_symbol_mapping.keys())
+        {
+            key_pairs.insert((
+                self.alphabet_symbol_mapping
+                    .get(symbol)
+                    .copied()
+                    .unwrap_or(self.alphabet_anything_value),
+                other
+                    .alphabet_symbol_mapping
+                    .get(symbol)
+                    .copied()
+                    .unwrap_or(other.alphabet_anything_value),
+            ));
+        }
+
+        // `None` stands for the state reached once the automaton rejects the input.
+        let is_final = |fsm: &FSMInfo, state: Option<State>| {
+            state.is_some_and(|state| fsm.finals.contains(&state))
+        };
+        let step = |fsm: &FSMInfo, state: Option<State>, key: TransitionKey| {
+            state.and_then(|state| fsm.transitions.get(&(state, key)).copied())
+        };
+
+        let initial = (Some(self.initial), Some(other.initial));
+        let mut seen = HashSet::from([initial]);
+        let mut queue = VecDeque::from([initial]);
+        while let Some((state, other_state)) = queue.pop_front() {
+            if is_final(self, state) != is_final(other, other_state) {
+                return false;
+            }
+            for &(key, other_key) in &key_pairs {
+                let next = (step(self, state, key), step(other, other_state, other_key));
+                if next != (None, None) && seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        true
+    }
+
+    /// Renders the automaton in the Graphviz DOT language, with one edge per pair of states
+    /// labelled by the symbols leading from one to the other.
+    pub fn to_dot(&self) -> String {
+        let mut symbols: HashMap<TransitionKey, Vec<&str>> = HashMap::new();
+        for (symbol, &key) in &self.alphabet_symbol_mapping {
+            symbols.entry(key).or_default().push(symbol);
+        }
+
+        let mut edges: BTreeMap<(State, State), Vec<&str>> = BTreeMap::new();
+        for (&(from_state, key), &to_state) in &self.transitions {
+            let edge = edges.entry((from_state, to_state)).or_default();
+            match symbols.get(&key) {
+                Some(key_symbols) => edge.extend(key_symbols),
+                None => edge.push("anything else"),
+            }
+        }
+
+        let mut finals: Vec<State> = self.finals.iter().copied().collect();
+        finals.sort_unstable();
+
+        let mut dot = String::from("digraph fsm {\n    rankdir=LR;\n    start [shape=point];\n");
+        for state in finals {
+            dot.push_str(&format!("    {} [shape=doublecircle];\n", state));
+        }
+        dot.push_str(&format!("    start -> {};\n", self.initial));
+        for ((from_state, to_state), symbols) in edges {
+            dot.push_str(&format!(
+                "    {} -> {} [label=\"{}\"];\n",
+                from_state,
+                to_state,
+                dot_label(symbols)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Restricts the automaton to the strings of at most `max_length` symbols.
+    ///
+    /// Each state of the result tracks the number of symbols consumed so far, and states from
+    /// which no final state can be reached within the remaining budget are removed, so that a
+    /// generation guided by the result can always be completed before the budget runs out. For
+    /// a byte-level automaton the budget is a number of bytes.
+    pub fn with_max_length(&self, max_length: usize) -> FSMInfo {
+        let adjacency = self.adjacency();
+        let distances = self.distances_to_final();
+        let within_budget = |state: State, length: usize| {
+            distances
+                .get(&state)
+                .is_some_and(|distance| length + distance <= max_length)
+        };
+
+        let mut finals = HashSet::new();
+        let mut transitions = HashMap::new();
+        let mut states: HashMap<(State, usize), State> = HashMap::from([((self.initial, 0), 0)]);
+        let mut queue = VecDeque::from([(self.initial, 0)]);
+
+        if !within_budget(self.initial, 0) {
+            queue.clear();
+        }
+
+        while let Some((state, length)) = queue.pop_front() {
+            let product_state = states[&(state, length)];
+            if self.finals.contains(&state) {
+                finals.insert(product_state);
+            }
+
+            for &(key, next_state) in adjacency.get(&state).into_iter().flatten() {
+                if !within_budget(next_state, length + 1) {
+                    continue;
+                }
+                let next = (next_state, length + 1);
+                let next_product_state = match states.get(&next) {
+                    Some(&next_product_state) => next_product_state,
+                    None => {
+                        let next_product_state = states.len() as State;
+                        states.insert(next, next_product_state);
+                        queue.push_back(next);
+                        next_product_state
+                    }
+                };
+                transitions.insert((product_state, key), next_product_state);
+            }
+        }
+
+        FSMInfo::new(
+            0,
+            finals,
+            transitions,
+            self.alphabet_anything_value,
+            self.alphabet_symbol_mapping.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::{get_token_transition_keys, walk_fsm_final};
+
+    /// Automaton of `a*b`, over the alphabet `{a: 0, b: 1}`.
+    fn a_star_b() -> FSMInfo {
+        FSMInfo::new(
+            0,
+            HashSet::from([1]),
+            HashMap::from([((0, 0), 0), ((0, 1), 1)]),
+            2,
+            HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)]),
+        )
+    }
+
+    fn accepts(fsm: &FSMInfo, input: &str) -> bool {
+        let keys: Vec<TransitionKey> = input
+            .chars()
+            .map(|c| fsm.alphabet_symbol_mapping[&c.to_string()])
+            .collect();
+        walk_fsm_final(&fsm.transitions, &fsm.finals, &keys, fsm.initial, true).is_some()
+    }
+
+    #[test]
+    fn compress_alphabet() {
+        // `[bc]d|a`, with `b` and `c` under distinct keys, and `e` leading nowhere like anything
+        // else.
+        let fsm = FSMInfo::new(
+            0,
+            HashSet::from([2]),
+            HashMap::from([((0, 1), 1), ((0, 2), 1), ((1, 3), 2), ((0, 0), 2)]),
+            5,
+            HashMap::from([
+                ("a".to_string(), 0),
+                ("b".to_string(), 1),
+                ("c".to_string(), 2),
+                ("d".to_string(), 3),
+                ("e".to_string(), 4),
+                ("<tool>".to_string(), 4),
+            ]),
+        );
+        let compressed = fsm.compress_alphabet();
+        let mapping = &compressed.alphabet_symbol_mapping;
+        assert_eq!(mapping["b"], mapping["c"]);
+        assert!(!mapping.contains_key("e"));
+        assert_eq!(mapping["<tool>"], compressed.alphabet_anything_value);
+        let mut keys: Vec<TransitionKey> = mapping.values().copied().collect();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), 4);
+        assert!(compressed.equivalent(&fsm));
+        assert!(accepts(&compressed, "cd"));
+        assert!(accepts(&compressed, "a"));
+    }
+
+    #[test]
+    fn from_regex() {
+        let fsm = FSMInfo::from_regex("a*b|é").unwrap();
+        let accepts = |input: &str| {
+            let symbols: String = input.bytes().map(byte_symbol).collect();
+            let keys = get_token_transition_keys(
+                &fsm.alphabet_symbol_mapping,
+                fsm.alphabet_anything_value,
+                &symbols,
+            );
+            walk_fsm_final(&fsm.transitions, &fsm.finals, &keys, fsm.initial, true).is_some()
+        };
+
+        assert!(accepts("b"));
+        assert!(accepts("aaab"));
+        assert!(accepts("é"));
+        assert!(!accepts("ba"));
+        assert!(!accepts("aa"));
+        assert_eq!(fsm.shortest_completion(fsm.initial).as_deref(), Some("b"));
+
+        assert!(matches!(
+            FSMInfo::from_regex("(a"),
+            Err(Error::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn equivalent() {
+        let fsm = FSMInfo::from_regex("a*b").unwrap();
+
+        assert!(fsm.equivalent(&FSMInfo::from_regex("b|a+b").unwrap()));
+        assert!(!fsm.equivalent(&FSMInfo::from_regex("a+b").unwrap()));
+        assert!(!fsm.equivalent(&FSMInfo::from_regex("a*b?").unwrap()));
+        assert!(FSMInfo::from_regex(r"[\p{Greek}&&\p{Ll}&&α-ω]")
+            .unwrap()
+            .equivalent(&FSMInfo::from_regex("[α-ω]").unwrap()));
+        assert!(FSMInfo::from_regex("(?i)ab[c-d]")
+            .unwrap()
+            .equivalent(&FSMInfo::from_regex("[aA][bB][c-dC-D]").unwrap()));
+        // The symbols missing from an alphabet are rejected, as every byte but `a` and `b` is.
+        assert!(fsm.equivalent(&a_star_b()));
+        assert!(fsm.trim().equivalent(&fsm));
+        assert!(a_star_b()
+            .with_max_length(3)
+            .equivalent(&FSMInfo::from_regex("a{0,2}b").unwrap()));
+    }
+
+    #[test]
+    fn to_dot() {
+        let fsm = FSMInfo::from_regex("[a-c]x?").unwrap();
+
+        assert_eq!(
+            fsm.to_dot(),
+            "digraph fsm {\n    rankdir=LR;\n    start [shape=point];\n    1 [shape=doublecircle];\n    2 [shape=doublecircle];\n    start -> 0;\n    0 -> 1 [label=\"a-c\"];\n    1 -> 2 [label=\"x\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn shortest_completion() {
+        let fsm = a_star_b();
+
+        assert_eq!(fsm.shortest_completion(0).as_deref(), Some("b"));
+        assert_eq!(fsm.shortest_completion(1).as_deref(), Some(""));
+
+        let fsm = FSMInfo::new(
+            0,
+            HashSet::from([2]),
+            HashMap::from([((0, 0), 1), ((0, 2), 2), ((1, 1), 2)]),
+            2,
+            HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)]),
+        );
+        // The single transition on anything else can not be written.
+        assert_eq!(fsm.shortest_completion(0).as_deref(), Some("ab"));
+        assert_eq!(fsm.shortest_completion(3), None);
+    }
+
+    #[test]
+    fn shortest_string() {
+        let fsm = FSMInfo::from_regex("(é|abc)d+").unwrap();
+        assert_eq!(
+            fsm.shortest_completion(fsm.initial).as_deref(),
+            Some("\0C3\0A9d")
+        );
+        assert_eq!(fsm.shortest_string().as_deref(), Some("éd"));
+
+        assert_eq!(a_star_b().shortest_string().as_deref(), Some("b"));
+        assert_eq!(
+            FSMInfo::from_regex("")
+                .unwrap()
+                .shortest_string()
+                .as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            FSMInfo::from_regex(r"[^\s\S]").unwrap().shortest_string(),
+            None
+        );
+    }
+
+    #[test]
+    fn enumerate_strings() {
+        let fsm = FSMInfo::from_regex("(a|b)c?|é").unwrap();
+        assert_eq!(fsm.enumerate_strings(3, 10), ["a", "ac", "b", "bc", "é"]);
+        assert_eq!(fsm.enumerate_strings(3, 2), ["a", "ac"]);
+        assert_eq!(fsm.enumerate_strings(1, 10), ["a", "b"]);
+
+        let fsm = a_star_b();
+        assert_eq!(fsm.enumerate_strings(3, 10), ["aab", "ab", "b"]);
+        assert!(fsm.enumerate_strings(0, 10).is_empty());
+    }
+
+    #[test]
+    fn from_dense_dfa() {
+        let dfa = dense::Builder::new()
+            .configure(dense::Config::new().match_kind(MatchKind::All))
+            .build("(é|abc)d+")
+            .unwrap();
+        let fsm = FSMInfo::from_dense_dfa(&dfa).unwrap();
+        assert!(fsm.equivalent(&FSMInfo::from_regex("(é|abc)d+").unwrap()));
+
+        // The bytes of the DFA are copied to an aligned buffer, wherever they start.
+        let (bytes, padding) = dfa.to_bytes_native_endian();
+        let bytes = &bytes[padding..];
+        for padding in 0..4 {
+            let padded: Vec<u8> = [&vec![0; padding], bytes].concat();
+            let fsm = FSMInfo::from_dense_dfa_bytes(&padded[padding..]).unwrap();
+            assert!(fsm.equivalent(&FSMInfo::from_regex("(é|abc)d+").unwrap()));
+        }
+
+        assert!(matches!(
+            FSMInfo::from_dense_dfa_bytes(&bytes[..bytes.len() / 2]),
+            Err(Error::InvalidDfa(_))
+        ));
+        let unanchored = dense::Builder::new()
+            .configure(dense::Config::new().start_kind(StartKind::Unanchored))
+            .build("a")
+            .unwrap();
+        assert!(matches!(
+            FSMInfo::from_dense_dfa(&unanchored),
+            Err(Error::InvalidDfa(_))
+        ));
+    }
+
+    #[test]
+    fn is_empty() {
+        assert!(FSMInfo::from_regex(r"[^\s\S]").unwrap().is_empty());
+        assert!(FSMInfo::from_regex(r"a[^\s\S]b").unwrap().is_empty());
+        assert!(!FSMInfo::from_regex(r"a[^\s\S]|b").unwrap().is_empty());
+        assert!(!FSMInfo::from_regex("").unwrap().is_empty());
+        assert!(!a_star_b().is_empty());
+    }
+
+    #[test]
+    fn max_length() {
+        let fsm = a_star_b().with_max_length(3);
+
+        assert!(accepts(&fsm, "b"));
+        assert!(accepts(&fsm, "aab"));
+        assert!(!accepts(&fsm, "aaab"));
+        // Every state left can still reach a final state within the budget.
+        let distances = fsm.distances_to_final();
+        assert!(fsm
+            .transitions
+            .iter()
+            .all(
+                |(&(from_state, _), to_state)| distances.contains_key(&from_state)
+                    && distances.contains_key(to_state)
+            ));
+
+        let fsm = a_star_b().with_max_length(0);
+        assert!(fsm.finals.is_empty());
+        assert!(fsm.transitions.is_empty());
+    }
+}
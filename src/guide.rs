@@ -0,0 +1,712 @@
+/// Guidance of generation through the states of an index.
+use crate::cancellation::CancellationToken;
+use crate::index::{Index, IndexOptions, Result, TokenDfa};
+use crate::json_schema::{self, Options, Whitespace};
+use crate::stats;
+use crate::vocabulary::{Vocabulary, VocabularyFingerprint};
+use std::collections::HashSet;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Current state of a generation constrained by an index, stepped token by token.
+///
+/// The guide shares the token-level automaton of the index, so that cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct Guide {
+    dfa: Arc<TokenDfa>,
+    state: u32,
+}
+
+impl Guide {
+    pub fn new(index: &Index) -> Self {
+        Self::from_token_dfa(Arc::new(index.to_token_dfa()))
+    }
+
+    pub fn from_token_dfa(dfa: Arc<TokenDfa>) -> Self {
+        let state = dfa.initial();
+        Guide { dfa, state }
+    }
+
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    /// Token-level automaton of the guide, shared with its clones.
+    pub fn token_dfa(&self) -> &Arc<TokenDfa> {
+        &self.dfa
+    }
+
+    /// Tokens allowed in the current state, sorted.
+    pub fn allowed_tokens(&self) -> &[u32] {
+        self.dfa.allowed_tokens(self.state)
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.dfa.is_final(self.state)
+    }
+
+    /// State the token would lead to, without moving.
+    pub fn peek(&self, token_id: u32) -> Option<u32> {
+        step(&self.dfa, self.state, token_id)
+    }
+
+    /// Guide in another state of the same automaton, such as one returned by [`Guide::peek`], so
+    /// that several hypotheses, as in beam search, are expanded from a shared automaton.
+    pub fn with_state(&self, state: u32) -> Result<Guide> {
+        let num_states = self.dfa.num_states();
+        if state as usize >= num_states {
+            return Err(crate::Error::UnknownState { state, num_states });
+        }
+        Ok(Guide {
+            dfa: self.dfa.clone(),
+            state,
+        })
+    }
+
+    /// Moves to the state the token leads to. The end-of-sequence token leaves the guide in its
+    /// final state.
+    pub fn advance(&mut self, token_id: u32) -> Result<u32> {
+        self.state =
+            step(&self.dfa, self.state, token_id).ok_or(crate::Error::TokenNotAllowed {
+                token_id,
+                state: self.state,
+            })?;
+        Ok(self.state)
+    }
+
+    /// Moves to the state reached by reading `text` from the initial state, such as text generated
+    /// before or edited by a user, so that the generation continues the text. The text is read
+    /// by the character-level automaton, so that it need not be split into the tokens of the
+    /// vocabulary. The guide stops at the longest prefix of the text that tokens reach, and the
+    /// rest of the text, such as the beginning of a token, is returned for the caller to
+    /// generate again.
+    pub fn resume<'t>(&mut self, text: &'t str) -> Result<&'t str> {
+        let (state, read) = self.dfa.read_text(text)?;
+        self.state = state;
+        Ok(&text[read..])
+    }
+
+    /// Number of tokens of a draft, such as the tokens proposed by a draft model in speculative
+    /// decoding, which are allowed one after the other from the current state. The guide does
+    /// not move.
+    pub fn verify(&self, token_ids: &[u32]) -> usize {
+        let mut state = self.state;
+        token_ids
+            .iter()
+            .take_while(|&&token_id| match step(&self.dfa, state, token_id) {
+                Some(next_state) => {
+                    state = next_state;
+                    true
+                }
+                None => false,
+            })
+            .count()
+    }
+
+    /// Writes the tokens allowed in the current state as a bitmask into `mask`, token `i` being
+    /// bit `i % 64` of `mask[i / 64]`, without allocating.
+    pub fn write_mask(&self, mask: &mut [u64]) -> Result<()> {
+        stats::MASKS_SERVED.add(1);
+        mask.fill(0);
+        let vocab_size = mask.len() * 64;
+        for &token_id in self.allowed_tokens() {
+            let word =
+                mask.get_mut(token_id as usize / 64)
+                    .ok_or(crate::Error::TokenOutsideMask {
+                        token_id,
+                        vocab_size,
+                    })?;
+            *word |= 1 << (token_id % 64);
+        }
+        Ok(())
+    }
+
+    /// Sets the logits of the tokens not allowed in the current state to negative infinity.
+    pub fn apply_mask(&self, logits: &mut [f32]) -> Result<()> {
+        self.dfa.apply_mask(logits, self.state)
+    }
+}
+
+/// States of several generations constrained by the same index, stepped together.
+///
+/// The states are kept in a flat array, the state of the generation `i` being `states()[i]`, so
+/// that stepping them all is a tight loop over the transitions of the automaton.
+#[derive(Debug, Clone)]
+pub struct BatchGuide {
+    dfa: Arc<TokenDfa>,
+    states: Vec<u32>,
+    next_states: Vec<u32>,
+}
+
+impl BatchGuide {
+    /// Starts `batch_size` generations in the initial state.
+    pub fn new(index: &Index, batch_size: usize) -> Self {
+        Self::from_token_dfa(Arc::new(index.to_token_dfa()), batch_size)
+    }
+
+    pub fn from_token_dfa(dfa: Arc<TokenDfa>, batch_size: usize) -> Self {
+        BatchGuide {
+            states: vec![dfa.initial(); batch_size],
+            next_states: Vec::with_capacity(batch_size),
+            dfa,
+        }
+    }
+
+    pub fn states(&self) -> &[u32] {
+        &self.states
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Moves each generation to the state its token leads to. When a token is not allowed, no
+    /// generation moves.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one token per generation.
+    pub fn advance_all(&mut self, token_ids: &[u32]) -> Result<()> {
+        assert_eq!(
+            token_ids.len(),
+            self.states.len(),
+            "advance_all takes one token per generation"
+        );
+        self.next_states.clear();
+        for (&state, &token_id) in self.states.iter().zip(token_ids) {
+            match step(&self.dfa, state, token_id) {
+                Some(next_state) => self.next_states.push(next_state),
+                None => return Err(crate::Error::TokenNotAllowed { token_id, state }),
+            }
+        }
+        std::mem::swap(&mut self.states, &mut self.next_states);
+        Ok(())
+    }
+
+    /// Starts a new generation in the initial state, and returns its position in the batch.
+    pub fn push(&mut self) -> usize {
+        self.states.push(self.dfa.initial());
+        self.states.len() - 1
+    }
+
+    /// Removes the generation at position `i`, the last generation taking its position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no generation at position `i`.
+    pub fn swap_remove(&mut self, i: usize) {
+        self.states.swap_remove(i);
+    }
+
+    /// Guide of the generation at position `i`, sharing the automaton of the batch.
+    pub fn guide(&self, i: usize) -> Guide {
+        Guide {
+            dfa: self.dfa.clone(),
+            state: self.states[i],
+        }
+    }
+}
+
+/// Largest number of compiled schemas kept by [`compile`].
+const MAX_COMPILED_SCHEMAS: usize = 64;
+
+/// What the automaton compiled by [`compile`] depends on.
+#[derive(Debug, PartialEq, Eq)]
+struct CompileKey {
+    schema: String,
+    whitespace: Whitespace,
+    vocabulary: VocabularyFingerprint,
+    eos_token_id: u32,
+}
+
+type CompiledSchemas = Mutex<Vec<(CompileKey, Arc<TokenDfa>)>>;
+
+/// Automata of the schemas last compiled by [`compile`], most recent last.
+static COMPILED: OnceLock<CompiledSchemas> = OnceLock::new();
+
+/// Compiles a JSON Schema into a guide in its initial state, from the schema to the regular
+/// expression, the automaton and the index of the vocabulary.
+///
+/// The automata of the last compiled schemas are kept, keyed by the schema, the whitespace and the
+/// fingerprint of the vocabulary, so that compiling a schema again is a lookup and the guides of
+/// the same schema share their automaton.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::guide::compile;
+/// # use outlines_core::json_schema::Whitespace;
+/// # use outlines_core::prelude::*;
+/// #
+/// let vocabulary = Vocabulary::new().insert("true", 1).insert("false", 2);
+/// let mut guide = compile(r#"{"type": "boolean"}"#, &Whitespace::None, &vocabulary, 0).unwrap();
+/// assert_eq!(guide.allowed_tokens(), &[1, 2]);
+/// guide.advance(2).unwrap();
+/// assert!(guide.is_final());
+/// ```
+pub fn compile(
+    schema: &str,
+    whitespace: &Whitespace,
+    vocabulary: &Vocabulary,
+    eos_token_id: u32,
+) -> anyhow::Result<Guide> {
+    compile_with(
+        schema,
+        whitespace,
+        vocabulary,
+        eos_token_id,
+        &IndexOptions::default(),
+    )
+}
+
+fn compile_with(
+    schema: &str,
+    whitespace: &Whitespace,
+    vocabulary: &Vocabulary,
+    eos_token_id: u32,
+    index_options: &IndexOptions,
+) -> anyhow::Result<Guide> {
+    let key = CompileKey {
+        schema: schema.to_string(),
+        whitespace: whitespace.clone(),
+        vocabulary: vocabulary.fingerprint(),
+        eos_token_id,
+    };
+    let compiled = COMPILED.get_or_init(Mutex::default);
+    {
+        let mut entries = compiled.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(position) = entries.iter().position(|(entry_key, _)| *entry_key == key) {
+            let entry = entries.remove(position);
+            let dfa = Arc::clone(&entry.1);
+            entries.push(entry);
+            stats::COMPILE_CACHE_HITS.add(1);
+            return Ok(Guide::from_token_dfa(dfa));
+        }
+    }
+    stats::COMPILE_CACHE_MISSES.add(1);
+
+    // The lock is not held while compiling, so that other schemas are compiled meanwhile.
+    let options = Options::new().whitespace(whitespace.clone());
+    let fsm_info = json_schema::build_fsm_from_schema(schema, &options)?;
+    let index = Index::with_options(
+        &fsm_info,
+        vocabulary,
+        eos_token_id,
+        HashSet::new(),
+        index_options,
+    )?;
+    let dfa = Arc::new(index.to_token_dfa());
+    let mut entries = compiled.lock().unwrap_or_else(PoisonError::into_inner);
+    if !entries.iter().any(|(entry_key, _)| *entry_key == key) {
+        if entries.len() == MAX_COMPILED_SCHEMAS {
+            entries.remove(0);
+        }
+        entries.push((key, Arc::clone(&dfa)));
+    }
+    Ok(Guide::from_token_dfa(dfa))
+}
+
+/// Compiles a JSON Schema into a guide like [`compile`], on a thread of its own, so that servers
+/// compile the schemas of their requests without blocking their event loop.
+///
+/// The handle is a future of the guide, which can be awaited on any executor, or waited for
+/// with [`CompileHandle::wait`]. Dropping it before the guide is compiled cancels the
+/// compilation, so that the compilations of a request a server gives up on stop with it.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::guide::compile_async;
+/// # use outlines_core::json_schema::Whitespace;
+/// # use outlines_core::prelude::*;
+/// #
+/// let vocabulary = Vocabulary::new().insert("true", 1).insert("false", 2);
+/// let handle = compile_async(r#"{"type": "boolean"}"#, &Whitespace::None, &vocabulary, 0);
+/// let guide = handle.wait().unwrap();
+/// assert_eq!(guide.allowed_tokens(), &[1, 2]);
+/// ```
+pub fn compile_async(
+    schema: &str,
+    whitespace: &Whitespace,
+    vocabulary: &Vocabulary,
+    eos_token_id: u32,
+) -> CompileHandle {
+    let cancellation = CancellationToken::new();
+    let compiled: Arc<Compiled> = Arc::default();
+    let done = Arc::clone(&compiled);
+    spawn_compile(
+        schema,
+        whitespace,
+        vocabulary,
+        eos_token_id,
+        cancellation.clone(),
+        move |result| {
+            let mut state = done.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            done.condvar.notify_all();
+        },
+    );
+    CompileHandle {
+        compiled,
+        cancellation,
+    }
+}
+
+/// Compiles a JSON Schema into a guide like [`compile`] on a new thread, which hands the
+/// outcome to `done`. The compilation gives up once `cancellation` is cancelled, even before it
+/// starts.
+pub(crate) fn spawn_compile(
+    schema: &str,
+    whitespace: &Whitespace,
+    vocabulary: &Vocabulary,
+    eos_token_id: u32,
+    cancellation: CancellationToken,
+    done: impl FnOnce(anyhow::Result<Guide>) + Send + 'static,
+) {
+    let schema = schema.to_string();
+    let whitespace = whitespace.clone();
+    let vocabulary = vocabulary.clone();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if cancellation.is_cancelled() {
+                return Err(crate::Error::Cancelled.into());
+            }
+            let options = IndexOptions::new().cancellation(cancellation);
+            compile_with(&schema, &whitespace, &vocabulary, eos_token_id, &options)
+        }))
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("The compilation panicked")));
+        done(result);
+    });
+}
+
+/// Guide compiled by [`compile_async`], once it is, and the task waiting for it.
+#[derive(Debug, Default)]
+struct Compiled {
+    state: Mutex<CompiledState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct CompiledState {
+    result: Option<anyhow::Result<Guide>>,
+    waker: Option<Waker>,
+}
+
+/// Guide being compiled in the background by [`compile_async`].
+#[derive(Debug)]
+pub struct CompileHandle {
+    compiled: Arc<Compiled>,
+    cancellation: CancellationToken,
+}
+
+impl CompileHandle {
+    /// Cancels the compilation, whose guide is then an [`crate::Error::Cancelled`] error unless
+    /// the compilation was done already.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Whether the guide is compiled, so that [`CompileHandle::wait`] returns without blocking.
+    pub fn is_finished(&self) -> bool {
+        self.lock().result.is_some()
+    }
+
+    /// Blocks until the guide is compiled.
+    pub fn wait(self) -> anyhow::Result<Guide> {
+        let mut state = self.lock();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self
+                .compiled
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CompiledState> {
+        self.compiled
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Future for CompileHandle {
+    type Output = anyhow::Result<Guide>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for CompileHandle {
+    fn drop(&mut self) {
+        // Once the guide is compiled, this does nothing.
+        self.cancellation.cancel();
+    }
+}
+
+/// State the token leads to, the end-of-sequence token staying in the final states which allow
+/// it.
+fn step(dfa: &TokenDfa, state: u32, token_id: u32) -> Option<u32> {
+    match dfa.next_state(state, token_id) {
+        Some(next_state) => Some(next_state),
+        None if token_id == dfa.eos_token_id() => dfa
+            .allowed_tokens(state)
+            .binary_search(&token_id)
+            .is_ok()
+            .then_some(state),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsm::byte_symbols;
+    use crate::index::FSMInfo;
+
+    fn guide() -> Guide {
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("b", 70)
+            .insert("ab", 3);
+        let fsm_info = FSMInfo::from_regex("(ab)+").unwrap();
+        Guide::new(&Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap())
+    }
+
+    #[test]
+    fn advance() {
+        let mut guide = guide();
+        assert_eq!(guide.allowed_tokens(), &[1, 3]);
+        assert!(guide.advance(70).is_err());
+        assert_eq!(guide.state(), 0);
+
+        guide.advance(1).unwrap();
+        assert_eq!(guide.allowed_tokens(), &[70]);
+        assert!(!guide.is_final());
+        let state = guide.advance(70).unwrap();
+        assert!(guide.is_final());
+        assert_eq!(guide.allowed_tokens(), &[0, 1, 3]);
+        assert_eq!(guide.advance(0).unwrap(), state);
+    }
+
+    #[test]
+    fn peek() {
+        let guide = guide();
+        assert_eq!(guide.peek(70), None);
+        let a = guide.peek(1).unwrap();
+        let ab = guide.peek(3).unwrap();
+        assert_eq!(guide.state(), 0);
+
+        let beams = [guide.with_state(a).unwrap(), guide.with_state(ab).unwrap()];
+        assert_eq!(beams[0].allowed_tokens(), &[70]);
+        assert_eq!(beams[1].peek(0), Some(ab));
+        assert!(Arc::ptr_eq(&beams[0].dfa, &beams[1].dfa));
+        let num_states = guide.dfa.num_states();
+        assert!(matches!(
+            guide.with_state(num_states as u32),
+            Err(crate::Error::UnknownState { .. })
+        ));
+    }
+
+    #[test]
+    fn verify() {
+        let mut guide = guide();
+        assert_eq!(guide.verify(&[1, 70, 3, 0, 0]), 5);
+        assert_eq!(guide.verify(&[1, 70, 70, 3]), 2);
+        assert_eq!(guide.verify(&[0]), 0);
+        assert_eq!(guide.verify(&[]), 0);
+        assert_eq!(guide.state(), 0);
+
+        guide.advance(1).unwrap();
+        assert_eq!(guide.verify(&[70, 70]), 1);
+    }
+
+    #[test]
+    fn resume() {
+        let mut guide = guide();
+        let mut tokens = guide.clone();
+        tokens.advance(3).unwrap();
+        tokens.advance(1).unwrap();
+        assert_eq!(guide.resume("aba").unwrap(), "");
+        assert_eq!(guide.state(), tokens.state());
+        assert_eq!(guide.allowed_tokens(), &[70]);
+        assert!(matches!(
+            guide.resume("abx"),
+            Err(crate::Error::TextMismatch { valid_up_to: 2 })
+        ));
+        assert_eq!(guide.resume("").unwrap(), "");
+        assert_eq!(guide.state(), 0);
+
+        // Characters outside of ASCII are read as the bytes of the automaton.
+        let vocabulary = Vocabulary::new()
+            .insert("\"", 1)
+            .insert("caf", 2)
+            .insert(byte_symbols("é".as_bytes()), 3);
+        let fsm_info = FSMInfo::from_regex(r#""[a-zé]*""#).unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let mut guide = Guide::new(&index);
+        assert_eq!(guide.resume("\"café").unwrap(), "");
+        assert_eq!(guide.allowed_tokens(), &[1, 2, 3]);
+
+        // Tokens never stop after the `a` of `ab`, so the text ending in the middle of a token
+        // is left unread from the last state tokens reach.
+        let vocabulary = Vocabulary::new().insert("ab", 1);
+        let fsm_info = FSMInfo::from_regex("(ab)+").unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let mut guide = Guide::new(&index);
+        assert_eq!(guide.resume("a").unwrap(), "a");
+        assert_eq!(guide.state(), 0);
+        assert_eq!(guide.resume("aba").unwrap(), "a");
+        assert_eq!(guide.allowed_tokens(), &[0, 1]);
+
+        let vocabulary = Vocabulary::new()
+            .insert("{\"name\"", 1)
+            .insert(":", 2)
+            .insert("}", 3);
+        let fsm_info = FSMInfo::from_regex(r#"\{"name":\}"#).unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let mut guide = Guide::new(&index);
+        assert_eq!(guide.resume("{\"na").unwrap(), "{\"na");
+        assert_eq!(guide.allowed_tokens(), &[1]);
+        assert_eq!(guide.resume("{\"name\":").unwrap(), "");
+        assert_eq!(guide.allowed_tokens(), &[3]);
+        let dfa: TokenDfa =
+            bincode::deserialize(&bincode::serialize(&index.to_token_dfa()).unwrap()).unwrap();
+        assert!(matches!(
+            Guide::from_token_dfa(Arc::new(dfa)).resume("ab"),
+            Err(crate::Error::MissingTextAutomaton)
+        ));
+    }
+
+    #[test]
+    fn write_mask() {
+        let mut guide = guide();
+        let mut mask = [u64::MAX; 2];
+        let before = stats::stats();
+        guide.write_mask(&mut mask).unwrap();
+        assert_eq!(mask, [1 << 1 | 1 << 3, 0]);
+        assert!(stats::stats().masks_served > before.masks_served);
+
+        guide.advance(1).unwrap();
+        guide.write_mask(&mut mask).unwrap();
+        assert_eq!(mask, [0, 1 << 6]);
+        assert!(matches!(
+            guide.write_mask(&mut mask[..1]),
+            Err(crate::Error::TokenOutsideMask {
+                token_id: 70,
+                vocab_size: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn compile() {
+        let schema = r#"{"type": "integer", "minimum": 0, "maximum": 9}"#;
+        let mut vocabulary = Vocabulary::new()
+            .insert("1", 1)
+            .insert("2", 2)
+            .insert("x", 3);
+        let guide = super::compile(schema, &Whitespace::None, &vocabulary, 0).unwrap();
+        assert_eq!(guide.allowed_tokens(), &[1, 2]);
+
+        let before = stats::stats();
+        let again = super::compile(schema, &Whitespace::None, &vocabulary, 0).unwrap();
+        assert!(Arc::ptr_eq(guide.token_dfa(), again.token_dfa()));
+        assert!(stats::stats().compile_cache_hits > before.compile_cache_hits);
+        let pretty = super::compile(schema, &Whitespace::Pretty(2), &vocabulary, 0).unwrap();
+        assert!(!Arc::ptr_eq(guide.token_dfa(), pretty.token_dfa()));
+
+        vocabulary.insert_in_place("3", 4);
+        let guide = super::compile(schema, &Whitespace::None, &vocabulary, 0).unwrap();
+        assert_eq!(guide.allowed_tokens(), &[1, 2, 4]);
+        assert!(super::compile("{", &Whitespace::None, &vocabulary, 0).is_err());
+    }
+
+    #[test]
+    fn advance_all() {
+        let dfa = guide().dfa;
+        let mut batch = BatchGuide::from_token_dfa(dfa.clone(), 2);
+        assert_eq!(batch.states(), &[0, 0]);
+
+        batch.advance_all(&[1, 3]).unwrap();
+        let mut guide = Guide::from_token_dfa(dfa);
+        let a = guide.clone().advance(1).unwrap();
+        let ab = guide.advance(3).unwrap();
+        assert_eq!(batch.states(), &[a, ab]);
+        assert!(batch.advance_all(&[70, 70]).is_err());
+        assert_eq!(batch.states(), &[a, ab]);
+
+        assert_eq!(batch.push(), 2);
+        batch.advance_all(&[70, 0, 3]).unwrap();
+        assert_eq!(batch.states(), &[ab, ab, ab]);
+        assert!(batch.guide(1).is_final());
+        batch.swap_remove(0);
+        assert_eq!(batch.len(), 2);
+    }
+
+    #[test]
+    fn compile_async() {
+        struct Unpark(thread::Thread);
+        impl std::task::Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let schema = r#"{"type": "integer", "minimum": 10, "maximum": 19}"#;
+        let vocabulary = Vocabulary::new().insert("1", 1).insert("2", 2);
+        let mut handle = super::compile_async(schema, &Whitespace::None, &vocabulary, 0);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let guide = loop {
+            match Pin::new(&mut handle).poll(&mut cx) {
+                Poll::Ready(guide) => break guide.unwrap(),
+                Poll::Pending => thread::park(),
+            }
+        };
+        assert_eq!(guide.allowed_tokens(), &[1]);
+        let handle = super::compile_async("{", &Whitespace::None, &vocabulary, 0);
+        assert!(handle.wait().is_err());
+
+        // A compilation cancelled before it starts gives up even when its guide is cached.
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        spawn_compile(
+            schema,
+            &Whitespace::None,
+            &vocabulary,
+            0,
+            cancellation,
+            move |result| sender.send(result).unwrap(),
+        );
+        let error = receiver.recv().unwrap().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(crate::Error::Cancelled)
+        ));
+    }
+}
@@ -1,8 +1,15 @@
 /// Construct an Index.
-use crate::prelude::{State, TransitionKey};
-use crate::regex::{get_vocabulary_transition_keys, state_scan_tokens};
+use crate::cancellation::{CancellationToken, Deadline};
+use crate::fsm::byte_symbols;
+use crate::prelude::{State, TokenId, TransitionKey};
+use crate::regex::{get_token_transition_keys, LiteralScanner, TokenTrie};
+use crate::stats;
 use crate::vocabulary::Vocabulary;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub type Result<T, E = crate::Error> = std::result::Result<T, E>;
 
@@ -31,14 +38,225 @@ impl FSMInfo {
             alphabet_symbol_mapping,
         }
     }
+
+    pub fn initial(&self) -> State {
+        self.initial
+    }
+
+    pub fn finals(&self) -> &HashSet<State> {
+        &self.finals
+    }
+
+    pub fn transitions(&self) -> &HashMap<(State, TransitionKey), State> {
+        &self.transitions
+    }
+
+    pub fn alphabet_anything_value(&self) -> TransitionKey {
+        self.alphabet_anything_value
+    }
+
+    pub fn alphabet_symbol_mapping(&self) -> &HashMap<String, TransitionKey> {
+        &self.alphabet_symbol_mapping
+    }
 }
 
-#[derive(Debug)]
+/// Progress of the construction of an [`Index`], reported after each state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of states whose tokens have been scanned.
+    pub states_processed: usize,
+    /// Number of states reached but not scanned yet.
+    pub frontier: usize,
+}
+
+/// Function receiving the [`Progress`] of a construction, which it can stop.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(Progress) -> ControlFlow<()> + Send + Sync>);
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Special tokens an [`Index`] allows, such as BOS, EOS, PAD or tool-call tokens, as opposed to
+/// the tokens writing the text of its automaton.
+///
+/// The special tokens are EOS, the frozen tokens and the listed tokens. EOS ends the output, so
+/// it is only allowed in the final states. The other special tokens are read whole, as a single
+/// symbol of the automaton, so they are only allowed where the automaton has a transition on
+/// their content, which is how a tool-call token can open a tool call. BOS and PAD, which have no
+/// place in an output, are forbidden by not listing them.
+///
+/// Without a policy, an index allows EOS and the frozen tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecialTokensPolicy {
+    /// No special token is allowed, not even EOS, the output being ended by the caller once it
+    /// reaches a final state.
+    ForbidAll,
+    /// Only EOS is allowed.
+    EosOnly,
+    /// Only the listed tokens are allowed, EOS being allowed if it is listed. The tokens of the
+    /// vocabulary with a listed id are read whole, like the frozen tokens.
+    Allow(Vec<TokenId>),
+}
+
+/// Options controlling how an [`Index`] is built.
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    cancellation: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    progress: Option<ProgressCallback>,
+    max_bytes: Option<usize>,
+    special_tokens: Option<SpecialTokensPolicy>,
+    token_healing: Option<String>,
+}
+
+impl IndexOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token cancelling the construction, which then fails with [`crate::Error::Cancelled`].
+    pub fn cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Longest time the construction may take, after which it fails with
+    /// [`crate::Error::Timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Function called with the progress of the construction after each state, from the thread
+    /// building the index. The construction fails with [`crate::Error::Cancelled`] when it
+    /// breaks.
+    pub fn progress(
+        mut self,
+        progress: impl Fn(Progress) -> ControlFlow<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(ProgressCallback(Arc::new(progress)));
+        self
+    }
+
+    /// Largest estimated size in bytes of the transitions of the index, beyond which the
+    /// construction fails with [`crate::Error::MemoryBudgetExceeded`].
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Special tokens the index allows, see [`SpecialTokensPolicy`].
+    pub fn special_tokens(mut self, policy: SpecialTokensPolicy) -> Self {
+        self.special_tokens = Some(policy);
+        self
+    }
+
+    /// Heals the end of a prompt cut back by `tail`, the text of its last tokens written with the
+    /// symbols of the vocabulary: the output starts by writing `tail` again, so that its first
+    /// token can extend the end of the prompt past the start of the constrained text, like the
+    /// model would have done without the cut.
+    ///
+    /// The tokens allowed in the initial state of the index are then the ones which write a
+    /// prefix of `tail`, or `tail` followed by the start of a valid output.
+    pub fn token_healing(mut self, tail: impl Into<String>) -> Self {
+        self.token_healing = Some(tail.into());
+        self
+    }
+}
+
+/// Applies a policy to the special tokens of a vocabulary: returns the tokens to read whole, the
+/// ids of the ones which are forbidden, and whether EOS is allowed.
+fn special_tokens(
+    vocabulary: &Vocabulary,
+    eos_token_id: TokenId,
+    mut frozen_tokens: HashSet<String>,
+    policy: Option<&SpecialTokensPolicy>,
+) -> Result<(HashSet<String>, HashSet<TokenId>, bool)> {
+    let (allowed, allow_eos) = match policy {
+        None => return Ok((frozen_tokens, HashSet::new(), true)),
+        Some(SpecialTokensPolicy::ForbidAll) => (HashSet::new(), false),
+        Some(SpecialTokensPolicy::EosOnly) => (HashSet::new(), true),
+        Some(SpecialTokensPolicy::Allow(token_ids)) => {
+            let allowed: HashSet<TokenId> = token_ids.iter().copied().collect();
+            let mut found: HashSet<TokenId> = HashSet::from([eos_token_id]);
+            for (token, ids) in vocabulary.iter() {
+                if ids.iter().any(|id| allowed.contains(id)) {
+                    frozen_tokens.insert(token.clone());
+                    found.extend(ids);
+                }
+            }
+            if let Some(&token_id) = token_ids.iter().find(|id| !found.contains(id)) {
+                return Err(crate::Error::UnknownSpecialToken { token_id });
+            }
+            let allow_eos = allowed.contains(&eos_token_id);
+            (allowed, allow_eos)
+        }
+    };
+    let forbidden = frozen_tokens
+        .iter()
+        .filter_map(|token| vocabulary.get(token))
+        .flatten()
+        .filter(|token_id| !allowed.contains(token_id))
+        .copied()
+        .collect();
+    Ok((frozen_tokens, forbidden, allow_eos))
+}
+
+/// Estimated size in bytes of the maps holding the transitions of an index.
+fn transitions_size(states: usize, transitions: usize) -> usize {
+    states * std::mem::size_of::<(u32, HashMap<u32, u32>)>()
+        + transitions * std::mem::size_of::<(u32, u32)>()
+}
+
+/// Serializes a set in increasing order, so that equal indexes serialize to the same bytes.
+fn serialize_sorted_set<S: Serializer>(
+    set: &HashSet<u32>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    set.iter().collect::<BTreeSet<_>>().serialize(serializer)
+}
+
+/// Serializes the transitions of an index sorted by state, then by token.
+fn serialize_sorted_transitions<S: Serializer>(
+    transitions: &HashMap<u32, HashMap<u32, u32>>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    sorted_transitions(transitions).serialize(serializer)
+}
+
+/// Transitions of an index sorted by state, then by token.
+fn sorted_transitions(
+    transitions: &HashMap<u32, HashMap<u32, u32>>,
+) -> BTreeMap<u32, BTreeMap<u32, u32>> {
+    transitions
+        .iter()
+        .map(|(&state, transitions)| {
+            let transitions = transitions.iter().map(|(&k, &v)| (k, v)).collect();
+            (state, transitions)
+        })
+        .collect()
+}
+
+/// Tokens allowed in each state of an automaton, with the states they lead to.
+///
+/// Its maps are unordered, but they are serialized sorted by state then by token, like
+/// [`Index::sorted_index`] and [`Index::allowed_tokens`] expose them, so that building the same
+/// index twice gives the same output.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Index {
     pub(crate) initial: u32,
+    #[serde(serialize_with = "serialize_sorted_set")]
     finals: HashSet<u32>,
+    #[serde(serialize_with = "serialize_sorted_transitions")]
     states_to_token_subsets: HashMap<u32, HashMap<u32, u32>>,
     eos_token_id: u32,
+    /// Automaton the index was built from, over the same states, which reads text into a state
+    /// of the index. Indexes read back from bytes do not keep it.
+    #[serde(skip)]
+    fsm_info: Option<Arc<FSMInfo>>,
 }
 
 impl Index {
@@ -48,28 +266,86 @@ impl Index {
         eos_token_id: u32,
         frozen_tokens: HashSet<String>,
     ) -> Result<Self> {
+        Self::with_options(
+            fsm_info,
+            vocabulary,
+            eos_token_id,
+            frozen_tokens,
+            &IndexOptions::default(),
+        )
+    }
+
+    pub fn with_options(
+        fsm_info: &FSMInfo,
+        vocabulary: &Vocabulary,
+        eos_token_id: u32,
+        frozen_tokens: HashSet<String>,
+        options: &IndexOptions,
+    ) -> Result<Self> {
+        let start = Instant::now();
+        let deadline = Deadline::new(options.cancellation.as_ref(), options.timeout);
+        let healed_fsm_info;
+        let fsm_info = match &options.token_healing {
+            Some(tail) => {
+                healed_fsm_info = fsm_info.with_prefix(tail);
+                &healed_fsm_info
+            }
+            None => fsm_info,
+        };
+        // Tokens are scanned over the classes of equivalent symbols, fewer keys making for
+        // fewer distinct key sequences and a smaller trie.
+        let compressed = Arc::new(fsm_info.compress_alphabet());
+        let fsm_info = &*compressed;
+        let (frozen_tokens, forbidden, allow_eos) = special_tokens(
+            vocabulary,
+            eos_token_id,
+            frozen_tokens,
+            options.special_tokens.as_ref(),
+        )?;
         let mut states_to_token_subsets: HashMap<u32, HashMap<u32, u32>> = HashMap::new();
         let mut seen: HashSet<State> = HashSet::new();
-        let mut next_states: HashSet<State> = HashSet::from([fsm_info.initial]);
+        // The states are visited lowest first, so that progress is reported the same way from
+        // one build to the next.
+        let mut next_states: BTreeSet<State> = BTreeSet::from([fsm_info.initial]);
+        let mut num_transitions = 0;
 
-        let vocabulary_transition_keys = get_vocabulary_transition_keys(
+        let vocabulary_transition_keys = vocabulary.transition_keys(
             &fsm_info.alphabet_symbol_mapping,
             fsm_info.alphabet_anything_value,
-            vocabulary,
             &frozen_tokens,
         );
+        let trie = TokenTrie::new(vocabulary, &vocabulary_transition_keys);
+        // The tokens of the states of a literal run are found together when the run is first
+        // met, and kept until each state is visited.
+        let literals = LiteralScanner::new(
+            &fsm_info.transitions,
+            vocabulary,
+            &vocabulary_transition_keys,
+        );
+        let mut literal_scans: HashMap<State, BTreeSet<(TokenId, State)>> = HashMap::new();
 
-        while let Some(start_state) = next_states.iter().cloned().next() {
-            next_states.remove(&start_state);
+        while let Some(start_state) = next_states.pop_first() {
+            deadline.check()?;
+            stats::STATES_EXPANDED.add(1);
 
-            let token_ids_end_states = state_scan_tokens(
-                &fsm_info.transitions,
-                fsm_info.initial,
-                &fsm_info.finals,
-                vocabulary,
-                &vocabulary_transition_keys,
-                start_state,
-            );
+            let mut token_ids_end_states = match literal_scans.remove(&start_state) {
+                Some(token_ids_end_states) => token_ids_end_states,
+                None => {
+                    let mut scans = literals.scan(&trie, start_state).into_iter();
+                    match scans.next() {
+                        Some((_, token_ids_end_states)) => {
+                            for (state, scan) in scans {
+                                if !seen.contains(&state) {
+                                    literal_scans.entry(state).or_insert(scan);
+                                }
+                            }
+                            token_ids_end_states
+                        }
+                        None => trie.scan(&fsm_info.transitions, start_state),
+                    }
+                }
+            };
+            token_ids_end_states.retain(|(token_id, _)| !forbidden.contains(token_id));
 
             for (token_id, end_state) in &token_ids_end_states {
                 let inner_map = states_to_token_subsets.entry(start_state).or_default();
@@ -80,12 +356,34 @@ impl Index {
                 }
             }
 
-            if fsm_info.finals.contains(&start_state) && !token_ids_end_states.is_empty() {
+            if allow_eos
+                && fsm_info.finals.contains(&start_state)
+                && !token_ids_end_states.is_empty()
+            {
                 let inner_map = states_to_token_subsets.entry(start_state).or_default();
                 inner_map.insert(eos_token_id, start_state);
             }
 
+            num_transitions += states_to_token_subsets
+                .get(&start_state)
+                .map_or(0, HashMap::len);
+            if let Some(max_bytes) = options.max_bytes {
+                let size = transitions_size(states_to_token_subsets.len(), num_transitions);
+                if size > max_bytes {
+                    return Err(crate::Error::MemoryBudgetExceeded { max_bytes, size });
+                }
+            }
+
             seen.insert(start_state);
+            if let Some(ProgressCallback(progress)) = &options.progress {
+                let progress = progress(Progress {
+                    states_processed: seen.len(),
+                    frontier: next_states.len(),
+                });
+                if progress.is_break() {
+                    return Err(crate::Error::Cancelled);
+                }
+            }
         }
 
         let is_valid = states_to_token_subsets
@@ -94,39 +392,1285 @@ impl Index {
             .any(|end_state| fsm_info.finals.contains(end_state));
 
         if is_valid {
+            stats::INDEXES_BUILT.add(1);
+            stats::INDEX_BUILD_NANOS.add(start.elapsed().as_nanos() as u64);
             Ok(Self {
                 initial: fsm_info.initial,
                 finals: fsm_info.finals.clone(),
                 states_to_token_subsets,
                 eos_token_id,
+                fsm_info: Some(Arc::clone(&compressed)),
             })
         } else {
             Err(crate::Error::IndexError)
         }
     }
 
-    pub(crate) fn allowed_tokens(&self, state: u32) -> Option<Vec<u32>> {
-        self.states_to_token_subsets
-            .get(&state)
-            .map_or_else(|| None, |res| Some(res.keys().cloned().collect()))
+    /// Tokens allowed in `state`, sorted.
+    pub fn allowed_tokens(&self, state: u32) -> Option<Vec<u32>> {
+        let mut allowed: Vec<u32> = self
+            .states_to_token_subsets
+            .get(&state)?
+            .keys()
+            .copied()
+            .collect();
+        allowed.sort_unstable();
+        Some(allowed)
     }
 
-    pub(crate) fn next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+    pub fn next_state(&self, state: u32, token_id: u32) -> Option<u32> {
         if token_id == self.eos_token_id {
             return None;
         }
         Some(*self.states_to_token_subsets.get(&state)?.get(&token_id)?)
     }
 
-    pub(crate) fn initial(&self) -> u32 {
+    pub fn initial(&self) -> u32 {
         self.initial
     }
 
-    pub(crate) fn is_final(&self, state: u32) -> bool {
+    pub fn is_final(&self, state: u32) -> bool {
         self.finals.contains(&state)
     }
 
-    pub(crate) fn index(&self) -> &HashMap<u32, HashMap<u32, u32>> {
+    /// Transitions of each state, in no particular order.
+    pub fn index(&self) -> &HashMap<u32, HashMap<u32, u32>> {
         &self.states_to_token_subsets
     }
+
+    /// Transitions of each state, sorted by state then by token.
+    pub fn sorted_index(&self) -> BTreeMap<u32, BTreeMap<u32, u32>> {
+        sorted_transitions(&self.states_to_token_subsets)
+    }
+
+    /// Serializes the index with bincode.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("the index only holds integers and maps")
+    }
+
+    /// Reads an index serialized by [`Index::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).map_err(|e| crate::Error::InvalidIndexData(e.to_string()))
+    }
+}
+
+/// Differences between two indexes, keyed by state, as computed by [`Index::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexDiff {
+    /// States with allowed tokens in the other index only, sorted.
+    pub added_states: Vec<u32>,
+    /// States with allowed tokens in this index only, sorted.
+    pub removed_states: Vec<u32>,
+    /// States with allowed tokens in both indexes which differ, sorted by state.
+    pub changed_states: Vec<StateDiff>,
+    /// States final in exactly one of the indexes, sorted.
+    pub changed_finals: Vec<u32>,
+}
+
+/// Differences between the transitions of a state in two indexes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    pub state: u32,
+    /// Tokens allowed in the other index only, with the state they lead to, sorted.
+    pub added_tokens: Vec<(u32, u32)>,
+    /// Tokens allowed in this index only, with the state they lead to, sorted.
+    pub removed_tokens: Vec<(u32, u32)>,
+    /// Tokens leading to different states, with the state of this index then of the other one.
+    pub redirected_tokens: Vec<(u32, u32, u32)>,
+}
+
+impl IndexDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_states.is_empty()
+            && self.removed_states.is_empty()
+            && self.changed_states.is_empty()
+            && self.changed_finals.is_empty()
+    }
+}
+
+impl Index {
+    /// Compares the transitions of the indexes state by state, which makes sense when both are
+    /// built from the same automaton: with the vocabularies of two tokenizers, for instance.
+    pub fn diff(&self, other: &Index) -> IndexDiff {
+        let own = &self.states_to_token_subsets;
+        let others = &other.states_to_token_subsets;
+        let sorted = |states: Vec<u32>| {
+            let mut states = states;
+            states.sort_unstable();
+            states
+        };
+
+        let mut changed_states: Vec<StateDiff> = own
+            .iter()
+            .filter_map(|(&state, transitions)| {
+                let other_transitions = others.get(&state)?;
+                let mut diff = StateDiff {
+                    state,
+                    ..Default::default()
+                };
+                for (&token_id, &next_state) in transitions {
+                    match other_transitions.get(&token_id) {
+                        None => diff.removed_tokens.push((token_id, next_state)),
+                        Some(&other_next_state) if other_next_state != next_state => diff
+                            .redirected_tokens
+                            .push((token_id, next_state, other_next_state)),
+                        Some(_) => {}
+                    }
+                }
+                for (&token_id, &next_state) in other_transitions {
+                    if !transitions.contains_key(&token_id) {
+                        diff.added_tokens.push((token_id, next_state));
+                    }
+                }
+                diff.added_tokens.sort_unstable();
+                diff.removed_tokens.sort_unstable();
+                diff.redirected_tokens.sort_unstable();
+                let unchanged = diff.added_tokens.is_empty()
+                    && diff.removed_tokens.is_empty()
+                    && diff.redirected_tokens.is_empty();
+                (!unchanged).then_some(diff)
+            })
+            .collect();
+        changed_states.sort_unstable_by_key(|diff| diff.state);
+
+        IndexDiff {
+            added_states: sorted(
+                others
+                    .keys()
+                    .filter(|state| !own.contains_key(state))
+                    .copied()
+                    .collect(),
+            ),
+            removed_states: sorted(
+                own.keys()
+                    .filter(|state| !others.contains_key(state))
+                    .copied()
+                    .collect(),
+            ),
+            changed_states,
+            changed_finals: sorted(
+                self.finals
+                    .symmetric_difference(&other.finals)
+                    .copied()
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Merges the transitions of both indexes, so that a state allows the tokens it allows in
+    /// either of them. The indexes must share their initial state and end-of-sequence token, and
+    /// a token allowed in a state by both must lead to the same state.
+    pub fn merge(&self, other: &Index) -> Result<Index> {
+        let conflict = |message: String| crate::Error::IndexMergeConflict(message);
+        if self.initial != other.initial {
+            return Err(conflict(format!(
+                "the initial states {} and {} differ",
+                self.initial, other.initial
+            )));
+        }
+        if self.eos_token_id != other.eos_token_id {
+            return Err(conflict(format!(
+                "the end-of-sequence tokens {} and {} differ",
+                self.eos_token_id, other.eos_token_id
+            )));
+        }
+
+        let mut states_to_token_subsets = self.states_to_token_subsets.clone();
+        for (&state, other_transitions) in &other.states_to_token_subsets {
+            let transitions = states_to_token_subsets.entry(state).or_default();
+            for (&token_id, &other_next_state) in other_transitions {
+                match transitions.insert(token_id, other_next_state) {
+                    Some(next_state) if next_state != other_next_state => {
+                        return Err(conflict(format!(
+                            "token {} leads from state {} to states {} and {}",
+                            token_id, state, next_state, other_next_state
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Index {
+            initial: self.initial,
+            finals: self.finals.union(&other.finals).copied().collect(),
+            states_to_token_subsets,
+            eos_token_id: self.eos_token_id,
+            fsm_info: None,
+        })
+    }
+}
+
+impl Index {
+    /// Determinizes the index into a token-level automaton whose transitions are stored in
+    /// compressed sparse rows, so that stepping through it needs no hashing.
+    pub fn to_token_dfa(&self) -> TokenDfa {
+        // States are renumbered in breadth-first order from the initial state.
+        let mut states: HashMap<u32, u32> = HashMap::from([(self.initial, 0)]);
+        let mut order = vec![self.initial];
+        let mut offsets = vec![0];
+        let mut token_ids = Vec::new();
+        let mut next_states = Vec::new();
+        let mut i = 0;
+        while let Some(&state) = order.get(i) {
+            let mut transitions: Vec<(u32, u32)> = self
+                .states_to_token_subsets
+                .get(&state)
+                .into_iter()
+                .flatten()
+                .map(|(&token_id, &next_state)| (token_id, next_state))
+                .collect();
+            transitions.sort_unstable();
+            for (token_id, next_state) in transitions {
+                let next_state = *states.entry(next_state).or_insert_with(|| {
+                    order.push(next_state);
+                    order.len() as u32 - 1
+                });
+                token_ids.push(token_id);
+                next_states.push(next_state);
+            }
+            offsets.push(token_ids.len());
+            i += 1;
+        }
+
+        let text = self.fsm_info.as_ref().map(|fsm_info| {
+            let byte_level = fsm_info
+                .alphabet_symbol_mapping
+                .keys()
+                .any(|symbol| symbol.starts_with('\0'));
+            Arc::new(TextAutomaton {
+                fsm_info: Arc::clone(fsm_info),
+                byte_level,
+                states,
+            })
+        });
+        TokenDfa {
+            finals: order
+                .iter()
+                .map(|state| self.finals.contains(state))
+                .collect(),
+            offsets,
+            token_ids,
+            next_states,
+            eos_token_id: self.eos_token_id,
+            text,
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl Index {
+    /// Exports the transitions of the index as an Arrow record batch of `state`, `token_id` and
+    /// `next_state` columns, sorted by state and token. The initial state, the final states and
+    /// the end-of-sequence token are stored in the metadata of the schema.
+    pub fn to_arrow(&self) -> arrow_array::RecordBatch {
+        use arrow_array::{ArrayRef, RecordBatch, UInt32Array};
+        use arrow_schema::{DataType, Field, Schema};
+        use std::sync::Arc;
+
+        let mut transitions: Vec<(u32, u32, u32)> = self
+            .states_to_token_subsets
+            .iter()
+            .flat_map(|(&state, token_ids_end_states)| {
+                token_ids_end_states
+                    .iter()
+                    .map(move |(&token_id, &next_state)| (state, token_id, next_state))
+            })
+            .collect();
+        transitions.sort_unstable();
+        let column = |values: Vec<u32>| Arc::new(UInt32Array::from(values)) as ArrayRef;
+
+        let mut finals: Vec<u32> = self.finals.iter().copied().collect();
+        finals.sort_unstable();
+        let metadata = HashMap::from([
+            ("initial_state".to_string(), self.initial.to_string()),
+            (
+                "final_states".to_string(),
+                finals
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            ("eos_token_id".to_string(), self.eos_token_id.to_string()),
+        ]);
+        let schema = Schema::new(vec![
+            Field::new("state", DataType::UInt32, false),
+            Field::new("token_id", DataType::UInt32, false),
+            Field::new("next_state", DataType::UInt32, false),
+        ])
+        .with_metadata(metadata);
+
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                column(transitions.iter().map(|t| t.0).collect()),
+                column(transitions.iter().map(|t| t.1).collect()),
+                column(transitions.iter().map(|t| t.2).collect()),
+            ],
+        )
+        .expect("the columns match the schema")
+    }
+}
+
+/// Table of the FlatBuffers schema in `index.fbs`, read in place from the buffer.
+#[cfg(feature = "flatbuffers")]
+pub(crate) struct IndexTable<'a>(flatbuffers::Table<'a>);
+
+#[cfg(feature = "flatbuffers")]
+impl IndexTable<'_> {
+    const FILE_IDENTIFIER: &'static str = "OCIX";
+    const INITIAL_STATE: flatbuffers::VOffsetT = 4;
+    const EOS_TOKEN_ID: flatbuffers::VOffsetT = 6;
+    const FINAL_STATES: flatbuffers::VOffsetT = 8;
+    const STATES: flatbuffers::VOffsetT = 10;
+    const OFFSETS: flatbuffers::VOffsetT = 12;
+    const TOKEN_IDS: flatbuffers::VOffsetT = 14;
+    const NEXT_STATES: flatbuffers::VOffsetT = 16;
+
+    fn scalar(&self, field: flatbuffers::VOffsetT) -> u32 {
+        // SAFETY: the buffer has been verified against the schema when the table was read.
+        unsafe { self.0.get::<u32>(field, Some(0)) }.unwrap_or_default()
+    }
+
+    fn vector(&self, field: flatbuffers::VOffsetT) -> flatbuffers::Vector<'_, u32> {
+        // SAFETY: the buffer has been verified against the schema when the table was read, and
+        // the vectors are all required.
+        unsafe {
+            self.0
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<u32>>>(field, None)
+                .unwrap()
+        }
+    }
+
+    /// Reads the table of the buffer, once checked against the schema and the layout of indexes.
+    pub(crate) fn read(bytes: &[u8]) -> Result<IndexTable<'_>> {
+        let invalid = |message: String| crate::Error::InvalidIndexData(message);
+        if bytes.len() < 8
+            || !flatbuffers::buffer_has_identifier(bytes, Self::FILE_IDENTIFIER, false)
+        {
+            return Err(invalid(
+                "Missing the file identifier of indexes".to_string(),
+            ));
+        }
+        let table = flatbuffers::root::<IndexTable>(bytes).map_err(|e| invalid(e.to_string()))?;
+
+        let states = table.vector(Self::STATES);
+        let offsets = table.vector(Self::OFFSETS);
+        let token_ids = table.vector(Self::TOKEN_IDS);
+        let increasing = |vector: flatbuffers::Vector<u32>| {
+            vector
+                .iter()
+                .zip(vector.iter().skip(1))
+                .all(|(previous, next)| previous < next)
+        };
+        let consistent = offsets.len() == states.len() + 1
+            && offsets.iter().next() == Some(0)
+            && offsets
+                .iter()
+                .zip(offsets.iter().skip(1))
+                .all(|(start, end)| start <= end)
+            && offsets.iter().next_back() == Some(token_ids.len() as u32)
+            && table.vector(Self::NEXT_STATES).len() == token_ids.len()
+            && increasing(states)
+            && increasing(table.vector(Self::FINAL_STATES));
+        if !consistent {
+            return Err(invalid(
+                "The offsets do not match the transitions of the index".to_string(),
+            ));
+        }
+        Ok(table)
+    }
+
+    pub(crate) fn initial(&self) -> u32 {
+        self.scalar(Self::INITIAL_STATE)
+    }
+
+    pub(crate) fn eos_token_id(&self) -> u32 {
+        self.scalar(Self::EOS_TOKEN_ID)
+    }
+
+    #[cfg(feature = "shared-memory")]
+    pub(crate) fn is_final(&self, state: u32) -> bool {
+        let finals = self.vector(Self::FINAL_STATES);
+        position(finals, 0..finals.len(), state).is_ok()
+    }
+
+    /// Tokens allowed in the state, with the states they lead to.
+    pub(crate) fn transitions(&self, state: u32) -> Option<impl Iterator<Item = (u32, u32)> + '_> {
+        let token_ids = self.vector(Self::TOKEN_IDS);
+        let next_states = self.vector(Self::NEXT_STATES);
+        Some(
+            self.row(state)?
+                .map(move |j| (token_ids.get(j), next_states.get(j))),
+        )
+    }
+
+    /// Positions of the transitions of the state in the vectors of tokens and next states.
+    fn row(&self, state: u32) -> Option<std::ops::Range<usize>> {
+        let states = self.vector(Self::STATES);
+        let i = position(states, 0..states.len(), state).ok()?;
+        let offsets = self.vector(Self::OFFSETS);
+        Some(offsets.get(i) as usize..offsets.get(i + 1) as usize)
+    }
+
+    #[cfg(feature = "shared-memory")]
+    pub(crate) fn next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+        if token_id == self.eos_token_id() {
+            return None;
+        }
+        let j = position(self.vector(Self::TOKEN_IDS), self.row(state)?, token_id).ok()?;
+        Some(self.vector(Self::NEXT_STATES).get(j))
+    }
+}
+
+/// Binary search of the sorted `range` of a vector, as [`slice::binary_search`].
+#[cfg(feature = "flatbuffers")]
+fn position(
+    vector: flatbuffers::Vector<u32>,
+    range: std::ops::Range<usize>,
+    value: u32,
+) -> std::result::Result<usize, usize> {
+    let (mut low, mut high) = (range.start, range.end);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match vector.get(middle).cmp(&value) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => return Ok(middle),
+        }
+    }
+    Err(low)
+}
+
+#[cfg(feature = "flatbuffers")]
+impl<'a> flatbuffers::Follow<'a> for IndexTable<'a> {
+    type Inner = Self;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        IndexTable(flatbuffers::Table::new(buf, loc))
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl flatbuffers::Verifiable for IndexTable<'_> {
+    fn run_verifier(
+        verifier: &mut flatbuffers::Verifier,
+        pos: usize,
+    ) -> std::result::Result<(), flatbuffers::InvalidFlatbuffer> {
+        type Vector<'a> = flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u32>>;
+        verifier
+            .visit_table(pos)?
+            .visit_field::<u32>("initial_state", Self::INITIAL_STATE, false)?
+            .visit_field::<u32>("eos_token_id", Self::EOS_TOKEN_ID, false)?
+            .visit_field::<Vector>("final_states", Self::FINAL_STATES, true)?
+            .visit_field::<Vector>("states", Self::STATES, true)?
+            .visit_field::<Vector>("offsets", Self::OFFSETS, true)?
+            .visit_field::<Vector>("token_ids", Self::TOKEN_IDS, true)?
+            .visit_field::<Vector>("next_states", Self::NEXT_STATES, true)?
+            .finish();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl Index {
+    /// Serializes the index as a FlatBuffer of the schema in `index.fbs`, which programs in other
+    /// languages can read in place with the code `flatc` generates for it.
+    pub fn to_flatbuffer(&self) -> Vec<u8> {
+        let mut finals: Vec<u32> = self.finals.iter().copied().collect();
+        finals.sort_unstable();
+        let mut states: Vec<u32> = self.states_to_token_subsets.keys().copied().collect();
+        states.sort_unstable();
+        let mut offsets = vec![0];
+        let mut token_ids = Vec::new();
+        let mut next_states = Vec::new();
+        for state in &states {
+            let mut transitions: Vec<(u32, u32)> = self.states_to_token_subsets[state]
+                .iter()
+                .map(|(&token_id, &next_state)| (token_id, next_state))
+                .collect();
+            transitions.sort_unstable();
+            for (token_id, next_state) in transitions {
+                token_ids.push(token_id);
+                next_states.push(next_state);
+            }
+            offsets.push(token_ids.len() as u32);
+        }
+
+        let mut builder = flatbuffers::FlatBufferBuilder::new();
+        let finals = builder.create_vector(&finals);
+        let states = builder.create_vector(&states);
+        let offsets = builder.create_vector(&offsets);
+        let token_ids = builder.create_vector(&token_ids);
+        let next_states = builder.create_vector(&next_states);
+        let table = builder.start_table();
+        builder.push_slot(IndexTable::INITIAL_STATE, self.initial, 0);
+        builder.push_slot(IndexTable::EOS_TOKEN_ID, self.eos_token_id, 0);
+        builder.push_slot_always(IndexTable::FINAL_STATES, finals);
+        builder.push_slot_always(IndexTable::STATES, states);
+        builder.push_slot_always(IndexTable::OFFSETS, offsets);
+        builder.push_slot_always(IndexTable::TOKEN_IDS, token_ids);
+        builder.push_slot_always(IndexTable::NEXT_STATES, next_states);
+        let table = builder.end_table(table);
+        builder.finish(table, Some(IndexTable::FILE_IDENTIFIER));
+        builder.finished_data().to_vec()
+    }
+
+    /// Reads an index serialized by [`Index::to_flatbuffer`].
+    pub fn from_flatbuffer(bytes: &[u8]) -> Result<Self> {
+        let table = IndexTable::read(bytes)?;
+        let states_to_token_subsets = table
+            .vector(IndexTable::STATES)
+            .iter()
+            .map(|state| {
+                (
+                    state,
+                    table.transitions(state).into_iter().flatten().collect(),
+                )
+            })
+            .collect();
+        Ok(Index {
+            initial: table.initial(),
+            finals: table.vector(IndexTable::FINAL_STATES).iter().collect(),
+            states_to_token_subsets,
+            eos_token_id: table.eos_token_id(),
+            fsm_info: None,
+        })
+    }
+}
+
+/// Token-level automaton of an [`Index`], built by [`Index::to_token_dfa`].
+///
+/// States are numbered from zero, the initial state being `0`, and the transitions of state `s`
+/// are the entries `offsets[s]..offsets[s + 1]` of `token_ids` and `next_states`, sorted by
+/// token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenDfa {
+    finals: Vec<bool>,
+    offsets: Vec<usize>,
+    token_ids: Vec<u32>,
+    next_states: Vec<u32>,
+    eos_token_id: u32,
+    /// Automaton of the characters the automaton was determinized from, see
+    /// [`TokenDfa::read_text`].
+    #[serde(skip)]
+    text: Option<Arc<TextAutomaton>>,
+}
+
+/// Character-level automaton of a [`TokenDfa`].
+#[derive(Debug)]
+struct TextAutomaton {
+    fsm_info: Arc<FSMInfo>,
+    /// Whether the characters outside of ASCII are read as their UTF-8 bytes, as in the
+    /// automata of `make_byte_level_fsm`.
+    byte_level: bool,
+    /// State of the token-level automaton that each state tokens reach is.
+    states: HashMap<State, u32>,
+}
+
+impl TokenDfa {
+    pub fn initial(&self) -> u32 {
+        0
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.finals.len()
+    }
+
+    pub fn eos_token_id(&self) -> u32 {
+        self.eos_token_id
+    }
+
+    pub fn is_final(&self, state: u32) -> bool {
+        self.finals.get(state as usize).copied().unwrap_or(false)
+    }
+
+    /// Tokens allowed in the state, sorted.
+    pub fn allowed_tokens(&self, state: u32) -> &[u32] {
+        match self.row(state) {
+            Some(row) => &self.token_ids[row],
+            None => &[],
+        }
+    }
+
+    pub fn next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+        if token_id == self.eos_token_id {
+            return None;
+        }
+        let row = self.row(state)?;
+        let position = self.token_ids[row.clone()].binary_search(&token_id).ok()?;
+        Some(self.next_states[row.start + position])
+    }
+
+    /// Sets the logits of the tokens not allowed in the state to negative infinity.
+    pub fn apply_mask(&self, logits: &mut [f32], state: u32) -> Result<()> {
+        stats::MASKS_SERVED.add(1);
+        let allowed_tokens = self.allowed_tokens(state);
+        // The tokens are sorted, so that checking the last one is enough.
+        if let Some(&token_id) = allowed_tokens
+            .last()
+            .filter(|&&last| last as usize >= logits.len())
+        {
+            return Err(crate::Error::TokenOutsideMask {
+                token_id,
+                vocab_size: logits.len(),
+            });
+        }
+        let mut start = 0;
+        for &token_id in allowed_tokens {
+            logits[start..token_id as usize].fill(f32::NEG_INFINITY);
+            start = token_id as usize + 1;
+        }
+        logits[start..].fill(f32::NEG_INFINITY);
+        Ok(())
+    }
+
+    /// Reads `text` from the initial state with the character-level automaton the index was
+    /// built from, whatever the tokens the text is split into.
+    ///
+    /// Returns the state reached by the longest prefix of `text` that tokens can stop at,
+    /// and the length in bytes of that prefix. The rest of the text, usually the beginning
+    /// of a token, is left to the caller.
+    pub fn read_text(&self, text: &str) -> Result<(u32, usize)> {
+        let automaton = self
+            .text
+            .as_ref()
+            .ok_or(crate::Error::MissingTextAutomaton)?;
+        let fsm_info = &automaton.fsm_info;
+        let mut state = fsm_info.initial;
+        let mut reached = (0, 0);
+        let mut buffer = [0; 4];
+        for (valid_up_to, (offset, c)) in text.char_indices().enumerate() {
+            let symbols = match c.is_ascii() || !automaton.byte_level {
+                true => c.to_string(),
+                false => byte_symbols(c.encode_utf8(&mut buffer).as_bytes()),
+            };
+            state = get_token_transition_keys(
+                &fsm_info.alphabet_symbol_mapping,
+                fsm_info.alphabet_anything_value,
+                &symbols,
+            )
+            .iter()
+            .try_fold(state, |state, key| {
+                fsm_info.transitions.get(&(state, *key)).copied()
+            })
+            .ok_or(crate::Error::TextMismatch { valid_up_to })?;
+            if let Some(&dfa_state) = automaton.states.get(&state) {
+                reached = (dfa_state, offset + c.len_utf8());
+            }
+        }
+        Ok(reached)
+    }
+
+    fn row(&self, state: u32) -> Option<std::ops::Range<usize>> {
+        let state = state as usize;
+        Some(*self.offsets.get(state)?..*self.offsets.get(state + 1)?)
+    }
+}
+
+impl Index {
+    /// Materializes the tokens allowed in every state as bitmasks of `vocab_size` tokens, stored
+    /// contiguously so that they can be uploaded to an accelerator once.
+    pub fn to_mask_table(&self, vocab_size: usize) -> Result<MaskTable> {
+        let words_per_row = vocab_size.div_ceil(32);
+        let mut states: Vec<u32> = self.states_to_token_subsets.keys().copied().collect();
+        states.sort_unstable();
+        let num_states = self
+            .states_to_token_subsets
+            .values()
+            .flat_map(|transitions| transitions.values())
+            .chain(&self.finals)
+            .chain(&states)
+            .fold(self.initial, |max, &state| max.max(state)) as usize
+            + 1;
+
+        // The last row is left empty, for the states which allow no token.
+        let mut rows = vec![states.len() as u32; num_states];
+        let mut masks = vec![0; (states.len() + 1) * words_per_row];
+        for (row, state) in states.iter().enumerate() {
+            rows[*state as usize] = row as u32;
+            for &token_id in self.states_to_token_subsets[state].keys() {
+                if token_id as usize >= vocab_size {
+                    return Err(crate::Error::TokenOutsideMask {
+                        token_id,
+                        vocab_size,
+                    });
+                }
+                masks[row * words_per_row + token_id as usize / 32] |= 1 << (token_id % 32);
+            }
+        }
+        Ok(MaskTable {
+            masks,
+            rows,
+            num_rows: states.len() + 1,
+            words_per_row,
+        })
+    }
+}
+
+/// Bitmasks of the tokens allowed in the states of an [`Index`], built by
+/// [`Index::to_mask_table`].
+///
+/// The masks are the rows of a `num_rows × words_per_row` matrix stored in row-major order, token
+/// `i` being bit `i % 32` of the word `i / 32` of a row. The states are mapped to their rows by
+/// `rows`, whose last row is empty and shared by all the states which allow no token.
+#[derive(Debug, Clone)]
+pub struct MaskTable {
+    masks: Vec<u32>,
+    rows: Vec<u32>,
+    num_rows: usize,
+    words_per_row: usize,
+}
+
+impl MaskTable {
+    pub fn masks(&self) -> &[u32] {
+        &self.masks
+    }
+
+    /// Row of each state, indexed by state.
+    pub fn rows(&self) -> &[u32] {
+        &self.rows
+    }
+
+    pub fn words_per_row(&self) -> usize {
+        self.words_per_row
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Mask of the state, empty for the states the index does not have.
+    pub fn mask(&self, state: u32) -> &[u32] {
+        stats::MASKS_SERVED.add(1);
+        let row = self
+            .rows
+            .get(state as usize)
+            .map_or(self.num_rows - 1, |&row| row as usize);
+        &self.masks[row * self.words_per_row..(row + 1) * self.words_per_row]
+    }
+}
+
+/// Builds the index of the constraint to write one of `choices`, straight from a trie of the
+/// choices rather than from an automaton.
+///
+/// The states are the prefixes of the choices, and the tokens of a state are the ones that
+/// extend its prefix into another prefix. Tokens are compared to the choices as they are, so the
+/// choices must be written with the symbols of the vocabulary, such as the byte symbols of
+/// [`crate::fsm::byte_symbol`] for a byte-level vocabulary.
+pub fn build_choice_index(
+    choices: Vec<String>,
+    vocabulary: &Vocabulary,
+    eos_token_id: u32,
+) -> Result<Index> {
+    let start = Instant::now();
+    let initial: State = 0;
+    let mut trie: Vec<HashMap<char, State>> = vec![HashMap::new()];
+    let mut finals: HashSet<State> = HashSet::new();
+    for choice in &choices {
+        let mut state = initial;
+        for c in choice.chars() {
+            let next_state = trie.len() as State;
+            state = *trie[state as usize].entry(c).or_insert(next_state);
+            if state == next_state {
+                trie.push(HashMap::new());
+            }
+        }
+        finals.insert(state);
+    }
+
+    let mut tokens_by_first_char: HashMap<char, Vec<(&str, &[u32])>> = HashMap::new();
+    for (token, token_ids) in vocabulary.iter() {
+        if let Some(c) = token.chars().next() {
+            tokens_by_first_char
+                .entry(c)
+                .or_default()
+                .push((token, token_ids));
+        }
+    }
+
+    let mut states_to_token_subsets: HashMap<u32, HashMap<u32, u32>> = HashMap::new();
+    let mut seen: HashSet<State> = HashSet::from([initial]);
+    let mut next_states: Vec<State> = vec![initial];
+    while let Some(start_state) = next_states.pop() {
+        stats::STATES_EXPANDED.add(1);
+        let mut token_ids_end_states: HashMap<u32, u32> = HashMap::new();
+        for c in trie[start_state as usize].keys() {
+            for &(token, token_ids) in tokens_by_first_char.get(c).into_iter().flatten() {
+                let end_state = token.chars().try_fold(start_state, |state, c| {
+                    trie[state as usize].get(&c).copied()
+                });
+                if let Some(end_state) = end_state {
+                    for &token_id in token_ids {
+                        token_ids_end_states.insert(token_id, end_state);
+                    }
+                    if seen.insert(end_state) {
+                        next_states.push(end_state);
+                    }
+                }
+            }
+        }
+
+        if finals.contains(&start_state) && !token_ids_end_states.is_empty() {
+            token_ids_end_states.insert(eos_token_id, start_state);
+        }
+        if !token_ids_end_states.is_empty() {
+            states_to_token_subsets.insert(start_state, token_ids_end_states);
+        }
+    }
+
+    let is_valid = states_to_token_subsets
+        .values()
+        .flat_map(|token_id_end_states| token_id_end_states.values())
+        .any(|end_state| finals.contains(end_state));
+
+    if is_valid {
+        stats::INDEXES_BUILT.add(1);
+        stats::INDEX_BUILD_NANOS.add(start.elapsed().as_nanos() as u64);
+        Ok(Index {
+            initial,
+            finals,
+            states_to_token_subsets,
+            eos_token_id,
+            fsm_info: None,
+        })
+    } else {
+        Err(crate::Error::IndexError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choice_index() {
+        let vocabulary = Vocabulary::new()
+            .insert("yes", 1)
+            .insert("y", 2)
+            .insert("es", 3)
+            .insert("no", 4)
+            .insert("n", 5)
+            .insert("o", 6)
+            .insert("ter", 7)
+            .insert("day", 8)
+            .insert("maybe", 9);
+        let index = build_choice_index(
+            vec!["yes".into(), "no".into(), "yesterday".into()],
+            &vocabulary,
+            0,
+        )
+        .unwrap();
+
+        let initial = index.initial();
+        let mut allowed = index.allowed_tokens(initial).unwrap();
+        allowed.sort_unstable();
+        assert_eq!(allowed, vec![1, 2, 4, 5]);
+
+        let yes = index.next_state(initial, 1).unwrap();
+        assert_eq!(
+            index.next_state(index.next_state(initial, 2).unwrap(), 3),
+            Some(yes)
+        );
+        assert!(index.is_final(yes));
+        let mut allowed = index.allowed_tokens(yes).unwrap();
+        allowed.sort_unstable();
+        assert_eq!(allowed, vec![0, 7]);
+
+        let yesterday = index
+            .next_state(index.next_state(yes, 7).unwrap(), 8)
+            .unwrap();
+        assert!(index.is_final(yesterday));
+        assert_eq!(index.next_state(initial, 9), None);
+
+        assert!(build_choice_index(vec!["maybe not".into()], &vocabulary, 0).is_err());
+    }
+
+    #[test]
+    fn token_dfa() {
+        let fsm_info = FSMInfo::from_regex("(ab|c)+").unwrap();
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("b", 2)
+            .insert("ab", 3)
+            .insert("c", 4)
+            .insert("d", 5);
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let dfa = index.to_token_dfa();
+
+        assert_eq!(dfa.allowed_tokens(dfa.initial()), &[1, 3, 4]);
+        assert!(!dfa.is_final(dfa.initial()));
+        let a = dfa.next_state(dfa.initial(), 1).unwrap();
+        assert_eq!(dfa.allowed_tokens(a), &[2]);
+        let ab = dfa.next_state(a, 2).unwrap();
+        assert!(dfa.is_final(ab));
+        assert_eq!(dfa.allowed_tokens(ab), &[0, 1, 3, 4]);
+        assert_eq!(dfa.next_state(ab, 0), None);
+        assert_eq!(dfa.next_state(ab, 5), None);
+        assert_eq!(dfa.next_state(dfa.initial(), 3), Some(ab));
+        assert_eq!(dfa.allowed_tokens(dfa.num_states() as u32), &[] as &[u32]);
+
+        // Every transition of the index is kept.
+        let transitions: usize = index.index().values().map(HashMap::len).sum();
+        assert_eq!(
+            (0..dfa.num_states() as u32)
+                .map(|state| dfa.allowed_tokens(state).len())
+                .sum::<usize>(),
+            transitions
+        );
+    }
+
+    #[test]
+    fn diff_and_merge() {
+        let fsm_info = FSMInfo::from_regex("ab|cd").unwrap();
+        let index = |vocabulary: Vocabulary, eos_token_id: u32| {
+            Index::new(&fsm_info, &vocabulary, eos_token_id, HashSet::new()).unwrap()
+        };
+        let ab = index(Vocabulary::new().insert("a", 1).insert("b", 2), 0);
+        let abcd = index(
+            Vocabulary::new()
+                .insert("a", 1)
+                .insert("b", 2)
+                .insert("c", 3)
+                .insert("d", 4),
+            0,
+        );
+        assert!(ab.diff(&ab).is_empty());
+
+        let c = abcd.next_state(abcd.initial(), 3).unwrap();
+        let diff = ab.diff(&abcd);
+        assert_eq!(diff.added_states, vec![c]);
+        assert!(diff.removed_states.is_empty());
+        assert!(diff.changed_finals.is_empty());
+        assert_eq!(
+            diff.changed_states,
+            vec![StateDiff {
+                state: ab.initial(),
+                added_tokens: vec![(3, c)],
+                ..Default::default()
+            }]
+        );
+        assert_eq!(abcd.diff(&ab).removed_states, vec![c]);
+
+        let merged = ab.merge(&abcd).unwrap();
+        assert!(merged.diff(&abcd).is_empty());
+        assert!(ab
+            .merge(&index(Vocabulary::new().insert("a", 1).insert("b", 2), 5))
+            .is_err());
+        assert!(ab
+            .merge(&index(Vocabulary::new().insert("c", 1).insert("d", 2), 0))
+            .is_err());
+    }
+
+    #[test]
+    fn cancellation() {
+        let vocabulary = Vocabulary::new().insert("a", 1);
+        let fsm_info = FSMInfo::from_regex("a+").unwrap();
+        let token = CancellationToken::new();
+        let options = IndexOptions::new()
+            .cancellation(token.clone())
+            .timeout(Duration::from_secs(3600));
+        assert!(Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options).is_ok());
+
+        token.cancel();
+        assert!(matches!(
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options),
+            Err(crate::Error::Cancelled)
+        ));
+        let options = IndexOptions::new().timeout(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(matches!(
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options),
+            Err(crate::Error::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn max_bytes() {
+        let vocabulary = Vocabulary::new().insert("a", 1).insert("b", 2);
+        let fsm_info = FSMInfo::from_regex("(ab)+").unwrap();
+        let size = transitions_size(4, 5);
+        let options = IndexOptions::new().max_bytes(size);
+        assert!(Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options).is_ok());
+
+        let options = IndexOptions::new().max_bytes(size - 1);
+        assert!(matches!(
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options),
+            Err(crate::Error::MemoryBudgetExceeded { max_bytes, size: reached })
+                if max_bytes == size - 1 && reached == size
+        ));
+    }
+
+    #[test]
+    fn progress() {
+        let vocabulary = Vocabulary::new().insert("a", 1).insert("b", 2);
+        let fsm_info = FSMInfo::from_regex("a|ab|abb").unwrap();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = IndexOptions::new().progress({
+            let reports = reports.clone();
+            move |progress| {
+                reports.lock().unwrap().push(progress);
+                ControlFlow::Continue(())
+            }
+        });
+        let index =
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options).unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(
+            reports
+                .iter()
+                .map(|p| p.states_processed)
+                .collect::<Vec<_>>(),
+            (1..=4).collect::<Vec<_>>()
+        );
+        assert_eq!(reports.last().unwrap().frontier, 0);
+        assert_eq!(index.index().len(), 3);
+
+        let options = IndexOptions::new().progress(|progress| match progress.states_processed {
+            2 => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        });
+        assert!(matches!(
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options),
+            Err(crate::Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn special_tokens_policy() {
+        // `a+`, optionally opened by a tool-call token.
+        let fsm_info = FSMInfo::new(
+            0,
+            HashSet::from([1]),
+            HashMap::from([((0, 0), 1), ((0, 1), 2), ((2, 0), 1), ((1, 0), 1)]),
+            2,
+            HashMap::from([("a".to_string(), 0), ("<tool>".to_string(), 1)]),
+        );
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("<tool>", 2)
+            .insert("<pad>", 3);
+        let build = |frozen_tokens: &[&str], policy: Option<SpecialTokensPolicy>| {
+            let frozen_tokens = frozen_tokens.iter().map(|t| t.to_string()).collect();
+            let options = match policy {
+                Some(policy) => IndexOptions::new().special_tokens(policy),
+                None => IndexOptions::new(),
+            };
+            Index::with_options(&fsm_info, &vocabulary, 0, frozen_tokens, &options)
+                .map(|index| index.sorted_index())
+        };
+        let transitions = |pairs: &[(u32, &[(u32, u32)])]| {
+            pairs
+                .iter()
+                .map(|&(state, tokens)| (state, tokens.iter().copied().collect()))
+                .collect::<BTreeMap<u32, BTreeMap<u32, u32>>>()
+        };
+
+        assert_eq!(
+            build(&["<tool>"], None).unwrap(),
+            transitions(&[
+                (0, &[(1, 1), (2, 2)]),
+                (1, &[(0, 1), (1, 1)]),
+                (2, &[(1, 1)])
+            ])
+        );
+        assert_eq!(
+            build(&["<tool>"], Some(SpecialTokensPolicy::ForbidAll)).unwrap(),
+            transitions(&[(0, &[(1, 1)]), (1, &[(1, 1)])])
+        );
+        assert_eq!(
+            build(&["<tool>"], Some(SpecialTokensPolicy::EosOnly)).unwrap(),
+            transitions(&[(0, &[(1, 1)]), (1, &[(0, 1), (1, 1)])])
+        );
+        assert_eq!(
+            build(&[], Some(SpecialTokensPolicy::Allow(vec![2]))).unwrap(),
+            transitions(&[(0, &[(1, 1), (2, 2)]), (1, &[(1, 1)]), (2, &[(1, 1)])])
+        );
+        assert_eq!(
+            build(&[], Some(SpecialTokensPolicy::Allow(vec![0, 2]))).unwrap(),
+            build(&["<tool>"], None).unwrap()
+        );
+        assert!(matches!(
+            build(&[], Some(SpecialTokensPolicy::Allow(vec![7]))),
+            Err(crate::Error::UnknownSpecialToken { token_id: 7 })
+        ));
+    }
+
+    #[test]
+    fn token_healing() {
+        // The prompt `Go to http` is cut back by its last token, ` http`, which the output writes
+        // again before the text of the constraint.
+        let vocabulary = Vocabulary::new()
+            .insert(" ", 1)
+            .insert(" h", 2)
+            .insert(" http", 3)
+            .insert(" https", 4)
+            .insert("s", 5)
+            .insert("://", 6)
+            .insert("x", 7);
+        let fsm_info = FSMInfo::from_regex("s?://x").unwrap();
+        let options = IndexOptions::new().token_healing(" http");
+        let index =
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options).unwrap();
+
+        let initial = index.initial();
+        assert_eq!(index.allowed_tokens(initial).unwrap(), [1, 2, 3, 4]);
+        let https = index.next_state(initial, 4).unwrap();
+        assert_eq!(index.allowed_tokens(https).unwrap(), [6]);
+        let http = index.next_state(initial, 3).unwrap();
+        assert_eq!(index.allowed_tokens(http).unwrap(), [5, 6]);
+
+        let options = IndexOptions::new().token_healing(" h");
+        let index =
+            Index::with_options(&fsm_info, &vocabulary, 0, HashSet::new(), &options).unwrap();
+        assert_eq!(index.allowed_tokens(index.initial()).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let vocabulary = Vocabulary::from_iter(
+            (0..50).map(|token_id| (format!("{}", token_id % 10), vec![token_id + 1])),
+        );
+        let fsm_info = FSMInfo::from_regex("[0-9]{1,4}").unwrap();
+        let build = || Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let index = build();
+
+        let bytes = index.to_bytes();
+        for _ in 0..5 {
+            assert_eq!(build().to_bytes(), bytes);
+        }
+        let read = Index::from_bytes(&bytes).unwrap();
+        assert_eq!(read.index(), index.index());
+        assert_eq!(read.finals, index.finals);
+
+        let allowed = index.allowed_tokens(index.initial()).unwrap();
+        assert!(allowed.windows(2).all(|pair| pair[0] < pair[1]));
+        let sorted = index.sorted_index();
+        assert_eq!(sorted.len(), index.index().len());
+        assert_eq!(
+            sorted[&index.initial()],
+            index.index()[&index.initial()]
+                .clone()
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn apply_mask() {
+        let vocabulary = Vocabulary::new().insert("a", 1).insert("b", 3);
+        let fsm_info = FSMInfo::from_regex("a|b").unwrap();
+        let dfa = Index::new(&fsm_info, &vocabulary, 0, HashSet::new())
+            .unwrap()
+            .to_token_dfa();
+
+        let mut logits = [0.5; 5];
+        dfa.apply_mask(&mut logits, dfa.initial()).unwrap();
+        let inf = f32::NEG_INFINITY;
+        assert_eq!(logits, [inf, 0.5, inf, 0.5, inf]);
+        let mut short = [0.5; 3];
+        assert!(dfa.apply_mask(&mut short, dfa.initial()).is_err());
+        assert_eq!(short, [0.5; 3]);
+        dfa.apply_mask(&mut logits, 42).unwrap();
+        assert_eq!(logits, [inf; 5]);
+    }
+
+    #[test]
+    fn mask_table() {
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("b", 33)
+            .insert("ab", 40);
+        let fsm_info = FSMInfo::from_regex("ab").unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let table = index.to_mask_table(64).unwrap();
+
+        assert_eq!(table.words_per_row(), 2);
+        assert_eq!(table.masks().len(), table.num_rows() * 2);
+        assert_eq!(table.mask(index.initial()), &[1 << 1, 1 << 8]);
+        let a = index.next_state(index.initial(), 1).unwrap();
+        assert_eq!(table.mask(a), &[0, 1 << 1]);
+        let ab = index.next_state(a, 33).unwrap();
+        // The final state allows no token, not even the end-of-sequence one.
+        assert_eq!(table.mask(ab), &[0, 0]);
+        assert_eq!(table.mask(1000), &[0, 0]);
+        for &state in &[index.initial(), a, ab] {
+            let row = table.rows()[state as usize] as usize;
+            assert_eq!(&table.masks()[row * 2..row * 2 + 2], table.mask(state));
+        }
+
+        assert!(matches!(
+            index.to_mask_table(40),
+            Err(crate::Error::TokenOutsideMask { token_id: 40, .. })
+        ));
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn to_arrow() {
+        use arrow_array::{cast::AsArray, types::UInt32Type};
+
+        let vocabulary = Vocabulary::new().insert("a", 1).insert("b", 2);
+        let fsm_info = FSMInfo::from_regex("ab?").unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let batch = index.to_arrow();
+
+        let column = |name: &str| -> Vec<u32> {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_primitive::<UInt32Type>()
+                .values()
+                .to_vec()
+        };
+        let a = index.next_state(index.initial(), 1).unwrap();
+        let ab = index.next_state(a, 2).unwrap();
+        let mut expected = [(index.initial(), 1, a), (a, 0, a), (a, 2, ab)];
+        expected.sort_unstable();
+        assert_eq!(
+            column("state"),
+            expected.iter().map(|t| t.0).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            column("token_id"),
+            expected.iter().map(|t| t.1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            column("next_state"),
+            expected.iter().map(|t| t.2).collect::<Vec<_>>()
+        );
+
+        let metadata = batch.schema_ref().metadata();
+        assert_eq!(metadata["initial_state"], index.initial().to_string());
+        assert_eq!(metadata["eos_token_id"], "0");
+        let mut finals = [a, ab];
+        finals.sort_unstable();
+        assert_eq!(
+            metadata["final_states"],
+            format!("{},{}", finals[0], finals[1])
+        );
+    }
+
+    #[cfg(feature = "flatbuffers")]
+    #[test]
+    fn flatbuffer() {
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("b", 2)
+            .insert("ab", 3);
+        let fsm_info = FSMInfo::from_regex("(ab)+").unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+
+        let bytes = index.to_flatbuffer();
+        let read = Index::from_flatbuffer(&bytes).unwrap();
+        assert_eq!(read.initial(), index.initial());
+        assert_eq!(read.finals, index.finals);
+        assert_eq!(read.index(), index.index());
+        assert_eq!(read.eos_token_id, index.eos_token_id);
+
+        assert!(Index::from_flatbuffer(&index.to_bytes()).is_err());
+        assert!(Index::from_flatbuffer(&bytes[..bytes.len() / 2]).is_err());
+        assert!(Index::from_flatbuffer(&bytes[..4]).is_err());
+    }
 }
@@ -0,0 +1,234 @@
+//! Entry points of the `ai.dottxt.outlines.OutlinesCore` Java class.
+//!
+//! Indexes are handed to the JVM as opaque handles, which must be released with `freeIndex`.
+use crate::index::{FSMInfo, Index};
+use crate::json_schema;
+use crate::prelude::*;
+use anyhow::{anyhow, Result};
+use jni::objects::{JByteBuffer, JClass, JIntArray, JObjectArray, JString};
+use jni::sys::{jboolean, jint, jintArray, jlong, jstring, JNI_FALSE};
+use jni::JNIEnv;
+use std::collections::{HashMap, HashSet};
+
+/// Runs `f`, throwing an `IllegalArgumentException` and returning `default` if it fails.
+fn run<'local, T>(
+    env: &mut JNIEnv<'local>,
+    default: T,
+    f: impl FnOnce(&mut JNIEnv<'local>) -> Result<T>,
+) -> T {
+    match f(env) {
+        Ok(value) => value,
+        Err(e) => {
+            // Errors raised by the JVM itself are already pending.
+            if !env.exception_check().unwrap_or(true) {
+                let _ = env.throw_new("java/lang/IllegalArgumentException", e.to_string());
+            }
+            default
+        }
+    }
+}
+
+fn get_ints(env: &mut JNIEnv, array: &JIntArray) -> Result<Vec<u32>> {
+    let mut ints = vec![0; env.get_array_length(array)? as usize];
+    env.get_int_array_region(array, 0, &mut ints)?;
+    Ok(ints.into_iter().map(|i| i as u32).collect())
+}
+
+fn get_strings(env: &mut JNIEnv, array: &JObjectArray) -> Result<Vec<String>> {
+    let length = env.get_array_length(array)?;
+    let mut strings = Vec::with_capacity(length as usize);
+    for i in 0..length {
+        let string = JString::from(env.get_object_array_element(array, i)?);
+        strings.push(env.get_string(&string)?.into());
+    }
+    Ok(strings)
+}
+
+fn get_index<'a>(handle: jlong) -> Result<&'a Index> {
+    // SAFETY: non-null handles are only created by `newIndex`, and stay valid until `freeIndex`.
+    unsafe { (handle as *const Index).as_ref() }.ok_or_else(|| anyhow!("Invalid index handle"))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_buildRegexFromSchema<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    json: JString<'local>,
+    whitespace_pattern: JString<'local>,
+) -> jstring {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let json: String = env.get_string(&json)?.into();
+        let mut options = json_schema::Options::new();
+        if !whitespace_pattern.is_null() {
+            options = options.whitespace_pattern(env.get_string(&whitespace_pattern)?);
+        }
+        let regex = json_schema::build_regex_from_schema_with_options(&json, &options)?;
+        Ok(env.new_string(regex)?.into_raw())
+    })
+}
+
+/// Builds an index from the automaton and the vocabulary, and returns its handle.
+///
+/// `transitions` holds the `fromState, transitionKey, toState` triples of the automaton one after
+/// the other, `symbols` and `symbolKeys` its alphabet, and `tokens` and `tokenIds` the pairs of the
+/// vocabulary.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_newIndex<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    initial: jint,
+    finals: JIntArray<'local>,
+    transitions: JIntArray<'local>,
+    alphabet_anything_value: jint,
+    symbols: JObjectArray<'local>,
+    symbol_keys: JIntArray<'local>,
+    tokens: JObjectArray<'local>,
+    token_ids: JIntArray<'local>,
+    eos_token_id: jint,
+) -> jlong {
+    run(&mut env, 0, |env| {
+        let transitions = get_ints(env, &transitions)?;
+        if transitions.len() % 3 != 0 {
+            return Err(anyhow!(
+                "Transitions must be fromState, transitionKey, toState triples"
+            ));
+        }
+        let transitions: HashMap<(State, TransitionKey), State> = transitions
+            .chunks(3)
+            .map(|transition| ((transition[0], transition[1]), transition[2]))
+            .collect();
+
+        let symbols = get_strings(env, &symbols)?;
+        let symbol_keys = get_ints(env, &symbol_keys)?;
+        if symbols.len() != symbol_keys.len() {
+            return Err(anyhow!("Each symbol must have a transition key"));
+        }
+
+        let tokens = get_strings(env, &tokens)?;
+        let token_ids = get_ints(env, &token_ids)?;
+        if tokens.len() != token_ids.len() {
+            return Err(anyhow!("Each token must have an id"));
+        }
+        let mut vocabulary: HashMap<Token, Vec<TokenId>> = HashMap::new();
+        for (token, token_id) in tokens.into_iter().zip(token_ids) {
+            vocabulary.entry(token).or_default().push(token_id);
+        }
+
+        let fsm_info = FSMInfo::new(
+            initial as State,
+            get_ints(env, &finals)?.into_iter().collect(),
+            transitions,
+            alphabet_anything_value as TransitionKey,
+            symbols.into_iter().zip(symbol_keys).collect(),
+        );
+        let index = Index::new(
+            &fsm_info,
+            &Vocabulary::from(vocabulary),
+            eos_token_id as u32,
+            HashSet::new(),
+        )?;
+        Ok(Box::into_raw(Box::new(index)) as jlong)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_freeIndex<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+) {
+    if handle != 0 {
+        // SAFETY: the handle was created by `newIndex`, and the Java side only frees it once.
+        drop(unsafe { Box::from_raw(handle as *mut Index) });
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_initialState<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+) -> jint {
+    run(&mut env, -1, |_| Ok(get_index(handle)?.initial() as jint))
+}
+
+/// Returns the state reached by generating `tokenId` in `state`, or -1 if it is not allowed.
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_nextState<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+    state: jint,
+    token_id: jint,
+) -> jint {
+    run(&mut env, -1, |_| {
+        Ok(get_index(handle)?
+            .next_state(state as u32, token_id as u32)
+            .map_or(-1, |next_state| next_state as jint))
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_isFinalState<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+    state: jint,
+) -> jboolean {
+    run(&mut env, JNI_FALSE, |_| {
+        Ok(get_index(handle)?.is_final(state as u32).into())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_allowedTokens<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+    state: jint,
+) -> jintArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let allowed_tokens: Vec<jint> = get_index(handle)?
+            .allowed_tokens(state as u32)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|token_id| token_id as jint)
+            .collect();
+        let array = env.new_int_array(allowed_tokens.len() as jint)?;
+        env.set_int_array_region(&array, 0, &allowed_tokens)?;
+        Ok(array.into_raw())
+    })
+}
+
+/// Writes the tokens allowed in `state` as a bitmask into the direct buffer `mask`, token `i`
+/// being bit `i % 8` of byte `i / 8`, so that the mask never goes through the Java heap.
+#[no_mangle]
+pub extern "system" fn Java_ai_dottxt_outlines_OutlinesCore_writeMask<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: jlong,
+    state: jint,
+    mask: JByteBuffer<'local>,
+) {
+    run(&mut env, (), |env| {
+        let index = get_index(handle)?;
+        let address = env.get_direct_buffer_address(&mask)?;
+        let capacity = env.get_direct_buffer_capacity(&mask)?;
+        // SAFETY: the JVM guarantees that a direct buffer spans `capacity` bytes from `address`.
+        let mask = unsafe { std::slice::from_raw_parts_mut(address, capacity) };
+
+        mask.fill(0);
+        for token_id in index.allowed_tokens(state as u32).unwrap_or_default() {
+            let byte = mask.get_mut(token_id as usize / 8).ok_or_else(|| {
+                anyhow!(
+                    "Token {} does not fit in a mask of {} bytes",
+                    token_id,
+                    capacity
+                )
+            })?;
+            *byte |= 1 << (token_id % 8);
+        }
+        Ok(())
+    })
+}
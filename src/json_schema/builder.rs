@@ -0,0 +1,295 @@
+/// Typed construction of JSON Schemas in code.
+use super::{to_regex_with_options, Options};
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+/// JSON Schema built with [`SchemaBuilder`], ready to be compiled.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::json_schema::{Options, SchemaBuilder, Str};
+/// let schema = SchemaBuilder::object()
+///     .property("name", Str::new().min_len(1))
+///     .property("age", SchemaBuilder::integer().minimum(0))
+///     .required(["name"])
+///     .build();
+/// let regex = schema.to_regex(&Options::new()).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema(Value);
+
+impl Schema {
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+
+    pub fn into_value(self) -> Value {
+        self.0
+    }
+
+    pub fn to_regex(&self, options: &Options) -> Result<String> {
+        to_regex_with_options(&self.0, &self.0, options)
+    }
+}
+
+/// Entry points of the builders of each type of schema.
+pub struct SchemaBuilder;
+
+impl SchemaBuilder {
+    pub fn object() -> Object {
+        Object::new()
+    }
+
+    pub fn array(items: impl Into<Schema>) -> Array {
+        Array::new().items(items)
+    }
+
+    pub fn string() -> Str {
+        Str::new()
+    }
+
+    pub fn integer() -> Int {
+        Int::new()
+    }
+
+    pub fn number() -> Num {
+        Num::new()
+    }
+
+    pub fn boolean() -> Schema {
+        Schema(json!({"type": "boolean"}))
+    }
+
+    pub fn null() -> Schema {
+        Schema(json!({"type": "null"}))
+    }
+
+    /// Schema of the given values only.
+    pub fn enumeration(values: impl IntoIterator<Item = impl Into<Value>>) -> Schema {
+        let values: Vec<Value> = values.into_iter().map(Into::into).collect();
+        Schema(json!({ "enum": values }))
+    }
+
+    pub fn constant(value: impl Into<Value>) -> Schema {
+        Schema(json!({ "const": value.into() }))
+    }
+
+    /// Schema of the values matching any of the given schemas.
+    pub fn any_of(schemas: impl IntoIterator<Item = impl Into<Schema>>) -> Schema {
+        let schemas: Vec<Value> = schemas.into_iter().map(|s| s.into().0).collect();
+        Schema(json!({ "anyOf": schemas }))
+    }
+}
+
+/// Builder of a schema whose keywords are set one after the other.
+macro_rules! keyword_builder {
+    ($(#[$meta:meta])* $name:ident, $type:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name(Map<String, Value>);
+
+        impl $name {
+            pub fn new() -> Self {
+                let mut keywords = Map::new();
+                keywords.insert("type".to_string(), Value::from($type));
+                $name(keywords)
+            }
+
+            fn set(mut self, keyword: &str, value: impl Into<Value>) -> Self {
+                self.0.insert(keyword.to_string(), value.into());
+                self
+            }
+
+            pub fn build(self) -> Schema {
+                self.into()
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl From<$name> for Schema {
+            fn from(builder: $name) -> Self {
+                Schema(Value::Object(builder.0))
+            }
+        }
+    };
+}
+
+keyword_builder!(
+    /// Builder of the schema of an object, whose properties come in the order they are added.
+    Object,
+    "object"
+);
+keyword_builder!(
+    /// Builder of the schema of a string.
+    Str,
+    "string"
+);
+keyword_builder!(
+    /// Builder of the schema of an integer.
+    Int,
+    "integer"
+);
+keyword_builder!(
+    /// Builder of the schema of a number.
+    Num,
+    "number"
+);
+keyword_builder!(
+    /// Builder of the schema of an array.
+    Array,
+    "array"
+);
+
+impl Object {
+    pub fn property(mut self, name: impl Into<String>, schema: impl Into<Schema>) -> Self {
+        let properties = self
+            .0
+            .entry("properties")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(properties) = properties {
+            properties.insert(name.into(), schema.into().0);
+        }
+        self
+    }
+
+    /// Adds required properties to the ones already required.
+    pub fn required(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let required = self
+            .0
+            .entry("required")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(required) = required {
+            required.extend(names.into_iter().map(|name| Value::String(name.into())));
+        }
+        self
+    }
+
+    pub fn additional_properties(self, schema: impl Into<Schema>) -> Self {
+        self.set("additionalProperties", schema.into().0)
+    }
+
+    /// Forbids the properties which have not been added.
+    pub fn closed(self) -> Self {
+        self.set("additionalProperties", false)
+    }
+
+    pub fn min_properties(self, min_properties: u64) -> Self {
+        self.set("minProperties", min_properties)
+    }
+
+    pub fn max_properties(self, max_properties: u64) -> Self {
+        self.set("maxProperties", max_properties)
+    }
+}
+
+impl Str {
+    pub fn min_len(self, min_length: u64) -> Self {
+        self.set("minLength", min_length)
+    }
+
+    pub fn max_len(self, max_length: u64) -> Self {
+        self.set("maxLength", max_length)
+    }
+
+    pub fn pattern(self, pattern: impl Into<String>) -> Self {
+        self.set("pattern", pattern.into())
+    }
+
+    /// Format of the string, such as `date-time`, `email` or `uuid`.
+    pub fn format(self, format: impl Into<String>) -> Self {
+        self.set("format", format.into())
+    }
+}
+
+impl Int {
+    pub fn minimum(self, minimum: i64) -> Self {
+        self.set("minimum", minimum)
+    }
+
+    pub fn maximum(self, maximum: i64) -> Self {
+        self.set("maximum", maximum)
+    }
+
+    pub fn exclusive_minimum(self, exclusive_minimum: i64) -> Self {
+        self.set("exclusiveMinimum", exclusive_minimum)
+    }
+
+    pub fn exclusive_maximum(self, exclusive_maximum: i64) -> Self {
+        self.set("exclusiveMaximum", exclusive_maximum)
+    }
+}
+
+impl Num {
+    pub fn minimum(self, minimum: f64) -> Self {
+        self.set("minimum", minimum)
+    }
+
+    pub fn maximum(self, maximum: f64) -> Self {
+        self.set("maximum", maximum)
+    }
+}
+
+impl Array {
+    pub fn items(self, items: impl Into<Schema>) -> Self {
+        self.set("items", items.into().0)
+    }
+
+    pub fn min_items(self, min_items: u64) -> Self {
+        self.set("minItems", min_items)
+    }
+
+    pub fn max_items(self, max_items: u64) -> Self {
+        self.set("maxItems", max_items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let schema = SchemaBuilder::object()
+            .property("name", Str::new().min_len(1))
+            .property("tags", SchemaBuilder::array(Str::new()).max_items(3))
+            .property(
+                "id",
+                SchemaBuilder::any_of([
+                    SchemaBuilder::integer().minimum(0).build(),
+                    Str::new().format("uuid").build(),
+                ]),
+            )
+            .property("kind", SchemaBuilder::enumeration(["a", "b"]))
+            .required(["name"])
+            .required(["id"])
+            .closed()
+            .build();
+        assert_eq!(
+            schema.as_value(),
+            &json!({
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string", "minLength": 1},
+                    "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3},
+                    "id": {"anyOf": [
+                        {"type": "integer", "minimum": 0},
+                        {"type": "string", "format": "uuid"}
+                    ]},
+                    "kind": {"enum": ["a", "b"]}
+                },
+                "required": ["name", "id"],
+                "additionalProperties": false
+            })
+        );
+        let regex =
+            regex::Regex::new(&format!("^{}$", schema.to_regex(&Options::new()).unwrap())).unwrap();
+        assert!(regex.is_match(r#"{"name": "x", "id": 3, "kind": "b"}"#));
+        assert!(!regex.is_match(r#"{"name": "", "id": 3}"#));
+    }
+}
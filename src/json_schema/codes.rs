@@ -0,0 +1,166 @@
+/// Patterns of the standard codes, compiled from their lists as tries so that each prefix is
+/// only written once.
+use std::sync::OnceLock;
+
+/// ISO 3166-1 alpha-2 country codes.
+const COUNTRIES: &str = "AD AE AF AG AI AL AM AO AQ AR AS AT AU AW AX AZ BA BB BD BE BF BG BH BI \
+    BJ BL BM BN BO BQ BR BS BT BV BW BY BZ CA CC CD CF CG CH CI CK CL CM CN CO CR CU CV CW CX CY \
+    CZ DE DJ DK DM DO DZ EC EE EG EH ER ES ET FI FJ FK FM FO FR GA GB GD GE GF GG GH GI GL GM GN \
+    GP GQ GR GS GT GU GW GY HK HM HN HR HT HU ID IE IL IM IN IO IQ IR IS IT JE JM JO JP KE KG KH \
+    KI KM KN KP KR KW KY KZ LA LB LC LI LK LR LS LT LU LV LY MA MC MD ME MF MG MH MK ML MM MN MO \
+    MP MQ MR MS MT MU MV MW MX MY MZ NA NC NE NF NG NI NL NO NP NR NU NZ OM PA PE PF PG PH PK PL \
+    PM PN PR PS PT PW PY QA RE RO RS RU RW SA SB SC SD SE SG SH SI SJ SK SL SM SN SO SR SS ST SV \
+    SX SY SZ TC TD TF TG TH TJ TK TL TM TN TO TR TT TV TW TZ UA UG UM US UY UZ VA VC VE VG VI VN \
+    VU WF WS YE YT ZA ZM ZW";
+
+/// ISO 4217 currency codes, the funds and precious metals included.
+const CURRENCIES: &str = "AED AFN ALL AMD ANG AOA ARS AUD AWG AZN BAM BBD BDT BGN BHD BIF BMD \
+    BND BOB BOV BRL BSD BTN BWP BYN BZD CAD CDF CHE CHF CHW CLF CLP CNY COP COU CRC CUC CUP CVE \
+    CZK DJF DKK DOP DZD EGP ERN ETB EUR FJD FKP GBP GEL GHS GIP GMD GNF GTQ GYD HKD HNL HTG HUF \
+    IDR ILS INR IQD IRR ISK JMD JOD JPY KES KGS KHR KMF KPW KRW KWD KYD KZT LAK LBP LKR LRD LSL \
+    LYD MAD MDL MGA MKD MMK MNT MOP MRU MUR MVR MWK MXN MXV MYR MZN NAD NGN NIO NOK NPR NZD OMR \
+    PAB PEN PGK PHP PKR PLN PYG QAR RON RSD RUB RWF SAR SBD SCR SDG SEK SGD SHP SLE SLL SOS SRD \
+    SSP STN SVC SYP SZL THB TJS TMT TND TOP TRY TTD TWD TZS UAH UGX USD USN UYI UYU UYW UZS VED \
+    VES VND VUV WST XAF XAG XAU XBA XBB XBC XBD XCD XCG XDR XOF XPD XPF XPT XSU XTS XUA XXX YER \
+    ZAR ZMW ZWG ZWL";
+
+/// ISO 639-1 language codes.
+const LANGUAGES: &str = "aa ab ae af ak am an ar as av ay az ba be bg bi bm bn bo br bs ca ce \
+    ch co cr cs cu cv cy da de dv dz ee el en eo es et eu fa ff fi fj fo fr fy ga gd gl gn gu gv \
+    ha he hi ho hr ht hu hy hz ia id ie ig ii ik io is it iu ja jv ka kg ki kj kk kl km kn ko kr \
+    ks ku kv kw ky la lb lg li ln lo lt lu lv mg mh mi mk ml mn mr ms mt my na nb nd ne ng nl nn \
+    no nr nv ny oc oj om or os pa pi pl ps pt qu rm rn ro ru rw sa sc sd se sg si sk sl sm sn so \
+    sq sr ss st su sv sw ta te tg th ti tk tl tn to tr ts tt tw ty ug uk ur uz ve vi vo wa wo xh \
+    yi yo za zh zu";
+
+/// Pattern matching exactly the given words, which must be ASCII alphanumeric: the words are
+/// grouped by their first character, recursively, and the characters ending words together are
+/// written as a class.
+pub(crate) fn trie_regex(words: &[&str]) -> String {
+    let mut words: Vec<&str> = words.to_vec();
+    words.sort_unstable();
+    words.dedup();
+    alternation(&words)
+}
+
+/// Alternation of the sorted, non-empty `words`.
+fn alternation(words: &[&str]) -> String {
+    let mut leaves = Vec::new();
+    let mut branches = Vec::new();
+    let mut rest = words;
+    while let Some(first) = rest.first() {
+        let c = first.as_bytes()[0];
+        let len = rest
+            .iter()
+            .take_while(|word| word.as_bytes()[0] == c)
+            .count();
+        let suffixes: Vec<&str> = rest[..len].iter().map(|word| &word[1..]).collect();
+        if suffixes.iter().all(|suffix| suffix.is_empty()) {
+            leaves.push(c);
+        } else {
+            let optional = suffixes[0].is_empty();
+            let inner = alternation(&suffixes[optional as usize..]);
+            branches.push(match (optional, inner.contains('|')) {
+                (true, _) => format!("{}({})?", c as char, inner),
+                (false, true) => format!("{}({})", c as char, inner),
+                (false, false) => format!("{}{}", c as char, inner),
+            });
+        }
+        rest = &rest[len..];
+    }
+    if !leaves.is_empty() {
+        branches.push(class(&leaves));
+    }
+    branches.join("|")
+}
+
+/// Class of the sorted characters `chars`, with the runs of consecutive characters as ranges.
+fn class(chars: &[u8]) -> String {
+    if let [c] = chars {
+        return (*c as char).to_string();
+    }
+    let mut class = String::from("[");
+    let mut i = 0;
+    while i < chars.len() {
+        let mut j = i;
+        while j + 1 < chars.len() && chars[j + 1] == chars[j] + 1 {
+            j += 1;
+        }
+        class.push(chars[i] as char);
+        if j >= i + 2 {
+            class.push('-');
+        }
+        if j > i {
+            class.push(chars[j] as char);
+        }
+        i = j + 1;
+    }
+    class.push(']');
+    class
+}
+
+fn quoted_trie(cell: &'static OnceLock<String>, codes: &str) -> &'static str {
+    cell.get_or_init(|| {
+        let codes: Vec<&str> = codes.split_whitespace().collect();
+        format!(r#""({})""#, trie_regex(&codes))
+    })
+}
+
+/// Pattern of the JSON strings holding an ISO 3166-1 alpha-2 country code.
+pub fn country_code_regex() -> &'static str {
+    static REGEX: OnceLock<String> = OnceLock::new();
+    quoted_trie(&REGEX, COUNTRIES)
+}
+
+/// Pattern of the JSON strings holding an ISO 4217 currency code.
+pub fn currency_code_regex() -> &'static str {
+    static REGEX: OnceLock<String> = OnceLock::new();
+    quoted_trie(&REGEX, CURRENCIES)
+}
+
+/// Pattern of the JSON strings holding a BCP 47 language tag of the common form: an ISO 639-1
+/// language, then an optional script and an optional ISO 3166-1 or UN M.49 region, as in
+/// `zh-Hant-TW`.
+pub fn language_tag_regex() -> &'static str {
+    static REGEX: OnceLock<String> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        let languages: Vec<&str> = LANGUAGES.split_whitespace().collect();
+        let countries: Vec<&str> = COUNTRIES.split_whitespace().collect();
+        format!(
+            r#""({})(-[A-Z][a-z]{{3}})?(-({}|[0-9]{{3}}))?""#,
+            trie_regex(&languages),
+            trie_regex(&countries)
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn trie() {
+        assert_eq!(
+            trie_regex(&["A", "AB", "AC", "B", "C", "D"]),
+            "A([BC])?|[B-D]"
+        );
+        assert_eq!(
+            trie_regex(&["AD", "AE", "BE", "BF", "CA"]),
+            "A[DE]|B[EF]|CA"
+        );
+
+        let countries = Regex::new(&format!("^{}$", country_code_regex())).unwrap();
+        for code in COUNTRIES.split_whitespace() {
+            assert!(countries.is_match(&format!(r#""{}""#, code)));
+        }
+        assert!(!countries.is_match(r#""AA""#));
+        assert!(!countries.is_match(r#""USA""#));
+
+        let tags = Regex::new(&format!("^{}$", language_tag_regex())).unwrap();
+        assert!(tags.is_match(r#""en""#));
+        assert!(tags.is_match(r#""zh-Hant-TW""#));
+        assert!(tags.is_match(r#""es-419""#));
+        assert!(!tags.is_match(r#""en-us""#));
+    }
+}
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::json_schema::types::Draft;
+
+/// Rewrites the keywords of `obj` whose meaning depends on `draft` into their draft 2020-12
+/// equivalent, which is what the parser compiles. Normalizing an already normalized schema
+/// leaves it unchanged, as the parser may compile rewritten schemas again.
+pub(crate) fn normalize(
+    draft: Draft,
+    obj: &Map<String, Value>,
+) -> Result<Cow<'_, Map<String, Value>>> {
+    match draft {
+        Draft::Draft202012 => {
+            if let Some(Value::Array(_)) = obj.get("items") {
+                return Err(anyhow!(
+                    "Array-form 'items' is not valid in draft 2020-12, use 'prefixItems' instead"
+                ));
+            }
+            if ["additionalItems", "dependencies"]
+                .iter()
+                .all(|k| !obj.contains_key(*k))
+            {
+                return Ok(Cow::Borrowed(obj));
+            }
+            let mut obj = obj.clone();
+            obj.remove("additionalItems");
+            obj.remove("dependencies");
+            Ok(Cow::Owned(obj))
+        }
+        Draft::Draft201909 | Draft::Draft7 => {
+            let mut normalized = obj.clone();
+
+            // Siblings of `$ref` are ignored before 2019-09.
+            if draft == Draft::Draft7 && obj.contains_key("$ref") {
+                normalized.retain(|k, _| k == "$ref");
+                return Ok(Cow::Owned(normalized));
+            }
+
+            normalized.remove("$dynamicRef");
+            let additional_items = normalized.remove("additionalItems");
+            match normalized.remove("items") {
+                Some(Value::Array(items)) => {
+                    normalized.insert("prefixItems".to_string(), Value::Array(items));
+                    if let Some(additional_items) = additional_items {
+                        normalized.insert("items".to_string(), additional_items);
+                    }
+                }
+                Some(items) => {
+                    normalized.insert("items".to_string(), items);
+                }
+                None => {}
+            }
+
+            if draft == Draft::Draft7 {
+                if let Some(dependencies) = normalized.remove("dependencies") {
+                    let dependencies = dependencies
+                        .as_object()
+                        .ok_or_else(|| anyhow!("'dependencies' must be an object"))?;
+                    let mut dependent_required = Map::new();
+                    let mut dependent_schemas = Map::new();
+                    for (name, dependency) in dependencies {
+                        match dependency {
+                            Value::Array(_) => {
+                                dependent_required.insert(name.clone(), dependency.clone())
+                            }
+                            _ => dependent_schemas.insert(name.clone(), dependency.clone()),
+                        };
+                    }
+                    if !dependent_required.is_empty() {
+                        normalized.insert(
+                            "dependentRequired".to_string(),
+                            Value::Object(dependent_required),
+                        );
+                    }
+                    if !dependent_schemas.is_empty() {
+                        normalized.insert(
+                            "dependentSchemas".to_string(),
+                            Value::Object(dependent_schemas),
+                        );
+                    }
+                }
+            } else {
+                normalized.remove("dependencies");
+            }
+
+            Ok(Cow::Owned(normalized))
+        }
+    }
+}
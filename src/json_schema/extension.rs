@@ -0,0 +1,106 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Vendor keywords understood by the compiler itself, which are never handed to a
+/// [`KeywordExtension`].
+pub(crate) const BUILTIN_KEYWORDS: [&str; 4] = [
+    "x-datetime-format",
+    "x-format",
+    "x-pattern-override",
+    "x-wordlist",
+];
+
+/// What a [`KeywordExtension`] makes of a subschema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extension {
+    /// Regular expression matching the JSON values of the subschema, used as is.
+    Regex(String),
+    /// Schema compiled in place of the subschema. It is handed to the extension in turn, so it
+    /// should not keep the keyword it replaces.
+    Schema(Value),
+}
+
+/// Handler of the vendor keywords, starting with `x-`, which the compiler does not know.
+pub trait KeywordExtension: Send + Sync {
+    /// Called with each unknown `x-*` keyword of `schema`, in order, and its value. Returning
+    /// `None` leaves the keyword ignored, as it is without extension.
+    fn extend(
+        &self,
+        keyword: &str,
+        value: &Value,
+        schema: &Map<String, Value>,
+    ) -> Result<Option<Extension>>;
+}
+
+impl<F> KeywordExtension for F
+where
+    F: Fn(&str, &Value, &Map<String, Value>) -> Result<Option<Extension>> + Send + Sync,
+{
+    fn extend(
+        &self,
+        keyword: &str,
+        value: &Value,
+        schema: &Map<String, Value>,
+    ) -> Result<Option<Extension>> {
+        self(keyword, value, schema)
+    }
+}
+
+impl std::fmt::Debug for dyn KeywordExtension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeywordExtension")
+    }
+}
+
+/// Outcome of the first unknown `x-*` keyword of `schema` that `extension` handles.
+pub(crate) fn extend(
+    extension: &dyn KeywordExtension,
+    schema: &Map<String, Value>,
+) -> Result<Option<Extension>> {
+    for (keyword, value) in schema {
+        if !keyword.starts_with("x-") || BUILTIN_KEYWORDS.contains(&keyword.as_str()) {
+            continue;
+        }
+        if let Some(outcome) = extension.extend(keyword, value, schema)? {
+            return Ok(Some(outcome));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_schema::{build_regex_from_schema_with_options, Options};
+
+    #[test]
+    fn keyword_extension() {
+        let options = Options::new().keyword_extension(
+            |keyword: &str, value: &Value, _: &Map<String, Value>| {
+                Ok(match (keyword, value.as_str()) {
+                    ("x-ticket", Some(project)) => {
+                        Some(Extension::Regex(format!(r#""{}-[0-9]+""#, project)))
+                    }
+                    ("x-nullable", _) => Some(Extension::Schema(
+                        serde_json::json!({"anyOf": [{"type": "string"}, {"type": "null"}]}),
+                    )),
+                    _ => None,
+                })
+            },
+        );
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "id": {"type": "string", "x-ticket": "ABC"},
+                "note": {"type": "string", "x-nullable": true, "x-format": "uuid"},
+                "tag": {"type": "string", "x-other": 1}
+            },
+            "required": ["id", "note", "tag"]
+        }"#;
+        let regex = build_regex_from_schema_with_options(schema, &options).unwrap();
+        let regex = regex::Regex::new(&format!("^{}$", regex)).unwrap();
+        assert!(regex.is_match(r#"{"id":"ABC-12","note":null,"tag":"x"}"#));
+        assert!(regex.is_match(r#"{"id":"ABC-12","note":"a","tag":"x"}"#));
+        assert!(!regex.is_match(r#"{"id":"XYZ-12","note":null,"tag":"x"}"#));
+    }
+}
@@ -38,3 +38,94 @@ pub fn get_num_items_pattern(min_items: Option<u64>, max_items: Option<u64>) ->
         }
     }
 }
+
+/// Finds a lookaround or a backreference in a regular expression, which the automata can not
+/// match.
+pub fn unsupported_pattern_feature(pattern: &str) -> Option<&'static str> {
+    let mut chars = pattern.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('1'..='9') if !in_class => return Some("a backreference"),
+                Some('k') if !in_class && matches!(chars.peek(), Some('<' | '{' | '\'')) => {
+                    return Some("a backreference")
+                }
+                _ => {}
+            },
+            '[' if !in_class => {
+                in_class = true;
+                // A closing bracket first in the class is one of its characters.
+                if chars.peek() == Some(&'^') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                }
+            }
+            ']' if in_class => in_class = false,
+            '(' if !in_class && chars.peek() == Some(&'?') => {
+                let group: String = chars.clone().skip(1).take(3).collect();
+                if group.starts_with('=') || group.starts_with('!') {
+                    return Some("a lookahead");
+                }
+                if group.starts_with("<=") || group.starts_with("<!") {
+                    return Some("a lookbehind");
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_features() {
+        assert_eq!(
+            unsupported_pattern_feature(r"^(?=.*[0-9]).{8,}$"),
+            Some("a lookahead")
+        );
+        assert_eq!(
+            unsupported_pattern_feature(r"^(?!admin)[a-z]+$"),
+            Some("a lookahead")
+        );
+        assert_eq!(
+            unsupported_pattern_feature(r"(?<=\$)[0-9]+"),
+            Some("a lookbehind")
+        );
+        assert_eq!(
+            unsupported_pattern_feature(r"(?<!-)[0-9]+"),
+            Some("a lookbehind")
+        );
+        assert_eq!(
+            unsupported_pattern_feature(r"(a)b\1"),
+            Some("a backreference")
+        );
+        assert_eq!(
+            unsupported_pattern_feature(r"(?<x>a)\k<x>"),
+            Some("a backreference")
+        );
+
+        for supported in [
+            r"^[a-z]+(?:-[a-z]+)*$",
+            r"(?<name>[a-z]+)",
+            r"(?i)abc",
+            r"\(?=\)",
+            r"[(?=]",
+            r"[\1-9]",
+            r"[]\d]+",
+            r"\\1",
+        ] {
+            assert_eq!(
+                unsupported_pattern_feature(supported),
+                None,
+                "{}",
+                supported
+            );
+        }
+    }
+}
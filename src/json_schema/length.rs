@@ -0,0 +1,745 @@
+/// Restriction of string patterns to a range of lengths, and to the strings of other patterns.
+use super::pattern::{write, write_atom, write_class};
+use super::types::LengthSemantics;
+use anyhow::{anyhow, Result};
+use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, Hir, HirKind, Repetition};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// Maximum number of states of the automata of a pattern, beyond which its restriction to a
+/// range of lengths is given up on.
+const MAX_STATES: usize = 4096;
+
+/// Maximum length of the regular expression of the automaton of a pattern restricted to a
+/// range of lengths, beyond which the restriction is approximated.
+const MAX_REGEX_LEN: usize = 16 * 1024;
+
+/// Rewrites `pattern` to match only its strings of `min_length` to `max_length` characters,
+/// counted as `semantics` tells, with no upper bound when `max_length` is `None`.
+///
+/// The bounds of a repetition of a single character are narrowed. Other patterns are compiled
+/// into an automaton over characters whose states also count the characters read so far, which
+/// is turned back into a regular expression by eliminating its states one after the other.
+///
+/// That expression grows exponentially with the number of repetitions whose lengths add up, as
+/// in `[a-z]+@[a-z]+\.[a-z]+`. The length is then rather shared out between the repetitions, by
+/// narrowing all their bounds alike, so that the pattern only matches some of its strings of
+/// the right length.
+pub(crate) fn restrict_length(
+    pattern: &str,
+    min_length: u64,
+    max_length: Option<u64>,
+    semantics: LengthSemantics,
+) -> Result<String> {
+    let hir = regex_syntax::parse(pattern)
+        .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+    let empty = || {
+        anyhow!(
+            "Pattern '{}' matches no string of {} to {} {}",
+            pattern,
+            min_length,
+            max_length.map_or("any number of".to_string(), |max| max.to_string()),
+            semantics.unit()
+        )
+    };
+
+    let (shortest, longest) = lengths(&hir, semantics);
+    let within = |length: u64| max_length.is_none_or(|max_length| length <= max_length);
+    if shortest >= min_length && longest.map_or(max_length.is_none(), within) {
+        return Ok(pattern.to_string());
+    }
+    if !within(shortest) || longest.is_some_and(|longest| longest < min_length) {
+        return Err(empty());
+    }
+
+    if let HirKind::Repetition(repetition) = hir.kind() {
+        if lengths(&repetition.sub, semantics) == (1, Some(1)) {
+            let min = min_length.max(repetition.min as u64);
+            let max = match (repetition.max.map(u64::from), max_length) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let quantifier = match max {
+                Some(max) if max == min => format!("{{{}}}", min),
+                Some(max) => format!("{{{},{}}}", min, max),
+                None => format!("{{{},}}", min),
+            };
+            return Ok(format!("{}{}", write_atom(&repetition.sub)?, quantifier));
+        }
+    }
+
+    let mut nfa = Nfa::default();
+    let start = nfa.add_state()?;
+    let end = nfa.add(&hir, start)?;
+    let exact = nfa
+        .to_counting_dfa(&[(start, end)], min_length, max_length, semantics)
+        .and_then(|dfa| dfa.to_regex());
+    match exact {
+        Ok(Some(regex)) => Ok(regex),
+        Ok(None) => Err(empty()),
+        Err(_) => match share_length(&hir, min_length, max_length, semantics) {
+            Some(hir) => write(&hir),
+            None => Err(anyhow!(
+                "Pattern '{}' can not be restricted to {} to {} {}",
+                pattern,
+                min_length,
+                max_length.map_or("any number of".to_string(), |max| max.to_string()),
+                semantics.unit()
+            )),
+        },
+    }
+}
+
+/// Pattern of the strings matched by both `left` and `right`, or `None` when no string is.
+///
+/// The patterns are compiled into automata over characters, read together by an automaton
+/// which is turned back into a regular expression like in [`restrict_length`]. Fails when the
+/// expression grows too large or the patterns have assertions.
+pub(crate) fn intersect_patterns(left: &str, right: &str) -> Result<Option<String>> {
+    let mut nfa = Nfa::default();
+    let mut paths = Vec::new();
+    for pattern in [left, right] {
+        let hir = regex_syntax::parse(pattern)
+            .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+        let start = nfa.add_state()?;
+        paths.push((start, nfa.add(&hir, start)?));
+    }
+    nfa.to_counting_dfa(&paths, 0, None, LengthSemantics::Codepoints)?
+        .to_regex()
+}
+
+/// Pattern of the contents of the strings of `min_length` to `max_length` characters counted as
+/// `semantics` tells, made of the characters of `string_inner`: a class of raw characters and
+/// escape sequences.
+///
+/// For each length `n` of characters, the strings of characters of length up to `n` are
+/// bounded to `max_length / n` of them, so that the pattern only matches strings of the right
+/// length, though not all of them. The `\u` escapes, whose characters have various lengths,
+/// are left out.
+pub(crate) fn restrict_string_length(
+    string_inner: &str,
+    min_length: u64,
+    max_length: Option<u64>,
+    semantics: LengthSemantics,
+) -> Result<String> {
+    let hir = regex_syntax::parse(string_inner)
+        .map_err(|e| anyhow!("Invalid pattern '{}': {}", string_inner, e))?;
+    let hir = match hir.kind() {
+        HirKind::Capture(capture) => &capture.sub,
+        _ => &hir,
+    };
+    let alternatives = match hir.kind() {
+        HirKind::Alternation(subs) => subs.as_slice(),
+        _ => std::slice::from_ref(hir),
+    };
+    let mut class = ClassUnicode::empty();
+    let mut escapes = Vec::new();
+    for alternative in alternatives {
+        match alternative.kind() {
+            HirKind::Class(Class::Unicode(raw)) => class.union(raw),
+            // Escapes of a single ASCII character.
+            _ if lengths(alternative, LengthSemantics::Codepoints) == (2, Some(2)) => {
+                escapes.push(write(alternative)?)
+            }
+            _ => {}
+        }
+    }
+
+    let mut limits: Vec<u32> = semantics.boundaries().to_vec();
+    limits.push(0x11_0000);
+    let mut terms = Vec::new();
+    let mut previous = None;
+    for (n, limit) in (1..).zip(limits) {
+        let mut shorter = ClassUnicode::new([ClassUnicodeRange::new(
+            '\0',
+            char::from_u32(limit - 1).unwrap_or(char::MAX),
+        )]);
+        shorter.intersect(&class);
+        if previous.as_ref() == Some(&shorter) {
+            continue;
+        }
+        let max_chars = max_length.map(|max_length| max_length / n);
+        if max_chars.is_some_and(|max_chars| max_chars < min_length) {
+            break;
+        }
+        let mut atoms = escapes.clone();
+        if shorter.iter().next().is_some() {
+            atoms.insert(0, write_class(&Class::Unicode(shorter.clone()))?);
+        }
+        let quantifier = match max_chars {
+            Some(max_chars) => format!("{{{},{}}}", min_length, max_chars),
+            None => format!("{{{},}}", min_length),
+        };
+        terms.push(format!("({}){}", atoms.join("|"), quantifier));
+        previous = Some(shorter);
+    }
+    // Without a maximum length, the strings of any characters include all the others.
+    if max_length.is_none() {
+        terms.drain(..terms.len().saturating_sub(1));
+    }
+    match terms.as_slice() {
+        [] => Err(anyhow!(
+            "No string has {} to {} {}",
+            min_length,
+            max_length.map_or("any number of".to_string(), |max| max.to_string()),
+            semantics.unit()
+        )),
+        [term] => Ok(term.clone()),
+        terms => Ok(format!("({})", terms.join("|"))),
+    }
+}
+
+/// Lengths of the shortest and longest strings of `hir`, the latter being `None` when there is
+/// no longest string.
+fn lengths(hir: &Hir, semantics: LengthSemantics) -> (u64, Option<u64>) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => (0, Some(0)),
+        HirKind::Literal(literal) => {
+            let length = String::from_utf8_lossy(&literal.0)
+                .chars()
+                .map(|c| semantics.char_length(c as u32))
+                .sum();
+            (length, Some(length))
+        }
+        HirKind::Class(Class::Unicode(class)) => {
+            let shortest = class.ranges().first().map(|range| range.start() as u32);
+            let longest = class.ranges().last().map(|range| range.end() as u32);
+            let length = |c: Option<u32>| semantics.char_length(c.unwrap_or(0));
+            (length(shortest), Some(length(longest)))
+        }
+        HirKind::Class(Class::Bytes(_)) => (1, Some(1)),
+        HirKind::Repetition(repetition) => {
+            let (shortest, longest) = lengths(&repetition.sub, semantics);
+            let longest = match (longest, repetition.max) {
+                (Some(0), _) => Some(0),
+                (Some(longest), Some(max)) => Some(longest.saturating_mul(max as u64)),
+                _ => None,
+            };
+            (shortest.saturating_mul(repetition.min as u64), longest)
+        }
+        HirKind::Capture(capture) => lengths(&capture.sub, semantics),
+        HirKind::Concat(subs) => subs.iter().map(|sub| lengths(sub, semantics)).fold(
+            (0, Some(0)),
+            |(shortest, longest), (sub_shortest, sub_longest)| {
+                (
+                    shortest.saturating_add(sub_shortest),
+                    longest.zip(sub_longest).map(|(a, b)| a.saturating_add(b)),
+                )
+            },
+        ),
+        HirKind::Alternation(subs) => {
+            let lengths: Vec<(u64, Option<u64>)> =
+                subs.iter().map(|sub| lengths(sub, semantics)).collect();
+            let shortest = lengths.iter().map(|&(shortest, _)| shortest).min();
+            let longest = lengths
+                .iter()
+                .map(|&(_, longest)| longest)
+                .try_fold(0, |a, b| b.map(|b| a.max(b)));
+            (shortest.unwrap_or(0), longest)
+        }
+    }
+}
+
+/// Narrows the bounds `{min, max}` of every repetition of `hir` to
+/// `{min(min + raise, max'), max'}` with `max' = min(max, min + slack)`, with the largest slack
+/// and then the smallest raise that bring the lengths of its strings within the range.
+fn share_length(
+    hir: &Hir,
+    min_length: u64,
+    max_length: Option<u64>,
+    semantics: LengthSemantics,
+) -> Option<Hir> {
+    let within = |slack: u64| {
+        lengths(&narrow(hir, slack, 0), semantics)
+            .1
+            .is_some_and(|longest| max_length.is_none_or(|max_length| longest <= max_length))
+    };
+    let slack = match max_length {
+        Some(max_length) => {
+            if !within(0) {
+                return None;
+            }
+            // Largest slack within the maximum length.
+            let (mut lo, mut hi) = (0, max_length);
+            while lo < hi {
+                let mid = lo + (hi - lo).div_ceil(2);
+                match within(mid) {
+                    true => lo = mid,
+                    false => hi = mid - 1,
+                }
+            }
+            Some(lo)
+        }
+        None => None,
+    };
+
+    let long_enough = |raise: u64| {
+        let hir = narrow(hir, slack.unwrap_or(u64::MAX), raise);
+        (lengths(&hir, semantics).0 >= min_length).then_some(hir)
+    };
+    if let Some(hir) = long_enough(0) {
+        return Some(hir);
+    }
+    long_enough(min_length)?;
+    // Smallest raise reaching the minimum length.
+    let (mut lo, mut hi) = (0, min_length);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match long_enough(mid) {
+            Some(_) => hi = mid,
+            None => lo = mid + 1,
+        }
+    }
+    long_enough(lo)
+}
+
+/// Narrows the bounds of the repetitions of `hir`, see [`share_length`].
+fn narrow(hir: &Hir, slack: u64, raise: u64) -> Hir {
+    match hir.kind() {
+        HirKind::Repetition(repetition) => {
+            let max = match repetition.max {
+                Some(max) => (max as u64).min((repetition.min as u64).saturating_add(slack)),
+                None => (repetition.min as u64).saturating_add(slack),
+            };
+            let min = (repetition.min as u64).saturating_add(raise).min(max);
+            let bound = |bound: u64| u32::try_from(bound).unwrap_or(u32::MAX);
+            Hir::repetition(Repetition {
+                min: bound(min),
+                max: (max < u64::MAX).then(|| bound(max)),
+                greedy: repetition.greedy,
+                sub: Box::new(narrow(&repetition.sub, slack, raise)),
+            })
+        }
+        HirKind::Capture(capture) => narrow(&capture.sub, slack, raise),
+        HirKind::Concat(subs) => {
+            Hir::concat(subs.iter().map(|sub| narrow(sub, slack, raise)).collect())
+        }
+        HirKind::Alternation(subs) => {
+            Hir::alternation(subs.iter().map(|sub| narrow(sub, slack, raise)).collect())
+        }
+        _ => hir.clone(),
+    }
+}
+
+/// Inclusive ranges of code points.
+type Ranges = Vec<(u32, u32)>;
+
+/// Automaton with ε-transitions over ranges of characters.
+#[derive(Default)]
+struct Nfa {
+    epsilons: Vec<Vec<usize>>,
+    edges: Vec<Vec<(Ranges, usize)>>,
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> Result<usize> {
+        if self.edges.len() >= MAX_STATES * 4 {
+            return Err(anyhow!(
+                "The pattern is too large to be restricted to the length bounds"
+            ));
+        }
+        self.epsilons.push(Vec::new());
+        self.edges.push(Vec::new());
+        Ok(self.edges.len() - 1)
+    }
+
+    fn add_edge(&mut self, from: usize, ranges: Ranges) -> Result<usize> {
+        let to = self.add_state()?;
+        self.edges[from].push((ranges, to));
+        Ok(to)
+    }
+
+    /// Adds the strings of `hir` from the state `from`, and returns the state they lead to.
+    fn add(&mut self, hir: &Hir, from: usize) -> Result<usize> {
+        match hir.kind() {
+            HirKind::Empty => Ok(from),
+            HirKind::Literal(literal) => std::str::from_utf8(&literal.0)
+                .map_err(|_| anyhow!("Patterns must match text"))?
+                .chars()
+                .try_fold(from, |state, c| {
+                    self.add_edge(state, vec![(c as u32, c as u32)])
+                }),
+            HirKind::Class(Class::Unicode(class)) => {
+                let ranges = class
+                    .ranges()
+                    .iter()
+                    .map(|range| (range.start() as u32, range.end() as u32))
+                    .collect();
+                self.add_edge(from, ranges)
+            }
+            HirKind::Class(Class::Bytes(class)) => {
+                let ranges = class
+                    .ranges()
+                    .iter()
+                    .map(|range| match range.end().is_ascii() {
+                        true => Ok((range.start() as u32, range.end() as u32)),
+                        false => Err(anyhow!("Patterns must match text")),
+                    })
+                    .collect::<Result<_>>()?;
+                self.add_edge(from, ranges)
+            }
+            HirKind::Look(look) => Err(anyhow!(
+                "Assertion {:?} is not supported in a pattern with length bounds",
+                look
+            )),
+            HirKind::Repetition(repetition) => {
+                let mut state = from;
+                for _ in 0..repetition.min {
+                    state = self.add(&repetition.sub, state)?;
+                }
+                match repetition.max {
+                    None => {
+                        let start = self.add_state()?;
+                        self.epsilons[state].push(start);
+                        let end = self.add(&repetition.sub, start)?;
+                        self.epsilons[end].push(start);
+                        let exit = self.add_state()?;
+                        self.epsilons[start].push(exit);
+                        Ok(exit)
+                    }
+                    Some(max) => {
+                        let end = self.add_state()?;
+                        for _ in repetition.min..max {
+                            self.epsilons[state].push(end);
+                            state = self.add(&repetition.sub, state)?;
+                        }
+                        self.epsilons[state].push(end);
+                        Ok(end)
+                    }
+                }
+            }
+            HirKind::Capture(capture) => self.add(&capture.sub, from),
+            HirKind::Concat(subs) => subs
+                .iter()
+                .try_fold(from, |state, sub| self.add(sub, state)),
+            HirKind::Alternation(subs) => {
+                let end = self.add_state()?;
+                for sub in subs {
+                    let start = self.add_state()?;
+                    self.epsilons[from].push(start);
+                    let sub_end = self.add(sub, start)?;
+                    self.epsilons[sub_end].push(end);
+                }
+                Ok(end)
+            }
+        }
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(&self.epsilons[state]);
+            }
+        }
+        closure.into_iter().collect()
+    }
+
+    /// Builds the deterministic automaton of the strings of `min_length` to `max_length`
+    /// characters which lead from `start` to `end` for each `(start, end)` of `paths`, by the
+    /// subset construction.
+    fn to_counting_dfa(
+        &self,
+        paths: &[(usize, usize)],
+        min_length: u64,
+        max_length: Option<u64>,
+        semantics: LengthSemantics,
+    ) -> Result<Dfa> {
+        // Boundaries of the intervals of characters told apart by the ranges of the edges and
+        // by their lengths.
+        let mut boundaries = BTreeSet::from([0, 0x11_0000]);
+        boundaries.extend(semantics.boundaries());
+        for (ranges, _) in self.edges.iter().flatten() {
+            for &(lo, hi) in ranges {
+                boundaries.insert(lo);
+                boundaries.insert(hi + 1);
+            }
+        }
+        let boundaries: Vec<u32> = boundaries.into_iter().collect();
+        let interval = |c: u32| boundaries.partition_point(|&b| b <= c) - 1;
+
+        let initial = (
+            paths
+                .iter()
+                .map(|&(start, _)| self.closure([start]))
+                .collect::<Vec<_>>(),
+            0,
+        );
+        let mut states = HashMap::from([(initial.clone(), 0)]);
+        let mut queue = VecDeque::from([initial]);
+        let mut dfa = Dfa::default();
+        while let Some((nfa_states, length)) = queue.pop_front() {
+            dfa.edges.push(Vec::new());
+            let ends = paths
+                .iter()
+                .zip(&nfa_states)
+                .all(|(&(_, end), nfa_states)| nfa_states.binary_search(&end).is_ok());
+            dfa.finals.push(length >= min_length && ends);
+            if max_length == Some(length) {
+                continue;
+            }
+
+            // States reached along each path by the characters of each interval.
+            let mut targets: Vec<Vec<Vec<usize>>> =
+                vec![vec![Vec::new(); paths.len()]; boundaries.len() - 1];
+            for (path, nfa_states) in nfa_states.iter().enumerate() {
+                for &nfa_state in nfa_states {
+                    for (ranges, to) in &self.edges[nfa_state] {
+                        for &(lo, hi) in ranges {
+                            for target in &mut targets[interval(lo)..=interval(hi)] {
+                                target[path].push(*to);
+                            }
+                        }
+                    }
+                }
+            }
+            let mut labels: HashMap<(Vec<Vec<usize>>, u64), Vec<ClassUnicodeRange>> =
+                HashMap::new();
+            for (i, target) in targets.into_iter().enumerate() {
+                if target.iter().any(Vec::is_empty) {
+                    continue;
+                }
+                let next_length = length + semantics.char_length(boundaries[i]);
+                let next_length = match max_length {
+                    Some(max_length) if next_length > max_length => continue,
+                    Some(_) => next_length,
+                    None => next_length.min(min_length),
+                };
+                let (lo, hi) = (boundaries[i], boundaries[i + 1] - 1);
+                // Surrogates are not characters.
+                let lo = char::from_u32(lo).unwrap_or('\u{E000}');
+                let hi = char::from_u32(hi).unwrap_or('\u{D7FF}');
+                if lo <= hi {
+                    let target = target.into_iter().map(|t| self.closure(t)).collect();
+                    labels
+                        .entry((target, next_length))
+                        .or_default()
+                        .push(ClassUnicodeRange::new(lo, hi));
+                }
+            }
+
+            for (next, ranges) in labels {
+                let next_state = match states.get(&next) {
+                    Some(&next_state) => next_state,
+                    None => {
+                        if states.len() == MAX_STATES {
+                            return Err(anyhow!(
+                                "The pattern is too large to be restricted to the length bounds"
+                            ));
+                        }
+                        states.insert(next.clone(), states.len());
+                        queue.push_back(next);
+                        states.len() - 1
+                    }
+                };
+                let label = write_class(&Class::Unicode(ClassUnicode::new(ranges)))?;
+                dfa.edges
+                    .last_mut()
+                    .expect("the state was pushed")
+                    .push((label, next_state));
+            }
+        }
+        Ok(dfa)
+    }
+}
+
+/// Deterministic automaton whose edges are labelled by regular expressions.
+#[derive(Default)]
+struct Dfa {
+    edges: Vec<Vec<(String, usize)>>,
+    finals: Vec<bool>,
+}
+
+impl Dfa {
+    /// Regular expression of the strings the automaton accepts from its state 0, or `None` when
+    /// it accepts none. Fails when the expression grows beyond `MAX_REGEX_LEN`.
+    fn to_regex(&self) -> Result<Option<String>> {
+        let n = self.edges.len();
+        let (start, end) = (n, n + 1);
+
+        // States from which a final state can be reached, the others being left out.
+        let mut predecessors = vec![Vec::new(); n];
+        for (from, state_edges) in self.edges.iter().enumerate() {
+            for &(_, to) in state_edges {
+                predecessors[to].push(from);
+            }
+        }
+        let mut live = self.finals.clone();
+        let mut stack: Vec<usize> = (0..n).filter(|&state| live[state]).collect();
+        while let Some(state) = stack.pop() {
+            for &from in &predecessors[state] {
+                if !live[from] {
+                    live[from] = true;
+                    stack.push(from);
+                }
+            }
+        }
+        if !live[0] {
+            return Ok(None);
+        }
+
+        // Alternatives of the expressions leading from each state to the others, and the states
+        // leading to each state.
+        let mut outs: Vec<BTreeMap<usize, Vec<String>>> = vec![BTreeMap::new(); n + 2];
+        let mut ins: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); n + 2];
+        let mut add = |from: usize, to: usize, label: String| {
+            outs[from].entry(to).or_default().push(label);
+            ins[to].insert(from);
+        };
+        add(start, 0, String::new());
+        for (from, state_edges) in self
+            .edges
+            .iter()
+            .enumerate()
+            .filter(|&(from, _)| live[from])
+        {
+            for (label, to) in state_edges.iter().filter(|&&(_, to)| live[to]) {
+                add(from, *to, label.clone());
+            }
+            if self.finals[from] {
+                add(from, end, String::new());
+            }
+        }
+
+        let mut remaining: BTreeSet<usize> = (0..n).filter(|&state| live[state]).collect();
+        while !remaining.is_empty() {
+            // The states with the fewest paths through them grow the expressions the least.
+            let state = *remaining
+                .iter()
+                .min_by_key(|&&state| ins[state].len() * outs[state].len())
+                .expect("states remain");
+            remaining.remove(&state);
+
+            let loops = outs[state]
+                .remove(&state)
+                .map(|loops| star(&alternation(loops)))
+                .unwrap_or_default();
+            ins[state].remove(&state);
+            let state_outs = std::mem::take(&mut outs[state]);
+            for &to in state_outs.keys() {
+                ins[to].remove(&state);
+            }
+            for from in std::mem::take(&mut ins[state]) {
+                let in_label = alternation(outs[from].remove(&state).unwrap_or_default());
+                for (&to, out_labels) in &state_outs {
+                    let path = format!("{}{}{}", in_label, loops, alternation(out_labels.clone()));
+                    if path.len() > MAX_REGEX_LEN {
+                        return Err(anyhow!(
+                            "The regular expression of the automaton is too large"
+                        ));
+                    }
+                    outs[from].entry(to).or_default().push(path);
+                    ins[to].insert(from);
+                }
+            }
+        }
+        Ok(outs[start].remove(&end).map(alternation))
+    }
+}
+
+fn alternation(mut alternatives: Vec<String>) -> String {
+    alternatives.sort_unstable();
+    alternatives.dedup();
+    let optional = alternatives.first().is_some_and(String::is_empty);
+    let alternatives = &alternatives[optional as usize..];
+    match (alternatives, optional) {
+        ([], _) => String::new(),
+        ([alternative], false) => alternative.clone(),
+        (alternatives, false) => format!("({})", alternatives.join("|")),
+        (alternatives, true) => format!("({})?", alternatives.join("|")),
+    }
+}
+
+fn star(regex: &str) -> String {
+    match regex {
+        "" => String::new(),
+        regex => format!("({})*", regex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_schema::types::STRING_INNER;
+    use regex::Regex;
+    use LengthSemantics::*;
+
+    #[test]
+    fn restrict() {
+        assert_eq!(
+            restrict_length("[a-z0-9_]+", 3, Some(16), Codepoints).unwrap(),
+            "[0-9_a-z]{3,16}"
+        );
+        assert_eq!(restrict_length("x*", 2, None, Codepoints).unwrap(), "x{2,}");
+        assert!(restrict_length("x{5}", 1, Some(3), Codepoints).is_err());
+
+        let full_match = |pattern: &str, min: u64, max: Option<u64>| {
+            let regex = restrict_length(pattern, min, max, Codepoints).unwrap();
+            Regex::new(&format!("^(?:{})$", regex)).unwrap()
+        };
+        let code = full_match("[A-Z]{2}-[0-9]+", 5, Some(6));
+        assert!(code.is_match("AB-123"));
+        assert!(code.is_match("AB-12"));
+        assert!(!code.is_match("AB-1"));
+        assert!(!code.is_match("AB-1234"));
+
+        let words = full_match("(foo|ba+r)( (foo|ba+r))*", 9, None);
+        assert!(words.is_match("foo baaar"));
+        assert!(words.is_match("bar foo bar"));
+        assert!(!words.is_match("foo bar"));
+        assert!(!words.is_match("foo baaa"));
+
+        let unicode = full_match("é[^a]*", 2, Some(3));
+        assert!(unicode.is_match("éxé"));
+        assert!(!unicode.is_match("éa"));
+        assert!(!unicode.is_match("é"));
+
+        // The lengths of the three repetitions add up, so the length is shared out between them.
+        let email = full_match("[a-z.]+@[a-z]+\\.[a-z]{2,}", 3, Some(64));
+        assert!(email.is_match("a@b.cd"));
+        assert!(email.is_match("jane.doe@example.org"));
+        assert!(!email.is_match(&format!("{}@b.cd", "a".repeat(60))));
+
+        assert!(restrict_length("ab|cd", 3, None, Codepoints).is_err());
+        assert_eq!(
+            restrict_length(r"\bx", 0, Some(3), Codepoints).unwrap(),
+            r"\bx"
+        );
+    }
+
+    #[test]
+    fn units() {
+        let full_match = |pattern: &str, min: u64, max: Option<u64>, semantics| {
+            let regex = restrict_length(pattern, min, max, semantics).unwrap();
+            Regex::new(&format!("^(?:{})$", regex)).unwrap()
+        };
+        let utf16 = full_match("[a-z😀]+", 2, Some(3), Utf16);
+        assert!(utf16.is_match("😀"));
+        assert!(utf16.is_match("a😀"));
+        assert!(!utf16.is_match("😀😀"));
+        assert!(!utf16.is_match("a"));
+        let bytes = full_match("[a-zé]*", 0, Some(3), Bytes);
+        assert!(bytes.is_match("éa"));
+        assert!(!bytes.is_match("éé"));
+
+        let string = |min: u64, max: Option<u64>, semantics| {
+            let regex = restrict_string_length(STRING_INNER, min, max, semantics).unwrap();
+            Regex::new(&format!("^(?:{})$", regex)).unwrap()
+        };
+        let utf16 = string(1, Some(4), Utf16);
+        assert!(utf16.is_match("abcd"));
+        assert!(utf16.is_match(r#"ab\"c"#));
+        assert!(utf16.is_match("😀😀"));
+        assert!(!utf16.is_match("abcde"));
+        assert!(!utf16.is_match("😀😀a"));
+        assert!(!utf16.is_match(""));
+        let bytes = string(1, Some(4), Bytes);
+        assert!(bytes.is_match("éé"));
+        assert!(bytes.is_match("😀"));
+        assert!(!bytes.is_match("éab"));
+        assert!(!bytes.is_match("😀a"));
+        let unbounded = string(2, None, Bytes);
+        assert!(unbounded.is_match("é€😀"));
+        assert!(!unbounded.is_match("a"));
+    }
+}
@@ -0,0 +1,354 @@
+/// Detection of the constructs of a schema which make its regular expression explode.
+use super::parsing::MAX_OPTIONAL_REGEX_PROPERTIES;
+use super::MAX_OPTIONAL_PROPERTIES;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Bounds of repetitions from which the regular expressions become large.
+const LARGE_REPETITION: u64 = 256;
+const HUGE_REPETITION: u64 = 4096;
+/// Numbers of `enum` values from which the alternations become large.
+const LARGE_ENUM: usize = 1000;
+const HUGE_ENUM: usize = 10_000;
+/// Depth of nested objects whose properties are all optional from which the alternations
+/// multiply out of hand, as each of them repeats the properties it nests.
+const DEEP_OPTIONAL_NESTING: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The regular expression is larger than needed.
+    Info,
+    /// The compilation is likely to be slow and its automaton large.
+    Warning,
+    /// The compilation is likely to exhaust its time or memory.
+    Error,
+}
+
+/// Construct of a schema flagged by [`lint_schema`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    pub severity: Severity,
+    /// JSON Pointer to the subschema holding the construct.
+    pub pointer: String,
+    pub message: String,
+    /// Rewrite of the schema avoiding the construct.
+    pub suggestion: String,
+}
+
+/// Flags the constructs of a schema known to make its compilation explode, without compiling
+/// it: huge bounded repetitions, deeply nested optional properties, massive enums and
+/// unbounded additional properties.
+pub fn lint_schema(schema: &Value) -> Vec<Lint> {
+    let mut linter = Linter::default();
+    linter.visit(schema, &mut String::new(), 0);
+    linter.lints
+}
+
+#[derive(Default)]
+struct Linter {
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    fn push(&mut self, severity: Severity, pointer: &str, message: String, suggestion: &str) {
+        self.lints.push(Lint {
+            severity,
+            pointer: pointer.to_string(),
+            message,
+            suggestion: suggestion.to_string(),
+        });
+    }
+
+    /// Visits a subschema, nested in `optional_depth` objects whose properties are all
+    /// optional.
+    fn visit(&mut self, schema: &Value, pointer: &mut String, optional_depth: usize) {
+        let Some(obj) = schema.as_object() else {
+            return;
+        };
+        self.check_repetitions(obj, pointer);
+        self.check_enum(obj, pointer);
+        self.check_additional_properties(obj, pointer);
+        let optional_depth = self.check_optional_properties(obj, pointer, optional_depth);
+
+        for (keyword, value) in obj {
+            match (keyword.as_str(), value) {
+                (
+                    "properties" | "patternProperties" | "$defs" | "definitions"
+                    | "dependentSchemas",
+                    Value::Object(subschemas),
+                ) => {
+                    for (name, subschema) in subschemas {
+                        let depth = match keyword.as_str() {
+                            "properties" => optional_depth,
+                            _ => 0,
+                        };
+                        self.visit_at(subschema, pointer, &[keyword, name], depth);
+                    }
+                }
+                ("allOf" | "anyOf" | "oneOf" | "prefixItems", Value::Array(subschemas)) => {
+                    for (i, subschema) in subschemas.iter().enumerate() {
+                        self.visit_at(subschema, pointer, &[keyword, &i.to_string()], 0);
+                    }
+                }
+                (
+                    "items" | "additionalProperties" | "contains" | "not" | "if" | "then" | "else",
+                    subschema,
+                ) => self.visit_at(subschema, pointer, &[keyword], 0),
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_at(
+        &mut self,
+        schema: &Value,
+        pointer: &mut String,
+        tokens: &[&str],
+        optional_depth: usize,
+    ) {
+        let len = pointer.len();
+        for token in tokens {
+            pointer.push('/');
+            pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+        }
+        self.visit(schema, pointer, optional_depth);
+        pointer.truncate(len);
+    }
+
+    fn check_repetitions(&mut self, obj: &Map<String, Value>, pointer: &str) {
+        for keyword in [
+            "maxItems",
+            "maxLength",
+            "maxProperties",
+            "minItems",
+            "minLength",
+        ] {
+            let Some(bound) = obj.get(keyword).and_then(Value::as_u64) else {
+                continue;
+            };
+            if let Some(severity) = repetition_severity(bound) {
+                self.push(
+                    severity,
+                    pointer,
+                    format!("'{keyword}' of {bound} unrolls into {bound} repetitions"),
+                    &format!("lower '{keyword}', or drop it and validate the bound afterwards"),
+                );
+            }
+        }
+        if let Some(pattern) = obj.get("pattern").and_then(Value::as_str) {
+            if let Some(bound) = largest_quantifier(pattern) {
+                if let Some(severity) = repetition_severity(bound) {
+                    self.push(
+                        severity,
+                        pointer,
+                        format!("'pattern' repeats a subpattern up to {bound} times"),
+                        "replace the bounded quantifier with '*' or '+' and validate the bound \
+                         afterwards",
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_enum(&mut self, obj: &Map<String, Value>, pointer: &str) {
+        let Some(values) = obj.get("enum").and_then(Value::as_array) else {
+            return;
+        };
+        let severity = match values.len() {
+            n if n >= HUGE_ENUM => Severity::Error,
+            n if n >= LARGE_ENUM => Severity::Warning,
+            _ => return,
+        };
+        self.push(
+            severity,
+            pointer,
+            format!("'enum' alternates between {} values", values.len()),
+            "replace the values with a 'pattern' they match, or split them by a discriminating \
+             property",
+        );
+    }
+
+    fn check_additional_properties(&mut self, obj: &Map<String, Value>, pointer: &str) {
+        let is_object = match obj.get("type") {
+            Some(Value::String(t)) => t == "object",
+            Some(Value::Array(types)) => types.iter().any(|t| t == "object"),
+            _ => false,
+        };
+        let additional = obj.get("additionalProperties");
+        if !is_object
+            || obj.contains_key("properties")
+            || obj.contains_key("maxProperties")
+            || matches!(additional, Some(Value::Bool(false)))
+        {
+            return;
+        }
+        // Arbitrary values are objects and arrays nested in one another, constrained values
+        // only repeat.
+        let (severity, message) = match additional {
+            Some(Value::Object(_)) => (
+                Severity::Info,
+                "the object allows any number of additional properties",
+            ),
+            _ => (
+                Severity::Warning,
+                "the object allows any number of arbitrary properties, nested in one another",
+            ),
+        };
+        self.push(
+            severity,
+            pointer,
+            message.to_string(),
+            "declare the expected 'properties' with 'additionalProperties: false', or set \
+             'maxProperties'",
+        );
+    }
+
+    /// Flags the objects with too many optional properties and no required one, and returns the
+    /// depth of nested such objects their properties are at.
+    fn check_optional_properties(
+        &mut self,
+        obj: &Map<String, Value>,
+        pointer: &str,
+        optional_depth: usize,
+    ) -> usize {
+        let Some(properties) = obj.get("properties").and_then(Value::as_object) else {
+            return optional_depth;
+        };
+        let has_required = obj
+            .get("required")
+            .and_then(Value::as_array)
+            .is_some_and(|required| !required.is_empty());
+        if has_required || properties.is_empty() {
+            return 0;
+        }
+
+        if properties.len() > MAX_OPTIONAL_PROPERTIES {
+            self.push(
+                Severity::Error,
+                pointer,
+                format!(
+                    "the {} properties are all optional, more than the {} supported",
+                    properties.len(),
+                    MAX_OPTIONAL_PROPERTIES
+                ),
+                "make some of the properties 'required'",
+            );
+        } else if properties.len() > MAX_OPTIONAL_REGEX_PROPERTIES {
+            self.push(
+                Severity::Warning,
+                pointer,
+                format!(
+                    "the {} properties are all optional, more than the {} regular expressions \
+                     support",
+                    properties.len(),
+                    MAX_OPTIONAL_REGEX_PROPERTIES
+                ),
+                "compile the schema into an automaton, or make some of the properties 'required'",
+            );
+        }
+        let depth = optional_depth + 1;
+        if depth == DEEP_OPTIONAL_NESTING {
+            self.push(
+                Severity::Warning,
+                pointer,
+                format!("{depth} objects with only optional properties are nested in one another"),
+                "make some of the nested properties 'required', or flatten the objects",
+            );
+        }
+        depth
+    }
+}
+
+fn repetition_severity(bound: u64) -> Option<Severity> {
+    match bound {
+        n if n >= HUGE_REPETITION => Some(Severity::Error),
+        n if n >= LARGE_REPETITION => Some(Severity::Warning),
+        _ => None,
+    }
+}
+
+/// Largest bound of the `{n}`, `{n,}` and `{n,m}` quantifiers of a regular expression.
+fn largest_quantifier(pattern: &str) -> Option<u64> {
+    let mut largest = None;
+    let mut chars = pattern.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '{' if !in_class => {
+                let quantifier: String = chars.clone().take_while(|&c| c != '}').collect();
+                let bounds = quantifier
+                    .split(',')
+                    .map(|bound| bound.trim().parse::<u64>().ok())
+                    .collect::<Vec<_>>();
+                if bounds.len() <= 2 && bounds[0].is_some() {
+                    largest = bounds.into_iter().flatten().chain(largest).max();
+                }
+            }
+            _ => {}
+        }
+    }
+    largest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lints() {
+        let enum_values: Vec<u64> = (0..2000).collect();
+        let nested = json!({
+            "type": "object",
+            "properties": {"a": {"type": "object", "properties": {"b": {
+                "type": "object", "properties": {"c": {
+                    "type": "object", "properties": {"d": {"type": "string"}}
+                }}
+            }}}}
+        });
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 5000},
+                "code": {"type": "string", "pattern": "^[A-Z]{2,300}$"},
+                "level": {"enum": enum_values},
+                "extra": {"type": "object"},
+                "a/b": nested,
+                "name": {"type": "string", "maxLength": 64},
+                "counts": {"type": "object", "additionalProperties": {"type": "integer"}}
+            },
+            "required": ["name"]
+        });
+
+        let lints = lint_schema(&schema);
+        let flagged: Vec<(Severity, &str)> = lints
+            .iter()
+            .map(|lint| (lint.severity, lint.pointer.as_str()))
+            .collect();
+        assert_eq!(
+            flagged,
+            [
+                (Severity::Error, "/properties/tags"),
+                (Severity::Warning, "/properties/code"),
+                (Severity::Warning, "/properties/level"),
+                (Severity::Warning, "/properties/extra"),
+                (
+                    Severity::Warning,
+                    "/properties/a~1b/properties/a/properties/b/properties/c"
+                ),
+                (Severity::Info, "/properties/counts"),
+            ]
+        );
+        assert_eq!(
+            lints[1].message,
+            "'pattern' repeats a subpattern up to 300 times"
+        );
+        assert!(lint_schema(&json!({"type": "string", "pattern": "[{]9999}"})).is_empty());
+    }
+}
@@ -0,0 +1,688 @@
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use crate::json_schema::resolver::{self, Resolver};
+use crate::json_schema::{length, pattern};
+use crate::Error;
+
+/// Keywords whose merged value is the largest of the two bounds.
+static LOWER_BOUNDS: [&str; 6] = [
+    "minimum",
+    "exclusiveMinimum",
+    "minLength",
+    "minItems",
+    "minProperties",
+    "minContains",
+];
+
+/// Keywords whose merged value is the smallest of the two bounds.
+static UPPER_BOUNDS: [&str; 6] = [
+    "maximum",
+    "exclusiveMaximum",
+    "maxLength",
+    "maxItems",
+    "maxProperties",
+    "maxContains",
+];
+
+/// Pairs of bounds that must stay ordered once merged.
+static BOUND_PAIRS: [(&str, &str); 4] = [
+    ("minimum", "maximum"),
+    ("minLength", "maxLength"),
+    ("minItems", "maxItems"),
+    ("minProperties", "maxProperties"),
+];
+
+/// Pairs of bounds of which at least one excludes its value, which must stay strictly ordered
+/// once merged.
+static STRICT_BOUND_PAIRS: [(&str, &str); 3] = [
+    ("minimum", "exclusiveMaximum"),
+    ("exclusiveMinimum", "maximum"),
+    ("exclusiveMinimum", "exclusiveMaximum"),
+];
+
+/// Keywords which do not constrain the instances, the merged schema keeping the first value.
+static ANNOTATIONS: [&str; 13] = [
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+    "$comment",
+    "$schema",
+    "$id",
+    "$anchor",
+    "$dynamicAnchor",
+    "$vocabulary",
+];
+
+/// Schema merged from an `allOf`, together with the targets of the references flattened into
+/// it.
+pub struct Merged<'a> {
+    pub schema: Value,
+    pub references: Vec<(String, &'a Value)>,
+}
+
+/// Merges an `allOf` schema, including its sibling keywords, into a single schema.
+///
+/// The subschemas of the merged keywords which are references are not resolved but merged into
+/// a nested `allOf`, compiled in turn, so that recursive definitions are unrolled no deeper than
+/// the references of the compiled schema.
+pub fn merge_all_of<'a>(
+    obj: &Map<String, Value>,
+    resolver: &Resolver<'a>,
+    base_uri: &str,
+) -> Result<Merged<'a>> {
+    let mut merger = Merger {
+        resolver,
+        base_uri,
+        resolving: Vec::new(),
+        references: Vec::new(),
+    };
+    // The merged schema mixes keywords of several resources, whose references are therefore
+    // made absolute, and is compiled within the resource it identifies already.
+    let mut obj = obj.clone();
+    obj.remove("$id");
+    resolver::absolutize_refs(&mut obj, base_uri);
+    let schema = merger.merge_all_of(&obj)?;
+    Ok(Merged {
+        schema,
+        references: merger.references,
+    })
+}
+
+struct Merger<'r, 'a> {
+    resolver: &'r Resolver<'a>,
+    base_uri: &'r str,
+    /// Targets of the references being flattened.
+    resolving: Vec<&'a Value>,
+    /// Targets of the references flattened so far.
+    references: Vec<(String, &'a Value)>,
+}
+
+impl<'a> Merger<'_, 'a> {
+    /// Merges an `allOf` schema, including its sibling keywords, into a single schema.
+    fn merge_all_of(&mut self, obj: &Map<String, Value>) -> Result<Value> {
+        let all_of = match obj.get("allOf") {
+            Some(Value::Array(all_of)) => all_of,
+            _ => return Err(anyhow!("'allOf' must be an array")),
+        };
+
+        let mut siblings = obj.clone();
+        siblings.remove("allOf");
+
+        let mut merged = Value::Object(siblings);
+        for subschema in all_of {
+            merged = self.merge(&merged, subschema)?;
+        }
+
+        self.distribute_alternatives(merged)
+    }
+
+    /// Merges two schemas into one accepting the intersection of their instances.
+    fn merge(&mut self, left: &Value, right: &Value) -> Result<Value> {
+        let left = self.flatten(left)?;
+        let right = self.flatten(right)?;
+
+        if left == right {
+            return Ok(left);
+        }
+
+        let (left, right) = match (left, right) {
+            (Value::Bool(true), other) | (other, Value::Bool(true)) => return Ok(other),
+            (Value::Bool(false), _) | (_, Value::Bool(false)) => return Ok(Value::Bool(false)),
+            (Value::Object(left), Value::Object(right)) => (left, right),
+            (left, right) => {
+                return Err(anyhow!(
+                    "Invalid JSON Schema in 'allOf': expected objects, got {} and {}",
+                    left,
+                    right
+                ))
+            }
+        };
+
+        let mut merged = left;
+        for (keyword, right_value) in right {
+            let value = match merged.get(&keyword) {
+                None => right_value,
+                Some(left_value) => self.merge_keyword(&keyword, left_value, &right_value)?,
+            };
+            merged.insert(keyword, value);
+        }
+
+        check_merged(&mut merged)?;
+
+        Ok(Value::Object(merged))
+    }
+
+    /// Merges the subschemas of a keyword, leaving the ones which are references to a nested
+    /// `allOf`.
+    fn merge_subschemas(&mut self, left: &Value, right: &Value) -> Result<Value> {
+        let is_reference = |schema: &Value| schema.get("$ref").is_some();
+        if left != right && (is_reference(left) || is_reference(right)) {
+            return Ok(json!({"allOf": [left, right]}));
+        }
+        self.merge(left, right)
+    }
+
+    /// Resolves `$ref`s and nested `allOf`s so that the schema's keywords can be merged directly.
+    fn flatten(&mut self, schema: &Value) -> Result<Value> {
+        let obj = match schema {
+            Value::Object(obj) => obj,
+            _ => return Ok(schema.clone()),
+        };
+
+        if let Some(ref_path) = obj.get("$ref") {
+            let ref_path = ref_path
+                .as_str()
+                .ok_or_else(|| anyhow!("'$ref' must be a string"))?;
+            let referenced = self.resolver.resolve(self.base_uri, ref_path)?;
+            if self
+                .resolving
+                .iter()
+                .any(|&target| std::ptr::eq(target, referenced.schema))
+            {
+                return Err(anyhow!(
+                    "Reference '{}' is merged into itself through 'allOf'",
+                    ref_path
+                ));
+            }
+            if !self
+                .references
+                .iter()
+                .any(|&(_, target)| std::ptr::eq(target, referenced.schema))
+            {
+                self.references
+                    .push((ref_path.to_string(), referenced.schema));
+            }
+
+            // The target is merged out of its resource, which it does not identify anymore.
+            let mut target = referenced.schema.clone();
+            if let Value::Object(target) = &mut target {
+                resolver::absolutize_refs(target, &referenced.base_uri);
+                target.remove("$id");
+            }
+            let mut siblings = obj.clone();
+            siblings.remove("$ref");
+
+            self.resolving.push(referenced.schema);
+            let merged = self.merge(&target, &Value::Object(siblings));
+            self.resolving.pop();
+            return merged;
+        }
+
+        if obj.contains_key("allOf") {
+            return self.merge_all_of(obj);
+        }
+
+        Ok(schema.clone())
+    }
+
+    fn merge_keyword(&mut self, keyword: &str, left: &Value, right: &Value) -> Result<Value> {
+        if left == right {
+            return Ok(left.clone());
+        }
+
+        match keyword {
+            "type" => merge_types(left, right),
+            "properties" => {
+                let (left_props, right_props) = match (left.as_object(), right.as_object()) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return Err(anyhow!("'properties' must be an object")),
+                };
+
+                let mut merged = left_props.clone();
+                for (name, right_schema) in right_props {
+                    let schema = match merged.get(name) {
+                        None => right_schema.clone(),
+                        Some(left_schema) => self.merge_subschemas(left_schema, right_schema)?,
+                    };
+                    merged.insert(name.clone(), schema);
+                }
+                Ok(Value::Object(merged))
+            }
+            "required" => {
+                let mut merged = left.as_array().cloned().unwrap_or_default();
+                for name in right.as_array().into_iter().flatten() {
+                    if !merged.contains(name) {
+                        merged.push(name.clone());
+                    }
+                }
+                Ok(Value::Array(merged))
+            }
+            "enum" => {
+                let (left_values, right_values) = match (left.as_array(), right.as_array()) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return Err(anyhow!("'enum' must be an array")),
+                };
+
+                let common: Vec<Value> = left_values
+                    .iter()
+                    .filter(|value| right_values.contains(value))
+                    .cloned()
+                    .collect();
+                if common.is_empty() {
+                    return Err(conflict(keyword, left, right));
+                }
+                Ok(Value::Array(common))
+            }
+            "items"
+            | "additionalProperties"
+            | "additionalItems"
+            | "unevaluatedItems"
+            | "unevaluatedProperties"
+            | "contains"
+            | "propertyNames" => self.merge_subschemas(left, right),
+            "patternProperties" | "dependentSchemas" => {
+                let (left_schemas, right_schemas) = match (left.as_object(), right.as_object()) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return Err(anyhow!("'{}' must be an object", keyword)),
+                };
+
+                let mut merged = left_schemas.clone();
+                for (key, right_schema) in right_schemas {
+                    let schema = match merged.get(key) {
+                        None => right_schema.clone(),
+                        Some(left_schema) => self.merge_subschemas(left_schema, right_schema)?,
+                    };
+                    merged.insert(key.clone(), schema);
+                }
+                Ok(Value::Object(merged))
+            }
+            "dependentRequired" => {
+                let (left_names, right_names) = match (left.as_object(), right.as_object()) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return Err(anyhow!("'dependentRequired' must be an object")),
+                };
+
+                let mut merged = left_names.clone();
+                for (key, names) in right_names {
+                    let required = merged.entry(key.clone()).or_insert_with(|| json!([]));
+                    let required = required
+                        .as_array_mut()
+                        .ok_or_else(|| anyhow!("'dependentRequired' must map to arrays"))?;
+                    for name in names.as_array().into_iter().flatten() {
+                        if !required.contains(name) {
+                            required.push(name.clone());
+                        }
+                    }
+                }
+                Ok(Value::Object(merged))
+            }
+            // The references being absolute, the definitions are only kept for the record.
+            "$defs" | "definitions" => {
+                let mut merged = right.as_object().cloned().unwrap_or_default();
+                merged.extend(left.as_object().cloned().unwrap_or_default());
+                Ok(Value::Object(merged))
+            }
+            "allOf" => {
+                let mut merged = left.as_array().cloned().unwrap_or_default();
+                merged.extend(right.as_array().cloned().unwrap_or_default());
+                Ok(Value::Array(merged))
+            }
+            // An instance matching neither schema matches neither of the two.
+            "not" => Ok(json!({"anyOf": [left, right]})),
+            "uniqueItems" => match (left.as_bool(), right.as_bool()) {
+                (Some(l), Some(r)) => Ok(Value::Bool(l || r)),
+                _ => Err(anyhow!("'uniqueItems' must be a boolean")),
+            },
+            "multipleOf" => {
+                multiple_of(left, right).ok_or_else(|| unmergeable(keyword, left, right))
+            }
+            "prefixItems" => {
+                let (left_items, right_items) = match (left.as_array(), right.as_array()) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return Err(anyhow!("'prefixItems' must be an array")),
+                };
+
+                let (longest, shortest) = if left_items.len() >= right_items.len() {
+                    (left_items, right_items)
+                } else {
+                    (right_items, left_items)
+                };
+
+                let merged: Result<Vec<Value>> = longest
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| match shortest.get(i) {
+                        Some(other) => self.merge_subschemas(item, other),
+                        None => Ok(item.clone()),
+                    })
+                    .collect();
+                Ok(Value::Array(merged?))
+            }
+            "anyOf" | "oneOf" => {
+                let (left_alternatives, right_alternatives) =
+                    match (left.as_array(), right.as_array()) {
+                        (Some(l), Some(r)) => (l, r),
+                        _ => return Err(anyhow!("'{}' must be an array", keyword)),
+                    };
+
+                let mut alternatives = Vec::new();
+                for l in left_alternatives {
+                    for r in right_alternatives {
+                        match self.merge(l, r) {
+                            Ok(alternative) => alternatives.push(alternative),
+                            Err(e) if is_conflict(&e) => continue,
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                if alternatives.is_empty() {
+                    return Err(conflict(keyword, left, right));
+                }
+                Ok(Value::Array(alternatives))
+            }
+            // Distinct constants can not both hold.
+            "const" => Err(conflict(keyword, left, right)),
+            // Both patterns hold, so the merged one matches the strings they both match.
+            "pattern" | "x-pattern-override" => {
+                let (Some(left_pattern), Some(right_pattern)) = (left.as_str(), right.as_str())
+                else {
+                    return Err(anyhow!("'{}' must be a string", keyword));
+                };
+                let intersection = pattern::embedded_pattern(left_pattern).and_then(|l| {
+                    let r = pattern::embedded_pattern(right_pattern)?;
+                    length::intersect_patterns(&l, &r)
+                });
+                match intersection {
+                    Ok(Some(pattern)) => Ok(Value::String(pattern)),
+                    Ok(None) => Err(conflict(keyword, left, right)),
+                    Err(_) => Err(unmergeable(keyword, left, right)),
+                }
+            }
+            keyword if LOWER_BOUNDS.contains(&keyword) => Ok(pick_bound(left, right, f64::max)),
+            keyword if UPPER_BOUNDS.contains(&keyword) => Ok(pick_bound(left, right, f64::min)),
+            keyword if ANNOTATIONS.contains(&keyword) => Ok(left.clone()),
+            // Keeping either value would accept instances the other rejects, as with
+            // `if`/`then`/`else`.
+            _ => Err(unmergeable(keyword, left, right)),
+        }
+    }
+
+    /// Pushes sibling constraints into each `anyOf`/`oneOf` alternative, so that they are not
+    /// ignored once the schema is compiled.
+    fn distribute_alternatives(&mut self, merged: Value) -> Result<Value> {
+        let obj = match merged {
+            Value::Object(obj) => obj,
+            other => return Ok(other),
+        };
+
+        let keyword = match ["anyOf", "oneOf"]
+            .into_iter()
+            .find(|k| obj.contains_key(*k))
+        {
+            Some(keyword) => keyword,
+            None => return Ok(Value::Object(obj)),
+        };
+
+        let mut siblings = obj.clone();
+        let alternatives = siblings.remove(keyword).unwrap_or(Value::Null);
+        let alternatives = alternatives
+            .as_array()
+            .ok_or_else(|| anyhow!("'{}' must be an array", keyword))?;
+
+        let has_constraints = siblings.keys().any(|k| {
+            !matches!(
+                k.as_str(),
+                "title"
+                    | "description"
+                    | "default"
+                    | "examples"
+                    | "$defs"
+                    | "definitions"
+                    | "$id"
+                    | "$schema"
+            )
+        });
+        if !has_constraints {
+            return Ok(Value::Object(obj));
+        }
+
+        let siblings = Value::Object(siblings);
+        let mut distributed = Vec::new();
+        for alternative in alternatives {
+            match self.merge(&siblings, alternative) {
+                Ok(alternative) => distributed.push(alternative),
+                Err(e) if is_conflict(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if distributed.is_empty() {
+            return Err(conflict(
+                keyword,
+                &siblings,
+                &Value::Array(alternatives.clone()),
+            ));
+        }
+
+        let mut result = Map::new();
+        result.insert(keyword.to_string(), Value::Array(distributed));
+        Ok(Value::Object(result))
+    }
+}
+
+fn merge_types(left: &Value, right: &Value) -> Result<Value> {
+    let as_types = |value: &Value| -> Result<Vec<String>> {
+        match value {
+            Value::String(t) => Ok(vec![t.clone()]),
+            Value::Array(types) => types
+                .iter()
+                .map(|t| {
+                    t.as_str()
+                        .map(String::from)
+                        .ok_or_else(|| anyhow!("'type' must be a string or an array of strings"))
+                })
+                .collect(),
+            _ => Err(anyhow!("'type' must be a string or an array of strings")),
+        }
+    };
+
+    let left_types = as_types(left)?;
+    let right_types = as_types(right)?;
+
+    let mut common: Vec<String> = Vec::new();
+    for l in &left_types {
+        for r in &right_types {
+            let t = match (l.as_str(), r.as_str()) {
+                (l, r) if l == r => l,
+                ("integer", "number") | ("number", "integer") => "integer",
+                _ => continue,
+            };
+            if !common.iter().any(|c| c == t) {
+                common.push(t.to_string());
+            }
+        }
+    }
+
+    match common.as_slice() {
+        [] => Err(conflict("type", left, right)),
+        [single] => Ok(Value::String(single.clone())),
+        _ => Ok(Value::Array(
+            common.into_iter().map(Value::String).collect(),
+        )),
+    }
+}
+
+/// Checks the merged schema's constraints for contradictions.
+fn check_merged(merged: &mut Map<String, Value>) -> Result<()> {
+    for (min_keyword, max_keyword) in BOUND_PAIRS {
+        if let (Some(min), Some(max)) = (merged.get(min_keyword), merged.get(max_keyword)) {
+            if let (Some(min_value), Some(max_value)) = (min.as_f64(), max.as_f64()) {
+                if min_value > max_value {
+                    return Err(conflict(&format!("{min_keyword}/{max_keyword}"), min, max));
+                }
+            }
+        }
+    }
+
+    for (min_keyword, max_keyword) in STRICT_BOUND_PAIRS {
+        if let (Some(min), Some(max)) = (merged.get(min_keyword), merged.get(max_keyword)) {
+            if let (Some(min_value), Some(max_value)) = (min.as_f64(), max.as_f64()) {
+                if min_value >= max_value {
+                    return Err(conflict(&format!("{min_keyword}/{max_keyword}"), min, max));
+                }
+            }
+        }
+    }
+
+    if let (Some(constant), Some(choices)) = (merged.get("const"), merged.get("enum")) {
+        if !choices
+            .as_array()
+            .map(|choices| choices.contains(constant))
+            .unwrap_or(false)
+        {
+            return Err(conflict("const/enum", constant, choices));
+        }
+        merged.remove("enum");
+    }
+
+    Ok(())
+}
+
+/// Least common multiple of two `multipleOf` values, found when both are integers once scaled
+/// by the same power of ten.
+fn multiple_of(left: &Value, right: &Value) -> Option<Value> {
+    fn gcd(a: u64, b: u64) -> u64 {
+        match b {
+            0 => a,
+            b => gcd(b, a % b),
+        }
+    }
+
+    let (l, r) = (left.as_f64()?, right.as_f64()?);
+    if l <= 0.0 || r <= 0.0 {
+        return None;
+    }
+    let exponent = (0..=9).find(|&exponent| {
+        let scale = 10f64.powi(exponent);
+        [l, r]
+            .iter()
+            .all(|value| ((value * scale).round() - value * scale).abs() < 1e-9)
+    })?;
+    let scale = 10f64.powi(exponent);
+    let (l, r) = ((l * scale).round() as u64, (r * scale).round() as u64);
+    let lcm = (l / gcd(l, r)).checked_mul(r)?;
+    match exponent {
+        0 => Some(json!(lcm)),
+        _ => Some(json!(lcm as f64 / scale)),
+    }
+}
+
+fn pick_bound(left: &Value, right: &Value, choose: fn(f64, f64) -> f64) -> Value {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(l), Some(r)) if choose(l, r) == r => right.clone(),
+        _ => left.clone(),
+    }
+}
+
+fn conflict(keyword: &str, left: &Value, right: &Value) -> anyhow::Error {
+    Error::SchemaConflict {
+        keyword: keyword.to_string(),
+        left: left.to_string(),
+        right: right.to_string(),
+    }
+    .into()
+}
+
+fn unmergeable(keyword: &str, left: &Value, right: &Value) -> anyhow::Error {
+    Error::UnmergeableKeyword {
+        keyword: keyword.to_string(),
+        left: left.to_string(),
+        right: right.to_string(),
+    }
+    .into()
+}
+
+fn is_conflict(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<Error>(),
+        Some(Error::SchemaConflict { .. })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(schema: Value) -> Result<Value> {
+        let resolver = Resolver::new(&schema, &[]);
+        let obj = schema.as_object().expect("an object");
+        Ok(merge_all_of(obj, &resolver, "")?.schema)
+    }
+
+    #[test]
+    fn merge_keywords() {
+        let schema = merged(json!({"allOf": [
+            {"multipleOf": 4, "not": {"const": 1}, "uniqueItems": false, "title": "A"},
+            {"multipleOf": 6, "not": {"const": 2}, "uniqueItems": true, "title": "B"},
+            {"patternProperties": {"^a": {"minLength": 1}}, "dependentRequired": {"a": ["b"]}},
+            {"patternProperties": {"^a": {"maxLength": 3}}, "dependentRequired": {"a": ["c"]}},
+        ]}))
+        .unwrap();
+        assert_eq!(
+            schema,
+            json!({
+                "multipleOf": 12,
+                "not": {"anyOf": [{"const": 1}, {"const": 2}]},
+                "uniqueItems": true,
+                "title": "A",
+                "patternProperties": {"^a": {"minLength": 1, "maxLength": 3}},
+                "dependentRequired": {"a": ["b", "c"]},
+            })
+        );
+        let schema = merged(json!({"allOf": [{"multipleOf": 0.5}, {"multipleOf": 0.3}]}));
+        assert_eq!(schema.unwrap(), json!({"multipleOf": 1.5}));
+
+        let error = merged(json!({"allOf": [
+            {"if": {"const": 1}, "then": {"const": 1}},
+            {"if": {"const": 2}, "then": {"const": 2}},
+        ]}))
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(Error::UnmergeableKeyword { keyword, .. }) if keyword == "if"
+        ));
+        let error = merged(json!({"allOf": [{"x-unknown": 1}, {"x-unknown": 2}]})).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(Error::UnmergeableKeyword { .. })
+        ));
+
+        let schema = merged(json!({"allOf": [{"pattern": "[a-z]+"}, {"pattern": "[a-c0-9]+"}]}));
+        let pattern = schema.unwrap()["pattern"].as_str().unwrap().to_string();
+        let pattern = regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+        assert!(pattern.is_match("abc"));
+        assert!(!pattern.is_match("ab1") && !pattern.is_match(""));
+        let error = merged(json!({"allOf": [{"format": "date"}, {"format": "email"}]}));
+        assert!(matches!(
+            error.unwrap_err().downcast_ref(),
+            Some(Error::UnmergeableKeyword { keyword, .. }) if keyword == "format"
+        ));
+
+        for (schema, keyword) in [
+            (
+                json!({"allOf": [{"pattern": "a+"}, {"pattern": "b+"}]}),
+                "pattern",
+            ),
+            (
+                json!({"allOf": [{"minimum": 2}, {"exclusiveMaximum": 2}]}),
+                "minimum/exclusiveMaximum",
+            ),
+            (
+                json!({"allOf": [{"exclusiveMinimum": 3}, {"maximum": 2}]}),
+                "exclusiveMinimum/maximum",
+            ),
+        ] {
+            let error = merged(schema).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(Error::SchemaConflict { keyword: k, .. }) if k == keyword
+            ));
+        }
+        assert!(merged(json!({"allOf": [{"minimum": 1}, {"exclusiveMaximum": 2}]})).is_ok());
+    }
+}
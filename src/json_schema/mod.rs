@@ -1,11 +1,41 @@
+mod builder;
+mod codes;
+mod draft;
+mod extension;
 mod helpers;
+mod length;
+mod lint;
+mod merge;
 mod parsing;
+mod pattern;
+mod ranges;
+mod resolver;
+mod satisfiability;
+mod strftime;
 mod types;
+mod wordlist;
 
+pub use builder::{Array, Int, Num, Object, Schema, SchemaBuilder, Str};
+pub use codes::{country_code_regex, currency_code_regex, language_tag_regex};
+pub use extension::{Extension, KeywordExtension};
+pub use lint::{lint_schema, Lint, Severity};
+pub use parsing::{MAX_OPTIONAL_PROPERTIES, MAX_UNORDERED_PROPERTIES};
+pub use ranges::integer_range_regex;
+pub use resolver::RefLoader;
 pub use types::*;
+pub use wordlist::Wordlist;
 
+use crate::cancellation::CancellationToken;
+use crate::index::FSMInfo;
 use anyhow::{anyhow, Result};
 use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone)]
 enum SchemaKeyword {
@@ -17,13 +47,306 @@ enum SchemaKeyword {
     Enum,
     Const,
     Ref,
+    DynamicRef,
     Type,
     EmptyObject,
 }
 
+/// Options controlling how a JSON Schema is compiled to a regular expression.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    whitespace: Whitespace,
+    draft: Option<Draft>,
+    unicode_strings: bool,
+    strict_escapes: bool,
+    ascii_only: bool,
+    integer_forms: Option<IntegerForms>,
+    exponents: Option<bool>,
+    json5_numbers: bool,
+    time_format: Option<(FractionalSeconds, TimeOffset)>,
+    length_semantics: LengthSemantics,
+    unordered_required: bool,
+    case_insensitive: bool,
+    max_array_items: Option<u64>,
+    max_nesting_depth: Option<usize>,
+    cancellation: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    markdown_fence: bool,
+    fence_preamble: usize,
+    ref_loader: Option<Arc<dyn RefLoader>>,
+    keyword_extension: Option<Arc<dyn KeywordExtension>>,
+    wordlists: HashMap<String, Arc<Wordlist>>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pattern matched between JSON tokens, [`WHITESPACE`] by default.
+    pub fn whitespace_pattern(self, whitespace_pattern: impl Into<String>) -> Self {
+        self.whitespace(Whitespace::Pattern(whitespace_pattern.into()))
+    }
+
+    /// Whitespace allowed between JSON tokens.
+    pub fn whitespace(mut self, whitespace: Whitespace) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Draft used to interpret the schema. By default it is read from the `$schema` keyword of
+    /// each schema resource, falling back to draft 2020-12.
+    pub fn draft(mut self, draft: Draft) -> Self {
+        self.draft = Some(draft);
+        self
+    }
+
+    /// Lets strings be written with any JSON escape sequence, including `\uXXXX`, next to raw
+    /// non-ASCII code points. Raw control characters are still forbidden.
+    pub fn unicode_strings(mut self, unicode_strings: bool) -> Self {
+        self.unicode_strings = unicode_strings;
+        self
+    }
+
+    /// Accepts every legal JSON escape sequence in strings, but only those: `\u` escapes of
+    /// UTF-16 surrogates must form a high/low pair. Implies [`Options::unicode_strings`].
+    pub fn strict_escapes(mut self, strict_escapes: bool) -> Self {
+        self.strict_escapes = strict_escapes;
+        self
+    }
+
+    /// Restricts strings to printable ASCII characters, non-ASCII text has to be written with
+    /// `\uXXXX` escapes. Property names and `enum`/`const` values are escaped accordingly.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Writes the integers of `integer` schemas in the given lexical forms, bounded or not. By
+    /// default, unbounded integers may be written `-0` while bounded ones are canonical.
+    /// Integers bounded by their number of digits keep their form.
+    pub fn integer_forms(mut self, integer_forms: IntegerForms) -> Self {
+        self.integer_forms = Some(integer_forms);
+        self
+    }
+
+    /// Whether numbers may be written with an exponent, such as `1e-5`, which they may by
+    /// default. The `allowExponent` keyword of a `number` schema overrides it for that schema.
+    pub fn exponents(mut self, exponents: bool) -> Self {
+        self.exponents = Some(exponents);
+        self
+    }
+
+    /// Lets the numbers and integers whose schema sets no bounds and no numbers of digits be
+    /// written as JSON5 allows, for relaxed parsers: integers in hexadecimal, such as `0x1F`,
+    /// and numbers as `Infinity`, `-Infinity` or `NaN` too. The output is no longer JSON.
+    pub fn json5_numbers(mut self, json5_numbers: bool) -> Self {
+        self.json5_numbers = json5_numbers;
+        self
+    }
+
+    /// Writes the strings of the `time` format with or without fractional seconds and with the
+    /// given offset, instead of their default pattern [`TIME`]. Bounded times keep it.
+    pub fn time_format(
+        mut self,
+        fractional_seconds: FractionalSeconds,
+        offset: TimeOffset,
+    ) -> Self {
+        self.time_format = Some((fractional_seconds, offset));
+        self
+    }
+
+    /// Unit in which `minLength` and `maxLength` count the length of strings, code points by
+    /// default as the specification says. Lengths in UTF-16 code units agree with JavaScript
+    /// validators.
+    pub fn length_semantics(mut self, length_semantics: LengthSemantics) -> Self {
+        self.length_semantics = length_semantics;
+        self
+    }
+
+    /// Accepts the required properties of an object in any order rather than in declaration
+    /// order, for objects with at most [`MAX_UNORDERED_PROPERTIES`] required properties.
+    /// Optional properties follow the required ones.
+    ///
+    /// Automata keep track of the properties written so far, while regular expressions write
+    /// out every ordering, so they only accept the properties of objects with at most four
+    /// required properties in any order.
+    pub fn unordered_required(mut self, unordered_required: bool) -> Self {
+        self.unordered_required = unordered_required;
+        self
+    }
+
+    /// Matches the string `enum` and `const` values regardless of the case of their letters.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Largest number of items of the arrays whose schema sets no `maxItems`.
+    pub fn max_array_items(mut self, max_array_items: u64) -> Self {
+        self.max_array_items = Some(max_array_items);
+        self
+    }
+
+    /// Largest number of objects and arrays nested in one another. Optional properties,
+    /// alternatives and array items nested deeper are left out, and schemas requiring them
+    /// fail to compile.
+    pub fn max_nesting_depth(mut self, max_nesting_depth: usize) -> Self {
+        self.max_nesting_depth = Some(max_nesting_depth);
+        self
+    }
+
+    /// Token cancelling the compilation, which then fails with [`crate::Error::Cancelled`].
+    pub fn cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Longest time the compilation may take, after which it fails with
+    /// [`crate::Error::Timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Wraps the JSON in a Markdown code fence, `` ```json `` and `` ``` `` on their own lines,
+    /// as chat models are often prompted to answer.
+    pub fn markdown_fence(mut self, markdown_fence: bool) -> Self {
+        self.markdown_fence = markdown_fence;
+        self
+    }
+
+    /// Allows up to `max_len` characters of free text without backticks before the Markdown
+    /// fence, ended by a line break. Implies [`Options::markdown_fence`].
+    pub fn fence_preamble(mut self, max_len: usize) -> Self {
+        self.fence_preamble = max_len;
+        self.markdown_fence = true;
+        self
+    }
+
+    /// Loader of the documents of the absolute `$ref`s which are not resources of the schema.
+    /// They are all loaded before the compilation starts.
+    pub fn ref_loader(mut self, ref_loader: impl RefLoader + 'static) -> Self {
+        self.ref_loader = Some(Arc::new(ref_loader));
+        self
+    }
+
+    /// Handler of the `x-*` keywords of the subschemas, which are otherwise ignored.
+    pub fn keyword_extension(mut self, keyword_extension: impl KeywordExtension + 'static) -> Self {
+        self.keyword_extension = Some(Arc::new(keyword_extension));
+        self
+    }
+
+    /// Registers `wordlist` under `name`, the value of the `x-wordlist` keyword of the strings
+    /// restricted to its words.
+    pub fn wordlist(mut self, name: impl Into<String>, wordlist: Wordlist) -> Self {
+        self.wordlists.insert(name.into(), Arc::new(wordlist));
+        self
+    }
+
+    /// Wraps the regular expression of a document ending with a line break in the Markdown
+    /// fence of the options, if any.
+    fn fence(&self, language: &str, document: String) -> String {
+        if !self.markdown_fence {
+            return document;
+        }
+        let preamble = match self.fence_preamble {
+            0 => String::new(),
+            max_len => format!("([^`]{{0,{}}}\n)?", max_len),
+        };
+        format!("{}```{}\n{}```", preamble, language, document)
+    }
+}
+
 pub fn build_regex_from_schema(json: &str, whitespace_pattern: Option<&str>) -> Result<String> {
+    let mut options = Options::new();
+    if let Some(pattern) = whitespace_pattern {
+        options = options.whitespace_pattern(pattern);
+    }
+    build_regex_from_schema_with_options(json, &options)
+}
+
+pub fn build_regex_from_schema_with_options(json: &str, options: &Options) -> Result<String> {
     let json_value: Value = serde_json::from_str(json)?;
-    to_regex(&json_value, whitespace_pattern, &json_value)
+    to_regex_with_options(&json_value, &json_value, options)
+}
+
+/// Compiles the schema read from a file, which may be gzip-compressed with the `gzip` feature.
+/// The JSON is parsed as it is read, rather than loaded in memory first. Files named `*.yaml` or
+/// `*.yml` are read as YAML with the `yaml` feature, and files named `*.json5` or `*.jsonc` as
+/// JSON5 with the `json5` feature.
+pub fn build_regex_from_schema_file(path: impl AsRef<Path>, options: &Options) -> Result<String> {
+    let path = path.as_ref();
+    let file_error = |source| crate::Error::SchemaFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let parse_text: Option<fn(&str) -> Result<Value>> =
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Some(from_yaml),
+            #[cfg(feature = "json5")]
+            Some("json5" | "jsonc") => Some(from_json5),
+            _ => None,
+        };
+    if let Some(parse_text) = parse_text {
+        let text = std::fs::read_to_string(path).map_err(file_error)?;
+        let json_value = parse_text(&text)
+            .map_err(|e| anyhow!("Invalid schema file '{}': {}", path.display(), e))?;
+        return to_regex_with_options(&json_value, &json_value, options);
+    }
+    let file = File::open(path).map_err(file_error)?;
+    let json_value = read_schema(BufReader::new(file)).map_err(|e| {
+        match e
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<serde_json::Error>())
+        {
+            Some(json_error) => anyhow!(
+                "Invalid JSON in schema file '{}': {}",
+                path.display(),
+                json_error
+            ),
+            None => file_error(e).into(),
+        }
+    })?;
+    to_regex_with_options(&json_value, &json_value, options)
+}
+
+/// Compiles the schema read from a reader, which may be gzip-compressed with the `gzip`
+/// feature.
+pub fn build_regex_from_schema_reader(reader: impl Read, options: &Options) -> Result<String> {
+    let json_value = read_schema(BufReader::new(reader))?;
+    to_regex_with_options(&json_value, &json_value, options)
+}
+
+/// Parses a schema written in YAML, as OpenAPI documents often are, into the JSON value compiled
+/// by [`to_regex_with_options`].
+#[cfg(feature = "yaml")]
+pub fn from_yaml(yaml: &str) -> Result<Value> {
+    serde_yaml::from_str(yaml).map_err(|e| anyhow!("Invalid YAML schema: {}", e))
+}
+
+/// Parses a schema written in JSON5, which allows comments, trailing commas, unquoted keys and
+/// single-quoted strings, into the JSON value compiled by [`to_regex_with_options`].
+#[cfg(feature = "json5")]
+pub fn from_json5(json5: &str) -> Result<Value> {
+    json5::from_str(json5).map_err(|e| anyhow!("Invalid JSON5 schema: {}", e))
+}
+
+/// Parses a JSON document, decompressing it first when it starts with the gzip magic bytes.
+fn read_schema(mut reader: impl BufRead) -> io::Result<Value> {
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        #[cfg(feature = "gzip")]
+        return Ok(serde_json::from_reader(BufReader::new(
+            flate2::bufread::GzDecoder::new(reader),
+        ))?);
+        #[cfg(not(feature = "gzip"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reading gzip-compressed schemas requires the `gzip` feature",
+        ));
+    }
+    Ok(serde_json::from_reader(reader)?)
 }
 
 pub fn to_regex(
@@ -31,54 +354,127 @@ pub fn to_regex(
     whitespace_pattern: Option<&str>,
     full_schema: &Value,
 ) -> Result<String> {
-    let whitespace_pattern = whitespace_pattern.unwrap_or(types::WHITESPACE);
-
-    match json {
-        Value::Object(obj) => {
-            let keyword = if obj.is_empty() {
-                SchemaKeyword::EmptyObject
-            } else {
-                [
-                    ("properties", SchemaKeyword::Properties),
-                    ("allOf", SchemaKeyword::AllOf),
-                    ("anyOf", SchemaKeyword::AnyOf),
-                    ("oneOf", SchemaKeyword::OneOf),
-                    ("prefixItems", SchemaKeyword::PrefixItems),
-                    ("enum", SchemaKeyword::Enum),
-                    ("const", SchemaKeyword::Const),
-                    ("$ref", SchemaKeyword::Ref),
-                    ("type", SchemaKeyword::Type),
-                ]
-                .iter()
-                .find_map(|&(key, schema_keyword)| {
-                    if obj.contains_key(key) {
-                        Some(schema_keyword)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow!("Unsupported JSON Schema structure {} \nMake sure it is valid to the JSON Schema specification and check if it's supported by Outlines.\nIf it should be supported, please open an issue.", json))?
-            };
-
-            match keyword {
-                SchemaKeyword::Properties => {
-                    parsing::parse_properties(obj, whitespace_pattern, full_schema)
-                }
-                SchemaKeyword::AllOf => parsing::parse_all_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::AnyOf => parsing::parse_any_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::OneOf => parsing::parse_one_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::PrefixItems => {
-                    parsing::parse_prefix_items(obj, whitespace_pattern, full_schema)
-                }
-                SchemaKeyword::Enum => parsing::parse_enum(obj, whitespace_pattern),
-                SchemaKeyword::Const => parsing::parse_const(obj, whitespace_pattern),
-                SchemaKeyword::Ref => parsing::parse_ref(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::Type => parsing::parse_type(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::EmptyObject => {
-                    parsing::parse_empty_object(whitespace_pattern, full_schema)
+    let mut options = Options::new();
+    if let Some(pattern) = whitespace_pattern {
+        options = options.whitespace_pattern(pattern);
+    }
+    to_regex_with_options(json, full_schema, &options)
+}
+
+pub fn to_regex_with_options(
+    json: &Value,
+    full_schema: &Value,
+    options: &Options,
+) -> Result<String> {
+    compile(json, full_schema, options, false).map(|(regex, _)| regex)
+}
+
+/// Compiles a schema into a regular expression, written with the markers of automata built
+/// apart from it when `fragments` is set, which are returned with it.
+fn compile(
+    json: &Value,
+    full_schema: &Value,
+    options: &Options,
+    fragments: bool,
+) -> Result<(String, Vec<FSMInfo>)> {
+    let embedded;
+    let options = match &options.whitespace {
+        Whitespace::Pattern(whitespace) => match pattern::embeddable_pattern(whitespace, false) {
+            Ok(Cow::Borrowed(_)) => options,
+            Ok(Cow::Owned(whitespace)) => {
+                embedded = options.clone().whitespace_pattern(whitespace);
+                &embedded
+            }
+            Err(reason) => {
+                return Err(crate::Error::InvalidWhitespacePattern {
+                    pattern: whitespace.clone(),
+                    reason,
                 }
+                .into())
             }
+        },
+        _ => options,
+    };
+    let documents = match &options.ref_loader {
+        Some(ref_loader) => resolver::load_documents(full_schema, ref_loader.as_ref())?,
+        None => Vec::new(),
+    };
+    let mut parser = parsing::Parser::new(full_schema, &documents, options);
+    if fragments {
+        parser = parser.with_fragments();
+    }
+    let regex = parser.compile(json)?;
+    let regex = match options.markdown_fence {
+        true => options.fence("json", format!("({})\n", regex)),
+        false => regex,
+    };
+    Ok((regex, parser.fragments()))
+}
+
+/// Compiles a schema into a byte-level automaton, failing with
+/// [`crate::Error::UnsatisfiableSchema`] when it admits no output at all, such as a schema whose
+/// constraints contradict each other. The error lists the innermost subschemas admitting no
+/// value.
+pub fn to_fsm_with_options(
+    json: &Value,
+    full_schema: &Value,
+    options: &Options,
+) -> Result<FSMInfo> {
+    let (regex, fragments) = compile(json, full_schema, options, true)?;
+    let fsm_info = FSMInfo::from_regex_with_fragments(&regex, &fragments)?;
+    if fsm_info.is_empty() {
+        return Err(crate::Error::UnsatisfiableSchema {
+            conflicts: satisfiability::unsatisfiable_subschemas(json, full_schema, options),
+        }
+        .into());
+    }
+    Ok(fsm_info)
+}
+
+pub fn build_fsm_from_schema(json: &str, options: &Options) -> Result<FSMInfo> {
+    let json_value: Value = serde_json::from_str(json)?;
+    to_fsm_with_options(&json_value, &json_value, options)
+}
+
+/// Compiles a schema into the regular expression of JSON Lines: between `min_lines` and
+/// `max_lines` instances of the schema, each on its own line ended by `\n`.
+///
+/// The JSON of each line must not contain line breaks, so that pretty-printed JSON and
+/// whitespace patterns matching `\n` are rejected. A Markdown fence wraps all the lines.
+pub fn build_jsonl_regex_from_schema(
+    json: &str,
+    min_lines: u64,
+    max_lines: Option<u64>,
+    options: &Options,
+) -> Result<String> {
+    if let Some(max_lines) = max_lines.filter(|&max_lines| max_lines < min_lines) {
+        return Err(anyhow!(
+            "max_lines ({}) must be greater than or equal to min_lines ({})",
+            max_lines,
+            min_lines
+        ));
+    }
+    match &options.whitespace {
+        Whitespace::Pretty(_) => {
+            return Err(anyhow!("JSON Lines can not hold pretty-printed JSON"));
+        }
+        Whitespace::Pattern(pattern)
+            if regex::Regex::new(pattern)?
+                .find_iter("\n")
+                .any(|m| !m.is_empty()) =>
+        {
+            return Err(anyhow!(
+                "The whitespace pattern '{}' of JSON Lines must not match line breaks",
+                pattern
+            ));
         }
-        _ => Err(anyhow!("Invalid JSON Schema: expected an object")),
+        _ => {}
     }
+
+    let line = build_regex_from_schema_with_options(json, &options.clone().markdown_fence(false))?;
+    let max_lines = max_lines.map_or_else(String::new, |max_lines| max_lines.to_string());
+    Ok(options.fence(
+        "jsonl",
+        format!(r"(({})\n){{{},{}}}", line, min_lines, max_lines),
+    ))
 }
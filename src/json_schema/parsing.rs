@@ -1,322 +1,1436 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::{anyhow, Result};
 use regex::escape;
 use serde_json::json;
 use serde_json::Value;
 
+use crate::cancellation::Deadline;
+use crate::fsm::{self, dawg::Dawg};
+use crate::index::FSMInfo;
+use crate::json_schema::draft;
+use crate::json_schema::extension::{self, Extension, KeywordExtension};
 use crate::json_schema::helpers;
-use crate::json_schema::to_regex;
+use crate::json_schema::length;
+use crate::json_schema::merge;
+use crate::json_schema::pattern;
+use crate::json_schema::ranges;
+use crate::json_schema::resolver::{self, Resolved, Resolver};
+use crate::json_schema::strftime;
 use crate::json_schema::types;
+use crate::json_schema::types::{
+    Draft, FractionalSeconds, IntegerForms, LengthSemantics, TimeOffset, Whitespace,
+};
+use crate::json_schema::{Options, SchemaKeyword, Wordlist};
+use crate::Error;
+
+/// Types implied by the keywords which only apply to their instances.
+const IMPLIED_TYPES: [(&str, &[&str]); 4] = [
+    (
+        "object",
+        &[
+            "additionalProperties",
+            "patternProperties",
+            "propertyNames",
+            "required",
+            "minProperties",
+            "maxProperties",
+        ],
+    ),
+    (
+        "array",
+        &[
+            "items",
+            "additionalItems",
+            "contains",
+            "minItems",
+            "maxItems",
+            "uniqueItems",
+        ],
+    ),
+    ("string", &["minLength", "maxLength", "pattern", "format"]),
+    (
+        "number",
+        &[
+            "minimum",
+            "maximum",
+            "exclusiveMinimum",
+            "exclusiveMaximum",
+            "multipleOf",
+        ],
+    ),
+];
+
+/// Pattern matching no string, the compilation of the schemas admitting no value.
+pub(crate) const NOTHING: &str = r"[^\s\S]";
+
+/// Number of times a recursive reference is unrolled before the branch containing it is pruned.
+pub(crate) const DEFAULT_MAX_RECURSION_DEPTH: usize = 3;
+
+/// Largest number of required properties accepted in any order, objects with more required
+/// properties keep the declaration order. The automaton of such an object keeps track of the
+/// set of required properties written so far, see [`FSMInfo::any_order`].
+pub const MAX_UNORDERED_PROPERTIES: usize = 8;
+
+/// Largest number of required properties a regular expression accepts in any order. It has no
+/// state to remember the properties written so far, so it writes out every ordering of them,
+/// and objects with more required properties keep the declaration order in regular
+/// expressions.
+const MAX_UNORDERED_REGEX_PROPERTIES: usize = 4;
+
+/// Number of values from which an `enum` is written as its minimal automaton, with the values
+/// sharing their prefixes and merging into their common suffixes, rather than as the
+/// alternation of its values.
+const LARGE_ENUM: usize = 32;
+
+/// Largest number of properties of an object without required properties. The automaton of such
+/// an object keeps track of the next property and of whether a property was written already,
+/// see [`FSMInfo::subsequences`].
+pub const MAX_OPTIONAL_PROPERTIES: usize = 512;
+
+/// Largest number of properties of an object without required properties in regular
+/// expressions. They write out the later properties once per property which may come first,
+/// so that they grow with the square of the number of properties.
+pub(crate) const MAX_OPTIONAL_REGEX_PROPERTIES: usize = 128;
+
+/// Compiles JSON schema values to regular expressions.
+pub(crate) struct Parser<'a> {
+    resolver: Resolver<'a>,
+    whitespace: &'a Whitespace,
+    /// Number of objects and arrays enclosing the value being compiled.
+    depth: usize,
+    /// Pattern matching a single character of a string's content.
+    string_inner: &'static str,
+    /// Unit of the lengths of strings.
+    length_semantics: LengthSemantics,
+    unordered_required: bool,
+    /// Whether string `enum` and `const` values match regardless of case.
+    case_insensitive: bool,
+    /// Largest number of items of arrays whose schema does not bound it.
+    max_array_items: Option<u64>,
+    /// Largest number of nested objects and arrays.
+    max_nesting_depth: Option<usize>,
+    /// Whether literal strings are written with non-ASCII characters escaped.
+    ascii_only: bool,
+    /// Lexical forms of the integers, the default patterns being used when unset.
+    integer_forms: Option<IntegerForms>,
+    /// Whether numbers may have an exponent, unless their schema decides otherwise.
+    exponents: bool,
+    /// Whether unconstrained numbers may be written in the extended forms of JSON5.
+    json5_numbers: bool,
+    /// Fractional seconds and offset of the `time` format, [`types::TIME`] being used when unset.
+    time_format: Option<(FractionalSeconds, TimeOffset)>,
+    /// Draft forced by the options, overriding `$schema`.
+    forced_draft: Option<Draft>,
+    /// Draft of the schema resource being compiled.
+    draft: Draft,
+    max_recursion_depth: usize,
+    /// Targets of the references currently being unrolled.
+    ref_stack: Vec<&'a Value>,
+    /// Base URIs of the schema resources entered so far, outermost first. The last one is the
+    /// base against which references are resolved, and the whole stack is the dynamic scope
+    /// used to resolve `$dynamicRef`.
+    base_uris: Vec<String>,
+    /// Names of the properties leading to the value being compiled, outermost first.
+    path: Vec<String>,
+    deadline: Deadline,
+    /// Handler of the unknown `x-*` keywords.
+    keyword_extension: Option<&'a dyn KeywordExtension>,
+    /// Wordlists of the `x-wordlist` keyword, by name.
+    wordlists: &'a HashMap<String, Arc<Wordlist>>,
+    /// Automata written by their markers in the regular expression, when it is compiled into
+    /// an automaton, see [`FSMInfo::from_regex_with_fragments`].
+    fragments: Option<Vec<FSMInfo>>,
+}
 
-pub fn parse_properties(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let mut regex = String::from(r"\{");
+impl<'a> Parser<'a> {
+    pub fn new(root: &'a Value, documents: &'a [(String, Value)], options: &'a Options) -> Self {
+        let resolver = Resolver::new(root, documents);
+        let base_uris = vec![resolver.base_uri().to_string()];
+        Self {
+            resolver,
+            whitespace: &options.whitespace,
+            depth: 0,
+            string_inner: types::string_inner(
+                options.unicode_strings,
+                options.strict_escapes,
+                options.ascii_only,
+            ),
+            length_semantics: options.length_semantics,
+            ascii_only: options.ascii_only,
+            integer_forms: options.integer_forms,
+            exponents: options.exponents.unwrap_or(true),
+            json5_numbers: options.json5_numbers,
+            time_format: options.time_format,
+            unordered_required: options.unordered_required,
+            case_insensitive: options.case_insensitive,
+            max_array_items: options.max_array_items,
+            max_nesting_depth: options.max_nesting_depth,
+            forced_draft: options.draft,
+            draft: options.draft.unwrap_or_default(),
+            max_recursion_depth: DEFAULT_MAX_RECURSION_DEPTH,
+            ref_stack: Vec::new(),
+            base_uris,
+            path: Vec::new(),
+            deadline: Deadline::new(options.cancellation.as_ref(), options.timeout),
+            keyword_extension: options.keyword_extension.as_deref(),
+            wordlists: &options.wordlists,
+            fragments: None,
+        }
+    }
 
-    let properties = obj
-        .get("properties")
-        .and_then(Value::as_object)
-        .ok_or_else(|| anyhow!("'properties' not found or not an object"))?;
+    /// Compiles into a regular expression meant to be compiled into an automaton, with the
+    /// automata of [`Parser::fragments`] in place of their markers.
+    pub fn with_fragments(mut self) -> Self {
+        self.fragments = Some(Vec::new());
+        self
+    }
 
-    let required_properties = obj
-        .get("required")
-        .and_then(Value::as_array)
-        .map(|arr| arr.iter().filter_map(Value::as_str).collect::<Vec<_>>())
-        .unwrap_or_default();
+    /// Automata of the markers of the regular expressions compiled so far.
+    pub fn fragments(self) -> Vec<FSMInfo> {
+        self.fragments.unwrap_or_default()
+    }
 
-    let is_required: Vec<bool> = properties
-        .keys()
-        .map(|item| required_properties.contains(&item.as_str()))
-        .collect();
+    pub fn compile(&mut self, json: &Value) -> Result<String> {
+        // Schemas declaring their meta-schema switch the draft for their whole subtree.
+        let draft = match self.forced_draft {
+            Some(_) => None,
+            None => json
+                .get("$schema")
+                .and_then(Value::as_str)
+                .and_then(Draft::from_schema_uri),
+        };
+        if let Some(draft) = draft {
+            let outer_draft = std::mem::replace(&mut self.draft, draft);
+            let regex = self.compile_resource(json);
+            self.draft = outer_draft;
+            return regex;
+        }
 
-    if is_required.iter().any(|&x| x) {
-        let last_required_pos = is_required
-            .iter()
-            .enumerate()
-            .filter(|&(_, &value)| value)
-            .map(|(i, _)| i)
-            .max()
-            .unwrap();
-
-        for (i, (name, value)) in properties.iter().enumerate() {
-            let mut subregex = format!(
-                r#"{whitespace_pattern}"{}"{}:{}"#,
-                escape(name),
-                whitespace_pattern,
-                whitespace_pattern
-            );
-            subregex += &to_regex(value, Some(whitespace_pattern), full_schema)?;
-
-            match i {
-                i if i < last_required_pos => {
-                    subregex = format!("{}{},", subregex, whitespace_pattern)
+        self.compile_resource(json)
+    }
+
+    fn compile_resource(&mut self, json: &Value) -> Result<String> {
+        // Embedded schema resources change the base URI of the references they contain.
+        if let Some(id) = json.get("$id").and_then(Value::as_str) {
+            // The resources of the document are identified once, as the base URI of a
+            // reference to one of them is already its own.
+            let base_uri = match self.resolver.resource_uri(json) {
+                Some(uri) => uri.to_string(),
+                None => resolver::resolve_uri(self.base_uri(), id),
+            };
+            self.base_uris
+                .push(base_uri.split('#').next().unwrap_or_default().to_string());
+            let regex = self.compile_schema(json);
+            self.base_uris.pop();
+            return regex;
+        }
+
+        self.compile_schema(json)
+    }
+
+    fn compile_schema(&mut self, json: &Value) -> Result<String> {
+        self.deadline.check()?;
+        match json {
+            Value::Object(obj) => {
+                let obj: Cow<serde_json::Map<String, Value>> = draft::normalize(self.draft, obj)?;
+                let obj = obj.as_ref();
+                if let Some(pattern) = obj.get("x-pattern-override") {
+                    let pattern = pattern
+                        .as_str()
+                        .ok_or_else(|| anyhow!("'x-pattern-override' must be a string"))?;
+                    return self.override_pattern(pattern);
+                }
+                if let Some(name) = obj.get("x-wordlist") {
+                    return self.parse_wordlist(name);
                 }
-                i if i > last_required_pos => {
-                    subregex = format!("{},{}", whitespace_pattern, subregex)
+                if let Some(keyword_extension) = self.keyword_extension {
+                    match extension::extend(keyword_extension, obj)? {
+                        Some(Extension::Regex(regex)) => return Ok(format!("({})", regex)),
+                        Some(Extension::Schema(schema)) => return self.compile(&schema),
+                        None => {}
+                    }
+                }
+                let keyword = if obj.is_empty() {
+                    SchemaKeyword::EmptyObject
+                } else {
+                    let keyword = [
+                        ("allOf", SchemaKeyword::AllOf),
+                        ("properties", SchemaKeyword::Properties),
+                        ("anyOf", SchemaKeyword::AnyOf),
+                        ("oneOf", SchemaKeyword::OneOf),
+                        ("prefixItems", SchemaKeyword::PrefixItems),
+                        ("enum", SchemaKeyword::Enum),
+                        ("const", SchemaKeyword::Const),
+                        ("$ref", SchemaKeyword::Ref),
+                        ("$dynamicRef", SchemaKeyword::DynamicRef),
+                        ("type", SchemaKeyword::Type),
+                    ]
+                    .iter()
+                    .find_map(|&(key, schema_keyword)| {
+                        if obj.contains_key(key) {
+                            Some(schema_keyword)
+                        } else {
+                            None
+                        }
+                    });
+                    match keyword {
+                        Some(keyword) => keyword,
+                        None => return self.compile_untyped(obj, json),
+                    }
+                };
+                // Objects and tuples which may have other types are compiled as the union of
+                // those types, their keywords only applying to the object or array variant.
+                let keyword = match keyword {
+                    SchemaKeyword::Properties | SchemaKeyword::PrefixItems
+                        if obj.get("type").is_some_and(Value::is_array) =>
+                    {
+                        SchemaKeyword::Type
+                    }
+                    keyword => keyword,
+                };
+
+                match keyword {
+                    SchemaKeyword::Properties => self.parse_properties(obj),
+                    SchemaKeyword::AllOf => self.parse_all_of(obj),
+                    SchemaKeyword::AnyOf => self.parse_any_of(obj),
+                    SchemaKeyword::OneOf => self.parse_one_of(obj),
+                    SchemaKeyword::PrefixItems => self.parse_prefix_items(obj),
+                    SchemaKeyword::Enum => self.parse_enum(obj),
+                    SchemaKeyword::Const => self.parse_const(obj),
+                    SchemaKeyword::Ref => self.parse_ref(obj),
+                    SchemaKeyword::DynamicRef => self.parse_dynamic_ref(obj),
+                    SchemaKeyword::Type => self.parse_type(obj),
+                    SchemaKeyword::EmptyObject => self.parse_empty_object(),
                 }
-                _ => (),
             }
+            // The boolean schemas admit any value and none.
+            Value::Bool(true) => self.parse_empty_object(),
+            Value::Bool(false) => Ok(NOTHING.to_string()),
+            _ => Err(anyhow!(
+                "Invalid JSON Schema: expected an object or a boolean"
+            )),
+        }
+    }
+}
 
-            regex += &if is_required[i] {
-                subregex
-            } else {
-                format!("({})?", subregex)
-            };
+impl<'a> Parser<'a> {
+    /// Compiles a schema without `type` nor any keyword deciding its structure: a schema of
+    /// annotations only admits any value, and one with keywords specific to some types, such as
+    /// `items` or `minLength`, is compiled for those types.
+    fn compile_untyped(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        json: &Value,
+    ) -> Result<String> {
+        if obj.keys().all(|keyword| is_annotation(keyword)) {
+            return self.parse_empty_object();
         }
-    } else {
+        let types: Vec<&str> = IMPLIED_TYPES
+            .iter()
+            .filter(|(_, keywords)| keywords.iter().any(|keyword| obj.contains_key(*keyword)))
+            .map(|(instance_type, _)| *instance_type)
+            .collect();
+        let instance_type = match types.as_slice() {
+            [] => return Err(anyhow!("Unsupported JSON Schema structure {} \nMake sure it is valid to the JSON Schema specification and check if it's supported by Outlines.\nIf it should be supported, please open an issue.", json)),
+            [instance_type] => json!(instance_type),
+            types => json!(types),
+        };
+        let mut typed = obj.clone();
+        typed.insert("type".to_string(), instance_type);
+        self.compile(&Value::Object(typed))
+    }
+
+    pub fn parse_properties(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        if obj.contains_key("dependentRequired") {
+            let branches = split_dependent_required(obj)?;
+            return self.compile(&branches);
+        }
+        if obj.contains_key("dependentSchemas") {
+            let branches = split_dependent_schemas(obj)?;
+            return self.compile(&branches);
+        }
+
+        self.check_nesting_depth()?;
+        let separators = self.separators();
+        let (item, comma) = (separators.item(), separators.comma());
+        let mut regex = String::new();
+
+        let properties = obj
+            .get("properties")
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow!("'properties' not found or not an object"))?;
+
+        let required_properties = obj
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|arr| arr.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        // Optional properties whose schema can not be compiled (e.g. recursion exhausted)
+        // are left out of the object rather than failing it.
         let mut property_subregexes = Vec::new();
+        let mut is_required = Vec::new();
         for (name, value) in properties.iter() {
-            let mut subregex = format!(
-                r#"{whitespace_pattern}"{}"{}:{}"#,
-                escape(name),
-                whitespace_pattern,
-                whitespace_pattern
-            );
-
-            subregex += &to_regex(value, Some(whitespace_pattern), full_schema)?;
-            property_subregexes.push(subregex);
-        }
-
-        let mut possible_patterns = Vec::new();
-        for i in 0..property_subregexes.len() {
-            let mut pattern = String::new();
-            for subregex in &property_subregexes[..i] {
-                pattern += &format!("({}{},)?", subregex, whitespace_pattern);
+            let required = required_properties.contains(&name.as_str());
+            // Optional properties admitting no value are never written.
+            if !required && value == &Value::Bool(false) {
+                continue;
             }
-            pattern += &property_subregexes[i];
-            for subregex in &property_subregexes[i + 1..] {
-                pattern += &format!("({},{})?", whitespace_pattern, subregex);
+            self.path.push(name.clone());
+            let value_regex = self.compile_nested(value);
+            self.path.pop();
+            let value_regex = match value_regex {
+                Ok(value_regex) => value_regex,
+                Err(e) if !required && is_prunable(&e) => continue,
+                Err(e) => return Err(e),
+            };
+
+            property_subregexes.push(format!(
+                r#"{item}"{}"{}:{}{}"#,
+                escape(&self.literal(name)),
+                separators.colon_before(),
+                separators.colon_after(),
+                value_regex
+            ));
+            is_required.push(required);
+        }
+
+        let num_required = is_required.iter().filter(|&&x| x).count();
+        let max_unordered = match self.fragments {
+            Some(_) => MAX_UNORDERED_PROPERTIES,
+            None => MAX_UNORDERED_REGEX_PROPERTIES,
+        };
+        if self.unordered_required && (2..=max_unordered).contains(&num_required) {
+            // Required properties come first in any order, followed by the optional ones in
+            // declaration order.
+            let (required, optional): (Vec<_>, Vec<_>) = property_subregexes
+                .into_iter()
+                .zip(is_required)
+                .partition(|(_, required)| *required);
+            let required: Vec<String> = required.into_iter().map(|(s, _)| s).collect();
+
+            let separator = format!("{comma},");
+            regex += &match &mut self.fragments {
+                Some(fragments) => {
+                    let automaton =
+                        |pattern: &str| FSMInfo::from_regex_with_fragments(pattern, fragments);
+                    let parts = required
+                        .iter()
+                        .map(|subregex| automaton(subregex))
+                        .collect::<std::result::Result<Vec<_>, _>>()?;
+                    let separator = automaton(&separator)?;
+                    fragments.push(FSMInfo::any_order(&parts, &separator));
+                    fsm::fragment_marker(fragments.len() - 1)
+                }
+                None => permutations(&required, &separator),
+            };
+            for (subregex, _) in optional {
+                regex += &format!("({},{})?", comma, subregex);
             }
-            possible_patterns.push(pattern);
+            regex = separators.close(&regex, false);
+        } else if num_required > 0 {
+            let last_required_pos = is_required
+                .iter()
+                .enumerate()
+                .filter(|&(_, &value)| value)
+                .map(|(i, _)| i)
+                .max()
+                .unwrap();
+
+            for (i, mut subregex) in property_subregexes.into_iter().enumerate() {
+                match i {
+                    i if i < last_required_pos => subregex = format!("{}{},", subregex, comma),
+                    i if i > last_required_pos => subregex = format!("{},{}", comma, subregex),
+                    _ => (),
+                }
+
+                regex += &if is_required[i] {
+                    subregex
+                } else {
+                    format!("({})?", subregex)
+                };
+            }
+            regex = separators.close(&regex, false);
+        } else {
+            let max_optional = match self.fragments {
+                Some(_) => MAX_OPTIONAL_PROPERTIES,
+                None => MAX_OPTIONAL_REGEX_PROPERTIES,
+            };
+            if property_subregexes.len() > max_optional {
+                return Err(anyhow!(
+                    "Objects without required properties can have at most {} properties, not {}",
+                    max_optional,
+                    property_subregexes.len()
+                ));
+            }
+            let separator = format!("{comma},");
+            let members = match &mut self.fragments {
+                _ if property_subregexes.is_empty() => String::new(),
+                Some(fragments) => {
+                    let automaton =
+                        |pattern: &str| FSMInfo::from_regex_with_fragments(pattern, fragments);
+                    let parts = property_subregexes
+                        .iter()
+                        .map(|subregex| automaton(subregex))
+                        .collect::<std::result::Result<Vec<_>, _>>()?;
+                    let separator = automaton(&separator)?;
+                    fragments.push(FSMInfo::subsequences(&parts, &separator));
+                    fsm::fragment_marker(fragments.len() - 1)
+                }
+                // One alternative per property which may come first, followed by the later
+                // properties in declaration order.
+                None => (0..property_subregexes.len())
+                    .map(|first| {
+                        let mut members = property_subregexes[first].clone();
+                        for subregex in &property_subregexes[first + 1..] {
+                            members += &format!("({separator}{subregex})?");
+                        }
+                        members
+                    })
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            };
+
+            regex = separators.close(&members, true);
         }
 
-        regex += &format!("({})?", possible_patterns.join("|"));
+        Ok(format!(r"\{{{}\}}", regex))
     }
 
-    regex += &format!("{}\\}}", whitespace_pattern);
+    pub fn parse_all_of(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        let merged = merge::merge_all_of(obj, &self.resolver, self.base_uri())?;
+        // The merged schema unrolls the references it flattens, whose recursion is bounded
+        // like that of `$ref`s.
+        for (ref_path, target) in &merged.references {
+            self.check_recursion(ref_path, target)?;
+        }
+        self.ref_stack
+            .extend(merged.references.iter().map(|&(_, target)| target));
+        let regex = self.compile(&merged.schema);
+        self.ref_stack
+            .truncate(self.ref_stack.len() - merged.references.len());
+        regex
+    }
 
-    Ok(regex)
-}
+    pub fn parse_any_of(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        match obj.get("anyOf") {
+            Some(Value::Array(any_of)) => {
+                let any_of = discriminated_alternatives(obj, any_of)?;
+                let subregexes = self.parse_alternatives(&any_of)?;
 
-pub fn parse_all_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("allOf") {
-        Some(Value::Array(all_of)) => {
-            let subregexes: Result<Vec<String>> = all_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+                Ok(alternation(&subregexes))
+            }
+            _ => Err(anyhow!("'anyOf' must be an array")),
+        }
+    }
 
-            let subregexes = subregexes?;
-            let combined_regex = subregexes.join("");
+    pub fn parse_one_of(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        match obj.get("oneOf") {
+            Some(Value::Array(one_of)) => {
+                let one_of = discriminated_alternatives(obj, one_of)?;
+                let subregexes = self.parse_alternatives(&one_of)?;
 
-            Ok(format!(r"({})", combined_regex))
+                let xor_patterns: Vec<String> = subregexes
+                    .into_iter()
+                    .map(|subregex| format!(r"(?:{})", subregex))
+                    .collect();
+
+                Ok(alternation(&xor_patterns))
+            }
+            _ => Err(anyhow!("'oneOf' must be an array")),
         }
-        _ => Err(anyhow!("'allOf' must be an array")),
     }
-}
 
-pub fn parse_any_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("anyOf") {
-        Some(Value::Array(any_of)) => {
-            let subregexes: Result<Vec<String>> = any_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+    /// Compiles the alternatives of an `anyOf`/`oneOf`, dropping the ones that can not be
+    /// compiled as long as at least one remains.
+    fn parse_alternatives(&mut self, alternatives: &[Value]) -> Result<Vec<String>> {
+        let mut subregexes = Vec::new();
+        let mut pruned = None;
+        for alternative in alternatives {
+            match self.compile(alternative) {
+                Ok(subregex) => subregexes.push(subregex),
+                Err(e) if is_prunable(&e) => pruned = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
 
-            let subregexes = subregexes?;
+        match pruned {
+            Some(e) if subregexes.is_empty() => Err(e),
+            _ => Ok(subregexes),
+        }
+    }
 
-            Ok(format!(r"({})", subregexes.join("|")))
+    pub fn parse_prefix_items(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        match obj.get("prefixItems") {
+            Some(Value::Array(prefix_items)) => {
+                self.check_nesting_depth()?;
+                let element_patterns: Result<Vec<String>> = prefix_items
+                    .iter()
+                    .map(|t| self.compile_nested(t))
+                    .collect();
+
+                let element_patterns = element_patterns?;
+
+                let separators = self.separators();
+                let comma_split_pattern = format!("{},{}", separators.comma(), separators.item());
+                let tuple_inner = element_patterns.join(&comma_split_pattern);
+
+                // Tuples are closed unless `items` explicitly allows items past the declared
+                // positions.
+                let additional_items = match obj.get("items") {
+                    None | Some(Value::Bool(false)) => String::new(),
+                    Some(items) => {
+                        let max_additional_items = obj
+                            .get("maxItems")
+                            .and_then(Value::as_u64)
+                            .or(self.max_array_items)
+                            .map(|max| max.saturating_sub(prefix_items.len() as u64));
+                        if max_additional_items == Some(0) {
+                            String::new()
+                        } else {
+                            let items = match items {
+                                Value::Bool(true) => json!({}),
+                                items => items.clone(),
+                            };
+                            match self.compile_nested(&items) {
+                                Ok(items_regex) => format!(
+                                    "({comma_split_pattern}({items_regex})){}",
+                                    max_additional_items
+                                        .map_or("*".to_string(), |max| format!("{{0,{max}}}"))
+                                ),
+                                // No additional item can be generated, the tuple is closed.
+                                Err(e) if is_prunable(&e) => String::new(),
+                                Err(e) => return Err(e),
+                            }
+                        }
+                    }
+                };
+
+                Ok(format!(
+                    r"\[{}\]",
+                    separators.enclose(&format!("{tuple_inner}{additional_items}"), false)
+                ))
+            }
+            _ => Err(anyhow!("'prefixItems' must be an array")),
         }
-        _ => Err(anyhow!("'anyOf' must be an array")),
     }
-}
 
-pub fn parse_one_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("oneOf") {
-        Some(Value::Array(one_of)) => {
-            let subregexes: Result<Vec<String>> = one_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+    pub fn parse_enum(&self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        match obj.get("enum") {
+            Some(Value::Array(enum_values)) => {
+                let choices = enum_values.iter().filter(|choice| has_type(obj, choice));
+                if enum_values.len() >= LARGE_ENUM && !self.case_insensitive {
+                    let words: Vec<Vec<char>> = choices
+                        .map(|choice| match choice {
+                            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
+                                let json = serde_json::to_string(choice)?;
+                                Ok(self.literal(&json).chars().collect())
+                            }
+                            _ => Err(anyhow!("Unsupported data type in enum: {:?}", choice)),
+                        })
+                        .collect::<Result<_>>()?;
+                    return Ok(match words.as_slice() {
+                        [] => NOTHING.to_string(),
+                        words => Dawg::new(words).to_regex(),
+                    });
+                }
+                let choices: Result<Vec<String>> = choices
+                    .map(|choice| match choice {
+                        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
+                            self.value_literal(choice)
+                        }
+                        _ => Err(anyhow!("Unsupported data type in enum: {:?}", choice)),
+                    })
+                    .collect();
+
+                Ok(alternation(&choices?))
+            }
+            _ => Err(anyhow!("'enum' must be an array")),
+        }
+    }
 
-            let subregexes = subregexes?;
+    pub fn parse_const(&self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        match obj.get("const") {
+            Some(const_value) if !has_type(obj, const_value) => Ok(NOTHING.to_string()),
+            Some(const_value) => match const_value {
+                Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
+                    self.value_literal(const_value)
+                }
+                _ => Err(anyhow!("Unsupported data type in const: {:?}", const_value)),
+            },
+            None => Err(anyhow!("'const' key not found in object")),
+        }
+    }
 
-            let xor_patterns: Vec<String> = subregexes
-                .into_iter()
-                .map(|subregex| format!(r"(?:{})", subregex))
-                .collect();
+    pub fn parse_ref(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        let ref_path = obj["$ref"]
+            .as_str()
+            .ok_or_else(|| anyhow!("'$ref' must be a string"))?;
+
+        let resolved = self.resolver.resolve(self.base_uri(), ref_path)?;
+        self.unroll_ref(ref_path, resolved)
+    }
 
-            Ok(format!(r"({})", xor_patterns.join("|")))
+    pub fn parse_dynamic_ref(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        let ref_path = obj["$dynamicRef"]
+            .as_str()
+            .ok_or_else(|| anyhow!("'$dynamicRef' must be a string"))?;
+
+        let resolved = self.resolve_dynamic_ref(ref_path)?;
+        self.unroll_ref(ref_path, resolved)
+    }
+
+    /// Separators of an object or array at the current depth.
+    fn separators(&self) -> Separators<'a> {
+        match self.whitespace {
+            Whitespace::Pattern(pattern) => Separators::Uniform(pattern),
+            Whitespace::None => Separators::Uniform(""),
+            Whitespace::Pretty(indent) => Separators::Pretty {
+                item: format!(r"\n{}", " ".repeat(indent * (self.depth + 1))),
+                close: format!(r"\n{}", " ".repeat(indent * self.depth)),
+            },
         }
-        _ => Err(anyhow!("'oneOf' must be an array")),
     }
-}
 
-pub fn parse_prefix_items(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("prefixItems") {
-        Some(Value::Array(prefix_items)) => {
-            let element_patterns: Result<Vec<String>> = prefix_items
+    /// Fails when an object or array at the current depth would exceed the maximum nesting depth.
+    fn check_nesting_depth(&self) -> Result<()> {
+        match self.max_nesting_depth {
+            Some(depth) if self.depth >= depth => Err(Error::NestingLimitReached { depth }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails on the features of `pattern` that regular languages can not express.
+    fn check_pattern(&self, pattern: &str) -> Result<()> {
+        let path = || {
+            self.path
                 .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+                .map(|name| format!("/{}", name.replace('~', "~0").replace('/', "~1")))
+                .collect()
+        };
+        if let Some(feature) = helpers::unsupported_pattern_feature(pattern) {
+            return Err(Error::UnsupportedPatternFeature {
+                feature: feature.to_string(),
+                pattern: pattern.to_string(),
+                path: path(),
+            }
+            .into());
+        }
+        match pattern::embeddable_pattern(pattern, true) {
+            Ok(_) => Ok(()),
+            Err(reason) => Err(Error::InvalidPattern {
+                pattern: pattern.to_string(),
+                reason,
+                path: path(),
+            }
+            .into()),
+        }
+    }
+
+    /// Compiles a subschema to the user's `pattern`, which matches the whole JSON value, quotes
+    /// included, in place of what its other keywords would generate.
+    fn override_pattern(&self, pattern: &str) -> Result<String> {
+        self.check_pattern(pattern)?;
+        Ok(format!("({})", pattern::embedded_pattern(pattern)?))
+    }
 
-            let element_patterns = element_patterns?;
+    /// Compiles a subschema to the JSON strings of the words of the wordlist named `name`.
+    fn parse_wordlist(&self, name: &Value) -> Result<String> {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("'x-wordlist' must be a string"))?;
+        let wordlist = self
+            .wordlists
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown wordlist '{}'", name))?;
+        Ok(wordlist.json_regex(|json| self.literal(json))?)
+    }
 
-            let comma_split_pattern = format!("{},{}", whitespace_pattern, whitespace_pattern);
-            let tuple_inner = element_patterns.join(&comma_split_pattern);
+    /// Compiles a member of an object or array.
+    fn compile_nested(&mut self, json: &Value) -> Result<String> {
+        self.depth += 1;
+        let regex = self.compile(json);
+        self.depth -= 1;
+        regex
+    }
 
-            Ok(format!(
-                r"\[{whitespace_pattern}{tuple_inner}{whitespace_pattern}\]"
-            ))
+    /// Spells out literal JSON text the way generated output has to write it.
+    fn literal(&self, json: &str) -> String {
+        if self.ascii_only {
+            types::ascii_escape(json)
+        } else {
+            json.to_string()
         }
-        _ => Err(anyhow!("'prefixItems' must be an array")),
     }
-}
 
-pub fn parse_enum(
-    obj: &serde_json::Map<String, Value>,
-    _whitespace_pattern: &str,
-) -> Result<String> {
-    match obj.get("enum") {
-        Some(Value::Array(enum_values)) => {
-            let choices: Result<Vec<String>> = enum_values
-                .iter()
-                .map(|choice| match choice {
-                    Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
-                        let json_string = serde_json::to_string(choice)?;
-                        Ok(regex::escape(&json_string))
+    /// Matches the JSON of an `enum` or `const` value.
+    fn value_literal(&self, value: &Value) -> Result<String> {
+        let text = match value {
+            Value::String(text) if self.case_insensitive => text,
+            _ => return Ok(regex::escape(&self.literal(&serde_json::to_string(value)?))),
+        };
+
+        let mut regex = String::from('"');
+        for c in text.chars() {
+            let mut cases = vec![c];
+            let other_cases: [Vec<char>; 2] =
+                [c.to_lowercase().collect(), c.to_uppercase().collect()];
+            for case in other_cases {
+                // Cases written with several characters, like the uppercase `ß`, are left out.
+                if let [case] = case[..] {
+                    if !cases.contains(&case) {
+                        cases.push(case);
                     }
-                    _ => Err(anyhow!("Unsupported data type in enum: {:?}", choice)),
-                })
-                .collect();
+                }
+            }
+            let mut written = Vec::new();
+            for case in cases {
+                let json = serde_json::to_string(&case.to_string())?;
+                written.push(regex::escape(&self.literal(&json[1..json.len() - 1])));
+            }
+            regex += &match written.as_slice() {
+                [single] => single.clone(),
+                _ if written.iter().all(|w| w.chars().count() == 1) => {
+                    format!("[{}]", written.concat())
+                }
+                _ => format!("(?:{})", written.join("|")),
+            };
+        }
+        regex.push('"');
+        Ok(regex)
+    }
+
+    fn base_uri(&self) -> &str {
+        self.base_uris.last().map_or("", String::as_str)
+    }
+
+    /// Resolves a `$dynamicRef`: when it initially resolves to a `$dynamicAnchor`, it binds to
+    /// the outermost schema resource of the dynamic scope declaring that anchor, otherwise it
+    /// behaves like a `$ref`.
+    fn resolve_dynamic_ref(&self, ref_path: &str) -> Result<Resolved<'a>> {
+        let resolved = self.resolver.resolve(self.base_uri(), ref_path)?;
+
+        let anchor = match ref_path.split_once('#') {
+            Some((_, fragment)) if !fragment.is_empty() && !fragment.starts_with('/') => fragment,
+            _ => return Ok(resolved),
+        };
+        if resolved
+            .schema
+            .get("$dynamicAnchor")
+            .and_then(Value::as_str)
+            != Some(anchor)
+        {
+            return Ok(resolved);
+        }
 
-            let choices = choices?;
-            Ok(format!(r"({})", choices.join("|")))
+        for base_uri in &self.base_uris {
+            if let Some(schema) = self.resolver.dynamic_anchor(base_uri, anchor) {
+                return Ok(Resolved {
+                    schema,
+                    base_uri: base_uri.clone(),
+                });
+            }
         }
-        _ => Err(anyhow!("'enum' must be an array")),
+        Ok(resolved)
     }
-}
 
-pub fn parse_const(
-    obj: &serde_json::Map<String, Value>,
-    _whitespace_pattern: &str,
-) -> Result<String> {
-    match obj.get("const") {
-        Some(const_value) => match const_value {
-            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
-                let json_string = serde_json::to_string(const_value)?;
-                Ok(regex::escape(&json_string))
+    /// Compiles the target of a reference, unrolling recursive references at most
+    /// `max_recursion_depth` times.
+    fn unroll_ref(&mut self, ref_path: &str, resolved: Resolved<'a>) -> Result<String> {
+        self.check_recursion(ref_path, resolved.schema)?;
+
+        self.ref_stack.push(resolved.schema);
+        self.base_uris.push(resolved.base_uri);
+
+        let regex = self.compile(resolved.schema);
+
+        self.base_uris.pop();
+        self.ref_stack.pop();
+        regex
+    }
+
+    /// Compiles a schema with several types to the alternation of the schema restricted to each
+    /// of them, the keywords which only apply to objects or arrays being kept for those.
+    fn parse_type_union(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        types: &[Value],
+    ) -> Result<String> {
+        if types.is_empty() {
+            return Err(anyhow!("'type' must not be an empty array"));
+        }
+        let mut variants: Vec<Value> = Vec::new();
+        for instance_type in types {
+            let instance_type = instance_type
+                .as_str()
+                .ok_or_else(|| anyhow!("'type' must be a string or an array of strings"))?;
+            let mut variant = obj.clone();
+            variant.insert("type".to_string(), json!(instance_type));
+            if instance_type != "object" {
+                variant.remove("properties");
             }
-            _ => Err(anyhow!("Unsupported data type in const: {:?}", const_value)),
-        },
-        None => Err(anyhow!("'const' key not found in object")),
+            if instance_type != "array" {
+                variant.remove("prefixItems");
+            }
+            let variant = Value::Object(variant);
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+        let subregexes = self.parse_alternatives(&variants)?;
+        Ok(alternation(&subregexes))
     }
-}
 
-pub fn parse_ref(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let ref_path = obj["$ref"]
-        .as_str()
-        .ok_or_else(|| anyhow!("'$ref' must be a string"))?;
-
-    let parts: Vec<&str> = ref_path.split('#').collect();
-
-    match parts.as_slice() {
-        [fragment] | ["", fragment] => {
-            let path_parts: Vec<&str> = fragment.split('/').filter(|&s| !s.is_empty()).collect();
-            let referenced_schema = resolve_local_ref(full_schema, &path_parts)?;
-            to_regex(referenced_schema, Some(whitespace_pattern), full_schema)
-        }
-        [base, fragment] => {
-            if let Some(id) = full_schema["$id"].as_str() {
-                if *base == id || base.is_empty() {
-                    let path_parts: Vec<&str> =
-                        fragment.split('/').filter(|&s| !s.is_empty()).collect();
-                    let referenced_schema = resolve_local_ref(full_schema, &path_parts)?;
-                    return to_regex(referenced_schema, Some(whitespace_pattern), full_schema);
+    /// Fails when `target` is already unrolled `max_recursion_depth` times.
+    fn check_recursion(&self, ref_path: &str, target: &Value) -> Result<()> {
+        let depth = self
+            .ref_stack
+            .iter()
+            .filter(|&&unrolled| std::ptr::eq(unrolled, target))
+            .count();
+        if depth >= self.max_recursion_depth {
+            return Err(Error::RefRecursionLimitReached {
+                reference: ref_path.to_string(),
+                depth: self.max_recursion_depth,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn parse_type(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        let instance_type = match &obj["type"] {
+            Value::String(instance_type) => instance_type.as_str(),
+            Value::Array(types) => return self.parse_type_union(obj, types),
+            _ => return Err(anyhow!("'type' must be a string or an array of strings")),
+        };
+        match instance_type {
+            "string" => {
+                if let Some(pattern) = obj.get("pattern").and_then(Value::as_str) {
+                    self.check_pattern(pattern)?;
                 }
+                parse_string_type(
+                    obj,
+                    self.string_inner,
+                    self.length_semantics,
+                    self.time_format,
+                )
             }
-            Err(anyhow!(
-                "External references are not supported: {}",
-                ref_path
-            ))
+            "number" => parse_number_type(obj, self.exponents, self.json5_numbers),
+            "integer" => parse_integer_type(obj, self.integer_forms, self.json5_numbers),
+            "array" => self.parse_array_type(obj),
+            "object" => self.parse_object_type(obj),
+            "boolean" => parse_boolean_type(),
+            "null" => parse_null_type(),
+            _ => Err(anyhow!("Unsupported type: {}", instance_type)),
         }
-        _ => Err(anyhow!("Invalid reference format: {}", ref_path)),
     }
+
+    pub fn parse_empty_object(&mut self) -> Result<String> {
+        // JSON Schema Spec: Empty object means unconstrained, any json type is legal
+        let types = [
+            json!({"type": "boolean"}),
+            json!({"type": "null"}),
+            json!({"type": "number"}),
+            json!({"type": "integer"}),
+            json!({"type": "string"}),
+            json!({"type": "array"}),
+            json!({"type": "object"}),
+        ];
+
+        let regexes = self.parse_alternatives(&types)?;
+
+        let wrapped_regexes: Vec<String> =
+            regexes.into_iter().map(|r| format!("({})", r)).collect();
+
+        // Grouped so that the alternation can be embedded, as the value of a property.
+        Ok(format!("({})", wrapped_regexes.join("|")))
+    }
+
+    fn parse_object_type(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        self.check_nesting_depth()?;
+        let separators = self.separators();
+        let min_properties = obj.get("minProperties").and_then(|v| v.as_u64());
+        let max_properties = obj.get("maxProperties").and_then(|v| v.as_u64());
+
+        let num_repeats = helpers::get_num_items_pattern(min_properties, max_properties);
+
+        if num_repeats.is_none() {
+            return Ok(format!(r"\{{{}\}}", separators.empty()));
+        }
+
+        let allow_empty = min_properties.unwrap_or(0) == 0;
+
+        let additional_properties = obj.get("additionalProperties");
+
+        let value_pattern = match additional_properties {
+            None | Some(&Value::Bool(true)) => {
+                // parse unconstrained object case
+                let mut legal_types = vec![
+                    json!({"type": "string"}),
+                    json!({"type": "number"}),
+                    json!({"type": "boolean"}),
+                    json!({"type": "null"}),
+                ];
+
+                let depth = obj.get("depth").and_then(|v| v.as_u64()).unwrap_or(2);
+                if depth > 0 {
+                    legal_types.push(json!({"type": "object", "depth": depth - 1}));
+                    legal_types.push(json!({"type": "array", "depth": depth - 1}));
+                }
+
+                let any_of = json!({"anyOf": legal_types});
+                self.compile_nested(&any_of)?
+            }
+            Some(props) => match self.compile_nested(props) {
+                Ok(value_pattern) => value_pattern,
+                // No additional property can be generated, only the empty object is left.
+                Err(e) if allow_empty && is_prunable(&e) => {
+                    return Ok(format!(r"\{{{}\}}", separators.empty()));
+                }
+                Err(e) => return Err(e),
+            },
+        };
+
+        let key_value_pattern = format!(
+            r#""{}*"{}:{}{value_pattern}"#,
+            self.string_inner,
+            separators.colon_before(),
+            separators.colon_after()
+        );
+        let key_value_successor_pattern = format!(
+            "{},{}{key_value_pattern}",
+            separators.comma(),
+            separators.item()
+        );
+        let mut multiple_key_value_pattern =
+            format!("{key_value_pattern}({key_value_successor_pattern}){{0,}}");
+        if !allow_empty {
+            multiple_key_value_pattern = format!("({multiple_key_value_pattern})");
+        }
+
+        let res = format!(
+            r"\{{{}\}}",
+            separators.enclose(&multiple_key_value_pattern, allow_empty)
+        );
+
+        Ok(res)
+    }
+
+    fn parse_array_type(&mut self, obj: &serde_json::Map<String, Value>) -> Result<String> {
+        self.check_nesting_depth()?;
+        let separators = self.separators();
+        let min_items = obj.get("minItems").and_then(Value::as_u64);
+        let max_items = obj.get("maxItems").and_then(Value::as_u64).or_else(|| {
+            self.max_array_items
+                .map(|max| max.max(min_items.unwrap_or(0)))
+        });
+        let num_repeats = helpers::get_num_items_pattern(min_items, max_items)
+            .unwrap_or_else(|| String::from(""));
+
+        if num_repeats.is_empty() {
+            return Ok(format!(r"\[{0}\]", separators.empty()));
+        }
+
+        let allow_empty = obj.get("minItems").and_then(Value::as_u64).unwrap_or(0) == 0;
+
+        match obj.get("items") {
+            Some(Value::Bool(false)) if !allow_empty => {
+                return Err(anyhow!(
+                    "'items: false' only allows empty arrays but minItems > 0"
+                ));
+            }
+            Some(Value::Bool(false)) => return Ok(format!(r"\[{0}\]", separators.empty())),
+            _ => {}
+        }
+
+        if let Some(items) = obj.get("items").filter(|items| !items.is_boolean()) {
+            let items_regex = match self.compile_nested(items) {
+                Ok(items_regex) => items_regex,
+                // No item can be generated, only the empty array is left.
+                Err(e) if allow_empty && is_prunable(&e) => {
+                    return Ok(format!(r"\[{0}\]", separators.empty()));
+                }
+                Err(e) => return Err(e),
+            };
+            Ok(self.array_pattern(&items_regex, &num_repeats, allow_empty))
+        } else {
+            let mut legal_types = vec![
+                json!({"type": "boolean"}),
+                json!({"type": "null"}),
+                json!({"type": "number"}),
+                json!({"type": "integer"}),
+                json!({"type": "string"}),
+            ];
+
+            let depth = obj.get("depth").and_then(Value::as_u64).unwrap_or(2);
+            if depth > 0 {
+                legal_types.push(json!({"type": "object", "depth": depth - 1}));
+                legal_types.push(json!({"type": "array", "depth": depth - 1}));
+            }
+
+            self.depth += 1;
+            let regexes = self.parse_alternatives(&legal_types);
+            self.depth -= 1;
+
+            let regexes_joined = regexes?.join("|");
+
+            Ok(self.array_pattern(&regexes_joined, &num_repeats, allow_empty))
+        }
+    }
+
+    /// Array whose items match `items_regex`, repeated `num_repeats` times after the first one.
+    fn array_pattern(&self, items_regex: &str, num_repeats: &str, allow_empty: bool) -> String {
+        let separators = self.separators();
+        let members = format!(
+            "({items_regex})(,{}({items_regex})){num_repeats}",
+            separators.item()
+        );
+        let members = if allow_empty {
+            members
+        } else {
+            format!("({members})")
+        };
+        format!(r"\[{}\]", separators.enclose(&members, allow_empty))
+    }
+}
+
+/// Whitespace patterns around the tokens of an object or array.
+enum Separators<'a> {
+    /// The same pattern between any two tokens.
+    Uniform(&'a str),
+    /// Each member on its own line, `item` being the line break and indentation in front of a
+    /// member and `close` the one in front of the closing bracket.
+    Pretty { item: String, close: String },
 }
 
-fn resolve_local_ref<'a>(schema: &'a Value, path_parts: &[&str]) -> Result<&'a Value> {
-    let mut current = schema;
-    for &part in path_parts {
-        current = current
-            .get(part)
-            .ok_or_else(|| anyhow!("Invalid reference path: {}", part))?;
+impl Separators<'_> {
+    /// In front of each member, after the opening bracket or a comma.
+    fn item(&self) -> &str {
+        match self {
+            Separators::Uniform(pattern) => pattern,
+            Separators::Pretty { item, .. } => item,
+        }
+    }
+
+    /// In front of a comma.
+    fn comma(&self) -> &str {
+        match self {
+            Separators::Uniform(pattern) => pattern,
+            Separators::Pretty { .. } => "",
+        }
+    }
+
+    /// In front of a colon.
+    fn colon_before(&self) -> &str {
+        match self {
+            Separators::Uniform(pattern) => pattern,
+            Separators::Pretty { .. } => "",
+        }
+    }
+
+    /// After a colon.
+    fn colon_after(&self) -> &str {
+        match self {
+            Separators::Uniform(pattern) => pattern,
+            Separators::Pretty { .. } => " ",
+        }
+    }
+
+    /// Between the brackets of an empty container.
+    fn empty(&self) -> &str {
+        match self {
+            Separators::Uniform(pattern) => pattern,
+            Separators::Pretty { .. } => "",
+        }
+    }
+
+    /// Pattern between the brackets of a container whose members match `members`, the
+    /// whitespace in front of the first member excluded.
+    fn enclose(&self, members: &str, optional: bool) -> String {
+        match (self, optional) {
+            (Separators::Uniform(pattern), true) => format!("{pattern}({members})?{pattern}"),
+            (Separators::Uniform(pattern), false) => format!("{pattern}{members}{pattern}"),
+            (Separators::Pretty { item, close }, true) => format!("({item}{members}{close})?"),
+            (Separators::Pretty { item, close }, false) => format!("{item}{members}{close}"),
+        }
+    }
+
+    /// Pattern between the brackets of a container whose members match `members`, the
+    /// whitespace in front of the first member included.
+    fn close(&self, members: &str, optional: bool) -> String {
+        match (self, optional) {
+            (Separators::Uniform(pattern), true) => format!("({members})?{pattern}"),
+            (Separators::Uniform(pattern), false) => format!("{members}{pattern}"),
+            (Separators::Pretty { .. }, true) if members.is_empty() => String::new(),
+            (Separators::Pretty { close, .. }, true) => format!("(({members}){close})?"),
+            (Separators::Pretty { close, .. }, false) => format!("{members}{close}"),
+        }
     }
-    Ok(current)
 }
 
-pub fn parse_type(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let instance_type = obj["type"]
-        .as_str()
-        .ok_or_else(|| anyhow!("'type' must be a string"))?;
-    match instance_type {
-        "string" => parse_string_type(obj),
-        "number" => parse_number_type(obj),
-        "integer" => parse_integer_type(obj),
-        "array" => parse_array_type(obj, whitespace_pattern, full_schema),
-        "object" => parse_object_type(obj, whitespace_pattern, full_schema),
-        "boolean" => parse_boolean_type(),
-        "null" => parse_null_type(),
-        _ => Err(anyhow!("Unsupported type: {}", instance_type)),
+/// Alternation of every ordering of `subregexes`, separated by `separator`. Orderings sharing a
+/// prefix share its pattern, the pattern still grows factorially with the number of items.
+fn permutations(subregexes: &[String], separator: &str) -> String {
+    if let [subregex] = subregexes {
+        return subregex.clone();
     }
+
+    let alternatives: Vec<String> = (0..subregexes.len())
+        .map(|i| {
+            let mut rest = subregexes.to_vec();
+            let first = rest.remove(i);
+            format!("{first}{separator}{}", permutations(&rest, separator))
+        })
+        .collect();
+    format!("({})", alternatives.join("|"))
 }
 
-pub fn parse_empty_object(whitespace_pattern: &str, full_schema: &Value) -> Result<String> {
-    // JSON Schema Spec: Empty object means unconstrained, any json type is legal
-    let types = vec![
-        json!({"type": "boolean"}),
-        json!({"type": "null"}),
-        json!({"type": "number"}),
-        json!({"type": "integer"}),
-        json!({"type": "string"}),
-        json!({"type": "array"}),
-        json!({"type": "object"}),
-    ];
+/// Constrains the discriminating property of the alternatives referenced by the `mapping` of a
+/// `discriminator`, as written by OpenAPI and msgspec for tagged unions, to their tags.
+/// Alternatives left out of the mapping are kept as they are.
+fn discriminated_alternatives<'v>(
+    obj: &serde_json::Map<String, Value>,
+    alternatives: &'v [Value],
+) -> Result<Cow<'v, [Value]>> {
+    let Some(discriminator) = obj.get("discriminator") else {
+        return Ok(Cow::Borrowed(alternatives));
+    };
+    let property = discriminator
+        .get("propertyName")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("'discriminator' must have a string 'propertyName'"))?;
+    let Some(mapping) = discriminator.get("mapping").and_then(Value::as_object) else {
+        return Ok(Cow::Borrowed(alternatives));
+    };
 
-    let regexes: Result<Vec<String>> = types
+    let alternatives = alternatives
         .iter()
-        .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
+        .map(|alternative| {
+            let reference = alternative.get("$ref").and_then(Value::as_str);
+            let tags: Vec<&String> = mapping
+                .iter()
+                .filter(|(_, target)| reference.is_some() && target.as_str() == reference)
+                .map(|(tag, _)| tag)
+                .collect();
+            match tags.as_slice() {
+                [] => alternative.clone(),
+                [tag] => json!({"allOf": [
+                    alternative,
+                    {"properties": {property: {"const": tag}}, "required": [property]}
+                ]}),
+                tags => json!({"allOf": [
+                    alternative,
+                    {"properties": {property: {"enum": tags}}, "required": [property]}
+                ]}),
+            }
+        })
         .collect();
+    Ok(Cow::Owned(alternatives))
+}
+
+/// Alternation of the patterns, which matches nothing when there are none.
+fn alternation(patterns: &[String]) -> String {
+    match patterns {
+        [] => NOTHING.to_string(),
+        patterns => format!(r"({})", patterns.join("|")),
+    }
+}
+
+/// Whether a keyword only annotates the schema, without constraining its instances.
+fn is_annotation(keyword: &str) -> bool {
+    keyword.starts_with("x-")
+        || matches!(
+            keyword,
+            "title"
+                | "description"
+                | "default"
+                | "examples"
+                | "deprecated"
+                | "readOnly"
+                | "writeOnly"
+                | "$comment"
+                | "$schema"
+                | "$id"
+                | "$anchor"
+                | "$dynamicAnchor"
+                | "$defs"
+                | "definitions"
+        )
+}
+
+/// Whether an instance has the `type` of a schema, if it sets any.
+fn has_type(obj: &serde_json::Map<String, Value>, instance: &Value) -> bool {
+    let is = |instance_type: &str| match (instance_type, instance) {
+        ("null", Value::Null)
+        | ("boolean", Value::Bool(_))
+        | ("number", Value::Number(_))
+        | ("string", Value::String(_))
+        | ("array", Value::Array(_))
+        | ("object", Value::Object(_)) => true,
+        ("integer", Value::Number(number)) => {
+            number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        ("null" | "boolean" | "number" | "integer" | "string" | "array" | "object", _) => false,
+        // Types unknown to JSON Schema do not constrain the instances.
+        _ => true,
+    };
+    match obj.get("type") {
+        Some(Value::String(instance_type)) => is(instance_type),
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).any(is),
+        _ => true,
+    }
+}
+
+/// Whether compiling a branch of the schema failed in a way that allows dropping the branch
+/// from its enclosing schema instead of failing altogether.
+fn is_prunable(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<Error>(),
+        Some(Error::RefRecursionLimitReached { .. } | Error::NestingLimitReached { .. })
+    )
+}
+
+/// Rewrites an object with `dependentRequired` into an equivalent schema without it: an `anyOf`
+/// between the object where the first optional triggering property is absent and the one where
+/// it is required along with its dependencies.
+fn split_dependent_required(obj: &serde_json::Map<String, Value>) -> Result<Value> {
+    let mut dependent_required = match obj.get("dependentRequired") {
+        Some(Value::Object(dependent_required)) => dependent_required.clone(),
+        _ => return Err(anyhow!("'dependentRequired' must be an object")),
+    };
+    let properties = obj
+        .get("properties")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("'properties' not found or not an object"))?;
+    let mut required: Vec<Value> = obj
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    // Dependencies of required properties are required as well.
+    loop {
+        let triggered: Vec<String> = dependent_required
+            .keys()
+            .filter(|name| required.iter().any(|r| r.as_str() == Some(name.as_str())))
+            .cloned()
+            .collect();
+        if triggered.is_empty() {
+            break;
+        }
+        for name in triggered {
+            let dependencies = dependent_required.remove(&name).unwrap_or_default();
+            let dependencies = dependencies
+                .as_array()
+                .ok_or_else(|| anyhow!("'dependentRequired' values must be arrays"))?;
+            for dependency in dependencies {
+                if !required.contains(dependency) {
+                    required.push(dependency.clone());
+                }
+            }
+        }
+    }
+    dependent_required.retain(|name, _| properties.contains_key(name));
+
+    let mut resolved = obj.clone();
+    resolved.insert("required".to_string(), Value::Array(required.clone()));
+    let trigger = match dependent_required.keys().next() {
+        Some(trigger) => trigger.clone(),
+        None => {
+            resolved.remove("dependentRequired");
+            return Ok(Value::Object(resolved));
+        }
+    };
+
+    let mut absent = resolved.clone();
+    let mut remaining = dependent_required;
+    remaining.remove(&trigger);
+    absent.insert(
+        "dependentRequired".to_string(),
+        Value::Object(remaining.clone()),
+    );
+    let mut absent_properties = properties.clone();
+    absent_properties.remove(&trigger);
+    absent.insert("properties".to_string(), Value::Object(absent_properties));
 
-    let regexes = regexes?;
+    let mut present = resolved;
+    required.push(Value::String(trigger));
+    present.insert("required".to_string(), Value::Array(required));
 
-    let wrapped_regexes: Vec<String> = regexes.into_iter().map(|r| format!("({})", r)).collect();
+    Ok(json!({"anyOf": [Value::Object(absent), Value::Object(present)]}))
+}
+
+/// Rewrites an object with `dependentSchemas` into an equivalent schema without its first
+/// dependent schema: merged into the object in `allOf` when the triggering property is
+/// required, otherwise an `anyOf` between the object where the property is absent and the one
+/// where it is required and the dependent schema merged.
+fn split_dependent_schemas(obj: &serde_json::Map<String, Value>) -> Result<Value> {
+    let mut dependent_schemas = match obj.get("dependentSchemas") {
+        Some(Value::Object(dependent_schemas)) => dependent_schemas.clone(),
+        _ => return Err(anyhow!("'dependentSchemas' must be an object")),
+    };
+    let properties = obj
+        .get("properties")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("'properties' not found or not an object"))?;
+    let mut required: Vec<Value> = obj
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let is_required = |name: &str| required.iter().any(|r| r.as_str() == Some(name));
+
+    // Only the properties the object may have can trigger their dependent schema.
+    dependent_schemas.retain(|name, dependent_schema| {
+        dependent_schema != &Value::Bool(true)
+            && (properties.contains_key(name) || is_required(name))
+    });
+    let trigger = dependent_schemas
+        .keys()
+        .find(|name| is_required(name))
+        .or_else(|| dependent_schemas.keys().next())
+        .cloned();
+    let Some(trigger) = trigger else {
+        let mut resolved = obj.clone();
+        resolved.remove("dependentSchemas");
+        return Ok(Value::Object(resolved));
+    };
+    let dependent_schema = dependent_schemas.remove(&trigger).unwrap_or_default();
+    let mut resolved = obj.clone();
+    resolved.insert(
+        "dependentSchemas".to_string(),
+        Value::Object(dependent_schemas),
+    );
 
-    Ok(wrapped_regexes.join("|"))
+    let mut absent = resolved.clone();
+    let mut absent_properties = properties.clone();
+    absent_properties.remove(&trigger);
+    absent.insert("properties".to_string(), Value::Object(absent_properties));
+
+    let triggered = is_required(&trigger);
+    let mut present = resolved;
+    if !triggered {
+        required.push(Value::String(trigger.clone()));
+        present.insert("required".to_string(), Value::Array(required));
+    }
+    let present = match dependent_schema {
+        Value::Bool(false) if triggered => {
+            return Err(anyhow!(
+                "Property '{}' is required but its dependent schema is false",
+                trigger
+            ))
+        }
+        Value::Bool(false) => return Ok(Value::Object(absent)),
+        dependent_schema => json!({"allOf": [Value::Object(present), dependent_schema]}),
+    };
+    Ok(match triggered {
+        true => present,
+        false => json!({"anyOf": [Value::Object(absent), present]}),
+    })
 }
 
 fn parse_boolean_type() -> Result<String> {
@@ -329,7 +1443,18 @@ fn parse_null_type() -> Result<String> {
     Ok(format_type.to_regex().to_string())
 }
 
-fn parse_string_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
+fn parse_string_type(
+    obj: &serde_json::Map<String, Value>,
+    string_inner: &str,
+    length_semantics: LengthSemantics,
+    time_format: Option<(FractionalSeconds, TimeOffset)>,
+) -> Result<String> {
+    let pattern = obj
+        .get("pattern")
+        .and_then(Value::as_str)
+        .map(pattern::embedded_pattern)
+        .transpose()?;
+
     if obj.contains_key("maxLength") || obj.contains_key("minLength") {
         let max_items = obj.get("maxLength");
         let min_items = obj.get("minLength");
@@ -343,6 +1468,30 @@ fn parse_string_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
             _ => {}
         }
 
+        // The strings have to match the pattern and have the length, so they are compiled
+        // together.
+        if let Some(pattern) = pattern {
+            let min_length = min_items.and_then(Value::as_u64).unwrap_or(0);
+            let max_length = max_items.and_then(Value::as_u64);
+            return Ok(format!(
+                r#"("{}")"#,
+                length::restrict_length(&pattern, min_length, max_length, length_semantics)?
+            ));
+        }
+        if length_semantics != LengthSemantics::Codepoints {
+            let min_length = min_items.and_then(Value::as_u64).unwrap_or(0);
+            let max_length = max_items.and_then(Value::as_u64);
+            return Ok(format!(
+                r#""{}""#,
+                length::restrict_string_length(
+                    string_inner,
+                    min_length,
+                    max_length,
+                    length_semantics
+                )?
+            ));
+        }
+
         let formatted_max = max_items
             .and_then(Value::as_u64)
             .map_or("".to_string(), |n| format!("{}", n));
@@ -352,18 +1501,48 @@ fn parse_string_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
 
         Ok(format!(
             r#""{}{{{},{}}}""#,
-            types::STRING_INNER,
-            formatted_min,
-            formatted_max,
+            string_inner, formatted_min, formatted_max,
         ))
-    } else if let Some(pattern) = obj.get("pattern").and_then(Value::as_str) {
-        if pattern.starts_with('^') && pattern.ends_with('$') {
-            Ok(format!(r#"("{}")"#, &pattern[1..pattern.len() - 1]))
-        } else {
-            Ok(format!(r#"("{}")"#, pattern))
-        }
-    } else if let Some(format) = obj.get("format").and_then(Value::as_str) {
+    } else if let Some(pattern) = pattern {
+        Ok(format!(r#"("{}")"#, pattern))
+    } else if let Some(format) = obj.get("x-datetime-format") {
+        let format = format
+            .as_str()
+            .ok_or_else(|| anyhow!("'x-datetime-format' must be a string"))?;
+        Ok(format!(r#""{}""#, strftime::strftime_regex(format)?))
+    } else if let Some(format) = obj
+        .get("x-format")
+        .or_else(|| obj.get("format"))
+        .and_then(Value::as_str)
+    {
+        let bound = |keyword: &'static str, exclusive_keyword: &'static str| {
+            let (keyword, exclusive) = match obj.get(exclusive_keyword) {
+                Some(_) => (exclusive_keyword, true),
+                None => (keyword, false),
+            };
+            obj.get(keyword)
+                .map(|value| {
+                    let value = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("'{}' must be a string", keyword))?;
+                    Ok::<_, anyhow::Error>(ranges::FormatBound {
+                        keyword,
+                        value,
+                        exclusive,
+                    })
+                })
+                .transpose()
+        };
+        let lo = bound("formatMinimum", "formatExclusiveMinimum")?;
+        let hi = bound("formatMaximum", "formatExclusiveMaximum")?;
         match types::FormatType::from_str(format) {
+            Some(format_type) if lo.is_some() || hi.is_some() => {
+                ranges::format_range_regex(&format_type, lo, hi)
+            }
+            Some(types::FormatType::Time) => Ok(match time_format {
+                Some((fractional_seconds, offset)) => types::time_regex(fractional_seconds, offset),
+                None => types::TIME.to_string(),
+            }),
             Some(format_type) => Ok(format_type.to_regex().to_string()),
             None => Err(anyhow::anyhow!(
                 "Format {} is not supported by Outlines",
@@ -371,11 +1550,20 @@ fn parse_string_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
             )),
         }
     } else {
-        Ok(types::JsonType::String.to_regex().to_string())
+        Ok(format!(r#""{}*""#, string_inner))
     }
 }
 
-fn parse_number_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
+fn parse_number_type(
+    obj: &serde_json::Map<String, Value>,
+    exponents: bool,
+    json5_numbers: bool,
+) -> Result<String> {
+    let exponents = match obj.get("allowExponent") {
+        None => exponents,
+        Some(Value::Bool(allow_exponent)) => *allow_exponent,
+        Some(_) => return Err(anyhow!("'allowExponent' must be a boolean")),
+    };
     let bounds = [
         "minDigitsInteger",
         "maxDigitsInteger",
@@ -427,18 +1615,45 @@ fn parse_number_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
             (None, None) => "+".to_string(),
         };
 
+        let exponent = match exponents {
+            true => format!("([eE][+-][0-9]{})?", exponent_quantifier),
+            false => String::new(),
+        };
         Ok(format!(
-            r"((-)?(0|[1-9][0-9]{}))(\.[0-9]{})?([eE][+-][0-9]{})?",
-            integers_quantifier, fraction_quantifier, exponent_quantifier
+            r"((-)?(0|[1-9][0-9]{}))(\.[0-9]{})?{}",
+            integers_quantifier, fraction_quantifier, exponent
         ))
     } else {
-        let format_type = types::JsonType::Number;
-        Ok(format_type.to_regex().to_string())
+        let number = match exponents {
+            true => types::JsonType::Number.to_regex(),
+            false => types::NUMBER_WITHOUT_EXPONENT,
+        };
+        match json5_numbers {
+            true => Ok(format!(
+                "({}|{}|{})",
+                number,
+                types::HEX_INTEGER,
+                types::NON_FINITE_NUMBER
+            )),
+            false => Ok(number.to_string()),
+        }
     }
 }
 
-fn parse_integer_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
-    if obj.contains_key("minDigits") || obj.contains_key("maxDigits") {
+fn parse_integer_type(
+    obj: &serde_json::Map<String, Value>,
+    integer_forms: Option<IntegerForms>,
+    json5_numbers: bool,
+) -> Result<String> {
+    let (lo, hi) = integer_bounds(obj)?;
+    if lo.is_some() || hi.is_some() {
+        if obj.contains_key("minDigits") || obj.contains_key("maxDigits") {
+            return Err(anyhow!(
+                "Integer bounds can not be combined with 'minDigits' or 'maxDigits'"
+            ));
+        }
+        ranges::integer_forms_regex(lo, hi, &integer_forms.unwrap_or_default())
+    } else if obj.contains_key("minDigits") || obj.contains_key("maxDigits") {
         let (min_digits, max_digits) = helpers::validate_quantifiers(
             obj.get("minDigits").and_then(Value::as_u64),
             obj.get("maxDigits").and_then(Value::as_u64),
@@ -454,125 +1669,54 @@ fn parse_integer_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
 
         Ok(format!(r"(-)?(0|[1-9][0-9]{})", quantifier))
     } else {
-        let format_type = types::JsonType::Integer;
-        Ok(format_type.to_regex().to_string())
+        let integer = match integer_forms {
+            Some(integer_forms) => ranges::integer_forms_regex(None, None, &integer_forms)?,
+            None => types::JsonType::Integer.to_regex().to_string(),
+        };
+        match json5_numbers {
+            true => Ok(format!("({}|{})", integer, types::HEX_INTEGER)),
+            false => Ok(integer),
+        }
     }
 }
 
-fn parse_object_type(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let min_properties = obj.get("minProperties").and_then(|v| v.as_u64());
-    let max_properties = obj.get("maxProperties").and_then(|v| v.as_u64());
-
-    let num_repeats = helpers::get_num_items_pattern(min_properties, max_properties);
-
-    if num_repeats.is_none() {
-        return Ok(format!(r"\{{{}\}}", whitespace_pattern));
-    }
-
-    let allow_empty = if min_properties.unwrap_or(0) == 0 {
-        "?"
-    } else {
-        ""
-    };
-
-    let additional_properties = obj.get("additionalProperties");
-
-    let value_pattern = match additional_properties {
-        None | Some(&Value::Bool(true)) => {
-            // parse unconstrained object case
-            let mut legal_types = vec![
-                json!({"type": "string"}),
-                json!({"type": "number"}),
-                json!({"type": "boolean"}),
-                json!({"type": "null"}),
-            ];
-
-            let depth = obj.get("depth").and_then(|v| v.as_u64()).unwrap_or(2);
-            if depth > 0 {
-                legal_types.push(json!({"type": "object", "depth": depth - 1}));
-                legal_types.push(json!({"type": "array", "depth": depth - 1}));
+/// Smallest and largest integers allowed by `minimum`, `exclusiveMinimum`, `maximum` and
+/// `exclusiveMaximum`.
+fn integer_bounds(obj: &serde_json::Map<String, Value>) -> Result<(Option<i128>, Option<i128>)> {
+    let bound = |keyword: &str, round: fn(f64) -> f64, offset: i128| -> Result<Option<i128>> {
+        let Some(value) = obj.get(keyword) else {
+            return Ok(None);
+        };
+        let bound = if let Some(n) = value.as_i64() {
+            n as i128
+        } else if let Some(n) = value.as_u64() {
+            n as i128
+        } else if let Some(n) = value.as_f64() {
+            // Rounding towards the allowed integers makes an exclusive bound inclusive when
+            // it has a fraction, so only integral exclusive bounds are offset.
+            if round(n) != n {
+                return Ok(Some(round(n) as i128));
             }
-
-            let any_of = json!({"anyOf": legal_types});
-            to_regex(&any_of, Some(whitespace_pattern), full_schema)?
-        }
-        Some(props) => to_regex(props, Some(whitespace_pattern), full_schema)?,
-    };
-
-    let key_value_pattern = format!(
-        "{}{whitespace_pattern}:{whitespace_pattern}{value_pattern}",
-        types::STRING
-    );
-    let key_value_successor_pattern =
-        format!("{whitespace_pattern},{whitespace_pattern}{key_value_pattern}");
-    let multiple_key_value_pattern =
-        format!("({key_value_pattern}({key_value_successor_pattern}){{0,}}){allow_empty}");
-
-    let res = format!(
-        r"\{{{}{}{}\}}",
-        whitespace_pattern, multiple_key_value_pattern, whitespace_pattern
-    );
-
-    Ok(res)
-}
-
-fn parse_array_type(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let num_repeats = helpers::get_num_items_pattern(
-        obj.get("minItems").and_then(Value::as_u64),
-        obj.get("maxItems").and_then(Value::as_u64),
-    )
-    .unwrap_or_else(|| String::from(""));
-
-    if num_repeats.is_empty() {
-        return Ok(format!(r"\[{0}\]", whitespace_pattern));
-    }
-
-    let allow_empty = if obj.get("minItems").and_then(Value::as_u64).unwrap_or(0) == 0 {
-        "?"
-    } else {
-        ""
+            n as i128
+        } else {
+            return Err(anyhow!("'{}' must be a number", keyword));
+        };
+        Ok(Some(bound.saturating_add(offset)))
     };
 
-    if let Some(items) = obj.get("items") {
-        let items_regex = to_regex(items, Some(whitespace_pattern), full_schema)?;
-        Ok(format!(
-            r"\[{0}(({1})(,{0}({1})){2}){3}{0}\]",
-            whitespace_pattern, items_regex, num_repeats, allow_empty
-        ))
-    } else {
-        let mut legal_types = vec![
-            json!({"type": "boolean"}),
-            json!({"type": "null"}),
-            json!({"type": "number"}),
-            json!({"type": "integer"}),
-            json!({"type": "string"}),
-        ];
-
-        let depth = obj.get("depth").and_then(Value::as_u64).unwrap_or(2);
-        if depth > 0 {
-            legal_types.push(json!({"type": "object", "depth": depth - 1}));
-            legal_types.push(json!({"type": "array", "depth": depth - 1}));
-        }
-
-        let regexes: Result<Vec<String>> = legal_types
-            .iter()
-            .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-            .collect();
-
-        let regexes = regexes?;
-        let regexes_joined = regexes.join("|");
-
-        Ok(format!(
-            r"\[{0}(({1})(,{0}({1})){2}){3}{0}\]",
-            whitespace_pattern, regexes_joined, num_repeats, allow_empty
-        ))
-    }
+    let lo = [
+        bound("minimum", f64::ceil, 0)?,
+        bound("exclusiveMinimum", f64::ceil, 1)?,
+    ]
+    .into_iter()
+    .flatten()
+    .max();
+    let hi = [
+        bound("maximum", f64::floor, 0)?,
+        bound("exclusiveMaximum", f64::floor, -1)?,
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+    Ok((lo, hi))
 }
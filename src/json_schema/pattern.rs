@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use regex_syntax::ast::{self, AssertionKind, Ast, GroupKind};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use std::borrow::Cow;
+
+/// Checks that `pattern` can be embedded in the middle of the generated regular expression, and
+/// returns it ready to be: without the anchors at its ends, which are only allowed there when
+/// `end_anchors` is set, and in a group when it is an alternation.
+///
+/// The error is the reason why the pattern can not be embedded: it does not parse, has anchors
+/// elsewhere, which could never match once other expressions surround it, or has named capture
+/// groups, which are repeated as often as the pattern is.
+pub(crate) fn embeddable_pattern(pattern: &str, end_anchors: bool) -> Result<Cow<'_, str>, String> {
+    let ast = ast::parse::Parser::new()
+        .parse(pattern)
+        .map_err(|e| format!("{} at offset {}", e.kind(), e.span().start.offset))?;
+
+    let is_anchor = |ast: &Ast| match ast {
+        Ast::Assertion(assertion) => match assertion.kind {
+            AssertionKind::StartLine | AssertionKind::StartText => Some(true),
+            AssertionKind::EndLine | AssertionKind::EndText => Some(false),
+            _ => None,
+        },
+        _ => None,
+    };
+    // Spans of the pattern between the anchors at its ends.
+    let (start, end, body) = match &ast {
+        Ast::Concat(concat) if end_anchors => {
+            let asts = &concat.asts;
+            let start = asts.first().and_then(is_anchor) == Some(true);
+            let end = asts.len() > start as usize && asts.last().and_then(is_anchor) == Some(false);
+            (start, end, &asts[start as usize..asts.len() - end as usize])
+        }
+        Ast::Assertion(_) if end_anchors && is_anchor(&ast).is_some() => (true, false, &[][..]),
+        _ => (false, false, std::slice::from_ref(&ast)),
+    };
+    for ast in body {
+        check_embeddable(ast)?;
+    }
+
+    let trimmed = match body {
+        [] => "",
+        [first, ..] => &pattern[first.span().start.offset..body[body.len() - 1].span().end.offset],
+    };
+    Ok(match body {
+        [Ast::Alternation(_)] => Cow::Owned(format!("({})", trimmed)),
+        _ if !start && !end => Cow::Borrowed(pattern),
+        _ => Cow::Borrowed(trimmed),
+    })
+}
+
+fn check_embeddable(ast: &Ast) -> Result<(), String> {
+    match ast {
+        Ast::Assertion(assertion) => match assertion.kind {
+            AssertionKind::StartLine
+            | AssertionKind::StartText
+            | AssertionKind::EndLine
+            | AssertionKind::EndText => Err(format!(
+                "the anchor at offset {} can not match inside the generated expression",
+                assertion.span.start.offset
+            )),
+            _ => Ok(()),
+        },
+        Ast::Group(group) => match &group.kind {
+            GroupKind::CaptureName { name, .. } => Err(format!(
+                "the named capture group '{}' would be repeated in the generated expression",
+                name.name
+            )),
+            _ => check_embeddable(&group.ast),
+        },
+        Ast::Repetition(repetition) => check_embeddable(&repetition.ast),
+        Ast::Alternation(alternation) => alternation.asts.iter().try_for_each(check_embeddable),
+        Ast::Concat(concat) => concat.asts.iter().try_for_each(check_embeddable),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrites `pattern`, lowered by [`lower_pattern`] when it has to be, ready to be embedded in
+/// the generated regular expression like [`embeddable_pattern`] does.
+pub(crate) fn embedded_pattern(pattern: &str) -> Result<String> {
+    let pattern = match needs_lowering(pattern) {
+        true => Cow::Owned(lower_pattern(pattern)?),
+        false => Cow::Borrowed(pattern),
+    };
+    embeddable_pattern(&pattern, true)
+        .map(Cow::into_owned)
+        .map_err(|reason| anyhow!("Invalid pattern '{}': {}", pattern, reason))
+}
+
+/// Whether `pattern` has to be lowered by [`lower_pattern`] before it can be embedded in the
+/// generated regular expression: inline flags such as `(?i)` change the meaning of what follows
+/// them, which the enclosing expression can not scope, and Unicode property classes such as
+/// `\p{L}` are not understood by every engine the expression is handed to.
+pub(crate) fn needs_lowering(pattern: &str) -> bool {
+    let inline_flags = pattern.match_indices("(?").any(|(start, _)| {
+        let rest = &pattern[start + 2..];
+        let flags = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic() || *c == '-')
+            .count();
+        flags > 0 && matches!(rest[flags..].chars().next(), Some(':' | ')'))
+    });
+
+    let mut chars = pattern.chars();
+    let mut property_class = false;
+    while let Some(c) = chars.next() {
+        if c == '\\' && matches!(chars.next(), Some('p' | 'P')) {
+            property_class = true;
+            break;
+        }
+    }
+    inline_flags || property_class
+}
+
+/// Rewrites `pattern` without inline flags nor property classes, by writing out the characters
+/// they match: case insensitive letters become classes of their cases and `\p{Greek}` the
+/// ranges of the Greek script, for instance. Anchors at the ends of the
+/// pattern are dropped, as the pattern has to match the whole string anyway.
+pub(crate) fn lower_pattern(pattern: &str) -> Result<String> {
+    let hir = regex_syntax::parse(pattern)
+        .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+    let hir = match hir.kind() {
+        HirKind::Concat(subs) => {
+            let start = subs
+                .first()
+                .is_some_and(|sub| matches!(sub.kind(), HirKind::Look(Look::Start)));
+            let end = subs
+                .last()
+                .is_some_and(|sub| matches!(sub.kind(), HirKind::Look(Look::End)));
+            Hir::concat(subs[start as usize..subs.len() - end as usize].to_vec())
+        }
+        HirKind::Look(Look::Start | Look::End) => Hir::empty(),
+        _ => hir,
+    };
+    match hir.kind() {
+        HirKind::Alternation(_) => Ok(format!("(?:{})", write(&hir)?)),
+        _ => write(&hir),
+    }
+}
+
+pub(super) fn write(hir: &Hir) -> Result<String> {
+    Ok(match hir.kind() {
+        HirKind::Empty => String::new(),
+        HirKind::Literal(literal) => regex::escape(
+            std::str::from_utf8(&literal.0).map_err(|_| anyhow!("Patterns must match text"))?,
+        ),
+        HirKind::Class(class) => write_class(class)?,
+        HirKind::Look(look) => match look {
+            Look::Start => "^".to_string(),
+            Look::End => "$".to_string(),
+            Look::WordAscii | Look::WordUnicode => r"\b".to_string(),
+            Look::WordAsciiNegate | Look::WordUnicodeNegate => r"\B".to_string(),
+            _ => return Err(anyhow!("Unsupported assertion {:?} in pattern", look)),
+        },
+        HirKind::Repetition(repetition) => {
+            let quantifier = match (repetition.min, repetition.max) {
+                (0, None) => "*".to_string(),
+                (1, None) => "+".to_string(),
+                (0, Some(1)) => "?".to_string(),
+                (min, None) => format!("{{{},}}", min),
+                (min, Some(max)) if min == max => format!("{{{}}}", min),
+                (min, Some(max)) => format!("{{{},{}}}", min, max),
+            };
+            format!("{}{}", write_atom(&repetition.sub)?, quantifier)
+        }
+        HirKind::Capture(capture) => format!("({})", write(&capture.sub)?),
+        HirKind::Concat(subs) => subs
+            .iter()
+            .map(|sub| match sub.kind() {
+                HirKind::Alternation(_) => Ok(format!("(?:{})", write(sub)?)),
+                _ => write(sub),
+            })
+            .collect::<Result<String>>()?,
+        HirKind::Alternation(subs) => subs
+            .iter()
+            .map(write)
+            .collect::<Result<Vec<String>>>()?
+            .join("|"),
+    })
+}
+
+/// Writes `hir` so that a quantifier applies to all of it.
+pub(super) fn write_atom(hir: &Hir) -> Result<String> {
+    let single_char = match hir.kind() {
+        HirKind::Literal(literal) => std::str::from_utf8(&literal.0)
+            .map(|literal| literal.chars().count() == 1)
+            .unwrap_or(false),
+        HirKind::Class(_) | HirKind::Capture(_) => true,
+        _ => false,
+    };
+    match single_char {
+        true => write(hir),
+        false => Ok(format!("(?:{})", write(hir)?)),
+    }
+}
+
+pub(super) fn write_class(class: &Class) -> Result<String> {
+    let ranges: Vec<(char, char)> = match class {
+        Class::Unicode(class) => class
+            .ranges()
+            .iter()
+            .map(|range| (range.start(), range.end()))
+            .collect(),
+        Class::Bytes(class) => class
+            .ranges()
+            .iter()
+            .map(|range| {
+                if range.end().is_ascii() {
+                    Ok((char::from(range.start()), char::from(range.end())))
+                } else {
+                    Err(anyhow!("Patterns must match text"))
+                }
+            })
+            .collect::<Result<_>>()?,
+    };
+    let escape = |c: char| match c {
+        '\\' | ']' | '[' | '^' | '-' | '&' | '~' => format!("\\{}", c),
+        c => c.to_string(),
+    };
+    match ranges.as_slice() {
+        [] => Err(anyhow!("Patterns must not contain empty classes")),
+        [(start, end)] if start == end => Ok(regex::escape(&start.to_string())),
+        ranges => Ok(format!(
+            "[{}]",
+            ranges
+                .iter()
+                .map(|&(start, end)| match start == end {
+                    true => escape(start),
+                    false => format!("{}-{}", escape(start), escape(end)),
+                })
+                .collect::<String>()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn full_match(pattern: &str) -> Regex {
+        Regex::new(&format!("^(?:{})$", lower_pattern(pattern).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn inline_flags() {
+        assert!(needs_lowering("(?i)abc"));
+        assert!(needs_lowering("a(?i:b)c"));
+        assert!(needs_lowering("(?-i)abc"));
+        assert!(!needs_lowering("(?:abc)"));
+        assert!(!needs_lowering("(?P<name>abc)"));
+        assert!(!needs_lowering("abc"));
+
+        assert_eq!(lower_pattern("(?i)ab").unwrap(), "[Aa][Bb]");
+        assert_eq!(lower_pattern("^(?i)[a-f0-9]+$").unwrap(), "[0-9A-Fa-f]+");
+        assert_eq!(lower_pattern("(?i)on|no").unwrap(), "(?:[Oo][Nn]|[Nn][Oo])");
+
+        for (pattern, matching, others) in [
+            ("a(?i:b)c", &["abc", "aBc"][..], &["ABC", "abC"][..]),
+            (
+                "(?i)(red|green)+",
+                &["RED", "reDGreen"][..],
+                &["blue", ""][..],
+            ),
+            (
+                "(?i)x{2,3}-[^a]",
+                &["xX-b", "XXX-&"][..],
+                &["x-b", "xx-A"][..],
+            ),
+        ] {
+            let lowered =
+                Regex::new(&format!("^(?:{})$", lower_pattern(pattern).unwrap())).unwrap();
+            for text in matching {
+                assert!(lowered.is_match(text), "{} matches {}", pattern, text);
+            }
+            for text in others {
+                assert!(
+                    !lowered.is_match(text),
+                    "{} does not match {}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn property_classes() {
+        assert!(needs_lowering(r"^\p{L}+$"));
+        assert!(needs_lowering(r"\PN"));
+        assert!(!needs_lowering(r"\\p"));
+        assert!(!needs_lowering(r"[a-z]+"));
+
+        assert_eq!(lower_pattern(r"\p{Nd}").unwrap().chars().next(), Some('['));
+        let name = full_match(r"^\p{Lu}\p{Ll}+(?: \p{Lu}\p{Ll}+)*$");
+        assert!(name.is_match("Zoë Ångström"));
+        assert!(name.is_match("Ωμέγα"));
+        assert!(!name.is_match("zoë"));
+        assert!(!name.is_match("Zoë 42"));
+
+        let greek = full_match(r"[\p{Greek}\p{N}]+");
+        assert!(greek.is_match("αβγ123٣"));
+        assert!(!greek.is_match("abc"));
+    }
+
+    #[test]
+    fn embeddable() {
+        let embed = |pattern| embeddable_pattern(pattern, true);
+        assert_eq!(embed("^[a-z]+$").unwrap(), "[a-z]+");
+        assert_eq!(embed("^$").unwrap(), "");
+        assert_eq!(embed("^").unwrap(), "");
+        assert_eq!(embed("ab$").unwrap(), "ab");
+        assert_eq!(embed("^(a|b)$").unwrap(), "(a|b)");
+        assert_eq!(embed("(?:a)|b").unwrap(), "((?:a)|b)");
+        assert!(embed("^a|b").is_err());
+        assert!(matches!(embed(r"a\bb").unwrap(), Cow::Borrowed(r"a\bb")));
+
+        assert!(embed("a^")
+            .unwrap_err()
+            .starts_with("the anchor at offset 1"));
+        assert!(embed("(a$)b").is_err());
+        assert!(embed("(?P<x>a)").unwrap_err().contains("'x'"));
+        assert!(embed("(a").unwrap_err().ends_with("at offset 0"));
+        assert!(embeddable_pattern("^ ?", false).is_err());
+        assert_eq!(embeddable_pattern(" |\\t", false).unwrap(), "( |\\t)");
+    }
+}
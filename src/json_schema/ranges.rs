@@ -0,0 +1,620 @@
+use anyhow::{anyhow, Result};
+
+use crate::json_schema::types::{FormatType, IntegerForms};
+
+/// Builds a regular expression matching exactly the integers from `lo` to `hi`, both included,
+/// written the way JSON writes them: no leading zeros, no `+` and no `-0`.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::json_schema::integer_range_regex;
+/// #
+/// assert_eq!(integer_range_regex(1, 12).unwrap(), "([1-9]|1[0-2])");
+/// assert_eq!(integer_range_regex(-5, 5).unwrap(), "(-[1-5]|[0-5])");
+/// ```
+pub fn integer_range_regex(lo: i128, hi: i128) -> Result<String> {
+    bounded_integer_regex(Some(lo), Some(hi))
+}
+
+/// Builds a regular expression matching the integers between the bounds, both included; a
+/// missing bound leaves that side unbounded.
+pub(crate) fn bounded_integer_regex(lo: Option<i128>, hi: Option<i128>) -> Result<String> {
+    integer_forms_regex(lo, hi, &IntegerForms::default())
+}
+
+/// Builds a regular expression matching the integers between the bounds, like
+/// [`bounded_integer_regex`], each written in any of the lexical forms.
+pub(crate) fn integer_forms_regex(
+    lo: Option<i128>,
+    hi: Option<i128>,
+    forms: &IntegerForms,
+) -> Result<String> {
+    if let (Some(lo), Some(hi)) = (lo, hi) {
+        if lo > hi {
+            return Err(anyhow!("No integer is between {} and {}", lo, hi));
+        }
+    }
+
+    // Leading zeros may come before any magnitude, zero included.
+    let zeros = match forms.leading_zeros {
+        true => "0*",
+        false => "",
+    };
+    let mut alternatives = Vec::new();
+    if lo.is_none_or(|lo| lo < 0) {
+        // Negative integers are a minus sign followed by a range of magnitudes.
+        let min_magnitude = match hi {
+            Some(hi) if hi < 0 => hi.unsigned_abs(),
+            _ => 1,
+        };
+        let max_magnitude = lo.map(i128::unsigned_abs);
+        let magnitudes = natural_alternatives(min_magnitude, max_magnitude);
+        alternatives.push(format!("-{}{}", zeros, group(&magnitudes)));
+    }
+    let has_zero = lo.is_none_or(|lo| lo <= 0) && hi.is_none_or(|hi| hi >= 0);
+    if has_zero && forms.negative_zero {
+        alternatives.push(format!("-{}0", zeros));
+    }
+    if hi.is_none_or(|hi| hi >= 0) {
+        let lo = lo.map_or(0, |lo| lo.max(0).unsigned_abs());
+        let hi = hi.map(i128::unsigned_abs);
+        let naturals = natural_alternatives(lo, hi);
+        match forms.plus_sign {
+            true => alternatives.push(format!(r"\+?{}{}", zeros, group(&naturals))),
+            false if forms.leading_zeros => {
+                alternatives.push(format!("{}{}", zeros, group(&naturals)))
+            }
+            false => alternatives.extend(naturals),
+        }
+    }
+
+    Ok(group(&alternatives))
+}
+
+/// Alternatives matching the natural numbers from `lo` to `hi`, one per number of digits.
+fn natural_alternatives(lo: u128, hi: Option<u128>) -> Vec<String> {
+    let lo = lo.to_string();
+    let hi = hi.map(|hi| hi.to_string());
+    let max_digits = hi.as_ref().map_or(lo.len(), String::len);
+
+    let mut alternatives = Vec::new();
+    for digits in lo.len()..=max_digits {
+        let start = match digits == lo.len() {
+            true => lo.clone(),
+            false => format!("1{}", "0".repeat(digits - 1)),
+        };
+        let end = match &hi {
+            Some(hi) if hi.len() == digits => hi.clone(),
+            _ => "9".repeat(digits),
+        };
+        alternatives.push(same_length_range(&start, &end));
+    }
+    if hi.is_none() {
+        alternatives.push(format!("[1-9][0-9]{{{},}}", lo.len()));
+    }
+    alternatives
+}
+
+/// Matches the numbers from `lo` to `hi`, which are written with the same number of digits.
+fn same_length_range(lo: &str, hi: &str) -> String {
+    let (Some(first_lo), Some(first_hi)) = (lo.chars().next(), hi.chars().next()) else {
+        return String::new();
+    };
+    let (rest_lo, rest_hi) = (&lo[1..], &hi[1..]);
+    if first_lo == first_hi {
+        return format!(
+            "{}{}",
+            first_lo,
+            group(&[same_length_range(rest_lo, rest_hi)])
+        );
+    }
+
+    let lowest = rest_lo.chars().all(|c| c == '0');
+    let highest = rest_hi.chars().all(|c| c == '9');
+    if lowest && highest && (first_lo, first_hi) == ('0', '9') {
+        return any_digits(lo.len());
+    }
+    let mut alternatives = Vec::new();
+    // The numbers starting with `first_lo`, unless they all fall in the middle digits.
+    let middle_lo = if lowest {
+        first_lo
+    } else {
+        let rest_max = "9".repeat(rest_lo.len());
+        alternatives.push(format!(
+            "{}{}",
+            first_lo,
+            group(&[same_length_range(rest_lo, &rest_max)])
+        ));
+        next_digit(first_lo)
+    };
+    let middle_hi = if highest {
+        first_hi
+    } else {
+        previous_digit(first_hi)
+    };
+    if middle_lo <= middle_hi {
+        alternatives.push(format!(
+            "{}{}",
+            digit_class(middle_lo, middle_hi),
+            any_digits(rest_lo.len())
+        ));
+    }
+    if !highest {
+        let rest_min = "0".repeat(rest_hi.len());
+        alternatives.push(format!(
+            "{}{}",
+            first_hi,
+            group(&[same_length_range(&rest_min, rest_hi)])
+        ));
+    }
+    alternatives.join("|")
+}
+
+/// Fixed-width number inside a formatted string, such as the month of a date.
+struct Field {
+    /// Text written before the field.
+    separator: &'static str,
+    width: usize,
+    min: u32,
+    max: u32,
+    /// Whether the field can be left out, which counts as its minimum.
+    optional: bool,
+}
+
+const fn field(separator: &'static str, width: usize, min: u32, max: u32) -> Field {
+    Field {
+        separator,
+        width,
+        min,
+        max,
+        optional: false,
+    }
+}
+
+/// Milliseconds, written after a dot if they are not zero.
+const MILLISECONDS: Field = Field {
+    separator: ".",
+    width: 3,
+    min: 0,
+    max: 999,
+    optional: true,
+};
+
+static DATE_FIELDS: [Field; 3] = [
+    field("", 4, 0, 9999),
+    field("-", 2, 1, 12),
+    field("-", 2, 1, 31),
+];
+static TIME_FIELDS: [Field; 4] = [
+    field("", 2, 0, 23),
+    field(":", 2, 0, 59),
+    field(":", 2, 0, 59),
+    MILLISECONDS,
+];
+static DATE_TIME_FIELDS: [Field; 7] = [
+    field("", 4, 0, 9999),
+    field("-", 2, 1, 12),
+    field("-", 2, 1, 31),
+    field("T", 2, 0, 23),
+    field(":", 2, 0, 59),
+    field(":", 2, 0, 59),
+    MILLISECONDS,
+];
+
+/// Bound of a formatted string, such as `formatMinimum`.
+pub(crate) struct FormatBound<'a> {
+    pub(crate) keyword: &'a str,
+    pub(crate) value: &'a str,
+    pub(crate) exclusive: bool,
+}
+
+/// Builds the regular expression of the strings of a `date`, `time` or `date-time` format that
+/// are between the bounds, a missing bound leaving that side unbounded.
+///
+/// Dates have four-digit years and times are compared to the millisecond, so the fractions of
+/// seconds are narrowed to milliseconds. Time zones other than `Z` are not supported, and `Z` is
+/// neither required nor taken into account by the comparison.
+pub(crate) fn format_range_regex(
+    format: &FormatType,
+    lo: Option<FormatBound>,
+    hi: Option<FormatBound>,
+) -> Result<String> {
+    let (fields, zoned): (&[Field], bool) = match format {
+        FormatType::Date => (&DATE_FIELDS, false),
+        FormatType::Time => (&TIME_FIELDS, true),
+        FormatType::DateTime => (&DATE_TIME_FIELDS, true),
+        format => {
+            return Err(anyhow!(
+                "Bounds are not supported by the {:?} format",
+                format
+            ))
+        }
+    };
+
+    let lo = match lo {
+        Some(bound) => {
+            let value = parse_formatted(fields, zoned, &bound)?;
+            match bound.exclusive {
+                true => step(fields, value, 1)
+                    .ok_or_else(|| anyhow!("No value is after '{}'", bound.value))?,
+                false => value,
+            }
+        }
+        None => fields.iter().map(|field| field.min).collect(),
+    };
+    let hi = match hi {
+        Some(bound) => {
+            let value = parse_formatted(fields, zoned, &bound)?;
+            match bound.exclusive {
+                true => step(fields, value, -1)
+                    .ok_or_else(|| anyhow!("No value is before '{}'", bound.value))?,
+                false => value,
+            }
+        }
+        None => fields.iter().map(|field| field.max).collect(),
+    };
+    if lo > hi {
+        return Err(anyhow!("The lower bound is after the upper bound"));
+    }
+
+    Ok(format!(
+        r#""{}{}""#,
+        group(&[fields_range(fields, &lo, &hi)]),
+        if zoned { "(Z)?" } else { "" }
+    ))
+}
+
+/// Reads the fields of a bound written in its format, with optional milliseconds and `Z`.
+fn parse_formatted(fields: &[Field], zoned: bool, bound: &FormatBound) -> Result<Vec<u32>> {
+    let invalid = || anyhow!("'{}' is not a valid '{}' bound", bound.value, bound.keyword);
+    let mut rest = bound.value;
+    let mut values = Vec::new();
+    for field in fields {
+        let Some(after_separator) = rest.strip_prefix(field.separator) else {
+            if field.optional {
+                values.push(field.min);
+                continue;
+            }
+            return Err(invalid());
+        };
+        // Fractions shorter than the field are padded with zeros.
+        let digits = after_separator
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        let fits = match field.optional {
+            true => (1..=field.width).contains(&digits),
+            false => digits == field.width,
+        };
+        if !fits {
+            return Err(invalid());
+        }
+        let padded = format!(
+            "{:0<width$}",
+            &after_separator[..digits],
+            width = field.width
+        );
+        let value: u32 = padded.parse().map_err(|_| invalid())?;
+        if !(field.min..=field.max).contains(&value) {
+            return Err(invalid());
+        }
+        values.push(value);
+        rest = &after_separator[digits..];
+    }
+    if zoned {
+        rest = rest.strip_prefix('Z').unwrap_or(rest);
+    }
+    match rest.is_empty() {
+        true => Ok(values),
+        false => Err(invalid()),
+    }
+}
+
+/// Value right after (`1`) or right before (`-1`) `values`, or `None` past the first or last
+/// value.
+fn step(fields: &[Field], mut values: Vec<u32>, direction: i8) -> Option<Vec<u32>> {
+    for (field, value) in fields.iter().zip(values.iter_mut()).rev() {
+        match direction {
+            1 if *value < field.max => {
+                *value += 1;
+                return Some(values);
+            }
+            -1 if *value > field.min => {
+                *value -= 1;
+                return Some(values);
+            }
+            1 => *value = field.min,
+            _ => *value = field.max,
+        }
+    }
+    None
+}
+
+/// Matches the values of the fields from `lo` to `hi`, compared field by field.
+fn fields_range(fields: &[Field], lo: &[u32], hi: &[u32]) -> String {
+    let [field, rest_fields @ ..] = fields else {
+        return String::new();
+    };
+    let padded = |value: u32| format!("{:0width$}", value, width = field.width);
+    // Leaving an optional field out writes its minimum, which only the lowest values allow.
+    let digits = |lo: u32, hi: u32| {
+        let written = format!(
+            "{}{}",
+            field.separator.replace('.', r"\."),
+            group(&[same_length_range(&padded(lo), &padded(hi))])
+        );
+        match field.optional && lo == field.min {
+            true => format!("({})?", written),
+            false => written,
+        }
+    };
+
+    let (rest_lo, rest_hi) = (&lo[1..], &hi[1..]);
+    if lo[0] == hi[0] {
+        return format!(
+            "{}{}",
+            digits(lo[0], hi[0]),
+            group(&[fields_range(rest_fields, rest_lo, rest_hi)])
+        );
+    }
+
+    let rest_min: Vec<u32> = rest_fields.iter().map(|field| field.min).collect();
+    let rest_max: Vec<u32> = rest_fields.iter().map(|field| field.max).collect();
+    let lowest = rest_lo == rest_min;
+    let highest = rest_hi == rest_max;
+    let mut alternatives = Vec::new();
+    let middle_lo = if lowest {
+        lo[0]
+    } else {
+        alternatives.push(format!(
+            "{}{}",
+            digits(lo[0], lo[0]),
+            group(&[fields_range(rest_fields, rest_lo, &rest_max)])
+        ));
+        lo[0] + 1
+    };
+    let middle_hi = if highest { hi[0] } else { hi[0] - 1 };
+    if middle_lo <= middle_hi {
+        alternatives.push(format!(
+            "{}{}",
+            digits(middle_lo, middle_hi),
+            group(&[fields_range(rest_fields, &rest_min, &rest_max)])
+        ));
+    }
+    if !highest {
+        alternatives.push(format!(
+            "{}{}",
+            digits(hi[0], hi[0]),
+            group(&[fields_range(rest_fields, &rest_min, rest_hi)])
+        ));
+    }
+    alternatives.join("|")
+}
+
+fn group(alternatives: &[String]) -> String {
+    match alternatives {
+        [alternative] if !alternative.contains('|') => alternative.clone(),
+        _ => format!("({})", alternatives.join("|")),
+    }
+}
+
+fn digit_class(lo: char, hi: char) -> String {
+    match lo == hi {
+        true => lo.to_string(),
+        false => format!("[{}-{}]", lo, hi),
+    }
+}
+
+fn any_digits(count: usize) -> String {
+    match count {
+        0 => String::new(),
+        1 => "[0-9]".to_string(),
+        _ => format!("[0-9]{{{}}}", count),
+    }
+}
+
+fn next_digit(digit: char) -> char {
+    char::from(digit as u8 + 1)
+}
+
+fn previous_digit(digit: char) -> char {
+    char::from(digit as u8 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn full_match(pattern: &str) -> Regex {
+        Regex::new(&format!("^{}$", pattern)).unwrap()
+    }
+
+    #[test]
+    fn integer_ranges() {
+        for (lo, hi) in [
+            (0, 0),
+            (0, 9),
+            (1, 12),
+            (-5, 5),
+            (7, 1234),
+            (-1234, -7),
+            (-120, 1099),
+            (100, 199),
+            (1024, 65535),
+            (1900, 2099),
+        ] {
+            let regex = full_match(&integer_range_regex(lo, hi).unwrap());
+            for n in -2000..=70000 {
+                assert_eq!(
+                    regex.is_match(&n.to_string()),
+                    (lo..=hi).contains(&n),
+                    "{} in {}..={}",
+                    n,
+                    lo,
+                    hi
+                );
+            }
+            assert!(!regex.is_match("-0"));
+            assert!(!regex.is_match("007"));
+        }
+        assert!(integer_range_regex(2, 1).is_err());
+
+        let extremes = full_match(&integer_range_regex(i128::MIN, i128::MAX).unwrap());
+        assert!(extremes.is_match(&i128::MIN.to_string()));
+        assert!(extremes.is_match(&i128::MAX.to_string()));
+        assert!(!extremes.is_match(&(i128::MAX as u128 + 1).to_string()));
+    }
+
+    #[test]
+    fn half_bounded_ranges() {
+        let at_least = full_match(&bounded_integer_regex(Some(-15), None).unwrap());
+        let at_most = full_match(&bounded_integer_regex(None, Some(42)).unwrap());
+        for n in -20000..=20000 {
+            assert_eq!(at_least.is_match(&n.to_string()), n >= -15, "{} >= -15", n);
+            assert_eq!(at_most.is_match(&n.to_string()), n <= 42, "{} <= 42", n);
+        }
+    }
+
+    #[test]
+    fn integer_forms() {
+        let forms = |plus_sign, negative_zero, leading_zeros| IntegerForms {
+            plus_sign,
+            negative_zero,
+            leading_zeros,
+        };
+        let canonical =
+            full_match(&integer_forms_regex(None, None, &forms(false, false, false)).unwrap());
+        assert!(canonical.is_match("-12"));
+        assert!(canonical.is_match("0"));
+        assert!(!canonical.is_match("-0"));
+        assert!(!canonical.is_match("+1"));
+
+        let relaxed =
+            full_match(&integer_forms_regex(None, None, &forms(true, true, true)).unwrap());
+        for form in ["+0", "-0", "-00", "+007", "-012", "12"] {
+            assert!(relaxed.is_match(form), "{}", form);
+        }
+        assert!(!relaxed.is_match("+-1"));
+        assert!(!relaxed.is_match("-+1"));
+
+        let bounded = full_match(
+            &integer_forms_regex(Some(-5), Some(10), &forms(true, true, false)).unwrap(),
+        );
+        for form in ["-5", "-0", "+0", "+10", "10"] {
+            assert!(bounded.is_match(form), "{}", form);
+        }
+        assert!(!bounded.is_match("+11"));
+        assert!(!bounded.is_match("05"));
+        let positive =
+            full_match(&integer_forms_regex(Some(1), None, &forms(false, true, true)).unwrap());
+        assert!(positive.is_match("007"));
+        assert!(!positive.is_match("-0"));
+        assert!(!positive.is_match("00"));
+    }
+
+    fn format_regex(
+        format: FormatType,
+        lo: Option<&str>,
+        hi: Option<&str>,
+        exclusive: bool,
+    ) -> Regex {
+        let bound = |value| FormatBound {
+            keyword: "formatMinimum",
+            value,
+            exclusive,
+        };
+        full_match(&format_range_regex(&format, lo.map(bound), hi.map(bound)).unwrap())
+    }
+
+    #[test]
+    fn date_ranges() {
+        let dates: Vec<String> = (1999..=2001)
+            .flat_map(|year| {
+                (1..=12).flat_map(move |month| {
+                    (1..=31).map(move |day| format!(r#""{:04}-{:02}-{:02}""#, year, month, day))
+                })
+            })
+            .collect();
+        for (lo, hi) in [
+            (Some("1999-12-31"), Some("2000-02-29")),
+            (Some("2000-01-01"), Some("2000-12-31")),
+            (Some("1999-06-15"), None),
+            (None, Some("2001-03-10")),
+        ] {
+            let inclusive = format_regex(FormatType::Date, lo, hi, false);
+            let exclusive = format_regex(FormatType::Date, lo, hi, true);
+            for date in &dates {
+                let date = date.as_str();
+                let above = |lo: &str| &date[1..11] > lo;
+                let below = |hi: &str| &date[1..11] < hi;
+                let equal = |bound: Option<&str>| bound == Some(&date[1..11]);
+                let strict = lo.is_none_or(above) && hi.is_none_or(below);
+                assert_eq!(
+                    inclusive.is_match(date),
+                    strict || equal(lo) || equal(hi),
+                    "{}",
+                    date
+                );
+                assert_eq!(exclusive.is_match(date), strict, "{}", date);
+            }
+        }
+        assert!(
+            !format_regex(FormatType::Date, None, Some("2000-01-01"), false)
+                .is_match(r#""1999-13-01""#)
+        );
+    }
+
+    #[test]
+    fn date_time_ranges() {
+        let regex = format_regex(
+            FormatType::DateTime,
+            Some("2024-03-01T09:30:00"),
+            Some("2024-03-01T17:00:00Z"),
+            false,
+        );
+        for (date_time, expected) in [
+            ("2024-03-01T09:29:59.999", false),
+            ("2024-03-01T09:30:00", true),
+            ("2024-03-01T09:30:00.000Z", true),
+            ("2024-03-01T12:45:10.250", true),
+            ("2024-03-01T17:00:00", true),
+            ("2024-03-01T17:00:00.000", true),
+            ("2024-03-01T17:00:00.001", false),
+            ("2024-03-02T10:00:00", false),
+        ] {
+            assert_eq!(
+                regex.is_match(&format!(r#""{}""#, date_time)),
+                expected,
+                "{}",
+                date_time
+            );
+        }
+
+        let after = format_regex(FormatType::Time, Some("12:00:00"), None, true);
+        assert!(!after.is_match(r#""12:00:00""#));
+        assert!(!after.is_match(r#""12:00:00.000""#));
+        assert!(after.is_match(r#""12:00:00.001""#));
+        assert!(after.is_match(r#""23:59:59Z""#));
+
+        let bound = |value| FormatBound {
+            keyword: "formatMaximum",
+            value,
+            exclusive: false,
+        };
+        for invalid in [
+            "2024-03-01",
+            "2024-03-01T25:00:00",
+            "2024-03-01T10:00:00+02:00",
+        ] {
+            assert!(format_range_regex(&FormatType::DateTime, None, Some(bound(invalid))).is_err());
+        }
+        assert!(format_range_regex(
+            &FormatType::Date,
+            Some(bound("2024-02-01")),
+            Some(bound("2024-01-01"))
+        )
+        .is_err());
+    }
+}
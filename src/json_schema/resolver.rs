@@ -0,0 +1,528 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+
+/// Source of the documents of absolute `$ref`s which are not resources of the compiled schema,
+/// such as a local store of schemas. Nothing is fetched from the network: without a loader,
+/// such references fail to compile.
+pub trait RefLoader: Send + Sync {
+    /// Loads the document at `uri`, an absolute URI without fragment.
+    fn load(&self, uri: &str) -> Result<Value>;
+}
+
+impl<F> RefLoader for F
+where
+    F: Fn(&str) -> Result<Value> + Send + Sync,
+{
+    fn load(&self, uri: &str) -> Result<Value> {
+        self(uri)
+    }
+}
+
+/// Documents keyed by their URI.
+impl RefLoader for HashMap<String, Value> {
+    fn load(&self, uri: &str) -> Result<Value> {
+        self.get(uri)
+            .cloned()
+            .ok_or_else(|| anyhow!("No document is known at '{}'", uri))
+    }
+}
+
+impl std::fmt::Debug for dyn RefLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RefLoader")
+    }
+}
+
+/// Loads the documents of the absolute references of `root` which are not resources of it,
+/// then those of the documents loaded in turn. Each is returned with its URI.
+pub(crate) fn load_documents(root: &Value, loader: &dyn RefLoader) -> Result<Vec<(String, Value)>> {
+    let mut documents = Vec::new();
+    loop {
+        let missing = Resolver::new(root, &documents).missing_documents();
+        if missing.is_empty() {
+            return Ok(documents);
+        }
+        for uri in missing {
+            let document = loader
+                .load(&uri)
+                .map_err(|e| anyhow!("Could not load the referenced document '{}': {}", uri, e))?;
+            documents.push((uri, document));
+        }
+    }
+}
+
+/// Index of the schema resources (`$id`) and anchors (`$anchor`, `$dynamicAnchor`) declared
+/// in a JSON Schema document, used to resolve references.
+pub(crate) struct Resolver<'a> {
+    root: &'a Value,
+    base_uri: String,
+    resources: HashMap<String, &'a Value>,
+    anchors: HashMap<(String, String), &'a Value>,
+    dynamic_anchors: HashMap<(String, String), &'a Value>,
+    /// Absolute URIs, without fragment, of the documents referenced.
+    referenced: BTreeSet<String>,
+}
+
+/// A resolved reference, together with the base URI of the resource it lives in.
+pub(crate) struct Resolved<'a> {
+    pub schema: &'a Value,
+    pub base_uri: String,
+}
+
+impl<'a> Resolver<'a> {
+    /// Indexes `root` and the `documents` loaded for its references, with their URIs.
+    pub fn new(root: &'a Value, documents: &'a [(String, Value)]) -> Self {
+        let base_uri = root
+            .get("$id")
+            .and_then(Value::as_str)
+            .map(|id| strip_fragment(id).to_string())
+            .unwrap_or_default();
+
+        let mut resolver = Self {
+            root,
+            base_uri: base_uri.clone(),
+            resources: HashMap::from([(base_uri.clone(), root)]),
+            anchors: HashMap::new(),
+            dynamic_anchors: HashMap::new(),
+            referenced: BTreeSet::new(),
+        };
+        resolver.index(root, &base_uri);
+        for (uri, document) in documents {
+            resolver.resources.entry(uri.clone()).or_insert(document);
+            resolver.index(document, uri);
+        }
+        resolver
+    }
+
+    /// URIs of the referenced documents which are not indexed.
+    fn missing_documents(&self) -> Vec<String> {
+        self.referenced
+            .iter()
+            .filter(|uri| !self.resources.contains_key(*uri))
+            .cloned()
+            .collect()
+    }
+
+    /// Base URI of the document root.
+    pub fn base_uri(&self) -> &str {
+        &self.base_uri
+    }
+
+    fn index(&mut self, value: &'a Value, base_uri: &str) {
+        match value {
+            Value::Object(obj) => {
+                let mut base_uri = base_uri.to_string();
+                if let Some(id) = obj.get("$id").and_then(Value::as_str) {
+                    base_uri = strip_fragment(&resolve_uri(&base_uri, id)).to_string();
+                    self.resources.entry(base_uri.clone()).or_insert(value);
+                }
+                if let Some(anchor) = obj.get("$anchor").and_then(Value::as_str) {
+                    self.anchors
+                        .entry((base_uri.clone(), anchor.to_string()))
+                        .or_insert(value);
+                }
+                if let Some(anchor) = obj.get("$dynamicAnchor").and_then(Value::as_str) {
+                    self.dynamic_anchors
+                        .entry((base_uri.clone(), anchor.to_string()))
+                        .or_insert(value);
+                }
+                if let Some(reference) = obj.get("$ref").and_then(Value::as_str) {
+                    let uri = resolve_uri(&base_uri, strip_fragment(reference));
+                    if has_scheme(&uri) {
+                        self.referenced.insert(uri);
+                    }
+                }
+                for (keyword, child) in obj {
+                    // `enum` and `const` hold instances, not subschemas.
+                    if keyword != "enum" && keyword != "const" {
+                        self.index(child, &base_uri);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for child in values {
+                    self.index(child, base_uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Resolves `reference` relative to `base_uri`.
+    pub fn resolve(&self, base_uri: &str, reference: &str) -> Result<Resolved<'a>> {
+        let (uri, fragment) = match reference.split_once('#') {
+            Some((uri, fragment)) => (uri, Some(fragment)),
+            None => (reference, None),
+        };
+
+        let resource_uri = if uri.is_empty() {
+            base_uri.to_string()
+        } else {
+            resolve_uri(base_uri, uri)
+        };
+
+        let not_loaded = || {
+            anyhow!(
+                "External reference '{}' is not loaded, no `RefLoader` provides it",
+                reference
+            )
+        };
+        let (resource_uri, resource) = match self.resources.get(&resource_uri) {
+            Some(resource) => (resource_uri, *resource),
+            // References without a fragment have historically been treated as JSON pointers
+            // into the document.
+            None if fragment.is_none() && !uri.contains(':') => {
+                let resolved = self.resolve_pointer(self.root, &self.base_uri, reference);
+                if resolved.is_ok() || !has_scheme(&resource_uri) {
+                    return resolved;
+                }
+                return Err(not_loaded());
+            }
+            None => return Err(not_loaded()),
+        };
+
+        match fragment {
+            None | Some("") => Ok(Resolved {
+                schema: resource,
+                base_uri: resource_uri,
+            }),
+            Some(pointer) if pointer.starts_with('/') => {
+                self.resolve_pointer(resource, &resource_uri, pointer)
+            }
+            Some(anchor) => {
+                let key = (resource_uri.clone(), anchor.to_string());
+                self.anchors
+                    .get(&key)
+                    .or_else(|| self.dynamic_anchors.get(&key))
+                    .map(|schema| Resolved {
+                        schema,
+                        base_uri: resource_uri,
+                    })
+                    .ok_or_else(|| anyhow!("Invalid reference, no anchor named '{}' found", anchor))
+            }
+        }
+    }
+
+    /// URI of the schema resource `schema`, when it is one of the document's.
+    pub fn resource_uri(&self, schema: &Value) -> Option<&str> {
+        self.resources
+            .iter()
+            .find(|(_, &resource)| std::ptr::eq(resource, schema))
+            .map(|(uri, _)| uri.as_str())
+    }
+
+    /// Finds the schema declaring `$dynamicAnchor: anchor` in the resource at `resource_uri`.
+    pub fn dynamic_anchor(&self, resource_uri: &str, anchor: &str) -> Option<&'a Value> {
+        self.dynamic_anchors
+            .get(&(resource_uri.to_string(), anchor.to_string()))
+            .copied()
+    }
+
+    fn resolve_pointer(
+        &self,
+        resource: &'a Value,
+        resource_uri: &str,
+        pointer: &str,
+    ) -> Result<Resolved<'a>> {
+        let mut current = resource;
+        let mut base_uri = resource_uri.to_string();
+        for part in pointer.split('/').filter(|&s| !s.is_empty()) {
+            let part = percent_decode(part).replace("~1", "/").replace("~0", "~");
+            current = match current {
+                Value::Array(values) => part.parse::<usize>().ok().and_then(|i| values.get(i)),
+                _ => current.get(&part),
+            }
+            .ok_or_else(|| anyhow!("Invalid reference path: {}", part))?;
+
+            if let Some(id) = current.get("$id").and_then(Value::as_str) {
+                base_uri = strip_fragment(&resolve_uri(&base_uri, id)).to_string();
+            }
+        }
+        Ok(Resolved {
+            schema: current,
+            base_uri,
+        })
+    }
+}
+
+/// Rewrites the `$ref`s of a schema, whose base URI is `base_uri`, into absolute references.
+pub(crate) fn absolutize_refs(obj: &mut Map<String, Value>, base_uri: &str) {
+    let base_uri = match obj.get("$id").and_then(Value::as_str) {
+        Some(id) => strip_fragment(&resolve_uri(base_uri, id)).to_string(),
+        None => base_uri.to_string(),
+    };
+    for (keyword, value) in obj.iter_mut() {
+        match value {
+            Value::String(reference) if keyword == "$ref" => {
+                *reference = resolve_uri(&base_uri, reference);
+            }
+            // `enum` and `const` hold instances, not subschemas.
+            _ if keyword == "enum" || keyword == "const" => {}
+            value => absolutize_value(value, &base_uri),
+        }
+    }
+}
+
+fn absolutize_value(value: &mut Value, base_uri: &str) {
+    match value {
+        Value::Object(obj) => absolutize_refs(obj, base_uri),
+        Value::Array(values) => {
+            for value in values {
+                absolutize_value(value, base_uri);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_scheme(uri: &str) -> bool {
+    uri.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+fn strip_fragment(uri: &str) -> &str {
+    uri.split_once('#').map_or(uri, |(uri, _)| uri)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Resolves a URI reference against a base URI, following RFC 3986 section 5.2.
+pub(crate) fn resolve_uri(base: &str, reference: &str) -> String {
+    if has_scheme(reference) {
+        return reference.to_string();
+    }
+    if reference.is_empty() {
+        return base.to_string();
+    }
+    if reference.starts_with('#') {
+        return format!("{}{}", strip_fragment(base), reference);
+    }
+
+    let (scheme, rest) = match base.split_once(':') {
+        Some((scheme, rest)) if has_scheme(base) => (format!("{scheme}:"), rest),
+        _ => (String::new(), base),
+    };
+
+    if let Some(reference) = reference.strip_prefix("//") {
+        return format!("{scheme}//{reference}");
+    }
+
+    let (authority, path) = match rest.strip_prefix("//") {
+        Some(rest) => match rest.find('/') {
+            Some(i) => (format!("//{}", &rest[..i]), &rest[i..]),
+            None => (format!("//{rest}"), ""),
+        },
+        None => (String::new(), rest),
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let merged = if reference.starts_with('/') {
+        reference.to_string()
+    } else if reference.starts_with('?') {
+        format!("{path}{reference}")
+    } else {
+        match path.rfind('/') {
+            Some(i) => format!("{}{}", &path[..=i], reference),
+            None if !authority.is_empty() => format!("/{reference}"),
+            None => reference.to_string(),
+        }
+    };
+
+    format!("{scheme}{authority}{}", remove_dot_segments(&merged))
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let segments: Vec<&str> = path.split('/').collect();
+    for (i, segment) in segments.iter().enumerate() {
+        match *segment {
+            "." => {
+                if i == segments.len() - 1 {
+                    output.push("");
+                }
+            }
+            ".." => {
+                if output.len() > 1 || output.first().is_some_and(|s| !s.is_empty()) {
+                    output.pop();
+                }
+                if i == segments.len() - 1 {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+    output.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_uris() {
+        let base = "https://example.com/schemas/root.json";
+
+        assert_eq!(
+            resolve_uri(base, "address.json"),
+            "https://example.com/schemas/address.json"
+        );
+        assert_eq!(
+            resolve_uri(base, "../common/address.json"),
+            "https://example.com/common/address.json"
+        );
+        assert_eq!(
+            resolve_uri(base, "/address.json"),
+            "https://example.com/address.json"
+        );
+        assert_eq!(resolve_uri(base, "urn:uuid:1234"), "urn:uuid:1234");
+        assert_eq!(
+            resolve_uri(base, "#/$defs/name"),
+            "https://example.com/schemas/root.json#/$defs/name"
+        );
+        assert_eq!(resolve_uri("", "address.json"), "address.json");
+        assert_eq!(
+            resolve_uri("https://example.com", "address.json"),
+            "https://example.com/address.json"
+        );
+    }
+
+    #[test]
+    fn absolute_refs() {
+        let mut schema = serde_json::json!({
+            "properties": {
+                "a": {"$ref": "#/$defs/a"},
+                "b": {"$id": "b/", "items": [{"$ref": "c.json#c"}]},
+                "c": {"const": {"$ref": "d.json"}}
+            }
+        });
+        absolutize_refs(
+            schema.as_object_mut().unwrap(),
+            "https://example.com/root.json",
+        );
+        assert_eq!(
+            schema,
+            serde_json::json!({
+                "properties": {
+                    "a": {"$ref": "https://example.com/root.json#/$defs/a"},
+                    "b": {"$id": "b/", "items": [{"$ref": "https://example.com/b/c.json#c"}]},
+                    "c": {"const": {"$ref": "d.json"}}
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn loaded_documents() {
+        let schema = serde_json::json!({
+            "$id": "https://example.com/root.json",
+            "properties": {
+                "a": {"$ref": "a.json#/$defs/a"},
+                "b": {"$ref": "urn:example:b"},
+                "c": {"$ref": "#/$defs/c"}
+            },
+            "$defs": {"c": {"type": "null"}}
+        });
+        let store = HashMap::from([
+            (
+                "https://example.com/a.json".to_string(),
+                serde_json::json!({"$defs": {"a": {"$ref": "urn:example:b"}}}),
+            ),
+            (
+                "urn:example:b".to_string(),
+                serde_json::json!({"type": "integer"}),
+            ),
+        ]);
+        let documents = load_documents(&schema, &store).unwrap();
+        let uris: Vec<&str> = documents.iter().map(|(uri, _)| uri.as_str()).collect();
+        assert_eq!(uris, ["https://example.com/a.json", "urn:example:b"]);
+
+        let resolver = Resolver::new(&schema, &documents);
+        let resolved = resolver
+            .resolve("https://example.com/root.json", "a.json#/$defs/a")
+            .unwrap();
+        assert_eq!(resolved.base_uri, "https://example.com/a.json");
+        let resolved = resolver
+            .resolve(&resolved.base_uri, "urn:example:b")
+            .unwrap();
+        assert_eq!(resolved.schema, &serde_json::json!({"type": "integer"}));
+
+        let error = load_documents(&schema, &HashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("https://example.com/a.json"));
+    }
+
+    #[test]
+    fn anchors_and_embedded_resources() {
+        let schema = serde_json::json!({
+            "$id": "https://example.com/root.json",
+            "$defs": {
+                "address": {
+                    "$id": "address.json",
+                    "type": "object",
+                    "$defs": {"street": {"$anchor": "street", "type": "string"}}
+                },
+                "name": {"$anchor": "name", "type": "string"}
+            }
+        });
+        let resolver = Resolver::new(&schema, &[]);
+
+        let resolved = resolver
+            .resolve("https://example.com/root.json", "#name")
+            .unwrap();
+        assert_eq!(resolved.schema, &schema["$defs"]["name"]);
+
+        let resolved = resolver
+            .resolve("https://example.com/root.json", "address.json")
+            .unwrap();
+        assert_eq!(resolved.schema, &schema["$defs"]["address"]);
+        assert_eq!(resolved.base_uri, "https://example.com/address.json");
+
+        let resolved = resolver.resolve(&resolved.base_uri, "#street").unwrap();
+        assert_eq!(
+            resolved.schema,
+            &schema["$defs"]["address"]["$defs"]["street"]
+        );
+
+        let resolved = resolver
+            .resolve(
+                "https://example.com/root.json",
+                "address.json#/$defs/street",
+            )
+            .unwrap();
+        assert_eq!(
+            resolved.schema,
+            &schema["$defs"]["address"]["$defs"]["street"]
+        );
+
+        assert!(resolver
+            .resolve("https://example.com/root.json", "#missing")
+            .is_err());
+        assert!(resolver
+            .resolve("https://example.com/root.json", "https://other.com/x.json")
+            .is_err());
+    }
+}
@@ -0,0 +1,122 @@
+/// Explanation of the schemas admitting no value.
+use super::{to_regex_with_options, Options};
+use crate::index::FSMInfo;
+use serde_json::{Map, Value};
+
+/// Longest description of the constraints of a subschema.
+const MAX_CONSTRAINTS_LEN: usize = 120;
+
+/// Describes the innermost subschemas of `schema` which admit no value, each with the JSON
+/// Pointer to it and its constraints, given that `schema` itself admits none.
+pub(crate) fn unsatisfiable_subschemas(
+    schema: &Value,
+    full_schema: &Value,
+    options: &Options,
+) -> Vec<String> {
+    let options = options.clone().markdown_fence(false);
+    let mut conflicts = Vec::new();
+    if !visit(
+        schema,
+        full_schema,
+        &options,
+        &mut String::new(),
+        &mut conflicts,
+    ) {
+        conflicts.push(describe("", schema));
+    }
+    conflicts
+}
+
+/// Whether a subschema admits no value.
+fn admits_nothing(schema: &Value, full_schema: &Value, options: &Options) -> bool {
+    to_regex_with_options(schema, full_schema, options)
+        .ok()
+        .and_then(|regex| FSMInfo::from_regex(&regex).ok())
+        .is_some_and(|fsm| fsm.is_empty())
+}
+
+/// Visits the subschemas of an unsatisfiable schema, collecting those which admit no value
+/// while their own subschemas do. Returns whether any was found.
+fn visit(
+    schema: &Value,
+    full_schema: &Value,
+    options: &Options,
+    pointer: &mut String,
+    conflicts: &mut Vec<String>,
+) -> bool {
+    let Some(obj) = schema.as_object() else {
+        return false;
+    };
+
+    let mut subschemas: Vec<(Vec<String>, &Value)> = Vec::new();
+    for (keyword, value) in obj {
+        match (keyword.as_str(), value) {
+            ("properties", Value::Object(properties)) => subschemas.extend(
+                properties
+                    .iter()
+                    .map(|(name, subschema)| (vec![keyword.clone(), name.clone()], subschema)),
+            ),
+            ("allOf" | "anyOf" | "oneOf" | "prefixItems" | "items", Value::Array(items)) => {
+                subschemas.extend(
+                    items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, subschema)| (vec![keyword.clone(), i.to_string()], subschema)),
+                )
+            }
+            ("items" | "additionalProperties", subschema @ Value::Object(_)) => {
+                subschemas.push((vec![keyword.clone()], subschema))
+            }
+            _ => {}
+        }
+    }
+
+    let mut found = false;
+    for (tokens, subschema) in subschemas {
+        if !admits_nothing(subschema, full_schema, options) {
+            continue;
+        }
+        let len = pointer.len();
+        for token in tokens {
+            pointer.push('/');
+            pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+        }
+        if !visit(subschema, full_schema, options, pointer, conflicts) {
+            conflicts.push(describe(pointer, subschema));
+        }
+        pointer.truncate(len);
+        found = true;
+    }
+    found
+}
+
+/// Pointer to a subschema, written as a URI fragment, followed by its constraints.
+fn describe(pointer: &str, schema: &Value) -> String {
+    let constraints = match schema {
+        Value::Object(obj) => {
+            let constraints: Map<String, Value> = obj
+                .iter()
+                .filter(|(keyword, _)| {
+                    !matches!(
+                        keyword.as_str(),
+                        "title"
+                            | "description"
+                            | "default"
+                            | "examples"
+                            | "$comment"
+                            | "$defs"
+                            | "definitions"
+                    )
+                })
+                .map(|(keyword, value)| (keyword.clone(), value.clone()))
+                .collect();
+            Value::Object(constraints).to_string()
+        }
+        schema => schema.to_string(),
+    };
+    let constraints = match constraints.char_indices().nth(MAX_CONSTRAINTS_LEN) {
+        Some((end, _)) => format!("{}…", &constraints[..end]),
+        None => constraints,
+    };
+    format!("#{} {}", pointer, constraints)
+}
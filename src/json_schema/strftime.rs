@@ -0,0 +1,143 @@
+/// Date and time patterns described by strftime-like format strings.
+use anyhow::{anyhow, Result};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Pattern of a single directive, without its `%`.
+fn directive_regex(directive: char) -> Option<String> {
+    let abbreviations = |names: &[&str]| {
+        let names: Vec<&str> = names.iter().map(|name| &name[..3]).collect();
+        format!("({})", names.join("|"))
+    };
+    let regex = match directive {
+        'Y' => "[0-9]{4}".to_string(),
+        'y' => "[0-9]{2}".to_string(),
+        'm' => "(0[1-9]|1[0-2])".to_string(),
+        'd' => "(0[1-9]|[12][0-9]|3[01])".to_string(),
+        'e' => "( [1-9]|[12][0-9]|3[01])".to_string(),
+        'j' => "(00[1-9]|0[1-9][0-9]|[12][0-9]{2}|3[0-5][0-9]|36[0-6])".to_string(),
+        'H' => "([01][0-9]|2[0-3])".to_string(),
+        'I' => "(0[1-9]|1[0-2])".to_string(),
+        'M' => "[0-5][0-9]".to_string(),
+        'S' => "([0-5][0-9]|60)".to_string(),
+        'f' => "[0-9]{6}".to_string(),
+        'p' => "(AM|PM)".to_string(),
+        'z' => "[+-]([01][0-9]|2[0-3])[0-5][0-9]".to_string(),
+        'Z' => "[A-Z]{3,5}".to_string(),
+        'b' | 'h' => abbreviations(&MONTHS),
+        'B' => format!("({})", MONTHS.join("|")),
+        'a' => abbreviations(&WEEKDAYS),
+        'A' => format!("({})", WEEKDAYS.join("|")),
+        'F' => format!(
+            "{}-{}-{}",
+            directive_regex('Y')?,
+            directive_regex('m')?,
+            directive_regex('d')?
+        ),
+        'D' => format!(
+            "{}/{}/{}",
+            directive_regex('m')?,
+            directive_regex('d')?,
+            directive_regex('y')?
+        ),
+        'T' => format!(
+            "{}:{}:{}",
+            directive_regex('H')?,
+            directive_regex('M')?,
+            directive_regex('S')?
+        ),
+        'R' => format!("{}:{}", directive_regex('H')?, directive_regex('M')?),
+        '%' => "%".to_string(),
+        _ => return None,
+    };
+    Some(regex)
+}
+
+/// Compiles a format string such as `%d/%m/%Y` into the pattern of the content of the JSON
+/// strings it formats, with the directives of C's `strftime` and Python's `%f`.
+///
+/// Each field is checked on its own, so that `%d/%m` matches `31/02`. The literal characters
+/// must not need escaping in JSON strings.
+pub(crate) fn strftime_regex(format: &str) -> Result<String> {
+    let mut regex = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                let directive = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("Format '{}' ends with a lone '%'", format))?;
+                let directive_regex = directive_regex(directive).ok_or_else(|| {
+                    anyhow!(
+                        "Unsupported directive '%{}' in format '{}'",
+                        directive,
+                        format
+                    )
+                })?;
+                regex.push_str(&directive_regex);
+            }
+            '"' | '\\' | '\0'..='\x1F' | '\x7F'..='\u{9F}' => {
+                return Err(anyhow!(
+                    "Format '{}' contains {:?}, which JSON strings escape",
+                    format,
+                    c
+                ))
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn full_match(format: &str) -> Regex {
+        Regex::new(&format!("^{}$", strftime_regex(format).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn strftime() {
+        let european = full_match("%d/%m/%Y");
+        assert!(european.is_match("31/12/1999"));
+        assert!(!european.is_match("12/31/1999"));
+        assert!(!european.is_match("1/1/1999"));
+
+        let full = full_match("%A, %B %e %Y at %I:%M %p (%%)");
+        assert!(full.is_match("Friday, March  7 2025 at 09:05 PM (%)"));
+        assert!(!full.is_match("Fri, March  7 2025 at 09:05 PM (%)"));
+
+        let timestamp = full_match("%FT%T.%f%z");
+        assert!(timestamp.is_match("2024-02-29T23:59:60.123456+0530"));
+        assert!(!timestamp.is_match("2024-02-29T24:00:00.123456+0530"));
+
+        assert!(strftime_regex("%Q").is_err());
+        assert!(strftime_regex("%Y%").is_err());
+        assert!(strftime_regex("%Y\"").is_err());
+    }
+}
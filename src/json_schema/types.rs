@@ -1,14 +1,58 @@
 // allow `\"`, `\\`, or any character which isn't a control sequence
 pub static STRING_INNER: &str = r#"([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])"#;
+// allow any code point which isn't a control character, and every JSON escape sequence
+pub static STRING_INNER_UNICODE: &str =
+    r#"([^"\\\x00-\x1F\x7F-\x9F]|\\["\\/bfnrt]|\\u[0-9a-fA-F]{4})"#;
+// allow any code point which isn't a control character, and only legal JSON escape sequences:
+// `\u` escapes of UTF-16 surrogates must come in high/low pairs
+pub static STRING_INNER_STRICT: &str = r#"([^"\\\x00-\x1F\x7F-\x9F]|\\["\\/bfnrt]|\\u([0-9a-cA-CeEfF][0-9a-fA-F]{3}|[dD][0-7][0-9a-fA-F]{2}|[dD][89abAB][0-9a-fA-F]{2}\\u[dD][c-fC-F][0-9a-fA-F]{2}))"#;
+// allow printable ASCII characters, and every JSON escape sequence
+pub static STRING_INNER_ASCII: &str =
+    r#"([\x20\x21\x23-\x5B\x5D-\x7E]|\\["\\/bfnrt]|\\u[0-9a-fA-F]{4})"#;
+// allow printable ASCII characters, and only legal JSON escape sequences
+pub static STRING_INNER_ASCII_STRICT: &str = r#"([\x20\x21\x23-\x5B\x5D-\x7E]|\\["\\/bfnrt]|\\u([0-9a-cA-CeEfF][0-9a-fA-F]{3}|[dD][0-7][0-9a-fA-F]{2}|[dD][89abAB][0-9a-fA-F]{2}\\u[dD][c-fC-F][0-9a-fA-F]{2}))"#;
 pub static STRING: &str = r#""([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])*""#;
 
 pub static INTEGER: &str = r#"(-)?(0|[1-9][0-9]*)"#;
 pub static NUMBER: &str = r#"((-)?(0|[1-9][0-9]*))(\.[0-9]+)?([eE][+-][0-9]+)?"#;
+/// [`NUMBER`] without the exponent.
+pub static NUMBER_WITHOUT_EXPONENT: &str = r#"((-)?(0|[1-9][0-9]*))(\.[0-9]+)?"#;
+/// Hexadecimal integers of JSON5.
+pub static HEX_INTEGER: &str = r#"[+-]?0[xX][0-9a-fA-F]+"#;
+/// Infinite and not-a-number values of JSON5.
+pub static NON_FINITE_NUMBER: &str = r#"[+-]?(Infinity|NaN)"#;
 pub static BOOLEAN: &str = r#"(true|false)"#;
 pub static NULL: &str = r#"null"#;
 
 pub static WHITESPACE: &str = r#"[ ]?"#;
 
+/// Pattern matching a single character of a string's content.
+pub fn string_inner(unicode_strings: bool, strict_escapes: bool, ascii_only: bool) -> &'static str {
+    match (ascii_only, strict_escapes, unicode_strings) {
+        (true, true, _) => STRING_INNER_ASCII_STRICT,
+        (true, false, _) => STRING_INNER_ASCII,
+        (false, true, _) => STRING_INNER_STRICT,
+        (false, false, true) => STRING_INNER_UNICODE,
+        (false, false, false) => STRING_INNER,
+    }
+}
+
+/// Escapes the non-ASCII characters of a JSON document as `\uXXXX` sequences.
+pub fn ascii_escape(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let mut units = [0u16; 2];
+    for c in json.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut units) {
+                escaped += &format!("\\u{:04x}", unit);
+            }
+        }
+    }
+    escaped
+}
+
 #[derive(Debug, PartialEq)]
 pub enum JsonType {
     String,
@@ -34,6 +78,31 @@ pub static DATE_TIME: &str = r#""(-?(?:[1-9][0-9]*)?[0-9]{4})-(1[0-2]|0[1-9])-(3
 pub static DATE: &str = r#""(?:\d{4})-(?:0[1-9]|1[0-2])-(?:0[1-9]|[1-2][0-9]|3[0-1])""#;
 pub static TIME: &str = r#""(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9])(\\.[0-9]+)?(Z)?""#;
 pub static UUID: &str = r#""[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}""#;
+/// Host names of ASCII letters, digits and hyphens, internationalized labels being written in
+/// punycode (`xn--…`).
+pub static HOSTNAME: &str = r#""[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*""#;
+/// Host names whose labels may also be written with non-ASCII characters, as U-labels.
+pub static IDN_HOSTNAME: &str = r#""([a-zA-Z0-9]|[^\x00-\x9F])((([a-zA-Z0-9]|[^\x00-\x9F])|-)*([a-zA-Z0-9]|[^\x00-\x9F]))?(\.([a-zA-Z0-9]|[^\x00-\x9F])((([a-zA-Z0-9]|[^\x00-\x9F])|-)*([a-zA-Z0-9]|[^\x00-\x9F]))?)*""#;
+/// Email addresses with a dot-atom local part and a [`HOSTNAME`] domain.
+pub static EMAIL: &str = r#""[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+(\.[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+)*@[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*""#;
+/// Email addresses whose local part and domain may also have non-ASCII characters.
+pub static IDN_EMAIL: &str = r#""([a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]|[^\x00-\x9F])+(\.([a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]|[^\x00-\x9F])+)*@([a-zA-Z0-9]|[^\x00-\x9F])((([a-zA-Z0-9]|[^\x00-\x9F])|-)*([a-zA-Z0-9]|[^\x00-\x9F]))?(\.([a-zA-Z0-9]|[^\x00-\x9F])((([a-zA-Z0-9]|[^\x00-\x9F])|-)*([a-zA-Z0-9]|[^\x00-\x9F]))?)*""#;
+/// URIs: a scheme followed by ASCII characters, the others being percent-encoded.
+pub static URI: &str =
+    r#""[a-zA-Z][a-zA-Z0-9+.-]*:([a-zA-Z0-9._~:/?#\[\]@!$&'()*+,;=-]|%[0-9a-fA-F]{2})*""#;
+/// IRIs: URIs which may also have non-ASCII characters.
+pub static IRI: &str = r#""[a-zA-Z][a-zA-Z0-9+.-]*:([a-zA-Z0-9._~:/?#\[\]@!$&'()*+,;=-]|%[0-9a-fA-F]{2}|[^\x00-\x9F])*""#;
+/// JSON Pointers (RFC 6901): reference tokens each following a `/`, with `~` and `/` escaped as
+/// `~0` and `~1`.
+pub static JSON_POINTER: &str = r#""(/([^"\\\x00-\x1F\x7F-\x9F~/]|\\["\\]|~[01])*)*""#;
+/// Relative JSON Pointers: a number of levels up, followed by `#` or a [`JSON_POINTER`].
+pub static RELATIVE_JSON_POINTER: &str =
+    r#""(0|[1-9][0-9]*)(#|(/([^"\\\x00-\x1F\x7F-\x9F~/]|\\["\\]|~[01])*)*)""#;
+/// Semantic versions (SemVer 2.0), with their optional pre-release and build metadata.
+pub static SEMVER: &str = r#""(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)(-(0|[1-9][0-9]*|[0-9]*[a-zA-Z-][0-9a-zA-Z-]*)(\.(0|[1-9][0-9]*|[0-9]*[a-zA-Z-][0-9a-zA-Z-]*))*)?(\+[0-9a-zA-Z-]+(\.[0-9a-zA-Z-]+)*)?""#;
+/// Phone numbers in E.164 form: `+`, a country code not starting with 0, then the national
+/// number, for 8 to 15 digits in all.
+pub static PHONE: &str = r#""\+[1-9][0-9]{7,14}""#;
 
 #[derive(Debug, PartialEq)]
 pub enum FormatType {
@@ -41,6 +110,19 @@ pub enum FormatType {
     Date,
     Time,
     Uuid,
+    Hostname,
+    IdnHostname,
+    Email,
+    IdnEmail,
+    Uri,
+    Iri,
+    JsonPointer,
+    RelativeJsonPointer,
+    Semver,
+    Phone,
+    CountryCode,
+    CurrencyCode,
+    LanguageTag,
 }
 
 impl FormatType {
@@ -50,6 +132,19 @@ impl FormatType {
             FormatType::Date => DATE,
             FormatType::Time => TIME,
             FormatType::Uuid => UUID,
+            FormatType::Hostname => HOSTNAME,
+            FormatType::IdnHostname => IDN_HOSTNAME,
+            FormatType::Email => EMAIL,
+            FormatType::IdnEmail => IDN_EMAIL,
+            FormatType::Uri => URI,
+            FormatType::Iri => IRI,
+            FormatType::JsonPointer => JSON_POINTER,
+            FormatType::RelativeJsonPointer => RELATIVE_JSON_POINTER,
+            FormatType::Semver => SEMVER,
+            FormatType::Phone => PHONE,
+            FormatType::CountryCode => super::codes::country_code_regex(),
+            FormatType::CurrencyCode => super::codes::currency_code_regex(),
+            FormatType::LanguageTag => super::codes::language_tag_regex(),
         }
     }
 
@@ -60,7 +155,216 @@ impl FormatType {
             "date" => Some(FormatType::Date),
             "time" => Some(FormatType::Time),
             "uuid" => Some(FormatType::Uuid),
+            "hostname" => Some(FormatType::Hostname),
+            "idn-hostname" => Some(FormatType::IdnHostname),
+            "email" => Some(FormatType::Email),
+            "idn-email" => Some(FormatType::IdnEmail),
+            "uri" => Some(FormatType::Uri),
+            "iri" => Some(FormatType::Iri),
+            "json-pointer" => Some(FormatType::JsonPointer),
+            "relative-json-pointer" => Some(FormatType::RelativeJsonPointer),
+            "semver" => Some(FormatType::Semver),
+            "phone" | "e164" => Some(FormatType::Phone),
+            "iso-3166-alpha2" => Some(FormatType::CountryCode),
+            "iso-4217" => Some(FormatType::CurrencyCode),
+            "bcp47" => Some(FormatType::LanguageTag),
             _ => None,
         }
     }
 }
+
+/// Whether the times of the `time` format are written with fractional seconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FractionalSeconds {
+    #[default]
+    Optional,
+    Required,
+    Forbidden,
+}
+
+impl FractionalSeconds {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<FractionalSeconds> {
+        match s {
+            "optional" => Some(FractionalSeconds::Optional),
+            "required" => Some(FractionalSeconds::Required),
+            "forbidden" => Some(FractionalSeconds::Forbidden),
+            _ => None,
+        }
+    }
+}
+
+/// How the times of the `time` format are related to UTC.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimeOffset {
+    /// An optional `Z`.
+    #[default]
+    OptionalUtc,
+    /// A required `Z`.
+    Utc,
+    /// A required numeric offset, such as `+05:30`.
+    Numeric,
+    /// A required `Z` or numeric offset, as RFC 3339 requires.
+    UtcOrNumeric,
+    /// No offset, for local times.
+    Forbidden,
+}
+
+impl TimeOffset {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<TimeOffset> {
+        match s {
+            "optional_utc" => Some(TimeOffset::OptionalUtc),
+            "utc" => Some(TimeOffset::Utc),
+            "numeric" => Some(TimeOffset::Numeric),
+            "utc_or_numeric" => Some(TimeOffset::UtcOrNumeric),
+            "forbidden" => Some(TimeOffset::Forbidden),
+            _ => None,
+        }
+    }
+}
+
+/// Unit in which `minLength` and `maxLength` count the length of strings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LengthSemantics {
+    /// Unicode code points, as the JSON Schema specification counts.
+    #[default]
+    Codepoints,
+    /// UTF-16 code units, as JavaScript validators count: code points beyond U+FFFF count twice.
+    Utf16,
+    /// Bytes of the UTF-8 encoding.
+    Bytes,
+}
+
+impl LengthSemantics {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<LengthSemantics> {
+        match s {
+            "codepoints" => Some(LengthSemantics::Codepoints),
+            "utf16" => Some(LengthSemantics::Utf16),
+            "bytes" => Some(LengthSemantics::Bytes),
+            _ => None,
+        }
+    }
+
+    /// Length of the code point `c`.
+    pub fn char_length(self, c: u32) -> u64 {
+        self.boundaries().iter().filter(|&&b| b <= c).count() as u64 + 1
+    }
+
+    /// Code points from which the length of a code point grows by one.
+    pub(crate) fn boundaries(self) -> &'static [u32] {
+        match self {
+            LengthSemantics::Codepoints => &[],
+            LengthSemantics::Utf16 => &[0x1_0000],
+            LengthSemantics::Bytes => &[0x80, 0x800, 0x1_0000],
+        }
+    }
+
+    /// Name of the unit, for error messages.
+    pub(crate) fn unit(self) -> &'static str {
+        match self {
+            LengthSemantics::Codepoints => "characters",
+            LengthSemantics::Utf16 => "UTF-16 code units",
+            LengthSemantics::Bytes => "bytes",
+        }
+    }
+}
+
+/// Pattern of the strings of the `time` format, with their fractional seconds and their offset
+/// written as told. The default ones give [`TIME`], but for its fraction starting with `.`.
+pub fn time_regex(fractional_seconds: FractionalSeconds, offset: TimeOffset) -> String {
+    let fraction = match fractional_seconds {
+        FractionalSeconds::Optional => r"(\.[0-9]+)?",
+        FractionalSeconds::Required => r"\.[0-9]+",
+        FractionalSeconds::Forbidden => "",
+    };
+    let numeric = "[+-](2[0-3]|[01][0-9]):[0-5][0-9]";
+    let offset = match offset {
+        TimeOffset::OptionalUtc => "(Z)?".to_string(),
+        TimeOffset::Utc => "Z".to_string(),
+        TimeOffset::Numeric => numeric.to_string(),
+        TimeOffset::UtcOrNumeric => format!("(Z|{})", numeric),
+        TimeOffset::Forbidden => String::new(),
+    };
+    format!(
+        r#""(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9]){}{}""#,
+        fraction, offset
+    )
+}
+
+/// Lexical forms in which the integers of the generated JSON are written, for parsers which
+/// differ on the forms they accept. The default allows only the canonical JSON form: no leading
+/// `+`, no `-0` and no leading zeros.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IntegerForms {
+    /// Whether the non-negative integers may be written with a leading `+`.
+    pub plus_sign: bool,
+    /// Whether zero may be written `-0`.
+    pub negative_zero: bool,
+    /// Whether integers may be written with leading zeros, such as `007`.
+    pub leading_zeros: bool,
+}
+
+/// Whitespace allowed between the tokens of the generated JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Whitespace {
+    /// The same pattern is matched between any two tokens.
+    Pattern(String),
+    /// Pretty-printed JSON, as produced by `json.dumps(value, indent=n)`: every member of a
+    /// non-empty object or array on its own line, indented by `n` spaces per nesting level,
+    /// and a single space after colons.
+    Pretty(usize),
+    /// No whitespace at all, as produced by `json.dumps(value, separators=(",", ":"))`.
+    None,
+}
+
+impl Default for Whitespace {
+    fn default() -> Self {
+        Whitespace::Pattern(WHITESPACE.to_string())
+    }
+}
+
+/// JSON Schema dialect deciding the semantics of keywords that changed between drafts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Draft {
+    /// Draft-07 (also used for older drafts): array-form `items` with `additionalItems`,
+    /// `dependencies`, and `$ref` overriding its sibling keywords.
+    Draft7,
+    /// Draft 2019-09: as draft-07 for arrays, but `dependencies` is split into
+    /// `dependentRequired` and `dependentSchemas`.
+    Draft201909,
+    /// Draft 2020-12: `prefixItems` for tuples.
+    #[default]
+    Draft202012,
+}
+
+impl Draft {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Draft> {
+        match s {
+            "draft-07" | "draft7" | "draft-06" | "draft-04" => Some(Draft::Draft7),
+            "2019-09" | "draft-2019-09" | "draft2019-09" => Some(Draft::Draft201909),
+            "2020-12" | "draft-2020-12" | "draft2020-12" => Some(Draft::Draft202012),
+            _ => None,
+        }
+    }
+
+    /// Identifies the draft from a `$schema` meta-schema URI.
+    pub fn from_schema_uri(uri: &str) -> Option<Draft> {
+        let uri = uri.trim_end_matches('#');
+        if let Some(version) = uri
+            .strip_prefix("http://json-schema.org/")
+            .or_else(|| uri.strip_prefix("https://json-schema.org/"))
+        {
+            match version.strip_suffix("/schema")? {
+                "draft-04" | "draft-06" | "draft-07" => Some(Draft::Draft7),
+                "draft/2019-09" => Some(Draft::Draft201909),
+                "draft/2020-12" => Some(Draft::Draft202012),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,136 @@
+/// Constraints to the words of a list, such as known drug names or cities.
+use super::parsing::NOTHING;
+use crate::fsm::dawg::Dawg;
+use crate::index::FSMInfo;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// List of the words a string may be, compiled to their minimal automaton so that lists of
+/// hundreds of thousands of words stay small.
+///
+/// It constrains text on its own, through [`Wordlist::to_fsm_info`] or [`Wordlist::regex`],
+/// and the strings of a schema once registered under a name with [`super::Options::wordlist`]:
+/// `{"type": "string", "x-wordlist": "cities"}` matches the JSON strings of the words of the
+/// list named `cities`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    pub fn new<W: Into<String>>(words: impl IntoIterator<Item = W>) -> Self {
+        Self {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Reads a list of one word per line, the empty lines being skipped. Lines may end with
+    /// `\r\n`.
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut words = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let word = line.strip_suffix('\r').unwrap_or(&line);
+            if !word.is_empty() {
+                words.push(word.to_string());
+            }
+        }
+        Ok(Self { words })
+    }
+
+    /// Reads a list of one word per line from a file, see [`Wordlist::from_reader`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        let path = path.as_ref();
+        let file_error = |source| crate::Error::WordlistFile {
+            path: path.to_path_buf(),
+            source,
+        };
+        let file = File::open(path).map_err(file_error)?;
+        Self::from_reader(BufReader::new(file)).map_err(file_error)
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Byte-level automaton accepting exactly the words, built without a regular expression.
+    pub fn to_fsm_info(&self) -> FSMInfo {
+        FSMInfo::from_words(&self.words)
+    }
+
+    /// Regular expression matching exactly the words.
+    pub fn regex(&self) -> String {
+        Self::words_regex(self.words.iter().map(|word| word.chars().collect()))
+    }
+
+    /// Regular expression matching the JSON strings of the words, written by `literal` from
+    /// their JSON text.
+    pub(crate) fn json_regex(
+        &self,
+        literal: impl Fn(&str) -> String,
+    ) -> serde_json::Result<String> {
+        let words = self
+            .words
+            .iter()
+            .map(|word| Ok(literal(&serde_json::to_string(word)?).chars().collect()))
+            .collect::<serde_json::Result<Vec<Vec<char>>>>()?;
+        Ok(Self::words_regex(words.into_iter()))
+    }
+
+    fn words_regex(words: impl Iterator<Item = Vec<char>>) -> String {
+        let words: Vec<Vec<char>> = words.collect();
+        match words.is_empty() {
+            true => NOTHING.to_string(),
+            false => Dawg::new(&words).to_regex(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_schema::{build_regex_from_schema_with_options, Options};
+    use regex::Regex;
+
+    #[test]
+    fn wordlist() {
+        let wordlist =
+            Wordlist::from_reader(&b"Paris\r\nParma\n\nPorto\nSan Jos\xc3\xa9\n"[..]).unwrap();
+        assert_eq!(wordlist.words(), ["Paris", "Parma", "Porto", "San José"]);
+        assert_eq!(wordlist.regex(), "(P(ar(is|ma)|orto)|San José)");
+        assert_eq!(
+            wordlist.to_fsm_info().enumerate_strings(5, 10),
+            ["Paris", "Parma", "Porto"]
+        );
+
+        let options = Options::new().ascii_only(true).wordlist("cities", wordlist);
+        let schema = r#"{
+            "type": "object",
+            "properties": {"city": {"type": "string", "x-wordlist": "cities"}},
+            "required": ["city"]
+        }"#;
+        let regex = build_regex_from_schema_with_options(schema, &options).unwrap();
+        let regex = Regex::new(&format!("^{}$", regex)).unwrap();
+        assert!(regex.is_match(r#"{"city":"Porto"}"#));
+        assert!(regex.is_match(r#"{"city":"San Jos\u00e9"}"#));
+        assert!(!regex.is_match(r#"{"city":"San José"}"#));
+        assert!(!regex.is_match(r#"{"city":"Pari"}"#));
+
+        let schema = r#"{"type": "string", "x-wordlist": "towns"}"#;
+        let error = build_regex_from_schema_with_options(schema, &options).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown wordlist 'towns'");
+        assert!(matches!(
+            Wordlist::from_file("missing-wordlist.txt"),
+            Err(crate::Error::WordlistFile { .. })
+        ));
+    }
+}
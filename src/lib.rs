@@ -1,25 +1,173 @@
+//! Structured generation: the tokens a language model may sample at each step, so that its
+//! output matches a regular expression or a JSON Schema.
+//!
+//! The crate is a Rust library first. Engines written in Rust use it directly, while the
+//! bindings to other languages are each behind their own feature, none of them enabled by
+//! default: `python-bindings`, `node-bindings`, `jni` and `ruby-bindings`. Without them, and
+//! without the `flatbuffers` and `shared-memory` features reading indexes in place, the crate
+//! has no unsafe code, which it forbids.
+//!
+//! A constraint goes through:
+//! - [`json_schema`], compiling a JSON Schema to a regular expression or an automaton,
+//! - [`index::FSMInfo`], the automaton of a regular expression,
+//! - [`index::Index`], the tokens of a [`vocabulary::Vocabulary`] allowed from each state of
+//!   the automaton, and the states they lead to,
+//! - [`guide::Guide`], following the index token by token during generation.
+//!
+//! ## Examples
+//!
+//! ```rust
+//! use outlines_core::guide::Guide;
+//! use outlines_core::index::Index;
+//! use outlines_core::json_schema::{self, Options, Whitespace};
+//! use outlines_core::prelude::*;
+//! use std::collections::HashSet;
+//!
+//! let schema = r#"{"type": "object", "properties": {"ok": {"type": "boolean"}}, "required": ["ok"]}"#;
+//! let options = Options::new().whitespace(Whitespace::None);
+//! let fsm_info = json_schema::build_fsm_from_schema(schema, &options)?;
+//!
+//! let vocabulary = Vocabulary::new()
+//!     .insert(r#"{"ok":"#, 1)
+//!     .insert("true", 2)
+//!     .insert("false", 3)
+//!     .insert("}", 4);
+//! let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new())?;
+//!
+//! let mut guide = Guide::new(&index);
+//! assert_eq!(guide.allowed_tokens(), &[1]);
+//! for token_id in [1, 3, 4] {
+//!     guide.advance(token_id)?;
+//! }
+//! assert!(guide.is_final());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+#![cfg_attr(
+    not(any(
+        feature = "python-bindings",
+        feature = "node-bindings",
+        feature = "jni",
+        feature = "ruby-bindings",
+        feature = "flatbuffers",
+        feature = "shared-memory",
+    )),
+    forbid(unsafe_code)
+)]
+
+pub mod cancellation;
+pub mod fsm;
+pub mod guide;
 pub mod index;
 pub mod json_schema;
 pub mod prelude;
 pub mod primitives;
 pub mod regex;
+#[cfg(all(unix, feature = "shared-memory"))]
+pub mod shared_memory;
+pub mod stats;
+pub mod table;
+pub mod template;
+pub mod validator;
 pub mod vocabulary;
 
 #[cfg(feature = "python-bindings")]
 mod python_bindings;
 
+#[cfg(feature = "node-bindings")]
+mod node_bindings;
+
+#[cfg(feature = "jni")]
+mod jni_bindings;
+
+#[cfg(feature = "ruby-bindings")]
+mod ruby_bindings;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("The vocabulary does not allow us to build a sequence that matches the input")]
     IndexError,
-}
-
-#[cfg(feature = "python-bindings")]
-impl From<Error> for pyo3::PyErr {
-    fn from(e: Error) -> Self {
-        use pyo3::{exceptions::PyValueError, PyErr};
-        PyErr::new::<PyValueError, _>(e.to_string())
-    }
+    #[error(
+        "Conflicting '{keyword}' constraints can not be satisfied together: {left} and {right}"
+    )]
+    SchemaConflict {
+        keyword: String,
+        left: String,
+        right: String,
+    },
+    #[error("Distinct '{keyword}' constraints can not be merged into one: {left} and {right}")]
+    UnmergeableKeyword {
+        keyword: String,
+        left: String,
+        right: String,
+    },
+    #[error("This schema admits no outputs: {}", conflicts.join("; "))]
+    UnsatisfiableSchema {
+        /// JSON Pointers to the subschemas admitting no value, each followed by its constraints.
+        conflicts: Vec<String>,
+    },
+    #[error("Reference '{reference}' recurses beyond the maximum unrolling depth of {depth}")]
+    RefRecursionLimitReached { reference: String, depth: usize },
+    #[error("Objects and arrays are nested beyond the maximum depth of {depth}")]
+    NestingLimitReached { depth: usize },
+    #[error("Invalid regular expression '{pattern}': {reason}")]
+    InvalidRegex { pattern: String, reason: String },
+    #[error("Invalid DFA: {0}")]
+    InvalidDfa(String),
+    #[error("Pattern '{pattern}' at '{path}' uses {feature}, which is not supported")]
+    UnsupportedPatternFeature {
+        feature: String,
+        pattern: String,
+        /// JSON Pointer to the value constrained by the pattern.
+        path: String,
+    },
+    #[error("Invalid pattern '{pattern}' at '{path}': {reason}")]
+    InvalidPattern {
+        pattern: String,
+        reason: String,
+        /// JSON Pointer to the value constrained by the pattern.
+        path: String,
+    },
+    #[error("Invalid whitespace pattern '{pattern}': {reason}")]
+    InvalidWhitespacePattern { pattern: String, reason: String },
+    #[error("Invalid serialized index: {0}")]
+    InvalidIndexData(String),
+    #[error("Invalid interegular automaton: {0}")]
+    InvalidInteregularFsm(String),
+    #[error("The compilation was cancelled")]
+    Cancelled,
+    #[error("The compilation did not finish within {timeout:?}")]
+    Timeout { timeout: std::time::Duration },
+    #[error("The compilation reached {size} bytes, exceeding its budget of {max_bytes} bytes")]
+    MemoryBudgetExceeded { max_bytes: usize, size: usize },
+    #[error("Special token {token_id} is neither EOS nor in the vocabulary")]
+    UnknownSpecialToken { token_id: u32 },
+    #[error("Token {token_id} is not allowed in state {state}")]
+    TokenNotAllowed { token_id: u32, state: u32 },
+    #[error("State {state} is not one of the {num_states} states of the automaton")]
+    UnknownState { state: u32, num_states: usize },
+    #[error("Token {token_id} does not fit in masks of {vocab_size} tokens")]
+    TokenOutsideMask { token_id: u32, vocab_size: usize },
+    #[error("The text stops matching the automaton after {valid_up_to} characters")]
+    TextMismatch { valid_up_to: usize },
+    #[error("The index does not keep the automaton it was built from, which reads text")]
+    MissingTextAutomaton,
+    #[error("The indexes can not be merged: {0}")]
+    IndexMergeConflict(String),
+    #[error("Failed to read schema file '{}': {source}", path.display())]
+    SchemaFile {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to read wordlist file '{}': {source}", path.display())]
+    WordlistFile {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Shared memory segment '{name}' is not available: {source}")]
+    SharedMemory {
+        name: String,
+        source: std::io::Error,
+    },
 }
@@ -0,0 +1,227 @@
+// napi only registers the exported items outside of test builds.
+#![cfg_attr(test, allow(dead_code))]
+
+use crate::index::{FSMInfo, Index};
+use crate::json_schema;
+use crate::prelude::*;
+use napi::{Error, Result};
+use napi_derive::napi;
+use std::collections::{HashMap, HashSet};
+
+fn to_napi_error(e: impl Display) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+/// Options of the schema compilation, mirroring the keyword arguments of the Python bindings.
+#[napi(object)]
+#[derive(Default)]
+pub struct SchemaOptions {
+    pub whitespace_pattern: Option<String>,
+    pub draft: Option<String>,
+    pub unicode_strings: Option<bool>,
+    pub strict_escapes: Option<bool>,
+    pub ascii_only: Option<bool>,
+    pub unordered_required: Option<bool>,
+    pub case_insensitive: Option<bool>,
+    pub integer_forms: Option<IntegerForms>,
+    pub exponents: Option<bool>,
+    pub json5_numbers: Option<bool>,
+    pub time_fraction: Option<String>,
+    pub time_offset: Option<String>,
+    pub length_semantics: Option<String>,
+    pub indent: Option<u32>,
+    pub compact: Option<bool>,
+    pub max_array_items: Option<u32>,
+    pub max_nesting_depth: Option<u32>,
+    pub timeout_ms: Option<u32>,
+    pub markdown_fence: Option<bool>,
+    pub fence_preamble: Option<u32>,
+}
+
+/// Lexical forms of the integers, each one forbidden unless set.
+#[napi(object)]
+#[derive(Default)]
+pub struct IntegerForms {
+    pub plus_sign: Option<bool>,
+    pub negative_zero: Option<bool>,
+    pub leading_zeros: Option<bool>,
+}
+
+impl TryFrom<SchemaOptions> for json_schema::Options {
+    type Error = Error;
+
+    fn try_from(js_options: SchemaOptions) -> Result<Self> {
+        let mut options = json_schema::Options::new()
+            .unicode_strings(js_options.unicode_strings.unwrap_or_default())
+            .strict_escapes(js_options.strict_escapes.unwrap_or_default())
+            .ascii_only(js_options.ascii_only.unwrap_or_default())
+            .unordered_required(js_options.unordered_required.unwrap_or_default())
+            .case_insensitive(js_options.case_insensitive.unwrap_or_default())
+            .json5_numbers(js_options.json5_numbers.unwrap_or_default())
+            .markdown_fence(js_options.markdown_fence.unwrap_or_default());
+
+        if let Some(draft) = js_options.draft {
+            options = options.draft(json_schema::Draft::from_str(&draft).ok_or_else(|| {
+                to_napi_error(format!("Unsupported JSON Schema draft: {}", draft))
+            })?);
+        }
+        if let Some(forms) = js_options.integer_forms {
+            options = options.integer_forms(json_schema::IntegerForms {
+                plus_sign: forms.plus_sign.unwrap_or_default(),
+                negative_zero: forms.negative_zero.unwrap_or_default(),
+                leading_zeros: forms.leading_zeros.unwrap_or_default(),
+            });
+        }
+        if let Some(exponents) = js_options.exponents {
+            options = options.exponents(exponents);
+        }
+        if js_options.time_fraction.is_some() || js_options.time_offset.is_some() {
+            let fractional_seconds = match js_options.time_fraction {
+                Some(fraction) => json_schema::FractionalSeconds::from_str(&fraction)
+                    .ok_or_else(|| to_napi_error(format!("Unknown time fraction: {}", fraction)))?,
+                None => Default::default(),
+            };
+            let offset = match js_options.time_offset {
+                Some(offset) => json_schema::TimeOffset::from_str(&offset)
+                    .ok_or_else(|| to_napi_error(format!("Unknown time offset: {}", offset)))?,
+                None => Default::default(),
+            };
+            options = options.time_format(fractional_seconds, offset);
+        }
+        if let Some(semantics) = js_options.length_semantics {
+            options = options.length_semantics(
+                json_schema::LengthSemantics::from_str(&semantics).ok_or_else(|| {
+                    to_napi_error(format!("Unknown length semantics: {}", semantics))
+                })?,
+            );
+        }
+        if let Some(max_array_items) = js_options.max_array_items {
+            options = options.max_array_items(max_array_items.into());
+        }
+        if let Some(max_nesting_depth) = js_options.max_nesting_depth {
+            options = options.max_nesting_depth(max_nesting_depth as usize);
+        }
+        if let Some(fence_preamble) = js_options.fence_preamble {
+            options = options.fence_preamble(fence_preamble as usize);
+        }
+        if let Some(timeout_ms) = js_options.timeout_ms {
+            options = options.timeout(std::time::Duration::from_millis(timeout_ms.into()));
+        }
+
+        let mut whitespace_profiles = Vec::new();
+        if let Some(pattern) = js_options.whitespace_pattern {
+            whitespace_profiles.push("whitespacePattern");
+            options = options.whitespace_pattern(pattern);
+        }
+        if let Some(indent) = js_options.indent {
+            whitespace_profiles.push("indent");
+            options = options.whitespace(json_schema::Whitespace::Pretty(indent as usize));
+        }
+        if js_options.compact.unwrap_or_default() {
+            whitespace_profiles.push("compact");
+            options = options.whitespace(json_schema::Whitespace::None);
+        }
+        if let [first, second, ..] = whitespace_profiles.as_slice() {
+            return Err(to_napi_error(format!(
+                "'{}' and '{}' can not be used together",
+                first, second
+            )));
+        }
+
+        Ok(options)
+    }
+}
+
+#[napi]
+pub fn build_regex_from_schema(json: String, options: Option<SchemaOptions>) -> Result<String> {
+    let options = options.unwrap_or_default().try_into()?;
+    json_schema::build_regex_from_schema_with_options(&json, &options).map_err(to_napi_error)
+}
+
+#[napi(js_name = "FSMInfo")]
+pub struct JsFSMInfo(FSMInfo);
+
+#[napi]
+impl JsFSMInfo {
+    /// `transitions` lists the `[fromState, transitionKey, toState]` triples of the automaton.
+    #[napi(constructor)]
+    pub fn new(
+        initial: State,
+        finals: Vec<State>,
+        transitions: Vec<Vec<u32>>,
+        alphabet_anything_value: TransitionKey,
+        alphabet_symbol_mapping: HashMap<String, TransitionKey>,
+    ) -> Result<Self> {
+        let transitions = transitions
+            .into_iter()
+            .map(|transition| match transition.as_slice() {
+                &[from_state, key, to_state] => Ok(((from_state, key), to_state)),
+                _ => Err(to_napi_error(
+                    "Transitions must be [fromState, transitionKey, toState] triples",
+                )),
+            })
+            .collect::<Result<_>>()?;
+        Ok(JsFSMInfo(FSMInfo::new(
+            initial,
+            finals.into_iter().collect(),
+            transitions,
+            alphabet_anything_value,
+            alphabet_symbol_mapping,
+        )))
+    }
+
+    #[napi]
+    pub fn with_max_length(&self, max_length: u32) -> JsFSMInfo {
+        JsFSMInfo(self.0.with_max_length(max_length as usize))
+    }
+}
+
+#[napi(js_name = "Vocabulary")]
+pub struct JsVocabulary(Vocabulary);
+
+#[napi]
+impl JsVocabulary {
+    #[napi(factory)]
+    pub fn from_dict(map: HashMap<Token, Vec<TokenId>>) -> JsVocabulary {
+        JsVocabulary(Vocabulary::from(map))
+    }
+}
+
+#[napi(js_name = "Index")]
+pub struct JsIndex(Index);
+
+#[napi]
+impl JsIndex {
+    #[napi(constructor)]
+    pub fn new(
+        fsm_info: &JsFSMInfo,
+        vocabulary: &JsVocabulary,
+        eos_token_id: u32,
+        frozen_tokens: Vec<String>,
+    ) -> Result<Self> {
+        let frozen_tokens: HashSet<String> = frozen_tokens.into_iter().collect();
+        Index::new(&fsm_info.0, &vocabulary.0, eos_token_id, frozen_tokens)
+            .map(JsIndex)
+            .map_err(to_napi_error)
+    }
+
+    #[napi]
+    pub fn get_allowed_tokens(&self, state: u32) -> Option<Vec<u32>> {
+        self.0.allowed_tokens(state)
+    }
+
+    #[napi]
+    pub fn get_next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+        self.0.next_state(state, token_id)
+    }
+
+    #[napi]
+    pub fn is_final_state(&self, state: u32) -> bool {
+        self.0.is_final(state)
+    }
+
+    #[napi]
+    pub fn get_initial_state(&self) -> u32 {
+        self.0.initial()
+    }
+}
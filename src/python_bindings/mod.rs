@@ -1,18 +1,79 @@
-use crate::index::{FSMInfo, Index};
+// pyo3's macros expand `PyResult` returns into conversions clippy considers redundant.
+#![allow(clippy::useless_conversion)]
+
+use crate::cancellation::CancellationToken;
+use crate::fsm::{byte_symbols, InteregularFsm, ANYTHING_ELSE};
+use crate::guide::{compile, spawn_compile, BatchGuide, Guide};
+use crate::index::{
+    build_choice_index, FSMInfo, Index, IndexOptions, MaskTable, SpecialTokensPolicy, TokenDfa,
+};
 use crate::json_schema;
 use crate::prelude::*;
 use crate::regex::get_token_transition_keys;
 use crate::regex::get_vocabulary_transition_keys;
 use crate::regex::state_scan_tokens;
 use crate::regex::walk_fsm;
-use pyo3::exceptions::PyValueError;
+use crate::regex::TokenTrie;
+use crate::stats;
+use crate::table::{Cell, Table};
+use crate::template::Template;
+use crate::validator::PartialValidator;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
+use pyo3::types::{
+    PyBool, PyBytes, PyCFunction, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple,
+};
 use pyo3::wrap_pyfunction;
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+// The classes are frozen, and keep any mutable state behind a lock, so that their instances can
+// be shared between threads without relying on the GIL.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FSMInfo>();
+    assert_send_sync::<CancellationToken>();
+    assert_send_sync::<Index>();
+    assert_send_sync::<TokenDfa>();
+    assert_send_sync::<MaskTable>();
+    assert_send_sync::<Guide>();
+    assert_send_sync::<BatchGuide>();
+    assert_send_sync::<Vocabulary>();
+    assert_send_sync::<PartialValidator>();
+    assert_send_sync::<json_schema::Schema>();
+};
+
+impl From<crate::Error> for PyErr {
+    fn from(e: crate::Error) -> Self {
+        use crate::Error;
+        use pyo3::exceptions::{
+            PyFileNotFoundError, PyMemoryError, PyOSError, PyPermissionError, PyTimeoutError,
+        };
+        match e {
+            Error::Timeout { .. } => PyErr::new::<PyTimeoutError, _>(e.to_string()),
+            Error::SchemaFile { ref source, .. } | Error::WordlistFile { ref source, .. } => {
+                match source.kind() {
+                    std::io::ErrorKind::NotFound => {
+                        PyErr::new::<PyFileNotFoundError, _>(e.to_string())
+                    }
+                    std::io::ErrorKind::PermissionDenied => {
+                        PyErr::new::<PyPermissionError, _>(e.to_string())
+                    }
+                    _ => PyErr::new::<PyOSError, _>(e.to_string()),
+                }
+            }
+            Error::MemoryBudgetExceeded { .. } => PyErr::new::<PyMemoryError, _>(e.to_string()),
+            _ => PyErr::new::<PyValueError, _>(e.to_string()),
+        }
+    }
+}
 
-#[pyclass(name = "FSMInfo")]
+#[pyclass(name = "FSMInfo", frozen)]
 pub struct PyFSMInfo {
     #[pyo3(get)]
     initial: State,
@@ -70,23 +131,582 @@ impl PyFSMInfo {
         )
         .into()
     }
+
+    #[staticmethod]
+    fn from_interegular(fsm: &Bound<PyAny>) -> PyResult<PyFSMInfo> {
+        let fsm = match fsm.downcast::<PyBytes>() {
+            Ok(bytes) => fsm.py().import("pickle")?.call_method1("loads", (bytes,))?,
+            Err(_) => fsm.clone(),
+        };
+        let field = |name: &str| match fsm.downcast::<PyDict>() {
+            Ok(dict) => dict.get_item(name)?.ok_or_else(|| {
+                PyValueError::new_err(format!("The automaton has no '{}' field", name))
+            }),
+            Err(_) => fsm.getattr(name),
+        };
+
+        // An interegular `Alphabet` keeps the key of each symbol in `_symbol_mapping`, the
+        // `anything_else` sentinel being its only symbol which is not a string.
+        let alphabet = field("alphabet")?;
+        let symbol_mapping = alphabet.getattr("_symbol_mapping").unwrap_or(alphabet);
+        let mut alphabet = HashMap::new();
+        for item in symbol_mapping.call_method0("items")?.try_iter()? {
+            let (symbol, key): (Bound<PyAny>, TransitionKey) = item?.extract()?;
+            let symbol = match symbol.downcast::<PyString>() {
+                Ok(symbol) => symbol.to_str()?.to_string(),
+                Err(_) => ANYTHING_ELSE.to_string(),
+            };
+            alphabet.insert(symbol, key);
+        }
+
+        let fsm = InteregularFsm {
+            alphabet,
+            states: field("states")?.extract()?,
+            initial: field("initial")?.extract()?,
+            finals: field("finals")?.extract()?,
+            map: field("map")?.extract()?,
+        };
+        Ok(FSMInfo::from_interegular(fsm)?.into())
+    }
+
+    #[staticmethod]
+    fn from_words(words: Vec<Bound<PyAny>>) -> PyResult<PyFSMInfo> {
+        let words = words
+            .iter()
+            .map(|word| match word.downcast::<PyBytes>() {
+                Ok(bytes) => Ok(bytes.as_bytes().to_vec()),
+                Err(_) => Ok(word.extract::<String>()?.into_bytes()),
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(FSMInfo::from_words(words).into())
+    }
+
+    #[staticmethod]
+    fn from_wordlist(path: PathBuf) -> PyResult<PyFSMInfo> {
+        Ok(json_schema::Wordlist::from_file(path)?.to_fsm_info().into())
+    }
+
+    fn with_max_length(&self, max_length: usize) -> PyFSMInfo {
+        FSMInfo::from(self).with_max_length(max_length).into()
+    }
+
+    fn compress_alphabet(&self) -> PyFSMInfo {
+        FSMInfo::from(self).compress_alphabet().into()
+    }
+
+    fn shortest_completion(&self, state: State) -> Option<String> {
+        FSMInfo::from(self).shortest_completion(state)
+    }
+
+    fn shortest_string(&self) -> Option<String> {
+        FSMInfo::from(self).shortest_string()
+    }
+
+    fn enumerate_strings(&self, max_len: usize, limit: usize) -> Vec<String> {
+        FSMInfo::from(self).enumerate_strings(max_len, limit)
+    }
+
+    fn equivalent(&self, other: &PyFSMInfo) -> bool {
+        FSMInfo::from(self).equivalent(&other.into())
+    }
+
+    fn concat(&self, other: &PyFSMInfo) -> PyFSMInfo {
+        FSMInfo::from(self).concat(&other.into()).into()
+    }
+
+    fn union(&self, other: &PyFSMInfo) -> PyFSMInfo {
+        FSMInfo::from(self).union(&other.into()).into()
+    }
+
+    fn optional(&self) -> PyFSMInfo {
+        FSMInfo::from(self).optional().into()
+    }
+
+    #[pyo3(signature = (min, max=None))]
+    fn repeat(&self, min: usize, max: Option<usize>) -> PyFSMInfo {
+        FSMInfo::from(self).repeat(min, max).into()
+    }
+
+    fn forbid_substrings(&self, substrings: Vec<String>) -> PyFSMInfo {
+        let substrings: Vec<&str> = substrings.iter().map(String::as_str).collect();
+        FSMInfo::from(self).forbid_substrings(&substrings).into()
+    }
+}
+
+#[pyclass(name = "PartialValidator", frozen)]
+pub struct PyPartialValidator(Mutex<PartialValidator>);
+
+impl PyPartialValidator {
+    fn validator(&self) -> MutexGuard<'_, PartialValidator> {
+        // A panic while feeding leaves the validator usable, its state is updated per character.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[pymethods]
+impl PyPartialValidator {
+    #[new]
+    #[pyo3(signature = (fsm_info, byte_level=false))]
+    fn new(fsm_info: &PyFSMInfo, byte_level: bool) -> Self {
+        PyPartialValidator(Mutex::new(
+            PartialValidator::new(fsm_info.into()).byte_level(byte_level),
+        ))
+    }
+
+    fn feed(&self, text: &str) -> bool {
+        self.validator().feed(text)
+    }
+
+    fn is_viable(&self) -> bool {
+        self.validator().is_viable()
+    }
+
+    fn is_final(&self) -> bool {
+        self.validator().is_final()
+    }
+
+    #[getter]
+    fn state(&self) -> Option<State> {
+        self.validator().state()
+    }
+
+    #[getter]
+    fn valid_up_to(&self) -> usize {
+        self.validator().valid_up_to()
+    }
+
+    fn allowed_symbols(&self) -> Vec<String> {
+        self.validator()
+            .allowed_symbols()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn allows_anything_else(&self) -> bool {
+        self.validator().allows_anything_else()
+    }
+
+    fn completion(&self) -> Option<String> {
+        self.validator().completion()
+    }
+
+    fn reset(&self) {
+        self.validator().reset()
+    }
+}
+
+#[pyclass(name = "Template", frozen)]
+#[derive(Clone)]
+pub struct PyTemplate(Template);
+
+#[pymethods]
+impl PyTemplate {
+    #[new]
+    #[pyo3(signature = (whitespace_pattern=None, **options))]
+    fn new(whitespace_pattern: Option<&str>, options: Option<&Bound<PyDict>>) -> PyResult<Self> {
+        let options = schema_options(whitespace_pattern, options)?;
+        Ok(PyTemplate(Template::new().options(options)))
+    }
+
+    fn text(&self, text: String) -> PyTemplate {
+        PyTemplate(self.0.clone().text(text))
+    }
+
+    fn regex(&self, regex: String) -> PyTemplate {
+        PyTemplate(self.0.clone().regex(regex))
+    }
+
+    fn schema(&self, schema: String) -> PyTemplate {
+        PyTemplate(self.0.clone().schema(schema))
+    }
+
+    fn choice(&self, choices: Vec<String>) -> PyTemplate {
+        PyTemplate(self.0.clone().choice(choices))
+    }
+
+    #[pyo3(signature = (sentinel, max_length=None))]
+    fn free_text(&self, sentinel: String, max_length: Option<usize>) -> PyTemplate {
+        PyTemplate(self.0.clone().free_text(sentinel, max_length))
+    }
+
+    fn to_regex(&self) -> PyResult<String> {
+        self.0.to_regex().map_err(schema_error)
+    }
+
+    fn to_fsm(&self) -> PyResult<PyFSMInfo> {
+        self.0.to_fsm().map(Into::into).map_err(schema_error)
+    }
+}
+
+#[pyclass(name = "Table", frozen)]
+#[derive(Clone)]
+pub struct PyTable(Table);
+
+#[pymethods]
+impl PyTable {
+    #[new]
+    #[pyo3(signature = (min_rows=0, max_rows=None))]
+    fn new(min_rows: usize, max_rows: Option<usize>) -> Self {
+        PyTable(Table::new().rows(min_rows, max_rows))
+    }
+
+    /// Appends a column of `"text"`, `"integer"`, `"number"` or `"boolean"` cells, or of one
+    /// of a list of choices.
+    #[pyo3(signature = (name, cell=None))]
+    fn column(&self, name: String, cell: Option<&Bound<PyAny>>) -> PyResult<PyTable> {
+        let Some(cell) = cell else {
+            return Ok(PyTable(self.0.clone().column(name, Cell::Text)));
+        };
+        let cell = match cell.extract::<String>() {
+            Ok(cell) => match cell.as_str() {
+                "text" => Cell::Text,
+                "integer" => Cell::Integer,
+                "number" => Cell::Number,
+                "boolean" => Cell::Boolean,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "Unknown type of cells {:?}, expected 'text', 'integer', 'number' or \
+                         'boolean'",
+                        cell
+                    )))
+                }
+            },
+            Err(_) => Cell::Choice(cell.extract()?),
+        };
+        Ok(PyTable(self.0.clone().column(name, cell)))
+    }
+
+    fn pattern_column(&self, name: String, regex: String) -> PyTable {
+        PyTable(self.0.clone().column(name, Cell::Regex(regex)))
+    }
+
+    fn to_regex(&self) -> PyResult<String> {
+        self.0.to_regex().map_err(schema_error)
+    }
+
+    fn to_fsm(&self) -> PyResult<PyFSMInfo> {
+        self.0.to_fsm().map(Into::into).map_err(schema_error)
+    }
+}
+
+/// Schema being built, whose keywords depend on its type.
+#[derive(Clone)]
+enum SchemaKind {
+    Object(json_schema::Object),
+    Str(json_schema::Str),
+    Int(json_schema::Int),
+    Num(json_schema::Num),
+    Array(json_schema::Array),
+    Other(json_schema::Schema),
+}
+
+impl SchemaKind {
+    fn build(&self) -> json_schema::Schema {
+        match self.clone() {
+            SchemaKind::Object(b) => b.build(),
+            SchemaKind::Str(b) => b.build(),
+            SchemaKind::Int(b) => b.build(),
+            SchemaKind::Num(b) => b.build(),
+            SchemaKind::Array(b) => b.build(),
+            SchemaKind::Other(schema) => schema,
+        }
+    }
+}
+
+#[pyclass(name = "Schema", frozen)]
+#[derive(Clone)]
+pub struct PySchema(SchemaKind);
+
+impl PySchema {
+    fn keyword_error(&self, keyword: &str) -> PyErr {
+        let kind = match self.0 {
+            SchemaKind::Object(_) => "an object",
+            SchemaKind::Str(_) => "a string",
+            SchemaKind::Int(_) => "an integer",
+            SchemaKind::Num(_) => "a number",
+            SchemaKind::Array(_) => "an array",
+            SchemaKind::Other(_) => "this",
+        };
+        PyTypeError::new_err(format!("'{}' does not apply to {} schema", keyword, kind))
+    }
+}
+
+#[pymethods]
+impl PySchema {
+    fn property(&self, name: String, schema: &PySchema) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Object(b) => Ok(PySchema(SchemaKind::Object(
+                b.clone().property(name, schema.0.build()),
+            ))),
+            _ => Err(self.keyword_error("property")),
+        }
+    }
+
+    fn required(&self, names: Vec<String>) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Object(b) => Ok(PySchema(SchemaKind::Object(b.clone().required(names)))),
+            _ => Err(self.keyword_error("required")),
+        }
+    }
+
+    fn additional_properties(&self, schema: &PySchema) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Object(b) => Ok(PySchema(SchemaKind::Object(
+                b.clone().additional_properties(schema.0.build()),
+            ))),
+            _ => Err(self.keyword_error("additional_properties")),
+        }
+    }
+
+    fn closed(&self) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Object(b) => Ok(PySchema(SchemaKind::Object(b.clone().closed()))),
+            _ => Err(self.keyword_error("closed")),
+        }
+    }
+
+    fn min_len(&self, min_length: u64) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Str(b) => Ok(PySchema(SchemaKind::Str(b.clone().min_len(min_length)))),
+            _ => Err(self.keyword_error("min_len")),
+        }
+    }
+
+    fn max_len(&self, max_length: u64) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Str(b) => Ok(PySchema(SchemaKind::Str(b.clone().max_len(max_length)))),
+            _ => Err(self.keyword_error("max_len")),
+        }
+    }
+
+    fn pattern(&self, pattern: String) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Str(b) => Ok(PySchema(SchemaKind::Str(b.clone().pattern(pattern)))),
+            _ => Err(self.keyword_error("pattern")),
+        }
+    }
+
+    fn format(&self, format: String) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Str(b) => Ok(PySchema(SchemaKind::Str(b.clone().format(format)))),
+            _ => Err(self.keyword_error("format")),
+        }
+    }
+
+    fn minimum(&self, minimum: &Bound<PyAny>) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Int(b) => Ok(PySchema(SchemaKind::Int(
+                b.clone().minimum(minimum.extract()?),
+            ))),
+            SchemaKind::Num(b) => Ok(PySchema(SchemaKind::Num(
+                b.clone().minimum(minimum.extract()?),
+            ))),
+            _ => Err(self.keyword_error("minimum")),
+        }
+    }
+
+    fn maximum(&self, maximum: &Bound<PyAny>) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Int(b) => Ok(PySchema(SchemaKind::Int(
+                b.clone().maximum(maximum.extract()?),
+            ))),
+            SchemaKind::Num(b) => Ok(PySchema(SchemaKind::Num(
+                b.clone().maximum(maximum.extract()?),
+            ))),
+            _ => Err(self.keyword_error("maximum")),
+        }
+    }
+
+    fn min_items(&self, min_items: u64) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Array(b) => Ok(PySchema(SchemaKind::Array(b.clone().min_items(min_items)))),
+            _ => Err(self.keyword_error("min_items")),
+        }
+    }
+
+    fn max_items(&self, max_items: u64) -> PyResult<PySchema> {
+        match &self.0 {
+            SchemaKind::Array(b) => Ok(PySchema(SchemaKind::Array(b.clone().max_items(max_items)))),
+            _ => Err(self.keyword_error("max_items")),
+        }
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        Ok(serde_pyobject::to_pyobject(py, self.0.build().as_value())?)
+    }
+
+    #[pyo3(signature = (whitespace_pattern=None, **options))]
+    fn to_regex(
+        &self,
+        py: Python<'_>,
+        whitespace_pattern: Option<&str>,
+        options: Option<&Bound<PyDict>>,
+    ) -> PyResult<String> {
+        let options = schema_options(whitespace_pattern, options)?;
+        let schema = self.0.build();
+        py.allow_threads(|| schema.to_regex(&options))
+            .map_err(schema_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Schema({})", self.0.build().as_value())
+    }
+}
+
+#[pyclass(name = "SchemaBuilder", frozen)]
+pub struct PySchemaBuilder;
+
+#[pymethods]
+impl PySchemaBuilder {
+    #[staticmethod]
+    fn object() -> PySchema {
+        PySchema(SchemaKind::Object(json_schema::Object::new()))
+    }
+
+    #[staticmethod]
+    fn array(items: &PySchema) -> PySchema {
+        PySchema(SchemaKind::Array(json_schema::SchemaBuilder::array(
+            items.0.build(),
+        )))
+    }
+
+    #[staticmethod]
+    fn string() -> PySchema {
+        PySchema(SchemaKind::Str(json_schema::Str::new()))
+    }
+
+    #[staticmethod]
+    fn integer() -> PySchema {
+        PySchema(SchemaKind::Int(json_schema::Int::new()))
+    }
+
+    #[staticmethod]
+    fn number() -> PySchema {
+        PySchema(SchemaKind::Num(json_schema::Num::new()))
+    }
+
+    #[staticmethod]
+    fn boolean() -> PySchema {
+        PySchema(SchemaKind::Other(json_schema::SchemaBuilder::boolean()))
+    }
+
+    #[staticmethod]
+    fn null() -> PySchema {
+        PySchema(SchemaKind::Other(json_schema::SchemaBuilder::null()))
+    }
+
+    #[staticmethod]
+    fn enumeration(values: Vec<Bound<PyAny>>) -> PyResult<PySchema> {
+        let values = values
+            .iter()
+            .map(json_value)
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PySchema(SchemaKind::Other(
+            json_schema::SchemaBuilder::enumeration(values),
+        )))
+    }
+
+    #[staticmethod]
+    fn constant(value: &Bound<PyAny>) -> PyResult<PySchema> {
+        Ok(PySchema(SchemaKind::Other(
+            json_schema::SchemaBuilder::constant(json_value(value)?),
+        )))
+    }
+
+    #[staticmethod]
+    fn any_of(schemas: Vec<PySchema>) -> PySchema {
+        PySchema(SchemaKind::Other(json_schema::SchemaBuilder::any_of(
+            schemas.iter().map(|schema| schema.0.build()),
+        )))
+    }
+}
+
+#[pyclass(name = "CancellationToken", frozen)]
+pub struct PyCancellationToken(CancellationToken);
+
+#[pymethods]
+impl PyCancellationToken {
+    #[new]
+    fn new() -> Self {
+        PyCancellationToken(CancellationToken::new())
+    }
+
+    fn cancel(&self) {
+        self.0.cancel()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
 }
 
-#[pyclass(name = "Index")]
+#[pyclass(name = "Index", frozen)]
 pub struct PyIndex(Index);
 
 #[pymethods]
 impl PyIndex {
     #[new]
+    #[pyo3(signature = (fsm_info, vocabulary, eos_token_id, frozen_tokens, *, timeout=None, cancellation=None, progress=None, max_bytes=None, special_tokens=None, token_healing=None))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
+        py: Python<'_>,
         fsm_info: &PyFSMInfo,
         vocabulary: &PyVocabulary,
         eos_token_id: u32,
         frozen_tokens: HashSet<String>,
+        timeout: Option<f64>,
+        cancellation: Option<&PyCancellationToken>,
+        progress: Option<PyObject>,
+        max_bytes: Option<usize>,
+        special_tokens: Option<&Bound<PyAny>>,
+        token_healing: Option<PyToken>,
     ) -> PyResult<Self> {
-        Index::new(&fsm_info.into(), &vocabulary.0, eos_token_id, frozen_tokens)
-            .map(PyIndex)
-            .map_err(Into::into)
+        let fsm_info = fsm_info.into();
+        let mut options = IndexOptions::new();
+        if let Some(token_healing) = token_healing {
+            options = options.token_healing(Token::from(token_healing));
+        }
+        if let Some(special_tokens) = special_tokens {
+            options = options.special_tokens(special_tokens_policy(special_tokens)?);
+        }
+        if let Some(max_bytes) = max_bytes {
+            options = options.max_bytes(max_bytes);
+        }
+        if let Some(timeout) = timeout {
+            options = options.timeout(timeout_duration(timeout)?);
+        }
+        if let Some(cancellation) = cancellation {
+            options = options.cancellation(cancellation.0.clone());
+        }
+        // The construction stops on the first exception raised by the progress callback.
+        let callback_error: Arc<Mutex<Option<PyErr>>> = Arc::default();
+        if let Some(progress) = progress {
+            let callback_error = callback_error.clone();
+            options = options.progress(move |p| {
+                Python::with_gil(|py| progress.call1(py, (p.states_processed, p.frontier)))
+                    .map_or_else(
+                        |e| {
+                            *callback_error.lock().unwrap() = Some(e);
+                            ControlFlow::Break(())
+                        },
+                        |_| ControlFlow::Continue(()),
+                    )
+            });
+        }
+        let index = py.allow_threads(|| {
+            Index::with_options(
+                &fsm_info,
+                &vocabulary.0,
+                eos_token_id,
+                frozen_tokens,
+                &options,
+            )
+        });
+        if let Some(e) = callback_error.lock().unwrap().take() {
+            return Err(e);
+        }
+        index.map(PyIndex).map_err(Into::into)
     }
 
     fn get_allowed_tokens(&self, state: u32) -> Option<Vec<u32>> {
@@ -101,31 +721,742 @@ impl PyIndex {
         self.0.is_final(state)
     }
 
-    fn get_index_dict(&self) -> HashMap<u32, HashMap<u32, u32>> {
-        self.0.index().clone()
+    fn get_index_dict(&self) -> BTreeMap<u32, BTreeMap<u32, u32>> {
+        self.0.sorted_index()
+    }
+
+    fn get_initial_state(&self) -> u32 {
+        self.0.initial()
+    }
+
+    fn to_token_dfa(&self) -> PyTokenDfa {
+        PyTokenDfa(self.0.to_token_dfa())
+    }
+
+    fn to_mask_table(&self, vocab_size: usize) -> PyResult<PyMaskTable> {
+        self.0
+            .to_mask_table(vocab_size)
+            .map(PyMaskTable)
+            .map_err(Into::into)
+    }
+
+    fn diff<'py>(&self, py: Python<'py>, other: &PyIndex) -> PyResult<Bound<'py, PyAny>> {
+        Ok(serde_pyobject::to_pyobject(py, &self.0.diff(&other.0))?)
+    }
+
+    fn merge(&self, other: &PyIndex) -> PyResult<PyIndex> {
+        self.0.merge(&other.0).map(PyIndex).map_err(Into::into)
+    }
+}
+
+#[pyclass(name = "TokenDfa", frozen)]
+pub struct PyTokenDfa(TokenDfa);
+
+#[pymethods]
+impl PyTokenDfa {
+    fn get_allowed_tokens(&self, state: u32) -> Vec<u32> {
+        self.0.allowed_tokens(state).to_vec()
+    }
+
+    fn get_next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+        self.0.next_state(state, token_id)
+    }
+
+    fn is_final_state(&self, state: u32) -> bool {
+        self.0.is_final(state)
     }
 
     fn get_initial_state(&self) -> u32 {
         self.0.initial()
     }
+
+    fn num_states(&self) -> usize {
+        self.0.num_states()
+    }
+
+    fn apply_mask(&self, py: Python<'_>, logits: &Bound<PyAny>, state: u32) -> PyResult<()> {
+        let buffer = PyBuffer::<f32>::get(logits)?;
+        with_writable_buffer(py, buffer, |logits| self.0.apply_mask(logits, state))
+    }
+}
+
+#[pyclass(name = "MaskTable", frozen)]
+pub struct PyMaskTable(MaskTable);
+
+#[pymethods]
+impl PyMaskTable {
+    fn get_masks<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let bytes: Vec<u8> = self
+            .0
+            .masks()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        PyBytes::new(py, &bytes)
+    }
+
+    fn get_rows(&self) -> Vec<u32> {
+        self.0.rows().to_vec()
+    }
+
+    fn num_rows(&self) -> usize {
+        self.0.num_rows()
+    }
+
+    fn words_per_row(&self) -> usize {
+        self.0.words_per_row()
+    }
+}
+
+#[pyclass(name = "Guide", frozen)]
+pub struct PyGuide(Mutex<Guide>);
+
+impl PyGuide {
+    fn guide(&self) -> MutexGuard<'_, Guide> {
+        // The state of a guide is only replaced once the next one is known.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[pymethods]
+impl PyGuide {
+    #[new]
+    fn new(index: &PyIndex) -> Self {
+        PyGuide(Mutex::new(Guide::new(&index.0)))
+    }
+
+    fn get_state(&self) -> u32 {
+        self.guide().state()
+    }
+
+    fn get_allowed_tokens(&self) -> Vec<u32> {
+        self.guide().allowed_tokens().to_vec()
+    }
+
+    fn is_final(&self) -> bool {
+        self.guide().is_final()
+    }
+
+    fn advance(&self, token_id: u32) -> PyResult<u32> {
+        self.guide().advance(token_id).map_err(Into::into)
+    }
+
+    fn peek(&self, token_id: u32) -> Option<u32> {
+        self.guide().peek(token_id)
+    }
+
+    fn resume(&self, text: &str) -> PyResult<String> {
+        self.guide()
+            .resume(text)
+            .map(str::to_owned)
+            .map_err(Into::into)
+    }
+
+    fn with_state(&self, state: u32) -> PyResult<Self> {
+        Ok(PyGuide(Mutex::new(self.guide().with_state(state)?)))
+    }
+
+    fn verify(&self, token_ids: Vec<u32>) -> usize {
+        self.guide().verify(&token_ids)
+    }
+
+    fn write_mask(&self, py: Python<'_>, mask: &Bound<PyAny>) -> PyResult<()> {
+        let guide = self.guide();
+        match PyBuffer::<u64>::get(mask) {
+            Ok(buffer) => with_writable_buffer(py, buffer, |mask| guide.write_mask(mask)),
+            Err(_) => with_writable_buffer(py, PyBuffer::<i64>::get(mask)?, |mask| {
+                guide.write_mask(mask)
+            }),
+        }
+    }
+
+    fn apply_mask(&self, py: Python<'_>, logits: &Bound<PyAny>) -> PyResult<()> {
+        let buffer = PyBuffer::<f32>::get(logits)?;
+        let guide = self.guide();
+        with_writable_buffer(py, buffer, |logits| guide.apply_mask(logits))
+    }
+}
+
+/// Runs `f` on the contents of a writable, contiguous buffer such as a NumPy array, its items
+/// read as `U`.
+fn with_writable_buffer<T: pyo3::buffer::Element, U>(
+    py: Python<'_>,
+    buffer: PyBuffer<T>,
+    f: impl FnOnce(&mut [U]) -> crate::index::Result<()>,
+) -> PyResult<()> {
+    if buffer.readonly() || !buffer.is_c_contiguous() || buffer.item_size() != size_of::<U>() {
+        return Err(PyValueError::new_err(
+            "Expected a writable, contiguous buffer of numbers of the same size",
+        ));
+    }
+    // SAFETY: the buffer is writable, contiguous and holds `item_count` aligned items of the
+    // size of `U`. It stays alive while it is written, and the GIL is not released meanwhile.
+    let items =
+        unsafe { std::slice::from_raw_parts_mut(buffer.buf_ptr() as *mut U, buffer.item_count()) };
+    let result = f(items);
+    buffer.release(py);
+    result.map_err(Into::into)
+}
+
+#[pyclass(name = "BatchGuide", frozen)]
+pub struct PyBatchGuide(Mutex<BatchGuide>);
+
+impl PyBatchGuide {
+    fn batch(&self) -> MutexGuard<'_, BatchGuide> {
+        // The states of a batch are only replaced once all the next ones are known.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[pymethods]
+impl PyBatchGuide {
+    #[new]
+    fn new(index: &PyIndex, batch_size: usize) -> Self {
+        PyBatchGuide(Mutex::new(BatchGuide::new(&index.0, batch_size)))
+    }
+
+    fn get_states(&self) -> Vec<u32> {
+        self.batch().states().to_vec()
+    }
+
+    fn __len__(&self) -> usize {
+        self.batch().len()
+    }
+
+    fn advance_all(&self, token_ids: Vec<u32>) -> PyResult<()> {
+        let mut batch = self.batch();
+        if token_ids.len() != batch.len() {
+            return Err(PyValueError::new_err(format!(
+                "Expected {} tokens, one per generation, got {}",
+                batch.len(),
+                token_ids.len()
+            )));
+        }
+        batch.advance_all(&token_ids).map_err(Into::into)
+    }
+
+    fn push(&self) -> usize {
+        self.batch().push()
+    }
+
+    fn swap_remove(&self, i: usize) -> PyResult<()> {
+        let mut batch = self.batch();
+        if i >= batch.len() {
+            return Err(PyValueError::new_err(format!(
+                "No generation at position {}",
+                i
+            )));
+        }
+        batch.swap_remove(i);
+        Ok(())
+    }
+}
+
+/// Lexical forms of the integers, given as a dict of `plus_sign`, `negative_zero` and
+/// `leading_zeros` flags.
+fn integer_forms(forms: HashMap<String, bool>) -> PyResult<json_schema::IntegerForms> {
+    let mut integer_forms = json_schema::IntegerForms::default();
+    for (form, allowed) in forms {
+        match form.as_str() {
+            "plus_sign" => integer_forms.plus_sign = allowed,
+            "negative_zero" => integer_forms.negative_zero = allowed,
+            "leading_zeros" => integer_forms.leading_zeros = allowed,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown integer form: {}",
+                    form
+                )))
+            }
+        }
+    }
+    Ok(integer_forms)
+}
+
+/// Wordlist given as the path of a file of one word per line, or as a list of words.
+fn wordlist(words: &Bound<PyAny>) -> PyResult<json_schema::Wordlist> {
+    match words.extract::<PathBuf>() {
+        Ok(path) => Ok(json_schema::Wordlist::from_file(path)?),
+        Err(_) => Ok(json_schema::Wordlist::new(words.extract::<Vec<String>>()?)),
+    }
+}
+
+/// Builds the compilation options from the keyword arguments shared by the schema functions.
+fn schema_options(
+    whitespace_pattern: Option<&str>,
+    kwargs: Option<&Bound<PyDict>>,
+) -> PyResult<json_schema::Options> {
+    let mut options = json_schema::Options::new();
+    let mut whitespace_profiles = Vec::new();
+    let (mut fractional_seconds, mut time_offset) = Default::default();
+    if let Some(pattern) = whitespace_pattern {
+        whitespace_profiles.push("whitespace_pattern");
+        options = options.whitespace_pattern(pattern);
+    }
+
+    for (key, value) in kwargs.into_iter().flatten() {
+        let key: String = key.extract()?;
+        options = match key.as_str() {
+            "draft" => match value.extract::<Option<String>>()? {
+                Some(draft) => {
+                    options.draft(json_schema::Draft::from_str(&draft).ok_or_else(|| {
+                        PyValueError::new_err(format!("Unsupported JSON Schema draft: {}", draft))
+                    })?)
+                }
+                None => options,
+            },
+            "unicode_strings" => options.unicode_strings(value.extract()?),
+            "strict_escapes" => options.strict_escapes(value.extract()?),
+            "ascii_only" => options.ascii_only(value.extract()?),
+            "unordered_required" => options.unordered_required(value.extract()?),
+            "case_insensitive" => options.case_insensitive(value.extract()?),
+            "exponents" => options.exponents(value.extract()?),
+            "json5_numbers" => options.json5_numbers(value.extract()?),
+            "time_fraction" => {
+                let fraction: String = value.extract()?;
+                fractional_seconds = json_schema::FractionalSeconds::from_str(&fraction)
+                    .ok_or_else(|| {
+                        PyValueError::new_err(format!("Unknown time fraction: {}", fraction))
+                    })?;
+                options.time_format(fractional_seconds, time_offset)
+            }
+            "time_offset" => {
+                let offset: String = value.extract()?;
+                time_offset = json_schema::TimeOffset::from_str(&offset).ok_or_else(|| {
+                    PyValueError::new_err(format!("Unknown time offset: {}", offset))
+                })?;
+                options.time_format(fractional_seconds, time_offset)
+            }
+            "length_semantics" => match value.extract::<Option<String>>()? {
+                Some(semantics) => options.length_semantics(
+                    json_schema::LengthSemantics::from_str(&semantics).ok_or_else(|| {
+                        PyValueError::new_err(format!("Unknown length semantics: {}", semantics))
+                    })?,
+                ),
+                None => options,
+            },
+            "integer_forms" => match value.extract::<Option<HashMap<String, bool>>>()? {
+                Some(forms) => options.integer_forms(integer_forms(forms)?),
+                None => options,
+            },
+            "max_array_items" => match value.extract()? {
+                Some(max_array_items) => options.max_array_items(max_array_items),
+                None => options,
+            },
+            "max_nesting_depth" => match value.extract()? {
+                Some(max_nesting_depth) => options.max_nesting_depth(max_nesting_depth),
+                None => options,
+            },
+            "indent" => match value.extract::<Option<usize>>()? {
+                Some(indent) => {
+                    whitespace_profiles.push("indent");
+                    options.whitespace(json_schema::Whitespace::Pretty(indent))
+                }
+                None => options,
+            },
+            "compact" => match value.extract::<bool>()? {
+                true => {
+                    whitespace_profiles.push("compact");
+                    options.whitespace(json_schema::Whitespace::None)
+                }
+                false => options,
+            },
+            "markdown_fence" => options.markdown_fence(value.extract()?),
+            "fence_preamble" => match value.extract()? {
+                Some(max_len) => options.fence_preamble(max_len),
+                None => options,
+            },
+            "timeout" => match value.extract::<Option<f64>>()? {
+                Some(timeout) => options.timeout(timeout_duration(timeout)?),
+                None => options,
+            },
+            "cancellation" => match value.extract::<Option<PyRef<PyCancellationToken>>>()? {
+                Some(token) => options.cancellation(token.0.clone()),
+                None => options,
+            },
+            "ref_loader" if value.is_none() => options,
+            "ref_loader" => {
+                if !value.is_callable() {
+                    return Err(PyTypeError::new_err("'ref_loader' must be callable"));
+                }
+                let loader = value.clone().unbind();
+                options.ref_loader(move |uri: &str| {
+                    Python::with_gil(|py| {
+                        let document = loader.bind(py).call1((uri,))?;
+                        if document.is_none() {
+                            return Err(anyhow::anyhow!("The loader provides no document"));
+                        }
+                        Ok(match document.downcast::<PyString>() {
+                            Ok(text) => serde_json::from_str(text.to_str()?)?,
+                            Err(_) => json_value(&document)?,
+                        })
+                    })
+                })
+            }
+            "wordlists" => match value.extract::<Option<HashMap<String, Bound<PyAny>>>>()? {
+                Some(wordlists) => {
+                    let mut options = options;
+                    for (name, words) in wordlists {
+                        options = options.wordlist(name, wordlist(&words)?);
+                    }
+                    options
+                }
+                None => options,
+            },
+            "keyword_extension" if value.is_none() => options,
+            "keyword_extension" => {
+                if !value.is_callable() {
+                    return Err(PyTypeError::new_err("'keyword_extension' must be callable"));
+                }
+                let extension = value.clone().unbind();
+                options.keyword_extension(
+                    move |keyword: &str, value: &Value, schema: &serde_json::Map<String, Value>| {
+                        Python::with_gil(|py| {
+                            let outcome = extension.bind(py).call1((
+                                keyword,
+                                serde_pyobject::to_pyobject(py, value)?,
+                                serde_pyobject::to_pyobject(py, schema)?,
+                            ))?;
+                            if outcome.is_none() {
+                                return Ok(None);
+                            }
+                            Ok(Some(match outcome.downcast::<PyString>() {
+                                Ok(regex) => json_schema::Extension::Regex(regex.to_string()),
+                                Err(_) => json_schema::Extension::Schema(json_value(&outcome)?),
+                            }))
+                        })
+                    },
+                )
+            }
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "Unexpected keyword argument '{}'",
+                    key
+                )))
+            }
+        };
+    }
+
+    if let [first, second, ..] = whitespace_profiles.as_slice() {
+        return Err(PyValueError::new_err(format!(
+            "'{}' and '{}' can not be used together",
+            first, second
+        )));
+    }
+    Ok(options)
+}
+
+fn timeout_duration(timeout: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(timeout)
+        .map_err(|_| PyValueError::new_err(format!("Invalid timeout: {}", timeout)))
+}
+
+/// Reads a policy of special tokens written as `"forbid_all"`, `"eos_only"` or a list of the
+/// allowed token ids.
+fn special_tokens_policy(policy: &Bound<PyAny>) -> PyResult<SpecialTokensPolicy> {
+    match policy.extract::<String>() {
+        Ok(policy) => match policy.as_str() {
+            "forbid_all" => Ok(SpecialTokensPolicy::ForbidAll),
+            "eos_only" => Ok(SpecialTokensPolicy::EosOnly),
+            _ => Err(PyValueError::new_err(format!(
+                "Unknown policy of special tokens {:?}, expected 'forbid_all', 'eos_only' or a \
+                 list of token ids",
+                policy
+            ))),
+        },
+        Err(_) => Ok(SpecialTokensPolicy::Allow(policy.extract()?)),
+    }
+}
+
+/// Converts the errors of schema compilation, the errors of the crate keeping their exception.
+fn schema_error(e: anyhow::Error) -> PyErr {
+    match e.downcast::<crate::Error>() {
+        Ok(e) => e.into(),
+        Err(e) => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// Converts a Python object made of dicts with string keys, lists, tuples, strings, numbers,
+/// booleans and `None` to a JSON value.
+fn json_value(obj: &Bound<PyAny>) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if let Ok(i) = obj.downcast::<PyInt>() {
+        if let Ok(i) = i.extract::<i64>() {
+            Ok(i.into())
+        } else {
+            i.extract::<u64>()
+                .map(Into::into)
+                .map_err(|_| PyValueError::new_err(format!("Integer out of JSON range: {}", i)))
+        }
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        serde_json::Number::from_f64(f.value())
+            .map(Value::Number)
+            .ok_or_else(|| PyValueError::new_err(format!("Float out of JSON range: {}", f)))
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Ok(Value::String(s.to_str()?.to_string()))
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter().map(|item| json_value(&item)).collect()
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple.iter().map(|item| json_value(&item)).collect()
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        dict.iter()
+            .map(|(key, value)| {
+                let key = key.downcast::<PyString>().map_err(|_| {
+                    PyTypeError::new_err(format!("JSON object keys must be strings, not {}", key))
+                })?;
+                Ok((key.to_str()?.to_string(), json_value(&value)?))
+            })
+            .collect()
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "Object of type {} is not JSON serializable",
+            obj.get_type().name()?
+        )))
+    }
 }
 
 #[pyfunction(name = "build_regex_from_schema")]
-#[pyo3(signature = (json, whitespace_pattern=None))]
+#[pyo3(signature = (json, whitespace_pattern=None, **options))]
 pub fn build_regex_from_schema_py(
+    py: Python<'_>,
     json: String,
     whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<String> {
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| json_schema::build_regex_from_schema_with_options(&json, &options))
+        .map_err(schema_error)
+}
+
+#[pyfunction(name = "build_regex_from_schema_file")]
+#[pyo3(signature = (path, whitespace_pattern=None, **options))]
+pub fn build_regex_from_schema_file_py(
+    py: Python<'_>,
+    path: PathBuf,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
 ) -> PyResult<String> {
-    json_schema::build_regex_from_schema(&json, whitespace_pattern)
-        .map_err(|e| PyValueError::new_err(e.to_string()))
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| json_schema::build_regex_from_schema_file(&path, &options))
+        .map_err(schema_error)
+}
+
+#[pyfunction(name = "build_regex_from_yaml")]
+#[pyo3(signature = (yaml, whitespace_pattern=None, **options))]
+pub fn build_regex_from_yaml_py(
+    py: Python<'_>,
+    yaml: String,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<String> {
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| {
+        let json_value = json_schema::from_yaml(&yaml)?;
+        json_schema::to_regex_with_options(&json_value, &json_value, &options)
+    })
+    .map_err(schema_error)
+}
+
+#[pyfunction(name = "build_regex_from_json5")]
+#[pyo3(signature = (json5, whitespace_pattern=None, **options))]
+pub fn build_regex_from_json5_py(
+    py: Python<'_>,
+    json5: String,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<String> {
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| {
+        let json_value = json_schema::from_json5(&json5)?;
+        json_schema::to_regex_with_options(&json_value, &json_value, &options)
+    })
+    .map_err(schema_error)
+}
+
+#[pyfunction(name = "build_jsonl_regex_from_schema")]
+#[pyo3(signature = (json, min_lines=1, max_lines=None, whitespace_pattern=None, **options))]
+pub fn build_jsonl_regex_from_schema_py(
+    py: Python<'_>,
+    json: String,
+    min_lines: u64,
+    max_lines: Option<u64>,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<String> {
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| {
+        json_schema::build_jsonl_regex_from_schema(&json, min_lines, max_lines, &options)
+    })
+    .map_err(schema_error)
+}
+
+#[pyfunction(name = "build_fsm_from_schema")]
+#[pyo3(signature = (json, whitespace_pattern=None, **options))]
+pub fn build_fsm_from_schema_py(
+    py: Python<'_>,
+    json: String,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<PyFSMInfo> {
+    let options = schema_options(whitespace_pattern, options)?;
+    py.allow_threads(|| json_schema::build_fsm_from_schema(&json, &options))
+        .map(Into::into)
+        .map_err(schema_error)
 }
 
 #[pyfunction(name = "to_regex")]
-#[pyo3(signature = (json, whitespace_pattern=None))]
-pub fn to_regex_py(json: Bound<PyDict>, whitespace_pattern: Option<&str>) -> PyResult<String> {
-    let json_value: Value = serde_pyobject::from_pyobject(json)?;
-    json_schema::to_regex(&json_value, whitespace_pattern, &json_value)
-        .map_err(|e| PyValueError::new_err(e.to_string()))
+#[pyo3(signature = (json, whitespace_pattern=None, **options))]
+pub fn to_regex_py(
+    py: Python<'_>,
+    json: Bound<PyAny>,
+    whitespace_pattern: Option<&str>,
+    options: Option<&Bound<PyDict>>,
+) -> PyResult<String> {
+    let options = schema_options(whitespace_pattern, options)?;
+    let json_value = json_value(&json)?;
+    py.allow_threads(|| json_schema::to_regex_with_options(&json_value, &json_value, &options))
+        .map_err(schema_error)
+}
+
+#[pyfunction(name = "lint_schema")]
+#[pyo3(signature = (json))]
+pub fn lint_schema_py<'py>(py: Python<'py>, json: &str) -> PyResult<Bound<'py, PyAny>> {
+    let json_value: Value =
+        serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(serde_pyobject::to_pyobject(
+        py,
+        &json_schema::lint_schema(&json_value),
+    )?)
+}
+
+#[pyfunction(name = "integer_range_regex")]
+#[pyo3(signature = (lo, hi))]
+pub fn integer_range_regex_py(lo: i128, hi: i128) -> PyResult<String> {
+    json_schema::integer_range_regex(lo, hi).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+#[pyfunction(name = "build_choice_index")]
+#[pyo3(signature = (choices, vocabulary, eos_token_id))]
+pub fn build_choice_index_py(
+    py: Python<'_>,
+    choices: Vec<String>,
+    vocabulary: &PyVocabulary,
+    eos_token_id: u32,
+) -> PyResult<PyIndex> {
+    py.allow_threads(|| build_choice_index(choices, &vocabulary.0, eos_token_id))
+        .map(PyIndex)
+        .map_err(Into::into)
+}
+
+#[pyfunction(name = "compile")]
+#[pyo3(signature = (json, vocabulary, eos_token_id, whitespace_pattern=None))]
+pub fn compile_py(
+    py: Python<'_>,
+    json: String,
+    vocabulary: &PyVocabulary,
+    eos_token_id: u32,
+    whitespace_pattern: Option<String>,
+) -> PyResult<PyGuide> {
+    let whitespace = whitespace_pattern.map_or_else(
+        json_schema::Whitespace::default,
+        json_schema::Whitespace::Pattern,
+    );
+    py.allow_threads(|| compile(&json, &whitespace, &vocabulary.0, eos_token_id))
+        .map(|guide| PyGuide(Mutex::new(guide)))
+        .map_err(schema_error)
+}
+
+/// Compiles a JSON Schema into a guide like `compile`, on a thread of its own, into a future of
+/// the running event loop. Cancelling the future, as happens to the tasks of the requests a
+/// server gives up on, cancels the compilation.
+#[pyfunction(name = "compile_async")]
+#[pyo3(signature = (json, vocabulary, eos_token_id, whitespace_pattern=None))]
+pub fn compile_async_py<'py>(
+    py: Python<'py>,
+    json: String,
+    vocabulary: &PyVocabulary,
+    eos_token_id: u32,
+    whitespace_pattern: Option<String>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let cancellation = CancellationToken::new();
+    let on_cancel = cancellation.clone();
+    let cancel = PyCFunction::new_closure(py, None, None, move |args, _| -> PyResult<()> {
+        if args.get_item(0)?.call_method0("cancelled")?.is_truthy()? {
+            on_cancel.cancel();
+        }
+        Ok(())
+    })?;
+    future.call_method1("add_done_callback", (cancel,))?;
+
+    let whitespace = whitespace_pattern.map_or_else(
+        json_schema::Whitespace::default,
+        json_schema::Whitespace::Pattern,
+    );
+    let (event_loop_ref, future_ref) = (event_loop.unbind(), future.clone().unbind());
+    spawn_compile(
+        &json,
+        &whitespace,
+        &vocabulary.0,
+        eos_token_id,
+        cancellation,
+        move |result| {
+            Python::with_gil(|py| {
+                let outcome = || -> PyResult<()> {
+                    let (method, value) = match result {
+                        Ok(guide) => (
+                            "set_result",
+                            Py::new(py, PyGuide(Mutex::new(guide)))?.into_any(),
+                        ),
+                        Err(e) => ("set_exception", schema_error(e).into_value(py).into_any()),
+                    };
+                    // The future is only set from its event loop, unless it was cancelled meanwhile.
+                    let set =
+                        PyCFunction::new_closure(py, None, None, move |args, _| -> PyResult<()> {
+                            let future = args.get_item(0)?;
+                            if !future.call_method0("done")?.is_truthy()? {
+                                future.call_method1(method, (args.get_item(1)?,))?;
+                            }
+                            Ok(())
+                        })?;
+                    event_loop_ref
+                        .bind(py)
+                        .call_method1("call_soon_threadsafe", (set, future_ref, value))?;
+                    Ok(())
+                };
+                // Nothing awaits the guide anymore once the event loop is closed.
+                let _ = outcome();
+            })
+        },
+    );
+    Ok(future)
+}
+
+/// Counters of the work done in the process, with the average build time in milliseconds.
+#[pyfunction(name = "get_stats")]
+pub fn get_stats_py(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let stats = stats::stats();
+    let dict = PyDict::new(py);
+    dict.set_item("indexes_built", stats.indexes_built)?;
+    dict.set_item("index_build_ms", stats.index_build_time.as_secs_f64() * 1e3)?;
+    dict.set_item(
+        "average_build_ms",
+        stats
+            .average_build_time()
+            .map(|time| time.as_secs_f64() * 1e3),
+    )?;
+    dict.set_item("states_expanded", stats.states_expanded)?;
+    dict.set_item("compile_cache_hits", stats.compile_cache_hits)?;
+    dict.set_item("compile_cache_misses", stats.compile_cache_misses)?;
+    dict.set_item(
+        "transition_keys_cache_hits",
+        stats.transition_keys_cache_hits,
+    )?;
+    dict.set_item("masks_served", stats.masks_served)?;
+    Ok(dict)
 }
 
 #[pyfunction(name = "_walk_fsm")]
@@ -161,7 +1492,7 @@ pub fn state_scan_tokens_py(
     vocabulary: &PyVocabulary,
     vocabulary_transition_keys: HashMap<String, Vec<TransitionKey>>,
     start_state: State,
-) -> PyResult<HashSet<(TokenId, State)>> {
+) -> PyResult<BTreeSet<(TokenId, State)>> {
     Ok(state_scan_tokens(
         &fsm_transitions,
         fsm_initial,
@@ -177,12 +1508,12 @@ pub fn state_scan_tokens_py(
 pub fn get_token_transition_keys_py(
     alphabet_symbol_mapping: HashMap<String, TransitionKey>,
     alphabet_anything_value: TransitionKey,
-    token_str: String,
+    token_str: PyToken,
 ) -> PyResult<Vec<TransitionKey>> {
     Ok(get_token_transition_keys(
         &alphabet_symbol_mapping,
         alphabet_anything_value,
-        &token_str,
+        &Token::from(token_str),
     ))
 }
 
@@ -205,45 +1536,32 @@ pub fn get_vocabulary_transition_keys_py(
 }
 
 #[pyfunction(name = "create_fsm_index_end_to_end")]
-#[pyo3(text_signature = "(fsm_info, vocabulary, frozen_tokens)")]
-pub fn create_fsm_index_end_to_end_py<'py>(
-    py: Python<'py>,
+#[pyo3(signature = (fsm_info, vocabulary, frozen_tokens, progress=None))]
+pub fn create_fsm_index_end_to_end_py(
     fsm_info: &PyFSMInfo,
     vocabulary: &PyVocabulary,
     frozen_tokens: HashSet<String>,
-) -> PyResult<Bound<'py, PyDict>> {
-    let states_to_token_subsets = PyDict::new_bound(py);
+    progress: Option<&Bound<PyAny>>,
+) -> PyResult<BTreeMap<State, BTreeMap<TokenId, State>>> {
+    let mut states_to_token_subsets: BTreeMap<State, BTreeMap<TokenId, State>> = BTreeMap::new();
     let mut seen: HashSet<State> = HashSet::new();
-    let mut next_states: HashSet<State> = HashSet::from_iter(vec![fsm_info.initial]);
+    let mut next_states: BTreeSet<State> = BTreeSet::from([fsm_info.initial]);
 
-    let vocabulary_transition_keys = get_vocabulary_transition_keys(
+    let vocabulary_transition_keys = vocabulary.0.transition_keys(
         &fsm_info.alphabet_symbol_mapping,
         fsm_info.alphabet_anything_value,
-        &vocabulary.0,
         &frozen_tokens,
     );
+    let trie = TokenTrie::new(&vocabulary.0, &vocabulary_transition_keys);
 
-    while let Some(start_state) = next_states.iter().cloned().next() {
-        next_states.remove(&start_state);
-
-        // TODO: Return Pydict directly at construction
-        let token_ids_end_states = state_scan_tokens(
-            &fsm_info.transitions,
-            fsm_info.initial,
-            &fsm_info.finals,
-            &vocabulary.0,
-            &vocabulary_transition_keys,
-            start_state,
-        );
+    while let Some(start_state) = next_states.pop_first() {
+        let token_ids_end_states = trie.scan(&fsm_info.transitions, start_state);
 
         for (token_id, end_state) in token_ids_end_states {
-            if let Ok(Some(existing_dict)) = states_to_token_subsets.get_item(start_state) {
-                existing_dict.set_item(token_id, end_state)?;
-            } else {
-                let new_dict = PyDict::new_bound(py);
-                new_dict.set_item(token_id, end_state)?;
-                states_to_token_subsets.set_item(start_state, new_dict)?;
-            }
+            states_to_token_subsets
+                .entry(start_state)
+                .or_default()
+                .insert(token_id, end_state);
 
             if !seen.contains(&end_state) {
                 next_states.insert(end_state);
@@ -251,19 +1569,42 @@ pub fn create_fsm_index_end_to_end_py<'py>(
         }
 
         seen.insert(start_state);
+        if let Some(progress) = progress {
+            progress.call1((seen.len(), next_states.len()))?;
+        }
     }
 
     Ok(states_to_token_subsets)
 }
 
-#[pyclass(name = "Vocabulary")]
+/// Token given as text, or as bytes which need not be valid UTF-8.
+#[derive(FromPyObject)]
+pub enum PyToken {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<PyToken> for Token {
+    fn from(token: PyToken) -> Token {
+        match token {
+            PyToken::Text(token) => token,
+            PyToken::Bytes(bytes) => byte_symbols(&bytes),
+        }
+    }
+}
+
+#[pyclass(name = "Vocabulary", frozen)]
 pub struct PyVocabulary(Vocabulary);
 
 #[pymethods]
 impl PyVocabulary {
     #[staticmethod]
-    fn from_dict(map: HashMap<Token, Vec<TokenId>>) -> PyVocabulary {
-        PyVocabulary(Vocabulary::from(map))
+    fn from_dict(map: &Bound<PyDict>) -> PyResult<PyVocabulary> {
+        let mut tokens: Vec<(PyToken, Vec<TokenId>)> = Vec::with_capacity(map.len());
+        for (token, token_ids) in map {
+            tokens.push((token.extract()?, token_ids.extract()?));
+        }
+        Ok(PyVocabulary(Vocabulary::from_iter(tokens)))
     }
 
     fn __repr__(&self) -> String {
@@ -275,7 +1616,7 @@ impl PyVocabulary {
     }
 }
 
-#[pymodule]
+#[pymodule(gil_used = false)]
 fn outlines_core_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(walk_fsm_py, m)?)?;
     m.add_function(wrap_pyfunction!(state_scan_tokens_py, m)?)?;
@@ -286,21 +1627,59 @@ fn outlines_core_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add("BOOLEAN", json_schema::BOOLEAN)?;
     m.add("DATE", json_schema::DATE)?;
     m.add("DATE_TIME", json_schema::DATE_TIME)?;
+    m.add("EMAIL", json_schema::EMAIL)?;
+    m.add("HOSTNAME", json_schema::HOSTNAME)?;
+    m.add("IDN_EMAIL", json_schema::IDN_EMAIL)?;
+    m.add("IDN_HOSTNAME", json_schema::IDN_HOSTNAME)?;
     m.add("INTEGER", json_schema::INTEGER)?;
+    m.add("IRI", json_schema::IRI)?;
+    m.add("JSON_POINTER", json_schema::JSON_POINTER)?;
     m.add("NULL", json_schema::NULL)?;
     m.add("NUMBER", json_schema::NUMBER)?;
+    m.add("PHONE", json_schema::PHONE)?;
+    m.add("RELATIVE_JSON_POINTER", json_schema::RELATIVE_JSON_POINTER)?;
+    m.add("SEMVER", json_schema::SEMVER)?;
     m.add("STRING", json_schema::STRING)?;
     m.add("STRING_INNER", json_schema::STRING_INNER)?;
+    m.add("STRING_INNER_UNICODE", json_schema::STRING_INNER_UNICODE)?;
+    m.add("STRING_INNER_STRICT", json_schema::STRING_INNER_STRICT)?;
+    m.add("STRING_INNER_ASCII", json_schema::STRING_INNER_ASCII)?;
+    m.add(
+        "STRING_INNER_ASCII_STRICT",
+        json_schema::STRING_INNER_ASCII_STRICT,
+    )?;
     m.add("TIME", json_schema::TIME)?;
+    m.add("URI", json_schema::URI)?;
     m.add("UUID", json_schema::UUID)?;
     m.add("WHITESPACE", json_schema::WHITESPACE)?;
 
     m.add_function(wrap_pyfunction!(build_regex_from_schema_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_regex_from_schema_file_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_regex_from_yaml_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_regex_from_json5_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_jsonl_regex_from_schema_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_fsm_from_schema_py, m)?)?;
     m.add_function(wrap_pyfunction!(to_regex_py, m)?)?;
+    m.add_function(wrap_pyfunction!(lint_schema_py, m)?)?;
+    m.add_function(wrap_pyfunction!(integer_range_regex_py, m)?)?;
+    m.add_function(wrap_pyfunction!(build_choice_index_py, m)?)?;
+    m.add_function(wrap_pyfunction!(compile_py, m)?)?;
+    m.add_function(wrap_pyfunction!(compile_async_py, m)?)?;
+    m.add_function(wrap_pyfunction!(get_stats_py, m)?)?;
 
+    m.add_class::<PyCancellationToken>()?;
     m.add_class::<PyIndex>()?;
+    m.add_class::<PyTokenDfa>()?;
+    m.add_class::<PyMaskTable>()?;
+    m.add_class::<PyGuide>()?;
+    m.add_class::<PyBatchGuide>()?;
     m.add_class::<PyVocabulary>()?;
     m.add_class::<PyFSMInfo>()?;
+    m.add_class::<PyPartialValidator>()?;
+    m.add_class::<PyTemplate>()?;
+    m.add_class::<PyTable>()?;
+    m.add_class::<PySchema>()?;
+    m.add_class::<PySchemaBuilder>()?;
 
     Ok(())
 }
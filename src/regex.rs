@@ -1,4 +1,11 @@
+use crate::fsm::aho_corasick::AhoCorasick;
 use crate::prelude::*;
+use crate::stats;
+use std::cell::OnceCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, PoisonError};
 
 pub fn walk_fsm(
     fsm_transitions: &HashMap<(State, TransitionKey), State>,
@@ -37,45 +44,287 @@ pub fn walk_fsm(
     accepted_states
 }
 
+/// State reached by walking all of `token_transition_keys` from `start_state`, like
+/// [`walk_fsm`] without collecting the states along the way.
+///
+/// Returns `None` when no key is walked, when a key has no transition, unlike `walk_fsm` which
+/// then returns the states up to the last final one without `full_match`, or when `full_match`
+/// and the walk does not end in a final state.
+pub fn walk_fsm_final(
+    fsm_transitions: &HashMap<(State, TransitionKey), State>,
+    fsm_finals: &HashSet<State>,
+    token_transition_keys: &[TransitionKey],
+    start_state: State,
+    full_match: bool,
+) -> Option<State> {
+    if token_transition_keys.is_empty() {
+        return None;
+    }
+    let mut state = start_state;
+    for &trans_key in token_transition_keys {
+        state = *fsm_transitions.get(&(state, trans_key))?;
+    }
+    if full_match && !fsm_finals.contains(&state) {
+        return None;
+    }
+    Some(state)
+}
+
+/// Finds the tokens that can be read from `start_state`, along with the state each one leads
+/// to, sorted by token id.
 pub fn state_scan_tokens(
     fsm_transitions: &HashMap<(State, TransitionKey), State>,
-    fsm_initial: State,
+    _fsm_initial: State,
     fsm_finals: &HashSet<State>,
     vocabulary: &Vocabulary,
     vocabulary_transition_keys: &HashMap<Token, Vec<TransitionKey>>,
     start_state: State,
-) -> HashSet<(TokenId, State)> {
-    let mut res = HashSet::new();
+) -> BTreeSet<(TokenId, State)> {
+    let mut res = BTreeSet::new();
 
     for (token, token_ids) in vocabulary.iter() {
         let token_transition_keys = &vocabulary_transition_keys[token];
-        let state_seq = walk_fsm(
+        let Some(state) = walk_fsm_final(
             fsm_transitions,
-            fsm_initial,
             fsm_finals,
             token_transition_keys,
             start_state,
             false,
-        );
-
-        if state_seq.len() < token_transition_keys.len() {
+        ) else {
             continue;
-        }
+        };
 
         for &token_id in token_ids {
-            res.insert((token_id, *state_seq.last().unwrap()));
+            res.insert((token_id, state));
         }
     }
 
     res
 }
 
+/// Prefix trie of the transition keys of the tokens of a vocabulary, so that the tokens
+/// sharing a prefix walk it only once.
+#[derive(Debug, Clone)]
+pub struct TokenTrie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: Vec<(TransitionKey, usize)>,
+    /// Tokens whose transition keys lead to the node.
+    token_ids: Vec<TokenId>,
+}
+
+impl TokenTrie {
+    pub fn new(
+        vocabulary: &Vocabulary,
+        vocabulary_transition_keys: &HashMap<Token, Vec<TransitionKey>>,
+    ) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        let mut children: HashMap<(usize, TransitionKey), usize> = HashMap::new();
+        for (token, token_ids) in vocabulary.iter() {
+            let mut node = 0;
+            for &key in &vocabulary_transition_keys[token] {
+                node = *children.entry((node, key)).or_insert_with(|| {
+                    nodes.push(TrieNode::default());
+                    nodes.len() - 1
+                });
+            }
+            nodes[node].token_ids.extend(token_ids);
+        }
+        for ((parent, key), child) in children {
+            nodes[parent].children.push((key, child));
+        }
+        for node in &mut nodes {
+            node.children.sort_unstable();
+        }
+        TokenTrie { nodes }
+    }
+
+    /// Finds the tokens that can be read from `start_state`, along with the state each one
+    /// leads to, sorted like [`state_scan_tokens`].
+    pub fn scan(
+        &self,
+        fsm_transitions: &HashMap<(State, TransitionKey), State>,
+        start_state: State,
+    ) -> BTreeSet<(TokenId, State)> {
+        let mut res = BTreeSet::new();
+        // Tokens without any symbol are never read.
+        self.scan_beyond(fsm_transitions, 0, start_state, &mut res);
+        res
+    }
+
+    /// Adds to `res` the tokens extending those of `node` which can be read on from `state`,
+    /// along with the state each one leads to.
+    fn scan_beyond(
+        &self,
+        fsm_transitions: &HashMap<(State, TransitionKey), State>,
+        node: usize,
+        state: State,
+        res: &mut BTreeSet<(TokenId, State)>,
+    ) {
+        let mut stack: Vec<(usize, State)> = self.nodes[node]
+            .children
+            .iter()
+            .filter_map(|&(key, child)| {
+                let state = *fsm_transitions.get(&(state, key))?;
+                Some((child, state))
+            })
+            .collect();
+        while let Some((node, state)) = stack.pop() {
+            let node = &self.nodes[node];
+            res.extend(node.token_ids.iter().map(|&token_id| (token_id, state)));
+            for &(key, child) in &node.children {
+                if let Some(&next_state) = fsm_transitions.get(&(state, key)) {
+                    stack.push((child, next_state));
+                }
+            }
+        }
+    }
+
+    /// Node of the tokens starting with `keys`, if any.
+    fn descend(&self, keys: &[TransitionKey]) -> Option<usize> {
+        keys.iter().try_fold(0, |node, key| {
+            let children = &self.nodes[node].children;
+            let i = children.binary_search_by_key(key, |&(key, _)| key).ok()?;
+            Some(children[i].1)
+        })
+    }
+}
+
+/// Automaton of the distinct keys of the tokens, along with the number of keys and the tokens
+/// of each.
+type TokenAutomaton = (AhoCorasick<TransitionKey>, Vec<(usize, Vec<TokenId>)>);
+
+/// Shortest run of states with a single transition worth scanning as a literal.
+const MIN_LITERAL_KEYS: usize = 4;
+
+/// Scanner of the tokens along the literal runs of an automaton, such as the keys of an object
+/// or the values of an enum: the states having a single transition, which follow each other.
+///
+/// Rather than walking the trie of the vocabulary from each state of a run, the keys of the
+/// run are read once by an Aho-Corasick automaton of the keys of the tokens, which finds the
+/// tokens read within the run from all its states at once. Only the tokens running past the
+/// end of the run are left to the trie. The tokens found are those [`TokenTrie::scan`] finds.
+pub(crate) struct LiteralScanner<'a> {
+    fsm_transitions: &'a HashMap<(State, TransitionKey), State>,
+    /// Transition of each state having exactly one.
+    literals: HashMap<State, (TransitionKey, State)>,
+    vocabulary: &'a Vocabulary,
+    vocabulary_transition_keys: &'a HashMap<Token, Vec<TransitionKey>>,
+    /// Built with the first run.
+    automaton: OnceCell<TokenAutomaton>,
+}
+
+impl<'a> LiteralScanner<'a> {
+    pub(crate) fn new(
+        fsm_transitions: &'a HashMap<(State, TransitionKey), State>,
+        vocabulary: &'a Vocabulary,
+        vocabulary_transition_keys: &'a HashMap<Token, Vec<TransitionKey>>,
+    ) -> Self {
+        let mut transitions: HashMap<State, Option<(TransitionKey, State)>> = HashMap::new();
+        for (&(state, key), &next_state) in fsm_transitions {
+            transitions
+                .entry(state)
+                .and_modify(|transition| *transition = None)
+                .or_insert(Some((key, next_state)));
+        }
+        let literals = transitions
+            .into_iter()
+            .filter_map(|(state, transition)| Some((state, transition?)))
+            .collect();
+        LiteralScanner {
+            fsm_transitions,
+            literals,
+            vocabulary,
+            vocabulary_transition_keys,
+            automaton: OnceCell::new(),
+        }
+    }
+
+    /// Finds the tokens that can be read from each state of the literal run starting at
+    /// `start_state`, `start_state` first, along with the state each one leads to. Nothing is
+    /// found when the run is too short to be worth it.
+    pub(crate) fn scan(
+        &self,
+        trie: &TokenTrie,
+        start_state: State,
+    ) -> Vec<(State, BTreeSet<(TokenId, State)>)> {
+        let mut states = vec![start_state];
+        let mut keys = Vec::new();
+        let mut visited = HashSet::from([start_state]);
+        while let Some(&(key, next_state)) = self.literals.get(states.last().expect("one state")) {
+            keys.push(key);
+            states.push(next_state);
+            // A run going round a cycle ends where it meets itself.
+            if !visited.insert(next_state) {
+                break;
+            }
+        }
+        if keys.len() < MIN_LITERAL_KEYS {
+            return Vec::new();
+        }
+
+        let (automaton, tokens) = self.automaton.get_or_init(|| self.automaton());
+        let mut res = vec![BTreeSet::new(); keys.len()];
+        let mut position = 0;
+        for (i, key) in keys.iter().enumerate() {
+            position = automaton.next(position, key);
+            for pattern in automaton.matches(position) {
+                let (len, token_ids) = &tokens[pattern];
+                let end_state = states[i + 1];
+                res[i + 1 - len].extend(token_ids.iter().map(|&token_id| (token_id, end_state)));
+            }
+        }
+        let end_state = states[keys.len()];
+        for (start, token_ids_end_states) in res.iter_mut().enumerate() {
+            if let Some(node) = trie.descend(&keys[start..]) {
+                trie.scan_beyond(self.fsm_transitions, node, end_state, token_ids_end_states);
+            }
+        }
+        states.into_iter().zip(res).collect()
+    }
+
+    fn automaton(&self) -> TokenAutomaton {
+        let mut patterns: HashMap<&[TransitionKey], usize> = HashMap::new();
+        let mut keys = Vec::new();
+        let mut tokens: Vec<(usize, Vec<TokenId>)> = Vec::new();
+        for (token, token_ids) in self.vocabulary.iter() {
+            let token_keys = &self.vocabulary_transition_keys[token];
+            // Tokens without any symbol are never read.
+            if token_keys.is_empty() {
+                continue;
+            }
+            let pattern = *patterns.entry(token_keys).or_insert_with(|| {
+                keys.push(token_keys.clone());
+                tokens.push((token_keys.len(), Vec::new()));
+                tokens.len() - 1
+            });
+            tokens[pattern].1.extend(token_ids);
+        }
+        (AhoCorasick::new(&keys), tokens)
+    }
+}
+
 pub fn get_token_transition_keys(
     alphabet_symbol_mapping: &HashMap<String, TransitionKey>,
     alphabet_anything_value: TransitionKey,
     token_str: &str,
 ) -> Vec<TransitionKey> {
-    let mut token_transition_keys = Vec::new();
+    token_symbols(token_str)
+        .iter()
+        .map(|symbol| {
+            *alphabet_symbol_mapping
+                .get(symbol)
+                .unwrap_or(&alphabet_anything_value)
+        })
+        .collect()
+}
+
+/// Splits a token into the symbols it is read with, a byte symbol `\0XX` being one symbol.
+pub(crate) fn token_symbols(token_str: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
     let mut i = 0;
     let chars: Vec<char> = token_str.chars().collect();
 
@@ -93,16 +342,18 @@ pub fn get_token_transition_keys(
             symbol = chars[i].to_string();
             i += 1;
         }
-
-        let transition_key = *alphabet_symbol_mapping
-            .get(&symbol)
-            .unwrap_or(&alphabet_anything_value);
-        token_transition_keys.push(transition_key);
+        symbols.push(symbol);
     }
 
-    token_transition_keys
+    symbols
 }
 
+/// Gets the transition keys of every token of a vocabulary.
+///
+/// The frozen tokens are atomic: each one is read whole, with the single transition key of the
+/// symbol of the alphabet it is equal to. A frozen token of several characters which is not a
+/// symbol of the alphabet gets no key, so it is never read, as the key of anything else only
+/// stands for single characters.
 pub fn get_vocabulary_transition_keys(
     alphabet_symbol_mapping: &HashMap<String, TransitionKey>,
     alphabet_anything_value: TransitionKey,
@@ -114,27 +365,278 @@ pub fn get_vocabulary_transition_keys(
     for item in vocabulary.iter() {
         let token_str = item.0.clone();
 
-        let mut token_transition_keys;
-
         // Since these tokens are not expanded into byte-level transitions, we
         // can simply get their transition keys directly.
-        if frozen_tokens.contains(&token_str) {
-            token_transition_keys = Vec::new();
-            token_transition_keys.push(
-                *alphabet_symbol_mapping
-                    .get(&token_str)
-                    .unwrap_or(&alphabet_anything_value),
-            )
+        let token_transition_keys = if frozen_tokens.contains(&token_str) {
+            match alphabet_symbol_mapping.get(&token_str) {
+                Some(&key) => vec![key],
+                None if token_str.chars().count() == 1 => vec![alphabet_anything_value],
+                None => Vec::new(),
+            }
         } else {
-            token_transition_keys = get_token_transition_keys(
-                alphabet_symbol_mapping,
-                alphabet_anything_value,
-                &token_str,
-            );
-        }
+            get_token_transition_keys(alphabet_symbol_mapping, alphabet_anything_value, &token_str)
+        };
 
         vocab_transition_keys.insert(token_str, token_transition_keys);
     }
 
     vocab_transition_keys
 }
+
+/// Transition keys of every token of a vocabulary, as computed by
+/// [`get_vocabulary_transition_keys`].
+pub type VocabularyTransitionKeys = HashMap<Token, Vec<TransitionKey>>;
+
+/// Hash of everything the transition keys of a vocabulary depend on: the alphabet of the
+/// automaton and the frozen tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AlphabetFingerprint(u64);
+
+impl AlphabetFingerprint {
+    pub fn new(
+        alphabet_symbol_mapping: &HashMap<String, TransitionKey>,
+        alphabet_anything_value: TransitionKey,
+        frozen_tokens: &HashSet<String>,
+    ) -> Self {
+        let mut symbols: Vec<(&String, &TransitionKey)> = alphabet_symbol_mapping.iter().collect();
+        symbols.sort_unstable();
+        let mut frozen_tokens: Vec<&String> = frozen_tokens.iter().collect();
+        frozen_tokens.sort_unstable();
+
+        let mut hasher = DefaultHasher::new();
+        symbols.hash(&mut hasher);
+        alphabet_anything_value.hash(&mut hasher);
+        frozen_tokens.hash(&mut hasher);
+        AlphabetFingerprint(hasher.finish())
+    }
+}
+
+/// Largest number of alphabets whose transition keys a vocabulary keeps.
+const MAX_CACHED_ALPHABETS: usize = 8;
+
+/// Transition keys of a vocabulary for the alphabets it was last used with, most recent last.
+#[derive(Debug, Default)]
+pub(crate) struct TransitionKeysCache(
+    Mutex<Vec<(AlphabetFingerprint, Arc<VocabularyTransitionKeys>)>>,
+);
+
+impl TransitionKeysCache {
+    /// Gets the transition keys of the alphabet, computing them with `compute` if they are not
+    /// cached.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        fingerprint: AlphabetFingerprint,
+        compute: impl FnOnce() -> VocabularyTransitionKeys,
+    ) -> Arc<VocabularyTransitionKeys> {
+        {
+            let mut entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(position) = entries.iter().position(|(key, _)| *key == fingerprint) {
+                let entry = entries.remove(position);
+                let keys = Arc::clone(&entry.1);
+                entries.push(entry);
+                stats::TRANSITION_KEYS_CACHE_HITS.add(1);
+                return keys;
+            }
+        }
+
+        // The lock is not held while computing, so that building indexes with other alphabets
+        // is not blocked.
+        let keys = Arc::new(compute());
+        let mut entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !entries.iter().any(|(key, _)| *key == fingerprint) {
+            if entries.len() == MAX_CACHED_ALPHABETS {
+                entries.remove(0);
+            }
+            entries.push((fingerprint, Arc::clone(&keys)));
+        }
+        keys
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl Clone for TransitionKeysCache {
+    fn clone(&self) -> Self {
+        let entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        TransitionKeysCache(Mutex::new(entries.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::FSMInfo;
+
+    #[test]
+    fn walk_fsm_final() {
+        let fsm = FSMInfo::from_regex("ab|abcd").unwrap();
+        let keys = |input: &str| {
+            get_token_transition_keys(
+                &fsm.alphabet_symbol_mapping,
+                fsm.alphabet_anything_value,
+                input,
+            )
+        };
+        let walk = |input: &str, full_match: bool| {
+            super::walk_fsm_final(
+                &fsm.transitions,
+                &fsm.finals,
+                &keys(input),
+                fsm.initial,
+                full_match,
+            )
+        };
+
+        for input in ["ab", "abc", "abcd"] {
+            let states = walk_fsm(
+                &fsm.transitions,
+                fsm.initial,
+                &fsm.finals,
+                &keys(input),
+                fsm.initial,
+                false,
+            );
+            assert_eq!(walk(input, false), states.last().copied());
+        }
+        assert!(walk("abc", false).is_some());
+        assert_eq!(walk("abc", true), None);
+        assert!(walk("abcd", true).is_some());
+        // A walk that stops early is not cut back to its last final state.
+        assert_eq!(walk("abx", false), None);
+        assert_eq!(walk("", false), None);
+    }
+
+    #[test]
+    fn frozen_tokens() {
+        // `a[^a]<eot>`, the tokens of several characters only being read when frozen.
+        let fsm = FSMInfo::new(
+            0,
+            HashSet::from([3]),
+            HashMap::from([((0, 0), 1), ((1, 2), 2), ((2, 1), 3)]),
+            2,
+            HashMap::from([("a".to_string(), 0), ("<eot>".to_string(), 1)]),
+        );
+        let vocabulary = Vocabulary::new()
+            .insert("a", 0)
+            .insert("<eot>", 1)
+            .insert("<pad>", 2)
+            .insert("é", 3)
+            .insert("b", 4);
+        let frozen_tokens: HashSet<String> = ["<eot>", "<pad>", "é"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let keys = get_vocabulary_transition_keys(
+            &fsm.alphabet_symbol_mapping,
+            fsm.alphabet_anything_value,
+            &vocabulary,
+            &frozen_tokens,
+        );
+        assert_eq!(keys["<eot>"], vec![1]);
+        assert_eq!(keys["<pad>"], Vec::<TransitionKey>::new());
+        assert_eq!(keys["é"], vec![2]);
+        assert_eq!(keys["b"], vec![2]);
+
+        let scan = |state: State| {
+            state_scan_tokens(
+                &fsm.transitions,
+                fsm.initial,
+                &fsm.finals,
+                &vocabulary,
+                &keys,
+                state,
+            )
+        };
+        assert_eq!(scan(1), BTreeSet::from([(3, 2), (4, 2)]));
+        assert_eq!(scan(2), BTreeSet::from([(1, 3)]));
+        let trie = TokenTrie::new(&vocabulary, &keys);
+        for state in 0..4 {
+            assert_eq!(trie.scan(&fsm.transitions, state), scan(state));
+        }
+    }
+
+    #[test]
+    fn token_trie() {
+        let fsm = FSMInfo::from_regex(r"(ab|ac)+[0-9]{1,3}").unwrap();
+        let vocabulary = Vocabulary::new()
+            .insert("a", 0)
+            .insert("ab", 1)
+            .insert("abac", 2)
+            .insert("ab", 3)
+            .insert("c1", 4)
+            .insert("123", 5)
+            .insert("1234", 6)
+            .insert("x", 7)
+            .insert("", 8);
+        let keys = get_vocabulary_transition_keys(
+            &fsm.alphabet_symbol_mapping,
+            fsm.alphabet_anything_value,
+            &vocabulary,
+            &HashSet::new(),
+        );
+        let trie = TokenTrie::new(&vocabulary, &keys);
+
+        let vocabulary_without_empty = Vocabulary::from_iter(
+            vocabulary
+                .iter()
+                .filter(|(token, _)| !token.is_empty())
+                .map(|(token, token_ids)| (token.clone(), token_ids.clone())),
+        );
+        for state in 0..fsm.transitions.len() as State {
+            assert_eq!(
+                trie.scan(&fsm.transitions, state),
+                state_scan_tokens(
+                    &fsm.transitions,
+                    fsm.initial,
+                    &fsm.finals,
+                    &vocabulary_without_empty,
+                    &keys,
+                    state,
+                ),
+                "state {}",
+                state
+            );
+        }
+    }
+
+    #[test]
+    fn literal_scanner() {
+        let text = r#"{"name":"abc","ids":[0,1]}"#;
+        let mut vocabulary = Vocabulary::new().insert("", 0).insert("xyz", 1);
+        for len in 1..=6 {
+            for start in 0..=text.len() - len {
+                let token_id = vocabulary.len() as TokenId;
+                vocabulary = vocabulary.insert(&text[start..start + len], token_id);
+            }
+        }
+        // `(abcd)*` is a single run, going round a cycle.
+        vocabulary = vocabulary.insert("dabc", 1000).insert("bcdabcda", 1001);
+
+        for regex in [
+            r#"\{"name":"[a-z]+","ids":\[(0|1)(,(0|1))*\]\}"#,
+            r"(abcd)*",
+        ] {
+            let fsm = FSMInfo::from_regex(regex).unwrap();
+            let keys = get_vocabulary_transition_keys(
+                &fsm.alphabet_symbol_mapping,
+                fsm.alphabet_anything_value,
+                &vocabulary,
+                &HashSet::new(),
+            );
+            let trie = TokenTrie::new(&vocabulary, &keys);
+            let literals = LiteralScanner::new(&fsm.transitions, &vocabulary, &keys);
+            let scans: Vec<_> = (0..fsm.transitions.len() as State)
+                .flat_map(|state| literals.scan(&trie, state))
+                .collect();
+            assert!(!scans.is_empty());
+            for (state, scan) in scans {
+                assert_eq!(scan, trie.scan(&fsm.transitions, state), "state {}", state);
+            }
+        }
+    }
+}
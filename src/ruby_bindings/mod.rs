@@ -0,0 +1,126 @@
+use crate::index::{FSMInfo, Index};
+use crate::json_schema;
+use crate::prelude::*;
+use magnus::scan_args::scan_args;
+use magnus::{function, method, prelude::*, Error, Ruby, Value};
+use std::collections::{HashMap, HashSet};
+
+fn to_ruby_error(e: impl Display) -> Error {
+    let ruby = Ruby::get().expect("called from a Ruby thread");
+    Error::new(ruby.exception_arg_error(), e.to_string())
+}
+
+/// `OutlinesCore.build_regex_from_schema(json, whitespace_pattern = nil)`
+fn build_regex_from_schema(args: &[Value]) -> Result<String, Error> {
+    let args = scan_args::<(String,), (Option<Option<String>>,), (), (), (), ()>(args)?;
+    let (json,) = args.required;
+    let (whitespace_pattern,) = args.optional;
+    json_schema::build_regex_from_schema(&json, whitespace_pattern.flatten().as_deref())
+        .map_err(to_ruby_error)
+}
+
+#[magnus::wrap(class = "OutlinesCore::FSMInfo", free_immediately, size)]
+struct RbFSMInfo(FSMInfo);
+
+impl RbFSMInfo {
+    fn new(
+        initial: State,
+        finals: Vec<State>,
+        transitions: HashMap<(State, TransitionKey), State>,
+        alphabet_anything_value: TransitionKey,
+        alphabet_symbol_mapping: HashMap<String, TransitionKey>,
+    ) -> Self {
+        RbFSMInfo(FSMInfo::new(
+            initial,
+            finals.into_iter().collect(),
+            transitions,
+            alphabet_anything_value,
+            alphabet_symbol_mapping,
+        ))
+    }
+
+    fn with_max_length(&self, max_length: usize) -> Self {
+        RbFSMInfo(self.0.with_max_length(max_length))
+    }
+}
+
+#[magnus::wrap(class = "OutlinesCore::Vocabulary", free_immediately, size)]
+struct RbVocabulary(Vocabulary);
+
+impl RbVocabulary {
+    fn from_hash(map: HashMap<Token, Vec<TokenId>>) -> Self {
+        RbVocabulary(Vocabulary::from(map))
+    }
+}
+
+/// Steps through the states of an index, following the conventions of the Python `RegexGuide`:
+/// the state -1 is reached once generation is over, and only the EOS token is then allowed.
+#[magnus::wrap(class = "OutlinesCore::Guide", free_immediately, size)]
+struct RbGuide {
+    index: Index,
+    eos_token_id: u32,
+}
+
+impl RbGuide {
+    fn new(
+        fsm_info: &RbFSMInfo,
+        vocabulary: &RbVocabulary,
+        eos_token_id: u32,
+        frozen_tokens: Vec<String>,
+    ) -> Result<Self, Error> {
+        let frozen_tokens: HashSet<String> = frozen_tokens.into_iter().collect();
+        let index = Index::new(&fsm_info.0, &vocabulary.0, eos_token_id, frozen_tokens)
+            .map_err(to_ruby_error)?;
+        Ok(RbGuide {
+            index,
+            eos_token_id,
+        })
+    }
+
+    fn initial_state(&self) -> i64 {
+        self.index.initial().into()
+    }
+
+    fn allowed_tokens(&self, state: i64) -> Vec<u32> {
+        u32::try_from(state)
+            .ok()
+            .and_then(|state| self.index.allowed_tokens(state))
+            .unwrap_or_else(|| vec![self.eos_token_id])
+    }
+
+    fn next_state(&self, state: i64, token_id: u32) -> i64 {
+        u32::try_from(state)
+            .ok()
+            .and_then(|state| self.index.next_state(state, token_id))
+            .map_or(-1, i64::from)
+    }
+
+    fn is_final_state(&self, state: i64) -> bool {
+        u32::try_from(state).map_or(true, |state| self.index.is_final(state))
+    }
+}
+
+#[magnus::init(name = "outlines_core")]
+fn init(ruby: &Ruby) -> Result<(), Error> {
+    let module = ruby.define_module("OutlinesCore")?;
+    module.define_module_function(
+        "build_regex_from_schema",
+        function!(build_regex_from_schema, -1),
+    )?;
+
+    let fsm_info = module.define_class("FSMInfo", ruby.class_object())?;
+    fsm_info.define_singleton_method("new", function!(RbFSMInfo::new, 5))?;
+    fsm_info.define_method("with_max_length", method!(RbFSMInfo::with_max_length, 1))?;
+
+    let vocabulary = module.define_class("Vocabulary", ruby.class_object())?;
+    vocabulary.define_singleton_method("from_hash", function!(RbVocabulary::from_hash, 1))?;
+
+    let guide = module.define_class("Guide", ruby.class_object())?;
+    guide.define_singleton_method("new", function!(RbGuide::new, 4))?;
+    guide.define_method("initial_state", method!(RbGuide::initial_state, 0))?;
+    guide.define_method("allowed_tokens", method!(RbGuide::allowed_tokens, 1))?;
+    guide.define_method("next_state", method!(RbGuide::next_state, 2))?;
+    guide.define_method("final_state?", method!(RbGuide::is_final_state, 1))?;
+
+    Ok(())
+}
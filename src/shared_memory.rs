@@ -0,0 +1,201 @@
+/// Indexes placed in named shared memory segments, to be read by several processes.
+use crate::index::{Index, IndexTable, Result};
+use memmap2::{Mmap, MmapMut};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::fd::FromRawFd;
+
+/// Index read in place from a named shared memory segment, where it is stored as the FlatBuffer
+/// of [`Index::to_flatbuffer`].
+///
+/// The segment is removed when the index which created it with [`Index::share`] is dropped; the
+/// processes which attached to it with [`Index::attach`] can keep using it until then and after.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// # use outlines_core::index::{FSMInfo, Index};
+/// # use outlines_core::vocabulary::Vocabulary;
+/// # use std::collections::HashSet;
+/// #
+/// # let fsm_info = FSMInfo::from_regex("[0-9]+").unwrap();
+/// # let vocabulary = Vocabulary::new().insert("1", 1);
+/// let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+/// let _shared = index.share("index-digits").unwrap();
+///
+/// // In a worker process:
+/// let attached = Index::attach("index-digits").unwrap();
+/// assert_eq!(attached.allowed_tokens(attached.initial()), Some(vec![1]));
+/// ```
+#[derive(Debug)]
+pub struct SharedIndex {
+    name: String,
+    map: Mmap,
+    owner: bool,
+}
+
+impl Index {
+    /// Copies the index into a new shared memory segment named `name`.
+    pub fn share(&self, name: &str) -> Result<SharedIndex> {
+        let bytes = self.to_flatbuffer();
+        let error = |source| crate::Error::SharedMemory {
+            name: name.to_string(),
+            source,
+        };
+        let file = open(name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR).map_err(error)?;
+        let map = (|| {
+            file.set_len(bytes.len() as u64)?;
+            // SAFETY: the segment has just been created, nothing else maps it yet.
+            let mut map = unsafe { MmapMut::map_mut(&file) }?;
+            map.copy_from_slice(&bytes);
+            map.make_read_only()
+        })();
+        match map {
+            Ok(map) => Ok(SharedIndex {
+                name: name.to_string(),
+                map,
+                owner: true,
+            }),
+            Err(e) => {
+                unlink(name);
+                Err(error(e))
+            }
+        }
+    }
+
+    /// Maps the index of the shared memory segment `name`, without copying it.
+    pub fn attach(name: &str) -> Result<SharedIndex> {
+        let file = open(name, libc::O_RDONLY).map_err(|source| crate::Error::SharedMemory {
+            name: name.to_string(),
+            source,
+        })?;
+        // SAFETY: the segments of indexes are only written before being shared.
+        let map = unsafe { Mmap::map(&file) }.map_err(|source| crate::Error::SharedMemory {
+            name: name.to_string(),
+            source,
+        })?;
+        IndexTable::read(&map)?;
+        Ok(SharedIndex {
+            name: name.to_string(),
+            map,
+            owner: false,
+        })
+    }
+}
+
+impl SharedIndex {
+    fn table(&self) -> IndexTable<'_> {
+        // SAFETY: the segment has been checked by `IndexTable::read` when it was mapped.
+        unsafe { flatbuffers::root_unchecked::<IndexTable>(&self.map) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn allowed_tokens(&self, state: u32) -> Option<Vec<u32>> {
+        Some(
+            self.table()
+                .transitions(state)?
+                .map(|(token_id, _)| token_id)
+                .collect(),
+        )
+    }
+
+    pub fn next_state(&self, state: u32, token_id: u32) -> Option<u32> {
+        self.table().next_state(state, token_id)
+    }
+
+    pub fn initial(&self) -> u32 {
+        self.table().initial()
+    }
+
+    pub fn is_final(&self, state: u32) -> bool {
+        self.table().is_final(state)
+    }
+
+    /// Copies the index out of the segment.
+    pub fn to_index(&self) -> Result<Index> {
+        Index::from_flatbuffer(&self.map)
+    }
+}
+
+impl Drop for SharedIndex {
+    fn drop(&mut self) {
+        if self.owner {
+            unlink(&self.name);
+        }
+    }
+}
+
+fn segment_name(name: &str) -> io::Result<CString> {
+    if name.is_empty() || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "names of segments must be non-empty and must not contain '/'",
+        ));
+    }
+    CString::new(format!("/{}", name)).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn open(name: &str, flags: libc::c_int) -> io::Result<File> {
+    let name = segment_name(name)?;
+    // SAFETY: the name is a valid C string.
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, 0o600) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor has just been opened and is owned by nothing else.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn unlink(name: &str) {
+    if let Ok(name) = segment_name(name) {
+        // SAFETY: the name is a valid C string.
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::FSMInfo;
+    use crate::vocabulary::Vocabulary;
+    use std::collections::HashSet;
+
+    #[test]
+    fn share_and_attach() {
+        let vocabulary = Vocabulary::new()
+            .insert("a", 1)
+            .insert("b", 2)
+            .insert("ab", 3);
+        let fsm_info = FSMInfo::from_regex("(ab)+").unwrap();
+        let index = Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+        let name = format!("outlines-core-test-{}", std::process::id());
+
+        let shared = index.share(&name).unwrap();
+        assert!(index.share(&name).is_err());
+        let attached = Index::attach(&name).unwrap();
+        assert_eq!(attached.initial(), index.initial());
+        for (&state, transitions) in index.index() {
+            let mut allowed = index.allowed_tokens(state).unwrap();
+            allowed.sort_unstable();
+            assert_eq!(attached.allowed_tokens(state), Some(allowed));
+            assert_eq!(attached.is_final(state), index.is_final(state));
+            for &token_id in transitions.keys() {
+                assert_eq!(
+                    attached.next_state(state, token_id),
+                    index.next_state(state, token_id)
+                );
+            }
+        }
+        assert_eq!(attached.next_state(index.initial(), 42), None);
+        assert_eq!(attached.to_index().unwrap().index(), index.index());
+
+        drop(shared);
+        assert!(Index::attach(&name).is_err());
+        assert_eq!(attached.allowed_tokens(index.initial()).unwrap().len(), 2);
+        assert!(Index::attach("bad/name").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+/// Counters of the work done by the crate in the current process, for monitoring.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Monotonic counter shared by all threads.
+pub(crate) struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub(crate) fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) static INDEXES_BUILT: Counter = Counter::new();
+pub(crate) static INDEX_BUILD_NANOS: Counter = Counter::new();
+pub(crate) static STATES_EXPANDED: Counter = Counter::new();
+pub(crate) static COMPILE_CACHE_HITS: Counter = Counter::new();
+pub(crate) static COMPILE_CACHE_MISSES: Counter = Counter::new();
+pub(crate) static TRANSITION_KEYS_CACHE_HITS: Counter = Counter::new();
+pub(crate) static MASKS_SERVED: Counter = Counter::new();
+
+/// Snapshot of the counters, polled with [`stats`].
+///
+/// The counters only grow, so that rates are the differences between two snapshots.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::prelude::*;
+/// # use outlines_core::index::{FSMInfo, Index};
+/// # use outlines_core::stats::stats;
+/// # use std::collections::HashSet;
+/// #
+/// let before = stats();
+/// let fsm_info = FSMInfo::from_regex("a+").unwrap();
+/// let vocabulary = Vocabulary::new().insert("a", 1);
+/// Index::new(&fsm_info, &vocabulary, 0, HashSet::new()).unwrap();
+/// assert!(stats().indexes_built > before.indexes_built);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Indexes built successfully.
+    pub indexes_built: u64,
+    /// Time spent building the indexes counted by `indexes_built`.
+    pub index_build_time: Duration,
+    /// States of automata whose tokens were scanned while building indexes, the failed builds
+    /// included.
+    pub states_expanded: u64,
+    /// Schemas compiled by [`crate::guide::compile`] whose automaton was cached.
+    pub compile_cache_hits: u64,
+    /// Schemas compiled by [`crate::guide::compile`] whose automaton was not cached.
+    pub compile_cache_misses: u64,
+    /// Indexes reusing the transition keys cached by their vocabulary.
+    pub transition_keys_cache_hits: u64,
+    /// Masks of allowed tokens written or applied to logits.
+    pub masks_served: u64,
+}
+
+impl Stats {
+    /// Average time spent building an index, or `None` before the first one.
+    pub fn average_build_time(&self) -> Option<Duration> {
+        let indexes_built = u32::try_from(self.indexes_built).ok()?;
+        self.index_build_time.checked_div(indexes_built)
+    }
+}
+
+/// Current value of the counters.
+pub fn stats() -> Stats {
+    Stats {
+        indexes_built: INDEXES_BUILT.get(),
+        index_build_time: Duration::from_nanos(INDEX_BUILD_NANOS.get()),
+        states_expanded: STATES_EXPANDED.get(),
+        compile_cache_hits: COMPILE_CACHE_HITS.get(),
+        compile_cache_misses: COMPILE_CACHE_MISSES.get(),
+        transition_keys_cache_hits: TRANSITION_KEYS_CACHE_HITS.get(),
+        masks_served: MASKS_SERVED.get(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_build_time() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.average_build_time(), None);
+        stats.indexes_built = 4;
+        stats.index_build_time = Duration::from_millis(10);
+        assert_eq!(
+            stats.average_build_time(),
+            Some(Duration::from_micros(2500))
+        );
+    }
+}
@@ -0,0 +1,159 @@
+/// Constraints of GitHub-flavored Markdown tables.
+use crate::index::FSMInfo;
+use crate::json_schema::{BOOLEAN, INTEGER, NUMBER};
+use anyhow::{anyhow, Result};
+
+/// Text of a cell: any character but a newline, pipes and backslashes being escaped.
+static TEXT: &str = r"([^|\\\n]|\\[|\\])*";
+
+/// Type of the cells of a column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text,
+    Integer,
+    Number,
+    Boolean,
+    /// One of a few choices.
+    Choice(Vec<String>),
+    /// A string of the regular expression, which should match neither pipes nor newlines.
+    Regex(String),
+}
+
+impl Cell {
+    fn to_regex(&self) -> String {
+        match self {
+            Cell::Text => TEXT.to_string(),
+            Cell::Integer => INTEGER.to_string(),
+            Cell::Number => NUMBER.to_string(),
+            Cell::Boolean => BOOLEAN.to_string(),
+            Cell::Choice(choices) => {
+                let choices: Vec<String> = choices
+                    .iter()
+                    .map(|choice| regex::escape(&choice.replace('|', r"\|")))
+                    .collect();
+                format!("({})", choices.join("|"))
+            }
+            Cell::Regex(regex) => format!("({})", regex),
+        }
+    }
+}
+
+/// Markdown table with a header of column names, a separator row and a bounded number of data
+/// rows, each ended by a newline.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::table::{Cell, Table};
+/// #
+/// let table = Table::new()
+///     .column("Name", Cell::Text)
+///     .column("Age", Cell::Integer)
+///     .column("Status", Cell::Choice(vec!["active".into(), "retired".into()]))
+///     .rows(1, Some(10));
+///
+/// let regex = regex::Regex::new(&format!("^{}$", table.to_regex().unwrap())).unwrap();
+/// assert!(regex.is_match(
+///     "| Name | Age | Status |\n| --- | --- | --- |\n| Ada | 36 | retired |\n"
+/// ));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    columns: Vec<(String, Cell)>,
+    min_rows: usize,
+    max_rows: Option<usize>,
+}
+
+impl Table {
+    /// Creates a table without columns, with any number of rows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a column, whose name is written in the header.
+    pub fn column(mut self, name: impl Into<String>, cell: Cell) -> Self {
+        self.columns.push((name.into(), cell));
+        self
+    }
+
+    /// Bounds the number of data rows, with no upper bound when `max_rows` is `None`.
+    pub fn rows(mut self, min_rows: usize, max_rows: Option<usize>) -> Self {
+        self.min_rows = min_rows;
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Compiles the table into a single regular expression.
+    pub fn to_regex(&self) -> Result<String> {
+        if self.columns.is_empty() {
+            return Err(anyhow!("A table needs at least one column"));
+        }
+        if let Some((name, _)) = self.columns.iter().find(|(name, _)| name.contains('\n')) {
+            return Err(anyhow!("The name of column {:?} contains a newline", name));
+        }
+        if let Some(max_rows) = self.max_rows.filter(|&max_rows| max_rows < self.min_rows) {
+            return Err(anyhow!(
+                "The maximum number of rows {} is lower than the minimum {}",
+                max_rows,
+                self.min_rows
+            ));
+        }
+
+        let row = |cells: Vec<String>| format!(r"\| {} \|\n", cells.join(r" \| "));
+        let header = row(self
+            .columns
+            .iter()
+            .map(|(name, _)| regex::escape(&name.replace('|', r"\|")))
+            .collect());
+        let separator = row(vec!["---".to_string(); self.columns.len()]);
+        let data = row(self
+            .columns
+            .iter()
+            .map(|(_, cell)| cell.to_regex())
+            .collect());
+        let max_rows = self.max_rows.map(|max| max.to_string()).unwrap_or_default();
+        Ok(format!(
+            "{}{}({}){{{},{}}}",
+            header, separator, data, self.min_rows, max_rows
+        ))
+    }
+
+    /// Compiles the table into a byte-level automaton.
+    pub fn to_fsm(&self) -> Result<FSMInfo> {
+        Ok(FSMInfo::from_regex(&self.to_regex()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_regex() {
+        let table = Table::new()
+            .column("Item | unit", Cell::Text)
+            .column("Price", Cell::Number)
+            .column("Paid", Cell::Boolean)
+            .column("Code", Cell::Regex("[A-Z]{2}".to_string()))
+            .rows(1, Some(2));
+        let regex = regex::Regex::new(&format!("^{}$", table.to_regex().unwrap())).unwrap();
+        let header = "| Item \\| unit | Price | Paid | Code |\n| --- | --- | --- | --- |\n";
+
+        assert!(regex.is_match(&format!("{header}| Tea \\| 1kg | 4.5 | true | TE |\n")));
+        assert!(regex.is_match(&format!(
+            "{header}| a | 1 | false | AB |\n| b | -2e+3 | true | CD |\n"
+        )));
+        assert!(!regex.is_match(header));
+        assert!(!regex.is_match(&format!("{header}| a | b | true | AB |\n")));
+        assert!(!regex.is_match(&format!("{header}| a|b | 1 | true | AB |\n")));
+        assert!(!regex.is_match(&format!("{header}{}", "| a | 1 | true | AB |\n".repeat(3))));
+
+        assert!(Table::new().to_regex().is_err());
+        assert!(Table::new()
+            .column("a", Cell::Text)
+            .rows(2, Some(1))
+            .to_regex()
+            .is_err());
+        assert!(Table::new().column("a\nb", Cell::Text).to_regex().is_err());
+    }
+}
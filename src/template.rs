@@ -0,0 +1,330 @@
+/// Constraints made of fixed text with typed holes.
+use crate::index::FSMInfo;
+use crate::json_schema;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Regex(String),
+    Schema(String),
+    Choice(Vec<String>),
+    FreeText {
+        sentinel: String,
+        max_length: Option<usize>,
+    },
+}
+
+/// Literal text interleaved with holes, each filled by the strings of a regular expression, the
+/// JSON of a schema or one of a few choices.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use outlines_core::template::Template;
+/// #
+/// let template = Template::new()
+///     .text("Name: ")
+///     .regex("[A-Z][a-z]+")
+///     .text("\nAge: ")
+///     .schema(r#"{"type": "integer"}"#)
+///     .text("\nEmployed: ")
+///     .choice(["yes", "no"]);
+///
+/// assert!(template.to_regex().is_ok());
+/// ```
+///
+/// A free text ended by a sentinel lets a model reason before writing its answer:
+///
+/// ```rust
+/// # use outlines_core::template::Template;
+/// #
+/// let template = Template::new()
+///     .text("<think>")
+///     .free_text("</think>", Some(1024))
+///     .schema(r#"{"type": "object", "properties": {"answer": {"type": "integer"}}}"#);
+///
+/// assert!(template.to_fsm().is_ok());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    segments: Vec<Segment>,
+    options: json_schema::Options,
+}
+
+impl Template {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends text to write as is.
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.segments.push(Segment::Text(text.into()));
+        self
+    }
+
+    /// Appends a hole filled by a string of the regular expression.
+    pub fn regex(mut self, regex: impl Into<String>) -> Self {
+        self.segments.push(Segment::Regex(regex.into()));
+        self
+    }
+
+    /// Appends a hole filled by the JSON of an instance of the schema.
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.segments.push(Segment::Schema(schema.into()));
+        self
+    }
+
+    /// Appends a hole filled by one of the choices.
+    pub fn choice<T: Into<String>>(mut self, choices: impl IntoIterator<Item = T>) -> Self {
+        self.segments.push(Segment::Choice(
+            choices.into_iter().map(Into::into).collect(),
+        ));
+        self
+    }
+
+    /// Appends free text ended by the first occurrence of the sentinel, of at most `max_length`
+    /// bytes before the sentinel when given.
+    ///
+    /// A bounded free text has no reasonably sized regular expression, so a template with one
+    /// can only be compiled with [`Template::to_fsm`].
+    pub fn free_text(mut self, sentinel: impl Into<String>, max_length: Option<usize>) -> Self {
+        self.segments.push(Segment::FreeText {
+            sentinel: sentinel.into(),
+            max_length,
+        });
+        self
+    }
+
+    /// Options the schemas of the template are compiled with.
+    pub fn options(mut self, options: json_schema::Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Compiles the template into a single regular expression.
+    pub fn to_regex(&self) -> Result<String> {
+        Self::segments_regex(&self.segments, &self.options)
+    }
+
+    /// Compiles the template into a byte-level automaton.
+    pub fn to_fsm(&self) -> Result<FSMInfo> {
+        let mut fsm: Option<FSMInfo> = None;
+        let mut concat = |part: FSMInfo| {
+            fsm = Some(match fsm.take() {
+                Some(fsm) => fsm.concat(&part),
+                None => part,
+            })
+        };
+        // Runs of segments between the bounded free texts are compiled as regular expressions,
+        // the free texts as automata restricted to their length.
+        let mut start = 0;
+        for (i, segment) in self.segments.iter().enumerate() {
+            if let Segment::FreeText {
+                sentinel,
+                max_length: Some(max_length),
+            } = segment
+            {
+                if start < i {
+                    let regex = Self::segments_regex(&self.segments[start..i], &self.options)?;
+                    concat(FSMInfo::from_regex(&regex)?);
+                }
+                let free_text = FSMInfo::from_regex(&free_text_regex(sentinel)?)?;
+                concat(free_text.with_max_length(max_length + sentinel.len()));
+                start = i + 1;
+            }
+        }
+        if start < self.segments.len() || start == 0 {
+            let regex = Self::segments_regex(&self.segments[start..], &self.options)?;
+            concat(FSMInfo::from_regex(&regex)?);
+        }
+        Ok(fsm.expect("an automaton is compiled for the last segments"))
+    }
+
+    fn segments_regex(segments: &[Segment], options: &json_schema::Options) -> Result<String> {
+        let mut regex = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => regex.push_str(&regex::escape(text)),
+                Segment::Regex(hole) => regex.push_str(&format!("({})", hole)),
+                Segment::Schema(schema) => {
+                    let hole = json_schema::build_regex_from_schema_with_options(schema, options)?;
+                    regex.push_str(&format!("({})", hole));
+                }
+                Segment::Choice(choices) => {
+                    let choices: Vec<String> =
+                        choices.iter().map(|choice| regex::escape(choice)).collect();
+                    regex.push_str(&format!("({})", choices.join("|")));
+                }
+                Segment::FreeText {
+                    sentinel,
+                    max_length: None,
+                } => regex.push_str(&format!("({})", free_text_regex(sentinel)?)),
+                Segment::FreeText { .. } => {
+                    return Err(anyhow!(
+                        "A free text of bounded length can only be compiled into an automaton"
+                    ))
+                }
+            }
+        }
+        Ok(regex)
+    }
+}
+
+/// Regular expression of the strings whose only occurrence of the sentinel is at their end.
+///
+/// The expression is read off the automaton of the Knuth-Morris-Pratt search of the sentinel,
+/// whose state `i` is the length of the longest prefix of the sentinel ending the text read so
+/// far, by eliminating its states one after the other.
+fn free_text_regex(sentinel: &str) -> Result<String> {
+    let sentinel: Vec<char> = sentinel.chars().collect();
+    let m = sentinel.len();
+    if m == 0 {
+        return Err(anyhow!("The sentinel ending a free text is empty"));
+    }
+    let mut symbols = sentinel.clone();
+    symbols.sort_unstable();
+    symbols.dedup();
+
+    // `next[i][s]` is the state reached from `i` by reading `symbols[s]`, any other character
+    // leading back to the state 0.
+    let mut next = vec![vec![0; symbols.len()]; m];
+    let symbol = |c: char| {
+        symbols
+            .binary_search(&c)
+            .expect("the symbol is in the sentinel")
+    };
+    next[0][symbol(sentinel[0])] = 1;
+    let mut fallback = 0;
+    for i in 1..m {
+        next[i] = next[fallback].clone();
+        next[i][symbol(sentinel[i])] = i + 1;
+        fallback = next[fallback][symbol(sentinel[i])];
+    }
+
+    let escape = |c: char| regex::escape(&c.to_string());
+    let mut edges: Vec<Vec<Option<String>>> = vec![vec![None; m + 1]; m + 1];
+    for (i, next) in next.iter().enumerate() {
+        let mut targets: BTreeMap<usize, String> = BTreeMap::new();
+        for (&c, &j) in symbols.iter().zip(next) {
+            if j != 0 {
+                targets.entry(j).or_default().push_str(&escape(c));
+            }
+        }
+        let others: String = targets.values().map(String::as_str).collect();
+        edges[i][0] = Some(format!("[^{}]", others));
+        for (j, chars) in targets {
+            edges[i][j] = Some(match chars.chars().count() {
+                1 => chars,
+                _ => format!("[{}]", chars),
+            });
+        }
+    }
+
+    let union = |a: Option<String>, b: String| match a {
+        Some(a) => format!("({}|{})", a, b),
+        None => b,
+    };
+    for k in (1..m).rev() {
+        let through = match edges[k][k].take() {
+            Some(loops) => format!("({})*", loops),
+            None => String::new(),
+        };
+        for i in 0..k {
+            let Some(into) = edges[i][k].take() else {
+                continue;
+            };
+            for j in (0..k).chain([m]) {
+                if let Some(out) = &edges[k][j] {
+                    let path = format!("{}{}{}", into, through, out);
+                    edges[i][j] = Some(union(edges[i][j].take(), path));
+                }
+            }
+        }
+    }
+    let loops = edges[0][0].take().map(|loops| format!("({})*", loops));
+    let end = edges[0][m].take().expect("the sentinel can be read");
+    Ok(format!("{}{}", loops.unwrap_or_default(), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::{get_token_transition_keys, walk_fsm_final};
+
+    #[test]
+    fn to_regex() {
+        let template = Template::new()
+            .text("Total (USD): ")
+            .schema(r#"{"type": "integer"}"#)
+            .text(", paid: ")
+            .choice(["yes", "no"])
+            .regex("!*");
+
+        assert_eq!(
+            template.to_regex().unwrap(),
+            r"Total \(USD\): ((-)?(0|[1-9][0-9]*)), paid: (yes|no)(!*)"
+        );
+        assert!(template.to_fsm().unwrap().equivalent(
+            &FSMInfo::from_regex(r"Total \(USD\): -?(0|[1-9][0-9]*), paid: (yes|no)!*").unwrap()
+        ));
+        assert!(Template::new().schema("{").to_regex().is_err());
+    }
+
+    #[test]
+    fn free_text() {
+        // Every string of up to 7 letters among `a`, `b` and `c`.
+        let mut inputs = vec![String::new()];
+        let mut start = 0;
+        for _ in 0..7 {
+            let end = inputs.len();
+            for i in start..end {
+                for c in ['a', 'b', 'c'] {
+                    inputs.push(format!("{}{}", inputs[i], c));
+                }
+            }
+            start = end;
+        }
+        for sentinel in ["aab", "abab", "aaa", "cab"] {
+            let regex =
+                regex::Regex::new(&format!("^{}$", free_text_regex(sentinel).unwrap())).unwrap();
+            for input in &inputs {
+                let expected = input
+                    .find(sentinel)
+                    .is_some_and(|i| i + sentinel.len() == input.len());
+                assert_eq!(regex.is_match(input), expected, "{sentinel} {input}");
+            }
+        }
+        assert!(free_text_regex("").is_err());
+
+        let template = Template::new()
+            .text("<think>")
+            .free_text("</think>", None)
+            .choice(["yes", "no"]);
+        let regex = regex::Regex::new(&format!("^{}$", template.to_regex().unwrap())).unwrap();
+        assert!(regex.is_match("<think>a <b> c\n</think>yes"));
+        assert!(!regex.is_match("<think></think></think>yes"));
+
+        let template = Template::new()
+            .text("<")
+            .free_text(">", Some(3))
+            .regex("[0-9]");
+        assert!(template.to_regex().is_err());
+        let fsm = template.to_fsm().unwrap();
+        let accepts = |input: &str| {
+            let symbols: String = input.bytes().map(crate::fsm::byte_symbol).collect();
+            let keys = get_token_transition_keys(
+                &fsm.alphabet_symbol_mapping,
+                fsm.alphabet_anything_value,
+                &symbols,
+            );
+            walk_fsm_final(&fsm.transitions, &fsm.finals, &keys, fsm.initial, true).is_some()
+        };
+        assert!(accepts("<>1"));
+        assert!(accepts("<abc>2"));
+        assert!(!accepts("<abcd>2"));
+        assert!(!accepts("<é>>2"));
+        assert!(!accepts("<éé>2"));
+    }
+}
@@ -0,0 +1,216 @@
+/// Incremental validation of generated text.
+use crate::index::FSMInfo;
+use crate::prelude::*;
+use crate::regex::get_token_transition_keys;
+
+/// Checks text against an automaton as it is generated, one chunk at a time.
+///
+/// The text is viable as long as it is the prefix of a string the automaton accepts.
+#[derive(Debug)]
+pub struct PartialValidator {
+    fsm_info: FSMInfo,
+    byte_level: bool,
+    /// States from which a final state can be reached.
+    live_states: HashSet<State>,
+    state: Option<State>,
+    valid_up_to: usize,
+}
+
+impl PartialValidator {
+    pub fn new(fsm_info: FSMInfo) -> Self {
+        let live_states = fsm_info.distances_to_final().into_keys().collect();
+        let mut validator = Self {
+            fsm_info,
+            byte_level: false,
+            live_states,
+            state: None,
+            valid_up_to: 0,
+        };
+        validator.reset();
+        validator
+    }
+
+    /// Reads the text as UTF-8 bytes, for automata built by `make_byte_level_fsm` where bytes
+    /// outside of ASCII are written `\0XX`.
+    pub fn byte_level(mut self, byte_level: bool) -> Self {
+        self.byte_level = byte_level;
+        self
+    }
+
+    /// Consumes the next chunk of text, and returns whether the text read so far is still
+    /// viable.
+    pub fn feed(&mut self, text: &str) -> bool {
+        for c in text.chars() {
+            let Some(state) = self.state else {
+                return false;
+            };
+            self.state = self
+                .symbols(c)
+                .iter()
+                .try_fold(state, |state, key| {
+                    self.fsm_info.transitions.get(&(state, *key)).copied()
+                })
+                .filter(|state| self.live_states.contains(state));
+            if self.state.is_some() {
+                self.valid_up_to += 1;
+            }
+        }
+        self.is_viable()
+    }
+
+    fn symbols(&self, c: char) -> Vec<TransitionKey> {
+        let mut buffer = [0; 4];
+        let text = if self.byte_level && !c.is_ascii() {
+            c.encode_utf8(&mut buffer)
+                .bytes()
+                .map(|byte| format!("\0{:02X}", byte))
+                .collect()
+        } else {
+            c.to_string()
+        };
+        get_token_transition_keys(
+            &self.fsm_info.alphabet_symbol_mapping,
+            self.fsm_info.alphabet_anything_value,
+            &text,
+        )
+    }
+
+    /// Whether the text read so far is the prefix of an accepted string.
+    pub fn is_viable(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Whether the text read so far is accepted.
+    pub fn is_final(&self) -> bool {
+        self.state
+            .is_some_and(|state| self.fsm_info.finals.contains(&state))
+    }
+
+    /// Current state of the automaton, `None` once the text is no longer viable.
+    pub fn state(&self) -> Option<State> {
+        self.state
+    }
+
+    /// Number of characters read before the text stopped being viable.
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+
+    /// Symbols of the alphabet that can come next, in alphabetical order. Whether characters
+    /// outside of the alphabet can come next is given by [`PartialValidator::allows_anything_else`].
+    pub fn allowed_symbols(&self) -> Vec<&str> {
+        let mut symbols: Vec<&str> = self
+            .fsm_info
+            .alphabet_symbol_mapping
+            .iter()
+            .filter(|(_, key)| self.allows(**key))
+            .map(|(symbol, _)| symbol.as_str())
+            .collect();
+        symbols.sort_unstable();
+        symbols
+    }
+
+    /// Whether a character outside of the automaton's alphabet can come next.
+    pub fn allows_anything_else(&self) -> bool {
+        self.allows(self.fsm_info.alphabet_anything_value)
+    }
+
+    fn allows(&self, key: TransitionKey) -> bool {
+        self.state
+            .and_then(|state| self.fsm_info.transitions.get(&(state, key)))
+            .is_some_and(|next_state| self.live_states.contains(next_state))
+    }
+
+    /// Shortest text completing the text read so far into an accepted string, e.g. to repair an
+    /// output truncated by a token limit. `None` if the text is no longer viable.
+    pub fn completion(&self) -> Option<String> {
+        let completion = self.fsm_info.shortest_completion(self.state?)?;
+        if !self.byte_level {
+            return Some(completion);
+        }
+        let mut bytes = Vec::new();
+        let mut chars = completion.chars();
+        while let Some(c) = chars.next() {
+            if c == '\0' {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            } else {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    /// Starts over with an empty text.
+    pub fn reset(&mut self) {
+        let initial = self.fsm_info.initial;
+        self.state = Some(initial).filter(|state| self.live_states.contains(state));
+        self.valid_up_to = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Automaton of `a*b|é`, over the alphabet `{a: 0, b: 1, é: 2}`.
+    fn fsm_info() -> FSMInfo {
+        FSMInfo::new(
+            0,
+            HashSet::from([1]),
+            HashMap::from([((0, 0), 0), ((0, 1), 1), ((0, 2), 1), ((1, 0), 2)]),
+            3,
+            HashMap::from([
+                ("a".to_string(), 0),
+                ("b".to_string(), 1),
+                ("é".to_string(), 2),
+            ]),
+        )
+    }
+
+    #[test]
+    fn feed() {
+        let mut validator = PartialValidator::new(fsm_info());
+        assert!(validator.is_viable());
+        assert_eq!(validator.allowed_symbols(), vec!["a", "b", "é"]);
+
+        assert!(validator.feed("aa"));
+        assert!(!validator.is_final());
+        assert_eq!(validator.completion().as_deref(), Some("b"));
+        assert!(validator.feed("b"));
+        assert!(validator.is_final());
+        assert_eq!(validator.state(), Some(1));
+        // State 2 is a dead end.
+        assert!(validator.allowed_symbols().is_empty());
+        assert!(!validator.allows_anything_else());
+
+        assert!(!validator.feed("ab"));
+        assert_eq!(validator.valid_up_to(), 3);
+        assert_eq!(validator.state(), None);
+        assert_eq!(validator.completion(), None);
+
+        validator.reset();
+        assert!(validator.feed("é"));
+        assert!(validator.is_final());
+    }
+
+    #[test]
+    fn byte_level() {
+        // `é` is encoded as 0xC3 0xA9 in UTF-8.
+        let fsm_info = FSMInfo::new(
+            0,
+            HashSet::from([2]),
+            HashMap::from([((0, 0), 1), ((1, 1), 2)]),
+            2,
+            HashMap::from([("\0C3".to_string(), 0), ("\0A9".to_string(), 1)]),
+        );
+        let mut validator = PartialValidator::new(fsm_info).byte_level(true);
+
+        assert!(validator.feed("é"));
+        assert!(validator.is_final());
+
+        validator.reset();
+        assert_eq!(validator.completion().as_deref(), Some("é"));
+    }
+}
@@ -1,7 +1,19 @@
+use crate::fsm::byte_symbols;
 use crate::prelude::*;
+use crate::regex::{
+    get_vocabulary_transition_keys, AlphabetFingerprint, TransitionKeysCache,
+    VocabularyTransitionKeys,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
 
 /// Vocabulary of an LLM.
 ///
+/// Tokens are written with the symbols of the automata they are walked on. Tokens given as bytes,
+/// which may not be valid UTF-8 on their own, are written with the symbols of
+/// [`crate::fsm::byte_symbol`].
+///
 /// ## Examples
 ///
 /// ```rust
@@ -11,10 +23,22 @@ use crate::prelude::*;
 ///     .insert("blah", 0)
 ///     .insert("1a", 1)
 ///     .insert("2", 2)
-///     .insert("0", 3);
+///     .insert("0", 3)
+///     .insert_bytes(b"\xC3", 4);
+/// assert_eq!(vocabulary["\0C3"], &[4]);
 /// ```
 #[derive(Clone, Debug, Default)]
-pub struct Vocabulary(pub(crate) HashMap<Token, Vec<TokenId>>);
+pub struct Vocabulary {
+    tokens: HashMap<Token, Vec<TokenId>>,
+    /// Transition keys of the tokens, kept across the indexes built with the vocabulary.
+    transition_keys: TransitionKeysCache,
+    fingerprint: OnceLock<VocabularyFingerprint>,
+}
+
+/// Hash of the tokens of a vocabulary and of their identifiers, telling vocabularies apart
+/// without comparing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VocabularyFingerprint(u64);
 
 impl Vocabulary {
     /// Creates an empty vocabulary.
@@ -30,6 +54,12 @@ impl Vocabulary {
         self
     }
 
+    /// Inserts a token given as bytes to the vocabulary with the specified identifier.
+    pub fn insert_bytes(mut self, token: impl AsRef<[u8]>, id: TokenId) -> Vocabulary {
+        self.insert_bytes_in_place(token, id);
+        self
+    }
+
     /// Extends the vocabulary with tokens and their identifiers.
     pub fn extend<T: Into<Token>, I: IntoIterator<Item = TokenId>>(
         mut self,
@@ -44,7 +74,14 @@ impl Vocabulary {
     /// Inserts a token to the vocabulary with the specified identifier, in place.
     pub fn insert_in_place(&mut self, token: impl Into<Token>, id: TokenId) {
         let token = token.into();
-        self.0.entry(token).or_default().push(id);
+        self.transition_keys.clear();
+        self.fingerprint.take();
+        self.tokens.entry(token).or_default().push(id);
+    }
+
+    /// Inserts a token given as bytes to the vocabulary with the specified identifier, in place.
+    pub fn insert_bytes_in_place(&mut self, token: impl AsRef<[u8]>, id: TokenId) {
+        self.insert_in_place(byte_symbols(token.as_ref()), id);
     }
 
     /// Extends the vocabulary with tokens and their identifiers, in place.
@@ -52,18 +89,59 @@ impl Vocabulary {
         &mut self,
         tokens_and_ids: impl IntoIterator<Item = (T, I)>,
     ) {
+        self.transition_keys.clear();
+        self.fingerprint.take();
         for (token, ids) in tokens_and_ids.into_iter() {
             let token = token.into();
-            self.0.entry(token).or_default().extend(ids);
+            self.tokens.entry(token).or_default().extend(ids);
         }
     }
 }
 
+impl Vocabulary {
+    /// Gets the transition keys of the tokens for the alphabet of an automaton, reusing the
+    /// ones computed for an identical alphabet by a previous call.
+    pub fn transition_keys(
+        &self,
+        alphabet_symbol_mapping: &HashMap<String, TransitionKey>,
+        alphabet_anything_value: TransitionKey,
+        frozen_tokens: &HashSet<String>,
+    ) -> Arc<VocabularyTransitionKeys> {
+        let fingerprint = AlphabetFingerprint::new(
+            alphabet_symbol_mapping,
+            alphabet_anything_value,
+            frozen_tokens,
+        );
+        self.transition_keys.get_or_insert_with(fingerprint, || {
+            get_vocabulary_transition_keys(
+                alphabet_symbol_mapping,
+                alphabet_anything_value,
+                self,
+                frozen_tokens,
+            )
+        })
+    }
+}
+
+impl Vocabulary {
+    /// Fingerprint of the tokens and of their identifiers, computed once until the vocabulary
+    /// changes.
+    pub fn fingerprint(&self) -> VocabularyFingerprint {
+        *self.fingerprint.get_or_init(|| {
+            let mut tokens: Vec<(&Token, &Vec<TokenId>)> = self.tokens.iter().collect();
+            tokens.sort_unstable();
+            let mut hasher = DefaultHasher::new();
+            tokens.hash(&mut hasher);
+            VocabularyFingerprint(hasher.finish())
+        })
+    }
+}
+
 impl Deref for Vocabulary {
     type Target = HashMap<Token, Vec<TokenId>>;
 
     fn deref(&self) -> &HashMap<Token, Vec<TokenId>> {
-        &self.0
+        &self.tokens
     }
 }
 
@@ -82,7 +160,11 @@ impl Display for Vocabulary {
 
 impl From<HashMap<Token, Vec<TokenId>>> for Vocabulary {
     fn from(map: HashMap<Token, Vec<TokenId>>) -> Vocabulary {
-        Vocabulary(map)
+        Vocabulary {
+            tokens: map,
+            transition_keys: TransitionKeysCache::default(),
+            fingerprint: OnceLock::new(),
+        }
     }
 }
 
@@ -99,6 +181,7 @@ where
 #[cfg(test)]
 mod tests {
     use crate::prelude::*;
+    use std::sync::Arc;
 
     #[test]
     fn insert() {
@@ -130,4 +213,70 @@ mod tests {
         assert_eq!(vocabulary["2"], &[2]);
         assert_eq!(vocabulary["0"], &[3]);
     }
+
+    #[test]
+    fn insert_bytes() {
+        let vocabulary = Vocabulary::new()
+            .insert_bytes(b"a\xF0", 0)
+            .insert_bytes("é", 1)
+            .insert("\0C3\0A9", 2);
+
+        assert_eq!(vocabulary.len(), 2);
+        assert_eq!(vocabulary["a\0F0"], &[0]);
+        assert_eq!(vocabulary["\0C3\0A9"], &[1, 2]);
+
+        let mapping: HashMap<String, TransitionKey> = (0..=u8::MAX)
+            .map(|byte| (crate::fsm::byte_symbol(byte), byte as TransitionKey))
+            .collect();
+        let keys = vocabulary.transition_keys(&mapping, 256, &HashSet::new());
+        assert_eq!(keys["a\0F0"], vec![0x61, 0xF0]);
+        assert_eq!(keys["\0C3\0A9"], vec![0xC3, 0xA9]);
+    }
+
+    #[test]
+    fn transition_keys() {
+        let mut vocabulary = Vocabulary::new().insert("ab", 0).insert("b", 1);
+        let mapping = HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)]);
+        let frozen_tokens = HashSet::new();
+
+        let keys = vocabulary.transition_keys(&mapping, 2, &frozen_tokens);
+        assert_eq!(keys["ab"], vec![0, 1]);
+        assert_eq!(keys["b"], vec![1]);
+        assert!(Arc::ptr_eq(
+            &keys,
+            &vocabulary.transition_keys(&mapping, 2, &frozen_tokens)
+        ));
+
+        let other_mapping = HashMap::from([("a".to_string(), 0)]);
+        let other_keys = vocabulary.transition_keys(&other_mapping, 1, &frozen_tokens);
+        assert_eq!(other_keys["ab"], vec![0, 1]);
+        assert!(!Arc::ptr_eq(&keys, &other_keys));
+
+        vocabulary.insert_in_place("c", 2);
+        let keys = vocabulary.transition_keys(&mapping, 2, &frozen_tokens);
+        assert_eq!(keys["c"], vec![2]);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let mut vocabulary = Vocabulary::new().insert("a", 0).insert("b", 1);
+        let fingerprint = vocabulary.fingerprint();
+        assert_eq!(
+            fingerprint,
+            Vocabulary::new()
+                .insert("b", 1)
+                .insert("a", 0)
+                .fingerprint()
+        );
+        assert_ne!(
+            fingerprint,
+            Vocabulary::new()
+                .insert("a", 1)
+                .insert("b", 0)
+                .fingerprint()
+        );
+
+        vocabulary.insert_in_place("c", 2);
+        assert_ne!(vocabulary.fingerprint(), fingerprint);
+    }
 }
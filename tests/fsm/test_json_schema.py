@@ -1,6 +1,8 @@
+import asyncio
+import gzip
 import json
 import re
-from typing import Literal, Union
+from typing import Literal, Optional, Union
 
 import interegular
 import pytest
@@ -8,17 +10,42 @@ from outlines_core.fsm.json_schema import (
     BOOLEAN,
     DATE,
     DATE_TIME,
+    EMAIL,
+    HOSTNAME,
+    IDN_EMAIL,
+    IDN_HOSTNAME,
     INTEGER,
+    IRI,
+    JSON_POINTER,
     NULL,
     NUMBER,
+    PHONE,
+    RELATIVE_JSON_POINTER,
+    SEMVER,
     STRING,
     STRING_INNER,
     TIME,
+    URI,
     UUID,
     WHITESPACE,
+    SchemaBuilder,
+    build_fsm_from_schema,
+    build_jsonl_regex_from_schema,
+    build_regex_from_json5,
+    build_regex_from_model,
+    build_regex_from_msgspec,
     build_regex_from_schema,
+    build_regex_from_schema_file,
+    build_regex_from_yaml,
     to_regex,
 )
+from outlines_core.fsm.outlines_core_rs import (
+    CancellationToken,
+    PartialValidator,
+    Vocabulary,
+    compile_async,
+)
+from outlines_core.fsm.outlines_core_rs import compile as compile_schema
 from pydantic import BaseModel, Field, constr
 
 
@@ -288,6 +315,30 @@ def test_match_number(pattern, does_match):
                 ('{ "count": 100000 }', False),
             ],
         ),
+        # integer with minimum and exclusive maximum
+        (
+            {
+                "title": "Foo",
+                "type": "object",
+                "properties": {
+                    "count": {
+                        "title": "Count",
+                        "type": "integer",
+                        "minimum": 1,
+                        "exclusiveMaximum": 13,
+                    }
+                },
+                "required": ["count"],
+            },
+            '\\{[ ]?"count"[ ]?:[ ]?([1-9]|1[0-2])[ ]?\\}',
+            [
+                ('{ "count": 0 }', False),
+                ('{ "count": 1 }', True),
+                ('{ "count": 12 }', True),
+                ('{ "count": 13 }', False),
+                ('{ "count": -1 }', False),
+            ],
+        ),
         # number
         (
             {
@@ -526,10 +577,32 @@ def test_match_number(pattern, does_match):
         (
             {
                 "title": "Foo",
-                "allOf": [{"type": "string"}, {"type": "integer"}],
+                "allOf": [{"type": "string"}, {"maxLength": 3}],
             },
-            rf"({STRING}{INTEGER})",
-            [('"a"1', True), ('"a"', False), ('"1"', False)],
+            rf'"{STRING_INNER}{{0,3}}"',
+            [('"abc"', True), ('"abcd"', False), ("1", False)],
+        ),
+        # allOf merging properties and required lists
+        (
+            {
+                "title": "Foo",
+                "allOf": [
+                    {
+                        "type": "object",
+                        "properties": {"a": {"type": "integer"}},
+                        "required": ["a"],
+                    },
+                    {"properties": {"b": {"type": "string"}}, "required": ["b"]},
+                ],
+            },
+            rf'\{{{WHITESPACE}"a"{WHITESPACE}:{WHITESPACE}{INTEGER}{WHITESPACE},{WHITESPACE}"b"{WHITESPACE}:{WHITESPACE}{STRING}{WHITESPACE}\}}',
+            [('{"a": 1, "b": "x"}', True), ('{"a": 1}', False)],
+        ),
+        # allOf intersecting enums
+        (
+            {"title": "Foo", "allOf": [{"enum": [1, 2, 3]}, {"enum": [2, 3, 4]}]},
+            r"(2|3)",
+            [("2", True), ("3", True), ("1", False), ("4", False)],
         ),
         # Tuple / prefixItems
         (
@@ -716,12 +789,15 @@ def test_match_number(pattern, does_match):
                 "title": "Character",
                 "type": "object",
             },
-            f'\\{{([ ]?"name"[ ]?:[ ]?({STRING}|null)([ ]?,[ ]?"age"[ ]?:[ ]?({INTEGER}|null))?([ ]?,[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?|([ ]?"name"[ ]?:[ ]?({STRING}|null)[ ]?,)?[ ]?"age"[ ]?:[ ]?({INTEGER}|null)([ ]?,[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?|([ ]?"name"[ ]?:[ ]?({STRING}|null)[ ]?,)?([ ]?"age"[ ]?:[ ]?({INTEGER}|null)[ ]?,)?[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?[ ]?\\}}',
+            f'\\{{([ ]?"name"[ ]?:[ ]?({STRING}|null)([ ]?,[ ]?"age"[ ]?:[ ]?({INTEGER}|null))?([ ]?,[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?|[ ]?"age"[ ]?:[ ]?({INTEGER}|null)([ ]?,[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?|[ ]?"strength"[ ]?:[ ]?({INTEGER}|null))?[ ]?\\}}',
             [
                 ('{ "name" : "Player" }', True),
                 ('{ "name" : "Player", "age" : 10, "strength" : 10 }', True),
                 ('{ "age" : 10, "strength" : 10 }', True),
+                ('{ "strength" : 10 }', True),
                 ("{ }", True),
+                ('{ "strength" : 10, "age" : 10 }', False),
+                ('{ , "age" : 10 }', False),
             ],
         ),
     ],
@@ -803,6 +879,132 @@ def test_match(schema, regex, examples):
                 ('"15:30:00+01:00"', False),  # incorrect separator
             ],
         ),
+        # HOSTNAME
+        (
+            {"title": "Foo", "type": "string", "format": "hostname"},
+            HOSTNAME,
+            [
+                ('"example.com"', True),
+                ('"xn--bcher-kva.example"', True),  # punycode
+                ('"bücher.example"', False),
+                ('"-example.com"', False),
+                ('"example..com"', False),
+            ],
+        ),
+        # IDN-HOSTNAME
+        (
+            {"title": "Foo", "type": "string", "format": "idn-hostname"},
+            IDN_HOSTNAME,
+            [
+                ('"bücher.example"', True),
+                ('"xn--bcher-kva.example"', True),
+                ('"例え.テスト"', True),
+                ('"bücher-.example"', False),
+            ],
+        ),
+        # EMAIL
+        (
+            {"title": "Foo", "type": "string", "format": "email"},
+            EMAIL,
+            [
+                ('"john.doe+tag@example.com"', True),
+                ('"john@xn--bcher-kva.example"', True),
+                ('"jöhn@example.com"', False),
+                ('"john@@example.com"', False),
+                ('"john.@example.com"', False),
+            ],
+        ),
+        # IDN-EMAIL
+        (
+            {"title": "Foo", "type": "string", "format": "idn-email"},
+            IDN_EMAIL,
+            [
+                ('"jöhn@bücher.example"', True),
+                ('"john@example.com"', True),
+                ('"jöhn"', False),
+            ],
+        ),
+        # URI
+        (
+            {"title": "Foo", "type": "string", "format": "uri"},
+            URI,
+            [
+                ('"https://example.com/a%20b?q=1#top"', True),
+                ('"urn:isbn:0451450523"', True),
+                ('"https://example.com/a b"', False),
+                ('"https://example.com/%zz"', False),
+                ('"https://bücher.example"', False),
+                ('"//example.com"', False),
+            ],
+        ),
+        # IRI
+        (
+            {"title": "Foo", "type": "string", "format": "iri"},
+            IRI,
+            [
+                ('"https://bücher.example/straße"', True),
+                ('"https://example.com/a%20b"', True),
+                ('"https://example.com/a b"', False),
+            ],
+        ),
+        # JSON-POINTER
+        (
+            {"title": "Foo", "type": "string", "format": "json-pointer"},
+            JSON_POINTER,
+            [
+                ('""', True),
+                ('"/foo/0/a~1b/m~0n"', True),
+                ('"/"', True),
+                ('"foo"', False),
+                ('"/a~2b"', False),
+            ],
+        ),
+        # RELATIVE-JSON-POINTER
+        (
+            {"title": "Foo", "type": "string", "format": "relative-json-pointer"},
+            RELATIVE_JSON_POINTER,
+            [
+                ('"0"', True),
+                ('"1/foo/0"', True),
+                ('"2#"', True),
+                ('"01/foo"', False),
+                ('"/foo"', False),
+                ('"1#/foo"', False),
+            ],
+        ),
+        # SEMVER
+        (
+            {"title": "Foo", "type": "string", "format": "semver"},
+            SEMVER,
+            [
+                ('"1.0.0"', True),
+                ('"1.0.0-alpha.1"', True),
+                ('"1.0.0-0.3.7+build.20240101"', True),
+                ('"1.0.0-x-y-z.--"', True),
+                ('"01.0.0"', False),
+                ('"1.0"', False),
+                ('"1.0.0-01"', False),
+                ('"1.0.0+"', False),
+            ],
+        ),
+        # PHONE
+        (
+            {"title": "Foo", "type": "string", "format": "phone"},
+            PHONE,
+            [
+                ('"+14155552671"', True),
+                ('"+442071838750"', True),
+                ('"14155552671"', False),
+                ('"+04155552671"', False),
+                ('"+1234567"', False),
+                ('"+1234567890123456"', False),
+            ],
+        ),
+        (
+            {"title": "Foo", "type": "string", "x-format": "e164"},
+            PHONE,
+            [('"+14155552671"', True), ('"+1 415 555 2671"', False)],
+        ),
     ],
 )
 def test_format(schema, regex, examples):
@@ -898,6 +1100,30 @@ def test_format(schema, regex, examples):
                 ('{"time":20:20:39Z}', False),  # missing quotes for value
             ],
         ),
+        # NESTED DATE WITH BOUNDS
+        (
+            {
+                "title": "Foo",
+                "type": "object",
+                "properties": {
+                    "date": {
+                        "type": "string",
+                        "format": "date",
+                        "formatMinimum": "1900-01-01",
+                        "formatExclusiveMaximum": "2024-06-15",
+                    }
+                },
+            },
+            [
+                ('{"date": "1900-01-01"}', True),
+                ('{"date": "1987-11-30"}', True),
+                ('{"date": "2024-06-14"}', True),
+                ('{"date": "2024-06-15"}', False),  # upper bound is exclusive
+                ('{"date": "2031-01-01"}', False),  # after the upper bound
+                ('{"date": "1899-12-31"}', False),  # before the lower bound
+                ('{"date": "1987-13-01"}', False),  # incorrect month
+            ],
+        ),
         # Unconstrained Object
         (
             {
@@ -1025,3 +1251,1546 @@ def test_match_object():
         }
     )
     assert test_regex == r"\{[ ]?\}"
+
+
+@pytest.mark.parametrize(
+    "schema,keyword",
+    [
+        ({"allOf": [{"type": "string"}, {"type": "integer"}]}, "type"),
+        ({"allOf": [{"enum": [1, 2]}, {"enum": [3]}]}, "enum"),
+        ({"allOf": [{"const": "a"}, {"const": "b"}]}, "const"),
+        (
+            {"allOf": [{"type": "string", "minLength": 5}, {"maxLength": 2}]},
+            "minLength/maxLength",
+        ),
+        (
+            {"allOf": [{"type": "number", "minimum": 2}, {"exclusiveMaximum": 2}]},
+            "minimum/exclusiveMaximum",
+        ),
+        (
+            {"allOf": [{"type": "number", "exclusiveMinimum": 3}, {"maximum": 2}]},
+            "exclusiveMinimum/maximum",
+        ),
+    ],
+)
+def test_all_of_conflicts(schema, keyword):
+    with pytest.raises(ValueError, match=f"Conflicting '{keyword}' constraints"):
+        to_regex(schema)
+
+
+def test_all_of_patterns():
+    # The strings must match both patterns, which overlap.
+    regex = to_regex(
+        {
+            "allOf": [
+                {"type": "string", "pattern": "[a-z]+"},
+                {"type": "string", "pattern": "[a-c]+"},
+            ]
+        }
+    )
+    assert re.fullmatch(regex, '"abc"')
+    assert not re.fullmatch(regex, '"abd"')
+
+    # The alternatives whose patterns overlap are kept.
+    regex = to_regex(
+        {
+            "allOf": [
+                {
+                    "anyOf": [
+                        {"type": "string", "pattern": "[a-z]+"},
+                        {"type": "integer"},
+                    ]
+                },
+                {
+                    "anyOf": [
+                        {"type": "string", "pattern": "[a-c]+"},
+                        {"type": "integer"},
+                    ]
+                },
+            ]
+        }
+    )
+    assert re.fullmatch(regex, '"abc"')
+    assert re.fullmatch(regex, "12")
+    assert not re.fullmatch(regex, '"abd"')
+
+
+@pytest.mark.parametrize(
+    "schema,conflicts",
+    [
+        (
+            {
+                "type": "object",
+                "properties": {"a": {"type": "string", "enum": [1, 2]}},
+                "required": ["a"],
+            },
+            ['#/properties/a {"type":"string","enum":[1,2]}'],
+        ),
+        (
+            {"anyOf": [{"enum": []}, {"type": "integer", "const": "x"}]},
+            ['#/anyOf/0 {"enum":[]}', '#/anyOf/1 {"type":"integer","const":"x"}'],
+        ),
+        (
+            {"title": "Foo", "allOf": [{"type": "string"}, {"enum": [1]}]},
+            ['# {"allOf":[{"type":"string"},{"enum":[1]}]}'],
+        ),
+        (
+            {"properties": {"a": False}, "required": ["a"]},
+            ["#/properties/a false"],
+        ),
+    ],
+)
+def test_unsatisfiable_schema(schema, conflicts):
+    with pytest.raises(ValueError) as error:
+        build_fsm_from_schema(json.dumps(schema))
+    assert str(error.value) == "This schema admits no outputs: " + "; ".join(conflicts)
+
+
+def test_boolean_schemas():
+    schema = {
+        "type": "object",
+        "properties": {
+            "anything": True,
+            "empty": {},
+            "never": False,
+            "list": {"type": "array", "items": True, "maxItems": 2},
+        },
+        "required": ["anything", "empty", "list"],
+        "additionalProperties": False,
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, '{"anything": [1, "a"], "empty": null, "list": [{}]}')
+    assert re.fullmatch(regex, '{"anything": 1.5, "empty": "x", "list": []}')
+    assert not re.fullmatch(
+        regex, '{"anything": 1, "empty": 1, "list": [], "never": 1}'
+    )
+
+    assert re.fullmatch(to_regex(True), '{"a": [true]}')
+    with pytest.raises(ValueError, match="admits no outputs"):
+        build_fsm_from_schema("false")
+
+
+def test_type_arrays():
+    schema = {
+        "type": ["object", "array", "integer", "null"],
+        "properties": {"a": {"type": "integer"}},
+        "required": ["a"],
+        "prefixItems": [{"type": "string"}],
+        "maximum": 5,
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, '{"a": 1}')
+    assert re.fullmatch(regex, '["x"]')
+    assert re.fullmatch(regex, "5")
+    assert re.fullmatch(regex, "null")
+    assert not re.fullmatch(regex, "6")
+    assert not re.fullmatch(regex, "[1]")
+    assert not re.fullmatch(regex, '"a"')
+
+    with pytest.raises(ValueError, match="must not be an empty array"):
+        build_regex_from_schema(json.dumps({"type": []}))
+    with pytest.raises(ValueError, match="array of strings"):
+        build_regex_from_schema(json.dumps({"type": ["string", 1]}))
+
+
+@pytest.mark.parametrize(
+    "schema,matching,not_matching",
+    [
+        ({"type": "array", "items": {"type": "string"}}, '["a", "b"]', '["a", 1]'),
+        ({"items": {"type": "string"}}, '["a", "b"]', '{"a": "b"}'),
+        ({"type": "string", "maxLength": 2}, '"ab"', '"abc"'),
+        ({"minLength": 2}, '"ab"', "12"),
+        ({"type": "number"}, "1.5", '"1.5"'),
+        ({"enum": ["red", "green"]}, '"red"', '"blue"'),
+        (
+            {
+                "$ref": "#/$defs/Names",
+                "$defs": {"Names": {"items": {"type": "string"}}},
+            },
+            '["a"]',
+            "[1]",
+        ),
+        ({"title": "Anything", "$defs": {"A": {"type": "string"}}}, "[1]", "nul"),
+        ({"additionalProperties": {"type": "integer"}}, '{"a": 1}', '{"a": "b"}'),
+    ],
+)
+def test_non_object_roots(schema, matching, not_matching):
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, matching)
+    assert not re.fullmatch(regex, not_matching)
+
+
+def test_unsatisfiable_optional_property():
+    schema = {
+        "type": "object",
+        "properties": {"a": {"enum": []}, "b": {"type": "integer"}},
+    }
+    fsm_info = build_fsm_from_schema(json.dumps(schema))
+    assert fsm_info.finals
+
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, '{"b": 1}')
+    assert not re.fullmatch(regex, '{"a": 1, "b": 1}')
+
+
+def test_shortest_string():
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string", "minLength": 2},
+            "tags": {"type": "array", "items": {"enum": ["abc", "é"]}, "minItems": 1},
+            "age": {"type": "integer"},
+        },
+        "required": ["name", "tags"],
+    }
+    fsm_info = build_fsm_from_schema(json.dumps(schema))
+    assert fsm_info.shortest_string() == '{"name":"  ","tags":["é"]}'
+
+
+def test_enumerate_strings():
+    schema = {
+        "type": "object",
+        "properties": {"a": {"enum": [1, "é"]}, "b": {"type": "boolean"}},
+        "required": ["a", "b"],
+    }
+    fsm_info = build_fsm_from_schema(json.dumps(schema), compact=True)
+    strings = fsm_info.enumerate_strings(64, 100)
+    assert strings == [
+        '{"a":"é","b":false}',
+        '{"a":"é","b":true}',
+        '{"a":1,"b":false}',
+        '{"a":1,"b":true}',
+    ]
+    assert all(json.loads(string)["a"] in (1, "é") for string in strings)
+    assert fsm_info.enumerate_strings(64, 1) == strings[:1]
+    assert fsm_info.enumerate_strings(8, 100) == []
+
+
+@pytest.mark.parametrize(
+    "pattern,feature",
+    [
+        ("^(?=.*[0-9]).{8,}$", "a lookahead"),
+        ("(?<!-)[0-9]+", "a lookbehind"),
+        ("^(a)b\\1$", "a backreference"),
+    ],
+)
+def test_unsupported_pattern_features(pattern, feature):
+    schema = {
+        "type": "object",
+        "properties": {
+            "user": {
+                "type": "object",
+                "properties": {"password": {"type": "string", "pattern": pattern}},
+            }
+        },
+    }
+    with pytest.raises(ValueError, match=f"at '/user/password' uses {feature}"):
+        to_regex(schema)
+
+
+
+@pytest.mark.parametrize(
+    "pattern,reason",
+    [
+        ("[a-z", "unclosed character class at offset 0"),
+        ("a^b", "the anchor at offset 1 can not match"),
+        ("^a$|^b$", "the anchor at offset 0 can not match"),
+        ("(?P<code>[0-9]+)", "the named capture group 'code' would be repeated"),
+    ],
+)
+def test_invalid_patterns(pattern, reason):
+    schema = {
+        "type": "object",
+        "properties": {"code": {"type": "string", "pattern": pattern}},
+    }
+    with pytest.raises(ValueError, match=re.escape(f"at '/code': {reason}")):
+        to_regex(schema)
+
+
+def test_embedded_patterns():
+    schema = {"type": "string", "pattern": "^a|b$"}
+    with pytest.raises(ValueError, match="anchor"):
+        to_regex(schema)
+    assert to_regex({"type": "string", "pattern": "^ab+"}) == '("ab+")'
+    assert to_regex({"type": "string", "pattern": "a|b"}) == '("(a|b)")'
+
+    schema = json.dumps({"type": "array"})
+    regex = build_regex_from_schema(schema, r"\n|\t")
+    assert re.fullmatch(regex, "[\n1,\t2\n]")
+    with pytest.raises(ValueError, match=re.escape("Invalid whitespace pattern '\\s*$'")):
+        build_regex_from_schema(schema, r"\s*$")
+    with pytest.raises(ValueError, match=re.escape("pattern '(': unclosed group")):
+        build_regex_from_schema(schema, "(")
+
+
+
+@pytest.mark.parametrize(
+    "schema,matching,non_matching",
+    [
+        (
+            {"pattern": "^[a-z]+$", "minLength": 3, "maxLength": 5},
+            ["abc", "abcde"],
+            ["ab", "abcdef"],
+        ),
+        (
+            {"pattern": "^[A-Z]{2}-[0-9]+$", "maxLength": 6},
+            ["AB-1", "AB-123"],
+            ["AB-1234"],
+        ),
+        (
+            {"pattern": "^[a-z]+@[a-z]+\\.[a-z]{2,}$", "maxLength": 20},
+            ["a@b.cd", "abc@mail.org"],
+            ["abcdefghijk@mail.example.com"],
+        ),
+    ],
+)
+def test_pattern_with_length(schema, matching, non_matching):
+    regex = to_regex({"type": "string", **schema})
+    for string in matching:
+        assert re.fullmatch(regex, json.dumps(string)), string
+    for string in non_matching:
+        assert not re.fullmatch(regex, json.dumps(string)), string
+
+
+def test_pattern_with_length_conflict():
+    schema = {"type": "string", "pattern": "^[0-9]{4}$", "maxLength": 3}
+    with pytest.raises(ValueError, match="matches no string of 0 to 3 characters"):
+        to_regex(schema)
+
+def test_case_insensitive():
+    schema = {
+        "type": "object",
+        "properties": {
+            "color": {"enum": ["Red", "green", 1]},
+            "mode": {"const": "on"},
+            "hash": {"type": "string", "pattern": "^(?i)[0-9a-f]{4}$"},
+        },
+        "required": ["color", "mode", "hash"],
+    }
+    regex = build_regex_from_schema(
+        json.dumps(schema), case_insensitive=True, compact=True
+    )
+    assert re.fullmatch(regex, '{"color":"RED","mode":"On","hash":"BEef"}')
+    assert re.fullmatch(regex, '{"color":1,"mode":"on","hash":"00fF"}')
+    assert not re.fullmatch(regex, '{"color":"blue","mode":"on","hash":"00ff"}')
+    assert not re.fullmatch(regex, '{"color":"red","mode":"on","hash":"00fg"}')
+
+    # Patterns honor their inline flags without the option.
+    regex = build_regex_from_schema(json.dumps(schema), compact=True)
+    assert re.fullmatch(regex, '{"color":"Red","mode":"on","hash":"BEef"}')
+    assert not re.fullmatch(regex, '{"color":"RED","mode":"on","hash":"BEef"}')
+
+
+def test_unicode_property_classes():
+    schema = {"type": "string", "pattern": "^\\p{Lu}\\p{Ll}+( \\p{Lu}\\p{Ll}+)*$"}
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert "\\p" not in regex
+    assert re.fullmatch(regex, '"Zoë Ångström"')
+    assert re.fullmatch(regex, '"Ωμέγα"')
+    assert not re.fullmatch(regex, '"zoë"')
+    assert not re.fullmatch(regex, '"Zoë 42"')
+
+
+def test_recursive_ref_is_unrolled():
+    schema = {
+        "$defs": {
+            "Node": {
+                "type": "object",
+                "properties": {
+                    "value": {"type": "integer"},
+                    "children": {"type": "array", "items": {"$ref": "#/$defs/Node"}},
+                },
+                "required": ["value"],
+            }
+        },
+        "$ref": "#/$defs/Node",
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    # the reference is unrolled three times, the innermost children must be empty
+    assert re.fullmatch(regex, '{"value": 1, "children": [{"value": 2}]}')
+    assert re.fullmatch(
+        regex, '{"value": 1, "children": [{"value": 2, "children": [{"value": 3}]}]}'
+    )
+    assert not re.fullmatch(
+        regex,
+        '{"value": 1, "children": [{"value": 2, "children": [{"value": 3, "children": [{"value": 4}]}]}]}',
+    )
+
+
+def test_recursive_ref_without_base_case():
+    schema = {
+        "$defs": {
+            "A": {
+                "type": "object",
+                "properties": {"a": {"$ref": "#/$defs/A"}},
+                "required": ["a"],
+            }
+        },
+        "$ref": "#/$defs/A",
+    }
+    with pytest.raises(ValueError, match="maximum unrolling depth"):
+        build_regex_from_schema(json.dumps(schema))
+
+
+def test_dynamic_ref():
+    schema = {
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$dynamicAnchor": "list",
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "next": {"$dynamicRef": "#list"},
+        },
+        "required": ["name"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    assert re.fullmatch(regex, '{"name": "a"}')
+    assert re.fullmatch(regex, '{"name": "a", "next": {"name": "b"}}')
+    assert not re.fullmatch(regex, '{"name": "a", "next": 1}')
+
+
+def test_anchor_and_id_relative_refs():
+    schema = {
+        "$id": "https://example.com/schemas/customer",
+        "type": "object",
+        "properties": {
+            "name": {"$ref": "#name"},
+            "shipping": {"$ref": "address"},
+            "billing": {"$ref": "https://example.com/schemas/address#/properties/city"},
+        },
+        "required": ["name", "shipping", "billing"],
+        "$defs": {
+            "name": {"$anchor": "name", "type": "string"},
+            "address": {
+                "$id": "address",
+                "type": "object",
+                "properties": {"city": {"$ref": "#city"}},
+                "required": ["city"],
+                "$defs": {"city": {"$anchor": "city", "enum": ["Paris", "Lima"]}},
+            },
+        },
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    assert re.fullmatch(
+        regex, '{"name": "Jo", "shipping": {"city": "Lima"}, "billing": "Paris"}'
+    )
+    assert not re.fullmatch(
+        regex, '{"name": "Jo", "shipping": {"city": "Rome"}, "billing": "Paris"}'
+    )
+
+
+def test_cross_referencing_defs():
+    # the definitions come after the references to them, and refer to one another
+    schema = {
+        "type": "object",
+        "properties": {"person": {"$ref": "#/$defs/Person"}},
+        "required": ["person"],
+        "$defs": {
+            "Person": {
+                "type": "object",
+                "properties": {
+                    "name": {"$ref": "#/$defs/Name"},
+                    "pet": {"$ref": "#/$defs/Pet"},
+                },
+                "required": ["name"],
+            },
+            "Pet": {
+                "type": "object",
+                "properties": {"owner": {"$ref": "#/$defs/Person"}},
+            },
+            "Name": {"$ref": "#/$defs/Text"},
+            "Text": {"type": "string", "maxLength": 3},
+        },
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    assert re.fullmatch(regex, '{"person": {"name": "Jo"}}')
+    assert re.fullmatch(
+        regex, '{"person": {"name": "Jo", "pet": {"owner": {"name": "Al"}}}}'
+    )
+    assert not re.fullmatch(regex, '{"person": {"name": "Jojo"}}')
+    # Person and Pet are both unrolled three times
+    assert not re.fullmatch(
+        regex,
+        '{"person": {"name": "A", "pet": {"owner": {"name": "B", "pet": {"owner": '
+        '{"name": "C", "pet": {"owner": {"name": "D"}}}}}}}}',
+    )
+
+
+def test_refs_to_embedded_resources():
+    schema = {
+        "$id": "https://example.com/schemas/root.json",
+        "$ref": "sub/a.json",
+        "$defs": {
+            "A": {
+                "$id": "sub/a.json",
+                "type": "object",
+                "properties": {"b": {"$ref": "b.json"}, "c": {"$ref": "c.json"}},
+                "required": ["b", "c"],
+            },
+            "B": {"$id": "sub/b.json", "type": "integer"},
+            "C": {"$id": "sub/c.json", "allOf": [{"$ref": "b.json"}, {"minimum": 0}]},
+        },
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    assert re.fullmatch(regex, '{"b": -1, "c": 2}')
+    assert not re.fullmatch(regex, '{"b": -1, "c": -2}')
+
+
+def test_ref_loader():
+    schema = {
+        "$id": "https://example.com/schemas/order.json",
+        "type": "object",
+        "properties": {
+            "customer": {"$ref": "customer.json"},
+            "total": {"$ref": "https://example.com/common.json#/$defs/amount"},
+        },
+        "required": ["customer", "total"],
+    }
+    documents = {
+        "https://example.com/schemas/customer.json": {
+            "type": "object",
+            "properties": {"id": {"$ref": "urn:example:id"}},
+            "required": ["id"],
+        },
+        "https://example.com/common.json": json.dumps(
+            {"$defs": {"amount": {"type": "integer", "minimum": 0}}}
+        ),
+        "urn:example:id": {"type": "string", "format": "uuid"},
+    }
+    loaded = []
+
+    def load(uri):
+        loaded.append(uri)
+        return documents.get(uri)
+
+    regex = build_regex_from_schema(json.dumps(schema), ref_loader=load)
+    assert sorted(loaded) == sorted(documents)
+    assert re.fullmatch(
+        regex,
+        '{"customer": {"id": "123e4567-e89b-12d3-a456-426614174000"}, "total": 12}',
+    )
+    assert not re.fullmatch(regex, '{"customer": {"id": "x"}, "total": 12}')
+
+    with pytest.raises(ValueError, match="is not loaded"):
+        build_regex_from_schema(json.dumps(schema))
+
+    del documents["urn:example:id"]
+    with pytest.raises(ValueError, match="urn:example:id"):
+        build_regex_from_schema(json.dumps(schema), ref_loader=load)
+
+
+def test_keyword_extension():
+    schema = {
+        "type": "object",
+        "properties": {
+            "id": {"type": "string", "x-ticket": "ABC"},
+            "note": {"type": "string", "x-nullable": True},
+        },
+        "required": ["id", "note"],
+    }
+    seen = []
+
+    def extend(keyword, value, subschema):
+        seen.append(keyword)
+        if keyword == "x-ticket":
+            return f'"{value}-[0-9]+"'
+        if keyword == "x-nullable":
+            return {"anyOf": [{"type": subschema["type"]}, {"type": "null"}]}
+        return None
+
+    regex = build_regex_from_schema(json.dumps(schema), keyword_extension=extend)
+    assert seen == ["x-ticket", "x-nullable"]
+    assert re.fullmatch(regex, '{"id": "ABC-12", "note": null}')
+    assert re.fullmatch(regex, '{"id": "ABC-12", "note": "a"}')
+    assert not re.fullmatch(regex, '{"id": "XYZ-12", "note": null}')
+
+    with pytest.raises(TypeError, match="must be callable"):
+        build_regex_from_schema(json.dumps(schema), keyword_extension=1)
+
+
+def test_pattern_override():
+    schema = {
+        "type": "object",
+        "properties": {
+            "sku": {
+                "type": "string",
+                "maxLength": 3,
+                "x-pattern-override": '^"SKU-[0-9]{6}"$',
+            },
+            "count": {"type": "integer", "x-pattern-override": "[1-9]|10"},
+        },
+        "required": ["sku", "count"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, '{"sku": "SKU-123456", "count": 10}')
+    assert not re.fullmatch(regex, '{"sku": "SKU-123456", "count": 11}')
+    assert not re.fullmatch(regex, '{"sku": "ABC", "count": 1}')
+
+    with pytest.raises(ValueError, match="must be a string"):
+        build_regex_from_schema(json.dumps({"x-pattern-override": 1}))
+    with pytest.raises(ValueError, match="lookahead"):
+        build_regex_from_schema(json.dumps({"x-pattern-override": "(?=a)a"}))
+
+
+def test_all_of_recursive_refs():
+    schema = {
+        "$defs": {
+            "Node": {
+                "type": "object",
+                "properties": {"next": {"$ref": "#/$defs/Node"}, "a": {"type": "integer"}},
+            },
+            "Named": {
+                "type": "object",
+                "properties": {
+                    "next": {"$ref": "#/$defs/Named"},
+                    "name": {"type": "string"},
+                },
+                "required": ["name"],
+            },
+        },
+        "allOf": [{"$ref": "#/$defs/Node"}, {"$ref": "#/$defs/Named"}],
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    # both definitions constrain each level of the recursion
+    assert re.fullmatch(regex, '{"next": {"a": 1, "name": "b"}, "name": "a"}')
+    assert not re.fullmatch(regex, '{"next": {"a": 1}, "name": "a"}')
+    assert not re.fullmatch(regex, '{"next": {"a": "x", "name": "b"}, "name": "a"}')
+
+    schema = {
+        "$defs": {"A": {"$ref": "#/$defs/B"}, "B": {"allOf": [{"$ref": "#/$defs/A"}]}},
+        "allOf": [{"$ref": "#/$defs/A"}],
+    }
+    with pytest.raises(ValueError, match="merged into itself"):
+        build_regex_from_schema(json.dumps(schema))
+
+
+@pytest.mark.parametrize(
+    "schema,draft",
+    [
+        (
+            {
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "type": "array",
+                "items": [{"type": "integer"}, {"type": "string"}],
+            },
+            None,
+        ),
+        (
+            {"type": "array", "items": [{"type": "integer"}, {"type": "string"}]},
+            "2019-09",
+        ),
+        (
+            {"type": "array", "prefixItems": [{"type": "integer"}, {"type": "string"}]},
+            "2020-12",
+        ),
+    ],
+)
+def test_draft_tuple_items(schema, draft):
+    regex = build_regex_from_schema(json.dumps(schema), draft=draft)
+
+    assert re.fullmatch(regex, '[1, "a"]')
+    assert not re.fullmatch(regex, '["a", 1]')
+
+
+def test_draft_array_items_invalid_in_2020_12():
+    schema = {"type": "array", "items": [{"type": "integer"}]}
+    with pytest.raises(ValueError, match="prefixItems"):
+        build_regex_from_schema(json.dumps(schema), draft="2020-12")
+
+    with pytest.raises(ValueError, match="Unsupported JSON Schema draft"):
+        build_regex_from_schema(json.dumps(schema), draft="draft-03")
+
+
+def test_draft_07_ref_siblings_are_ignored():
+    schema = {
+        "definitions": {"name": {"type": "string"}},
+        "$ref": "#/definitions/name",
+        "type": "integer",
+    }
+    regex = build_regex_from_schema(json.dumps(schema), draft="draft-07")
+
+    assert re.fullmatch(regex, '"abc"')
+    assert not re.fullmatch(regex, "1")
+
+
+@pytest.mark.parametrize(
+    "dependencies,draft",
+    [
+        ({"dependencies": {"card": ["billing"]}}, "draft-07"),
+        ({"dependentRequired": {"card": ["billing"]}}, "2019-09"),
+        ({"dependentRequired": {"card": ["billing"]}}, None),
+    ],
+)
+def test_draft_dependent_required(dependencies, draft):
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "card": {"type": "integer"},
+            "billing": {"type": "string"},
+        },
+        "required": ["name"],
+        **dependencies,
+    }
+    regex = build_regex_from_schema(json.dumps(schema), draft=draft)
+
+    assert re.fullmatch(regex, '{"name": "a"}')
+    assert re.fullmatch(regex, '{"name": "a", "billing": "b"}')
+    assert re.fullmatch(regex, '{"name": "a", "card": 1, "billing": "b"}')
+    assert not re.fullmatch(regex, '{"name": "a", "card": 1}')
+
+
+@pytest.mark.parametrize(
+    "dependencies,draft",
+    [
+        ({"dependencies": {"card": {"required": ["billing"]}}}, "draft-07"),
+        ({"dependentSchemas": {"card": {"required": ["billing"]}}}, None),
+    ],
+)
+def test_dependent_schemas(dependencies, draft):
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "card": {"type": "integer"},
+            "billing": {"type": "string"},
+        },
+        "required": ["name"],
+        **dependencies,
+    }
+    regex = build_regex_from_schema(json.dumps(schema), draft=draft)
+
+    assert re.fullmatch(regex, '{"name": "a"}')
+    assert re.fullmatch(regex, '{"name": "a", "billing": "b"}')
+    assert re.fullmatch(regex, '{"name": "a", "card": 1, "billing": "b"}')
+    assert not re.fullmatch(regex, '{"name": "a", "card": 1}')
+
+
+def test_dependent_schemas_constraints():
+    schema = {
+        "type": "object",
+        "properties": {
+            "kind": {"type": "string"},
+            "size": {"type": "integer"},
+            "debug": {"type": "boolean"},
+        },
+        "required": ["kind"],
+        "dependentSchemas": {
+            "kind": {"properties": {"size": {"maximum": 9}}},
+            "debug": False,
+        },
+    }
+    regex = build_regex_from_schema(json.dumps(schema))
+
+    assert re.fullmatch(regex, '{"kind": "a", "size": 9}')
+    assert not re.fullmatch(regex, '{"kind": "a", "size": 10}')
+    assert not re.fullmatch(regex, '{"kind": "a", "debug": true}')
+
+    schema["required"].append("debug")
+    with pytest.raises(ValueError, match="dependent schema is false"):
+        build_regex_from_schema(json.dumps(schema))
+
+
+@pytest.mark.parametrize(
+    "schema,draft",
+    [
+        (
+            {
+                "type": "array",
+                "prefixItems": [{"type": "integer"}, {"type": "string"}],
+                "items": False,
+            },
+            None,
+        ),
+        (
+            {
+                "type": "array",
+                "items": [{"type": "integer"}, {"type": "string"}],
+                "additionalItems": False,
+            },
+            "draft-07",
+        ),
+    ],
+)
+def test_closed_tuples(schema, draft):
+    regex = build_regex_from_schema(json.dumps(schema), draft=draft)
+
+    assert re.fullmatch(regex, '[1, "a"]')
+    assert not re.fullmatch(regex, '[1, "a", 2]')
+
+
+@pytest.mark.parametrize(
+    "schema,draft",
+    [
+        (
+            {
+                "type": "array",
+                "prefixItems": [{"type": "integer"}, {"type": "string"}],
+                "items": {"type": "boolean"},
+                "maxItems": 4,
+            },
+            None,
+        ),
+        (
+            {
+                "type": "array",
+                "items": [{"type": "integer"}, {"type": "string"}],
+                "additionalItems": {"type": "boolean"},
+                "maxItems": 4,
+            },
+            "draft-07",
+        ),
+    ],
+)
+def test_tuples_with_additional_items(schema, draft):
+    regex = build_regex_from_schema(json.dumps(schema), draft=draft)
+
+    assert re.fullmatch(regex, '[1, "a"]')
+    assert re.fullmatch(regex, '[1, "a", true, false]')
+    assert not re.fullmatch(regex, '[1, "a", true, false, true]')
+    assert not re.fullmatch(regex, '[1, "a", 2]')
+
+
+def test_items_false():
+    regex = build_regex_from_schema(json.dumps({"type": "array", "items": False}))
+
+    assert re.fullmatch(regex, "[]")
+    assert not re.fullmatch(regex, "[1]")
+
+    with pytest.raises(ValueError, match="only allows empty arrays"):
+        build_regex_from_schema(
+            json.dumps({"type": "array", "items": False, "minItems": 1})
+        )
+
+
+def test_unicode_strings():
+    schema = json.dumps(
+        {
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "code": {"type": "string", "maxLength": 2},
+            },
+            "required": ["name", "code"],
+        }
+    )
+    regex = build_regex_from_schema(schema, unicode_strings=True)
+
+    assert re.fullmatch(regex, '{"name": "Zoë 東京", "code": "日本"}')
+    assert re.fullmatch(regex, r'{"name": "café\n\t\/", "code": "日"}')
+    assert not re.fullmatch(regex, '{"name": "a\u0001", "code": "a"}')
+    assert not re.fullmatch(regex, r'{"name": "\u12", "code": "a"}')
+    assert not re.fullmatch(regex, r'{"name": "\x", "code": "a"}')
+
+    # the default grammar only accepts the `\"` and `\\` escapes
+    regex = build_regex_from_schema(schema)
+    assert not re.fullmatch(regex, r'{"name": "caf\u00e9", "code": "a"}')
+
+
+@pytest.mark.parametrize(
+    "value,accepted",
+    [
+        (r'"a\"b\\c\/d\b\f\n\r\t"', True),
+        (r'"\u00e9\u20AC"', True),
+        (r'"\ud83d\ude00"', True),
+        ('"東京"', True),
+        (r'"\ud83d"', False),
+        (r'"\ude00"', False),
+        (r'"\ude00\ud83d"', False),
+        (r'"\x41"', False),
+        (r'"\a"', False),
+        ('"\\"', False),
+    ],
+)
+def test_strict_escapes(value, accepted):
+    regex = build_regex_from_schema(json.dumps({"type": "string"}), strict_escapes=True)
+
+    assert (re.fullmatch(regex, value) is not None) == accepted
+
+
+def test_ascii_only():
+    schema = json.dumps(
+        {
+            "type": "object",
+            "properties": {
+                "név": {"type": "string"},
+                "city": {"enum": ["Zürich", "東京"]},
+            },
+            "required": ["név", "city"],
+        }
+    )
+    regex = build_regex_from_schema(schema, ascii_only=True)
+
+    # `json.dumps` escapes non-ASCII characters by default
+    assert re.fullmatch(regex, json.dumps({"név": "café 😀", "city": "Zürich"}))
+    assert re.fullmatch(regex, json.dumps({"név": "a", "city": "東京"}))
+    assert not re.fullmatch(regex, '{"név": "a", "city": "Zürich"}')
+    assert not re.fullmatch(regex, '{"n\\u00e9v": "café", "city": "Z\\u00fcrich"}')
+
+
+def test_integer_forms():
+    schema = json.dumps({"type": "integer"})
+    assert re.fullmatch(build_regex_from_schema(schema), "-0")
+
+    regex = build_regex_from_schema(schema, integer_forms={})
+    assert re.fullmatch(regex, "-12")
+    assert not re.fullmatch(regex, "-0")
+
+    regex = build_regex_from_schema(
+        schema, integer_forms={"plus_sign": True, "leading_zeros": True}
+    )
+    assert re.fullmatch(regex, "+007")
+    assert re.fullmatch(regex, "-012")
+    assert not re.fullmatch(regex, "-0")
+
+    regex = build_regex_from_schema(
+        json.dumps({"type": "integer", "minimum": -3, "maximum": 3}),
+        integer_forms={"negative_zero": True},
+    )
+    assert re.fullmatch(regex, "-0")
+    assert not re.fullmatch(regex, "-4")
+
+    with pytest.raises(ValueError, match="Unknown integer form"):
+        build_regex_from_schema(schema, integer_forms={"octal": True})
+
+
+def test_exponents():
+    schema = {
+        "type": "object",
+        "properties": {
+            "a": {"type": "number"},
+            "b": {"type": "number", "allowExponent": True},
+            "c": {"type": "number", "maxDigitsFraction": 2},
+        },
+        "required": ["a", "b", "c"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema), compact=True)
+    assert re.fullmatch(regex, '{"a":1e-5,"b":1e+5,"c":1.25e+1}')
+
+    regex = build_regex_from_schema(json.dumps(schema), compact=True, exponents=False)
+    assert re.fullmatch(regex, '{"a":0.5,"b":1e+5,"c":-1.25}')
+    assert not re.fullmatch(regex, '{"a":1e-5,"b":1,"c":1}')
+    assert not re.fullmatch(regex, '{"a":1,"b":1,"c":1.2e+1}')
+
+    schema["properties"]["a"]["allowExponent"] = False
+    regex = build_regex_from_schema(json.dumps(schema), compact=True)
+    assert re.fullmatch(regex, '{"a":1,"b":1e+5,"c":1e+1}')
+    assert not re.fullmatch(regex, '{"a":1e+1,"b":1,"c":1}')
+
+    with pytest.raises(ValueError, match="'allowExponent' must be a boolean"):
+        build_regex_from_schema(json.dumps({"type": "number", "allowExponent": 1}))
+
+
+def test_json5_numbers():
+    schema = {
+        "type": "object",
+        "properties": {
+            "n": {"type": "number"},
+            "i": {"type": "integer"},
+            "bounded": {"type": "integer", "minimum": 0},
+        },
+        "required": ["n", "i", "bounded"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema), compact=True)
+    assert not re.fullmatch(regex, '{"n":NaN,"i":1,"bounded":1}')
+
+    regex = build_regex_from_schema(
+        json.dumps(schema), compact=True, json5_numbers=True
+    )
+    for n, i in [
+        ("NaN", "0x1F"),
+        ("-Infinity", "-0XaB"),
+        ("+0x10", "12"),
+        ("1.5", "-1"),
+    ]:
+        assert re.fullmatch(regex, f'{{"n":{n},"i":{i},"bounded":1}}')
+    assert not re.fullmatch(regex, '{"n":1,"i":NaN,"bounded":1}')
+    assert not re.fullmatch(regex, '{"n":1,"i":1,"bounded":0x1}')
+    assert not re.fullmatch(regex, '{"n":nan,"i":1,"bounded":1}')
+
+
+def test_datetime_format():
+    schema = {"type": "string", "x-datetime-format": "%d/%m/%Y %H:%M"}
+    regex = build_regex_from_schema(json.dumps(schema))
+    assert re.fullmatch(regex, '"31/12/1999 23:59"')
+    assert not re.fullmatch(regex, '"1999-12-31 23:59"')
+
+    with pytest.raises(ValueError, match="Unsupported directive '%Q'"):
+        build_regex_from_schema(
+            json.dumps({"type": "string", "x-datetime-format": "%Q"})
+        )
+
+
+def test_time_format():
+    schema = json.dumps({"type": "string", "format": "time"})
+    regex = build_regex_from_schema(
+        schema, time_fraction="required", time_offset="numeric"
+    )
+    assert re.fullmatch(regex, '"23:59:59.5+05:30"')
+    assert not re.fullmatch(regex, '"23:59:59+05:30"')
+    assert not re.fullmatch(regex, '"23:59:59.5Z"')
+
+    regex = build_regex_from_schema(schema, time_offset="utc_or_numeric")
+    assert re.fullmatch(regex, '"23:59:59Z"')
+    assert re.fullmatch(regex, '"23:59:59.123-01:00"')
+    assert not re.fullmatch(regex, '"23:59:59"')
+
+    regex = build_regex_from_schema(
+        schema, time_fraction="forbidden", time_offset="forbidden"
+    )
+    assert re.fullmatch(regex, '"08:00:00"')
+    assert not re.fullmatch(regex, '"08:00:00.1"')
+    assert not re.fullmatch(regex, '"08:00:00Z"')
+
+    with pytest.raises(ValueError, match="Unknown time offset"):
+        build_regex_from_schema(schema, time_offset="local")
+
+
+def test_length_semantics():
+    schema = json.dumps({"type": "string", "maxLength": 4})
+    regex = build_regex_from_schema(schema, length_semantics="utf16")
+    assert re.fullmatch(regex, '"abcd"')
+    assert re.fullmatch(regex, '"😀😀"')
+    assert not re.fullmatch(regex, '"😀😀a"')
+    assert re.fullmatch(build_regex_from_schema(schema), '"😀😀a"')
+
+    regex = build_regex_from_schema(schema, length_semantics="bytes")
+    assert re.fullmatch(regex, '"éé"')
+    assert not re.fullmatch(regex, '"éab"')
+
+    schema = json.dumps({"type": "string", "pattern": "[a-z😀]+", "maxLength": 3})
+    regex = build_regex_from_schema(schema, length_semantics="utf16")
+    assert re.fullmatch(regex, '"a😀"')
+    assert not re.fullmatch(regex, '"😀😀"')
+
+    with pytest.raises(ValueError, match="Unknown length semantics"):
+        build_regex_from_schema(schema, length_semantics="graphemes")
+
+
+def test_large_enum():
+    genes = [f"{prefix}{n}" for prefix in ["BRCA", "TP", "KRAS"] for n in range(1, 200)]
+    schema = {"enum": genes + [1.5, None, 'with "quotes"', "a.b"]}
+    regex = build_regex_from_schema(json.dumps(schema))
+    # The values share their prefixes instead of being written one after the other.
+    assert len(regex) < sum(len(gene) for gene in genes)
+    for value in schema["enum"]:
+        assert re.fullmatch(regex, json.dumps(value))
+    assert not re.fullmatch(regex, '"BRCA0"')
+    assert not re.fullmatch(regex, '"BRCA200"')
+    assert not re.fullmatch(regex, '"aXb"')
+    assert not re.fullmatch(regex, "1.50")
+
+
+def test_unordered_required():
+    schema = json.dumps(
+        {
+            "type": "object",
+            "properties": {
+                "a": {"type": "integer"},
+                "b": {"type": "string"},
+                "c": {"type": "boolean"},
+                "d": {"type": "null"},
+            },
+            "required": ["a", "b", "c"],
+        }
+    )
+    regex = build_regex_from_schema(schema, unordered_required=True)
+
+    assert re.fullmatch(regex, '{"a": 1, "b": "x", "c": true}')
+    assert re.fullmatch(regex, '{"c": true, "a": 1, "b": "x"}')
+    assert re.fullmatch(regex, '{"b": "x", "c": true, "a": 1, "d": null}')
+    assert not re.fullmatch(regex, '{"b": "x", "a": 1}')
+    assert not re.fullmatch(regex, '{"a": 1, "a": 1, "b": "x"}')
+    assert not re.fullmatch(regex, '{"d": null, "a": 1, "b": "x", "c": true}')
+
+    with pytest.raises(TypeError, match="unordered"):
+        build_regex_from_schema(schema, unordered=True)
+
+
+def test_unordered_required_automaton():
+    point = {
+        "type": "object",
+        "properties": {"x": {"type": "integer"}, "y": {"type": "string"}},
+        "required": ["x", "y"],
+    }
+    properties = {f"p{i}": point for i in range(6)}
+    schema = json.dumps(
+        {"type": "object", "properties": properties, "required": list(properties)}
+    )
+
+    # Regular expressions would write out every ordering, so they keep the declaration order
+    # of the outer object.
+    regex = build_regex_from_schema(schema, unordered_required=True)
+    assert len(regex) < 3 * len(build_regex_from_schema(schema))
+
+    # The automaton grows with the 64 sets of properties written so far, and the two orders of
+    # the properties of each point, not with the 720 orders of the properties.
+    fsm_info = build_fsm_from_schema(schema, unordered_required=True, compact=True)
+    ordered = build_fsm_from_schema(schema, compact=True)
+    assert len(fsm_info.transitions) < 2 * 64 * len(ordered.transitions)
+    members = [f'"p{i}":{{"y":"","x":{i}}}' for i in (3, 0, 5, 1, 4, 2)]
+    for text, accepted in [
+        ("{" + ",".join(members) + "}", True),
+        ("{" + ",".join(members[:5]) + "}", False),
+        ("{" + ",".join(members[:5] + members[:1]) + "}", False),
+    ]:
+        validator = PartialValidator(fsm_info, byte_level=True)
+        assert (validator.feed(text) and validator.is_final()) == accepted
+
+
+def test_many_optional_properties():
+    properties = {f"p{i}": {"type": "integer"} for i in range(300)}
+    schema = json.dumps({"type": "object", "properties": properties})
+
+    # Regular expressions repeat the later properties for each property which may come
+    # first, automata keep track of the next property instead.
+    with pytest.raises(ValueError, match="at most 128 properties"):
+        build_regex_from_schema(schema)
+    fsm_info = build_fsm_from_schema(schema, compact=True)
+    for text, accepted in [
+        ("{}", True),
+        ('{"p299":1}', True),
+        ('{"p0":0,"p150":1,"p299":2}', True),
+        ("{" + ",".join(f'"p{i}":{i}' for i in range(300)) + "}", True),
+        ('{"p150":1,"p0":0}', False),
+        ('{"p0":0,"p0":0}', False),
+        ('{,"p0":0}', False),
+    ]:
+        validator = PartialValidator(fsm_info, byte_level=True)
+        assert (validator.feed(text) and validator.is_final()) == accepted
+
+
+@pytest.mark.parametrize(
+    "format,examples",
+    [
+        (
+            "iso-3166-alpha2",
+            [('"FR"', True), ('"US"', True), ('"AA"', False), ('"USA"', False)],
+        ),
+        (
+            "iso-4217",
+            [('"EUR"', True), ('"JPY"', True), ('"EUX"', False), ('"EU"', False)],
+        ),
+        (
+            "bcp47",
+            [
+                ('"en"', True),
+                ('"en-GB"', True),
+                ('"zh-Hant-TW"', True),
+                ('"es-419"', True),
+                ('"en-gb"', False),
+                ('"xx-GB"', False),
+            ],
+        ),
+    ],
+)
+def test_code_formats(format, examples):
+    regex = build_regex_from_schema(json.dumps({"type": "string", "format": format}))
+    interegular.parse_pattern(regex)
+    for string, does_match in examples:
+        assert bool(re.fullmatch(regex, string)) == does_match
+
+
+def test_pretty_whitespace():
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "tags": {"type": "array", "items": {"type": "string"}},
+            "point": {"prefixItems": [{"type": "integer"}, {"type": "integer"}]},
+            "extra": {"type": "object"},
+        },
+        "required": ["name", "tags", "point", "extra"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema), indent=2)
+
+    for value in [
+        {"name": "a", "tags": ["x", "y"], "point": [1, 2], "extra": {"k": {"j": 1}}},
+        {"name": "a", "tags": [], "point": [1, 2], "extra": {}},
+    ]:
+        assert re.fullmatch(regex, json.dumps(value, indent=2))
+        assert not re.fullmatch(regex, json.dumps(value, indent=4))
+        assert not re.fullmatch(regex, json.dumps(value))
+
+    optional = {
+        "type": "object",
+        "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+    }
+    regex = build_regex_from_schema(json.dumps(optional), indent=2)
+    for value in [{}, {"a": 1}, {"b": 2}, {"a": 1, "b": 2}]:
+        assert re.fullmatch(regex, json.dumps(value, indent=2))
+
+    with pytest.raises(ValueError, match="can not be used together"):
+        build_regex_from_schema(json.dumps(schema), whitespace_pattern=" ", indent=2)
+
+
+def test_compact_whitespace():
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "tags": {"type": "array", "items": {"type": "string"}},
+            "extra": {"type": "object"},
+        },
+        "required": ["name", "tags"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema), compact=True)
+
+    value = {"name": "a", "tags": ["x", "y"], "extra": {"k": [1, {"j": None}]}}
+    assert re.fullmatch(regex, json.dumps(value, separators=(",", ":")))
+    assert not re.fullmatch(regex, json.dumps(value))
+    assert not re.fullmatch(regex, '{"name":"a","tags":[ ]}')
+
+    with pytest.raises(ValueError, match="can not be used together"):
+        build_regex_from_schema(json.dumps(schema), compact=True, indent=2)
+
+
+def test_max_array_items():
+    regex = build_regex_from_schema(
+        json.dumps({"type": "array", "items": {"type": "integer"}}), max_array_items=3
+    )
+    assert re.fullmatch(regex, "[1, 2, 3]")
+    assert not re.fullmatch(regex, "[1, 2, 3, 4]")
+
+    # explicit bounds take precedence
+    regex = build_regex_from_schema(
+        json.dumps({"type": "array", "items": {"type": "integer"}, "maxItems": 5}),
+        max_array_items=3,
+    )
+    assert re.fullmatch(regex, "[1, 2, 3, 4, 5]")
+
+    regex = build_regex_from_schema(
+        json.dumps({"prefixItems": [{"type": "integer"}], "items": {"type": "string"}}),
+        max_array_items=2,
+    )
+    assert re.fullmatch(regex, '[1, "a"]')
+    assert not re.fullmatch(regex, '[1, "a", "b"]')
+
+
+def test_max_nesting_depth():
+    regex = build_regex_from_schema(json.dumps({"type": "array"}), max_nesting_depth=1)
+    assert re.fullmatch(regex, '[1, "a", null]')
+    assert not re.fullmatch(regex, "[[]]")
+    assert not re.fullmatch(regex, "[{}]")
+
+    schema = {
+        "type": "object",
+        "properties": {
+            "a": {"type": "object", "properties": {"b": {"type": "integer"}}},
+            "c": {"type": "integer"},
+        },
+        "required": ["c"],
+    }
+    regex = build_regex_from_schema(json.dumps(schema), max_nesting_depth=1)
+    assert re.fullmatch(regex, '{"c": 1}')
+    assert not re.fullmatch(regex, '{"a": {"b": 1}, "c": 1}')
+
+    schema["required"] = ["a", "c"]
+    with pytest.raises(ValueError, match="maximum depth of 1"):
+        build_regex_from_schema(json.dumps(schema), max_nesting_depth=1)
+
+
+def test_cancellation():
+    schema = json.dumps({"type": "array", "items": {"type": "integer"}})
+    token = CancellationToken()
+    assert re.fullmatch(build_regex_from_schema(schema, cancellation=token), "[1]")
+
+    token.cancel()
+    assert token.is_cancelled()
+    with pytest.raises(ValueError, match="cancelled"):
+        build_regex_from_schema(schema, cancellation=token)
+    with pytest.raises(ValueError, match="Invalid timeout"):
+        build_regex_from_schema(schema, timeout=-1.0)
+    assert build_regex_from_schema(schema, timeout=60.0)
+
+
+def test_compile_async():
+    schema = json.dumps({"type": "integer", "minimum": 10, "maximum": 29})
+    vocabulary = Vocabulary.from_dict({"1": [1], "2": [2], "x": [3]})
+
+    async def compile_schemas():
+        guide = await compile_async(schema, vocabulary, 0)
+        assert guide.get_allowed_tokens() == [1, 2]
+        with pytest.raises(ValueError):
+            await compile_async("{", vocabulary, 0)
+
+        # The guide of a cancelled compilation is dropped.
+        future = compile_async(json.dumps({"type": "string"}), vocabulary, 0)
+        future.cancel()
+        with pytest.raises(asyncio.CancelledError):
+            await future
+        await asyncio.sleep(0.1)
+
+    asyncio.run(compile_schemas())
+    with pytest.raises(RuntimeError, match="no running event loop"):
+        compile_async(schema, vocabulary, 0)
+
+
+def test_guide_resume():
+    schema = json.dumps(
+        {
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"],
+        }
+    )
+    vocabulary = Vocabulary.from_dict({'{"name":"': [1], "a": [2], "b": [3], '"}': [4]})
+    guide = compile_schema(schema, vocabulary, 0)
+    assert guide.resume('{"name":"ab') == ""
+    assert guide.get_allowed_tokens() == [2, 3, 4]
+    assert guide.resume('{"na') == '{"na'
+    assert guide.get_state() == 0
+    assert guide.get_allowed_tokens() == [1]
+    with pytest.raises(ValueError, match="after 1 characters"):
+        guide.resume("{x")
+
+
+def test_to_regex_json_values():
+    assert to_regex({"type": "integer"}) == INTEGER
+    assert re.fullmatch(to_regex({"enum": [1, 2.5, None, "a"]}), "2.5")
+
+    with pytest.raises(ValueError, match="expected an object"):
+        to_regex(["not", "a", "schema"])
+    with pytest.raises(TypeError, match="not JSON serializable"):
+        to_regex({"const": object()})
+    with pytest.raises(TypeError, match="keys must be strings"):
+        to_regex({"properties": {1: {"type": "integer"}}})
+    with pytest.raises(ValueError, match="out of JSON range"):
+        to_regex({"const": float("nan")})
+
+
+def test_schema_file(tmp_path):
+    schema = {"type": "array", "items": {"type": "integer"}}
+    path = tmp_path / "schema.json"
+    path.write_text(json.dumps(schema))
+    assert build_regex_from_schema_file(path) == to_regex(schema)
+
+    gzipped = tmp_path / "schema.json.gz"
+    gzipped.write_bytes(gzip.compress(json.dumps(schema).encode()))
+    assert build_regex_from_schema_file(str(gzipped), compact=True) == to_regex(
+        schema, compact=True
+    )
+
+    with pytest.raises(FileNotFoundError, match="missing.json"):
+        build_regex_from_schema_file(tmp_path / "missing.json")
+    path.write_text('{"type": ')
+    with pytest.raises(ValueError, match="Invalid JSON in schema file"):
+        build_regex_from_schema_file(path)
+
+
+def test_wordlists(tmp_path):
+    path = tmp_path / "drugs.txt"
+    path.write_text("aspirin\nibuprofen\n\nparacetamol\n")
+    schema = {
+        "type": "object",
+        "properties": {
+            "drug": {"type": "string", "x-wordlist": "drugs"},
+            "city": {"x-wordlist": "cities"},
+        },
+        "required": ["drug", "city"],
+    }
+    regex = build_regex_from_schema(
+        json.dumps(schema), wordlists={"drugs": path, "cities": ["Oslo", "Osaka"]}
+    )
+    assert re.fullmatch(regex, '{"drug":"ibuprofen","city":"Osaka"}')
+    assert not re.fullmatch(regex, '{"drug":"aspirine","city":"Osaka"}')
+    assert not re.fullmatch(regex, '{"drug":"aspirin","city":"Rome"}')
+
+    with pytest.raises(ValueError, match="Unknown wordlist 'cities'"):
+        build_regex_from_schema(json.dumps(schema), wordlists={"drugs": path})
+    with pytest.raises(FileNotFoundError, match="missing.txt"):
+        build_regex_from_schema(
+            json.dumps(schema), wordlists={"drugs": tmp_path / "missing.txt"}
+        )
+
+
+def test_yaml_schema(tmp_path):
+    yaml = """
+# Written as in an OpenAPI document
+type: object
+properties:
+  name: {type: string}
+  tags:
+    type: array
+    items:
+      enum: [a, "yes", 1]
+required: [name]
+"""
+    schema = {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "tags": {"type": "array", "items": {"enum": ["a", "yes", 1]}},
+        },
+        "required": ["name"],
+    }
+    assert build_regex_from_yaml(yaml) == to_regex(schema)
+    path = tmp_path / "schema.yaml"
+    path.write_text(yaml)
+    assert build_regex_from_schema_file(path) == to_regex(schema)
+
+    with pytest.raises(ValueError, match="Invalid YAML schema"):
+        build_regex_from_yaml("type: [object")
+
+
+def test_json5_schema(tmp_path):
+    json5 = """{
+  // Maintained by hand
+  type: 'object',
+  properties: {
+    name: {type: "string"},
+  },
+  required: ["name",],
+}"""
+    schema = {
+        "type": "object",
+        "properties": {"name": {"type": "string"}},
+        "required": ["name"],
+    }
+    assert build_regex_from_json5(json5) == to_regex(schema)
+    path = tmp_path / "schema.jsonc"
+    path.write_text(json5)
+    assert build_regex_from_schema_file(path) == to_regex(schema)
+
+    with pytest.raises(ValueError, match="2:9"):
+        build_regex_from_json5("{\n  type: object}")
+
+
+def test_schema_builder():
+    schema = (
+        SchemaBuilder.object()
+        .property("name", SchemaBuilder.string().min_len(1))
+        .property("age", SchemaBuilder.integer().minimum(0))
+        .property("tags", SchemaBuilder.array(SchemaBuilder.string()).max_items(2))
+        .property("kind", SchemaBuilder.enumeration(["a", "b"]))
+        .required(["name"])
+        .closed()
+    )
+    assert schema.to_dict() == {
+        "type": "object",
+        "properties": {
+            "name": {"type": "string", "minLength": 1},
+            "age": {"type": "integer", "minimum": 0},
+            "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2},
+            "kind": {"enum": ["a", "b"]},
+        },
+        "required": ["name"],
+        "additionalProperties": False,
+    }
+    assert schema.to_regex() == to_regex(schema.to_dict())
+    assert re.fullmatch(schema.to_regex(compact=True), '{"name":"x","age":3}')
+
+    with pytest.raises(TypeError, match="'min_len' does not apply to an integer schema"):
+        SchemaBuilder.integer().min_len(1)
+
+
+def test_build_regex_from_model():
+    class Cat(BaseModel):
+        kind: Literal["cat"]
+        lives: int = Field(ge=0, le=9)
+
+    class Owner(BaseModel):
+        name: Optional[str]
+        pet: Optional[Cat] = None
+
+    regex = build_regex_from_model(Owner, compact=True)
+    assert re.fullmatch(regex, '{"name":null,"pet":{"kind":"cat","lives":9}}')
+    assert re.fullmatch(regex, '{"name":"a"}')
+    assert not re.fullmatch(regex, '{"name":"a","pet":{"kind":"dog","lives":9}}')
+
+    from outlines_core.fsm.json_schema import _normalize_pydantic
+
+    assert _normalize_pydantic(
+        {"anyOf": [{"anyOf": [{"enum": ["a"]}, {"type": "null"}]}, {"type": "null"}]}
+    ) == {"anyOf": [{"const": "a"}, {"type": "null"}]}
+    with pytest.raises(TypeError, match="not a Pydantic v2 model"):
+        build_regex_from_model(dict)
+
+
+def test_discriminator():
+    # Written by msgspec for `Union[Put, Delete]` of structs tagged in the field "op"
+    schema = {
+        "$ref": "#/$defs/Command",
+        "$defs": {
+            "Command": {
+                "anyOf": [{"$ref": "#/$defs/Put"}, {"$ref": "#/$defs/Delete"}],
+                "discriminator": {
+                    "propertyName": "op",
+                    "mapping": {"put": "#/$defs/Put", "del": "#/$defs/Delete"},
+                },
+            },
+            "Put": {
+                "type": "object",
+                "properties": {
+                    "op": {"type": "string"},
+                    "key": {"type": "string"},
+                    "value": {"type": "integer"},
+                },
+                "required": ["op", "key", "value"],
+            },
+            "Delete": {
+                "type": "object",
+                "properties": {"op": {"type": "string"}, "key": {"type": "string"}},
+                "required": ["op", "key"],
+            },
+        },
+    }
+    regex = to_regex(schema, compact=True)
+    assert re.fullmatch(regex, '{"op":"put","key":"a","value":1}')
+    assert re.fullmatch(regex, '{"op":"del","key":"a"}')
+    assert not re.fullmatch(regex, '{"op":"del","key":"a","value":1}')
+    assert not re.fullmatch(regex, '{"op":"get","key":"a"}')
+
+
+def test_build_regex_from_msgspec():
+    msgspec = pytest.importorskip("msgspec")
+
+    class Put(msgspec.Struct, tag="put", tag_field="op"):
+        key: str
+        value: int
+
+    class Delete(msgspec.Struct, tag="del", tag_field="op"):
+        key: str
+
+    regex = build_regex_from_msgspec(Union[Put, Delete], compact=True)
+    for command in [Put("a", 1), Delete("a")]:
+        assert re.fullmatch(regex, msgspec.json.encode(command).decode())
+
+
+def test_jsonl():
+    schema = json.dumps({"type": "object", "properties": {"id": {"type": "integer"}}})
+    regex = build_jsonl_regex_from_schema(schema, min_lines=2, max_lines=3)
+    assert re.fullmatch(regex, '{"id": 1}\n{"id": 2}\n')
+    assert re.fullmatch(regex, '{"id": 1}\n{}\n{"id": 3}\n')
+    assert not re.fullmatch(regex, '{"id": 1}\n')
+    assert not re.fullmatch(regex, '{"id": 1}\n' * 4)
+    assert not re.fullmatch(regex, '{"id": 1}{"id": 2}\n')
+    assert re.fullmatch(build_jsonl_regex_from_schema(schema), '{"id": 1}\n' * 10)
+
+    with pytest.raises(ValueError, match="pretty-printed"):
+        build_jsonl_regex_from_schema(schema, indent=2)
+    with pytest.raises(ValueError, match="must not match line breaks"):
+        build_jsonl_regex_from_schema(schema, whitespace_pattern=r"[\n ]*")
+    with pytest.raises(ValueError, match="greater than or equal to min_lines"):
+        build_jsonl_regex_from_schema(schema, min_lines=2, max_lines=1)
+
+
+def test_markdown_fence():
+    schema = json.dumps({"type": "object", "properties": {"id": {"type": "integer"}}})
+    regex = build_regex_from_schema(schema, markdown_fence=True)
+    assert re.fullmatch(regex, '```json\n{"id": 1}\n```')
+    assert not re.fullmatch(regex, 'Sure!\n```json\n{"id": 1}\n```')
+
+    regex = build_regex_from_schema(schema, fence_preamble=10)
+    assert re.fullmatch(regex, 'Sure!\n```json\n{"id": 1}\n```')
+    assert re.fullmatch(regex, '```json\n{"id": 1}\n```')
+    assert not re.fullmatch(regex, 'Sure, here it is!\n```json\n{"id": 1}\n```')
+
+    regex = build_jsonl_regex_from_schema(schema, max_lines=2, markdown_fence=True)
+    assert re.fullmatch(regex, '```jsonl\n{"id": 1}\n{"id": 2}\n```')
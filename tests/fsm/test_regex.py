@@ -1,13 +1,16 @@
+import pickle
 from typing import List, Tuple, Union
 
 import interegular
 import pytest
 import torch
 from datasets.fingerprint import Hasher
+from interegular.fsm import anything_else
 from outlines_core.fsm.outlines_core_rs import Vocabulary
 from outlines_core.fsm.regex import (
     BetterAlphabet,
     BetterFSM,
+    FSMInfo,
     _walk_fsm,
     create_fsm_index_end_to_end,
     create_fsm_index_tokenizer,
@@ -337,6 +340,39 @@ def test_create_fsm_index_end_to_end_multi_byte():
     assert res == {0: {5: 3, 6: 3, 7: 7, 2: 2}, 3: {2: 3, 3: 3, 4: 3}}
 
 
+def test_create_fsm_index_end_to_end_byte_tokens():
+    regex_str = "😇| [😈-😍][😇-😎]*"
+
+    regex_pattern = interegular.parse_pattern(regex_str)
+    regex_fsm, _ = make_deterministic_fsm(regex_pattern.to_fsm().reduce())
+    byte_fsm = make_byte_level_better_fsm(regex_fsm, keep_utf8=True)
+
+    # Tokens given as bytes need not be valid UTF-8 on their own.
+    tokens_to_token_ids = {
+        "😍".encode(): [4],
+        " 😍".encode(): [6],
+        " 😍".encode()[:-1]: [7],
+        b"\xff": [9],
+    }
+
+    res = create_fsm_index_end_to_end(
+        byte_fsm.fsm_info,
+        Vocabulary.from_dict(tokens_to_token_ids),
+        frozenset(),
+    )
+
+    assert res == {0: {6: 3, 7: 7}, 3: {4: 3}}
+    assert get_token_transition_keys(
+        byte_fsm.fsm_info.alphabet_symbol_mapping,
+        byte_fsm.fsm_info.alphabet_anything_value,
+        "😍".encode(),
+    ) == get_token_transition_keys(
+        byte_fsm.fsm_info.alphabet_symbol_mapping,
+        byte_fsm.fsm_info.alphabet_anything_value,
+        merge_symbols(("F0", "9F", "98", "8D")),
+    )
+
+
 @pytest.mark.parametrize(
     "hf_tokenizer_uri, revision",
     [
@@ -433,6 +469,47 @@ def test_make_byte_level_fsm(regex, string, should_accept):
         assert mix_state_utf8 == str_state
 
 
+def test_fsm_info_from_interegular():
+    interegular_fsm = interegular.parse_pattern("a[bc]*d?").to_fsm()
+    fsm_info = FSMInfo.from_interegular(interegular_fsm)
+    assert fsm_info.enumerate_strings(2, 10) == ["a", "ab", "ac", "ad"]
+    assert fsm_info.alphabet_anything_value == interegular_fsm.alphabet[anything_else]
+
+    pickled = pickle.dumps(interegular_fsm)
+    assert FSMInfo.from_interegular(pickled).equivalent(fsm_info)
+
+    layout = {
+        "alphabet": dict(interegular_fsm.alphabet._symbol_mapping),
+        "states": set(interegular_fsm.states),
+        "initial": interegular_fsm.initial,
+        "finals": set(interegular_fsm.finals),
+        "map": dict(interegular_fsm.map),
+    }
+    assert FSMInfo.from_interegular(layout).equivalent(fsm_info)
+
+    with pytest.raises(ValueError, match="not a state"):
+        FSMInfo.from_interegular(dict(layout, initial=max(layout["states"]) + 1))
+
+
+def test_fsm_info_from_words():
+    fsm_info = FSMInfo.from_words(["tap", "taps", b"top", "tops"])
+    assert fsm_info.enumerate_strings(4, 10) == ["tap", "taps", "top", "tops"]
+    assert len(fsm_info.transitions) == 5
+
+
+def test_fsm_info_from_wordlist(tmp_path):
+    path = tmp_path / "words.txt"
+    path.write_text("tap\r\ntaps\ntop\n\ntops\n")
+    words = ["tap", "taps", "top", "tops"]
+    assert FSMInfo.from_wordlist(path).equivalent(FSMInfo.from_words(words))
+
+
+def test_fsm_info_forbid_substrings():
+    fsm_info = FSMInfo.from_words(["ssn: 1", "name: 2", "SSN"])
+    allowed = fsm_info.forbid_substrings(["ssn", "SSN"])
+    assert allowed.enumerate_strings(10, 10) == ["name: 2"]
+
+
 @pytest.mark.skip(reason="Only for local profiling")
 def test_regex_index_performance():
     from line_profiler import LineProfiler  # type: ignore [import]